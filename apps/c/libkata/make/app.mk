@@ -29,6 +29,8 @@ $(BUILD_DIR)/build/%.o: %.c $(BUILD_ROOT)/libkata/libkata.a | $(BUILD_DIR)
 $(BUILD_DIR):
 	mkdir -p $(BUILD_DIR)/build
 
+include $(MYDIR)/sign.mk
+
 clean:
 	rm -rf $(BUILD_DIR)
 
@@ -0,0 +1,43 @@
+# Copyright 2022 Google LLC
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     https://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+## Package signing
+#
+# Packages must be signed with a key the SecurityCoordinator trusts (see
+# SecurityCoordinator/kata-security-coordinator/src/trusted_keys.rs). The
+# bundle image made from $(APPNAME).elf is $(APPNAME).unsigned.app; it is
+# signed to make $(APPNAME).app, which is what goes in the builtins
+# archive. KATA_PACKAGE_SIGNING_KEY names a file holding the signing key
+# (64 hex digits); without it the well-known development key is used,
+# which only development builds of the SecurityCoordinator trust.
+#
+# Signing also adds the package manifest: the bundle id is $(APPNAME)
+# and the version (for anti-rollback) is $(APP_VERSION), which should be
+# raised with each release of the application.
+
+SIGN_PACKAGE_DIR := $(MYDIR)/../../../system/components/SecurityCoordinator/tools/kata-sign-package
+SIGN_PACKAGE     := $(BUILD_ROOT)/host/release/kata-sign-package
+HOST_CARGO       ?= cargo
+APP_VERSION      ?= 0
+
+ifneq ($(KATA_PACKAGE_SIGNING_KEY),)
+    SIGN_PACKAGE_OPTS := --seed $(KATA_PACKAGE_SIGNING_KEY)
+endif
+
+$(SIGN_PACKAGE):
+	$(HOST_CARGO) build --release --manifest-path $(SIGN_PACKAGE_DIR)/Cargo.toml \
+		--target-dir $(BUILD_ROOT)/host
+
+$(BUILD_DIR)/$(APPNAME).app: $(BUILD_DIR)/$(APPNAME).unsigned.app $(SIGN_PACKAGE)
+	$(SIGN_PACKAGE) $(SIGN_PACKAGE_OPTS) --bundle-id $(APPNAME) --version $(APP_VERSION) $< $@
@@ -51,11 +51,7 @@ pub fn main(_a0: u32, _a1: u32, _a2: u32, _a3: u32) {
         Err(e) => sdk_log(&format!("read error {:?}", e)),
         Ok(kv) => sdk_log(&format!("read returned {:?}", kv)),
     };
-    keyval
-        .split_at_mut(3)
-        .0
-        .copy_from_slice(String::from("123").as_bytes());
-    let _ = match sdk_write_key(KEY, &keyval) {
+    let _ = match sdk_write_key(KEY, String::from("123").as_bytes()) {
         Ok(_) => sdk_log("write ok"),
         Err(e) => sdk_log(&format!("write error {:?}", e)),
     };
@@ -64,6 +60,31 @@ pub fn main(_a0: u32, _a1: u32, _a2: u32, _a3: u32) {
         Err(e) => sdk_log(&format!("read failed: {:?}", e)),
         Ok(kv) => sdk_log(&format!("read returned {:?}", kv)),
     };
+    let _ = match sdk_list_keys(None) {
+        Ok((keys, _)) => sdk_log(&format!("list returned {:?}", keys)),
+        Err(e) => sdk_log(&format!("list failed: {:?}", e)),
+    };
+    // A max-size value is passed between SDKRuntime & SecurityCoordinator
+    // in page frames.
+    for (i, b) in keyval.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    let _ = match sdk_write_key(KEY, &keyval) {
+        Ok(_) => sdk_log("write (large) ok"),
+        Err(e) => sdk_log(&format!("write (large) error {:?}", e)),
+    };
+    keyval.fill(0);
+    let _ = match sdk_read_key(KEY, &mut keyval) {
+        Err(e) => sdk_log(&format!("read (large) failed: {:?}", e)),
+        Ok(kv) => {
+            if kv.len() == KEY_VALUE_DATA_SIZE && kv.iter().enumerate().all(|(i, b)| *b == i as u8)
+            {
+                sdk_log("read (large) ok")
+            } else {
+                sdk_log(&format!("read (large) returned bad data, len {}", kv.len()))
+            }
+        }
+    };
     let _ = match sdk_delete_key(KEY) {
         Ok(_) => sdk_log("delete ok"),
         Err(e) => sdk_log(&format!("delete error {:?}", e)),
@@ -29,6 +29,8 @@ lib${APPNAME}:
 $(BUILD_DIR):
 	mkdir -p $(BUILD_DIR)
 
+include $(MYDIR)/sign.mk
+
 clean:
 	rm -rf $(BUILD_DIR)
 
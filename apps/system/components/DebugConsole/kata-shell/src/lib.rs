@@ -15,9 +15,14 @@
 #![no_std]
 
 extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt;
 use core::fmt::Write;
+use core::mem::size_of;
+use core::ptr;
 use cpio::CpioNewcReader;
 use hashbrown::HashMap;
 
@@ -26,19 +31,29 @@ use kata_line_reader::LineReader;
 use kata_memory_interface::*;
 #[cfg(feature = "ml_support")]
 use kata_ml_interface::*;
+use kata_os_common::copyregion::CopyRegion;
 use kata_os_common::sel4_sys;
 use kata_os_common::slot_allocator;
+use kata_proc_interface::kata_pkg_mgmt_factory_reset;
 use kata_proc_interface::kata_pkg_mgmt_install;
 use kata_proc_interface::kata_pkg_mgmt_uninstall;
 use kata_proc_interface::kata_proc_ctrl_get_running_bundles;
 use kata_proc_interface::kata_proc_ctrl_start;
 use kata_proc_interface::kata_proc_ctrl_stop;
+use kata_security_interface::kata_security_allow_rollback;
 use kata_security_interface::kata_security_delete_key;
+use kata_security_interface::kata_security_get_attestation_report;
+use kata_security_interface::kata_security_get_audit_log;
+use kata_security_interface::kata_security_get_key_usage;
+use kata_security_interface::kata_security_list_keys;
 use kata_security_interface::kata_security_read_key;
 use kata_security_interface::kata_security_write_key;
+use kata_security_interface::KEY_VALUE_DATA_SIZE;
 
 use sel4_sys::seL4_CNode_Delete;
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_PageBits;
+use sel4_sys::seL4_Word;
 use sel4_sys::seL4_WordBits;
 
 use slot_allocator::KATA_CSPACE_SLOTS;
@@ -62,8 +77,12 @@ mod test_timer_service;
 #[cfg(feature = "TEST_UART")]
 mod test_uart;
 
+const PAGE_SIZE: usize = 1 << seL4_PageBits;
+
 extern "C" {
     static SELF_CNODE: seL4_CPtr;
+    // Copyregion for passing large data (key values, manifests) to/from SecurityCoordinator.
+    static mut KEY_VALUE: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
 }
 
 /// Error type indicating why a command line is not runnable.
@@ -117,15 +136,23 @@ type CmdFn = fn(
 fn get_cmds() -> HashMap<&'static str, CmdFn> {
     let mut cmds = HashMap::<&str, CmdFn>::new();
     cmds.extend([
+        ("allow_rollback", allow_rollback_command as CmdFn),
+        ("attest", attest_command as CmdFn),
+        ("audit", audit_command as CmdFn),
         ("builtins", builtins_command as CmdFn),
         ("bundles", bundles_command as CmdFn),
         ("capscan", capscan_command as CmdFn),
+        ("factory_reset", factory_reset_command as CmdFn),
         ("kvdelete", kvdelete_command as CmdFn),
+        ("kvlist", kvlist_command as CmdFn),
         ("kvread", kvread_command as CmdFn),
+        ("kvusage", kvusage_command as CmdFn),
         ("kvwrite", kvwrite_command as CmdFn),
         ("install", install_command as CmdFn),
         ("loglevel", loglevel_command as CmdFn),
         ("mdebug", mdebug_command as CmdFn),
+        ("mleaks", mleaks_command as CmdFn),
+        ("mlimit", mlimit_command as CmdFn),
         ("mstats", mstats_command as CmdFn),
         ("ps", ps_command as CmdFn),
         ("source", source_command as CmdFn),
@@ -244,6 +271,83 @@ fn source_command(
     Ok(())
 }
 
+/// Implements an "allow_rollback" command that permits the next install
+/// of a bundle to be an older version than the highest installed so far.
+fn allow_rollback_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
+    match kata_security_allow_rollback(bundle_id) {
+        Ok(_) => {
+            writeln!(output, "Bundle \"{}\" may be rolled back.", bundle_id)?;
+        }
+        Err(status) => {
+            writeln!(output, "allow_rollback failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
+/// Implements an "attest" command that dumps a signed attestation report
+/// of the installed software. The optional argument is a hex-encoded nonce
+/// (e.g. from a remote verifier) to include in the report.
+fn attest_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let nonce = match args.next() {
+        Some(nonce) => hex::decode(nonce).map_err(|_| CommandError::BadArgs)?,
+        None => Vec::new(),
+    };
+    let mut copy_region = unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+    match kata_security_get_attestation_report(&nonce, &mut copy_region) {
+        Ok(attestation) => {
+            write!(output, "{}", attestation.report)?;
+            writeln!(output, "public_key={}", hex::encode(&attestation.public_key))?;
+            writeln!(output, "signature={}", hex::encode(&attestation.signature))?;
+        }
+        Err(status) => {
+            writeln!(output, "Get attestation report failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
+/// Implements an "audit" command that dumps the SecurityCoordinator audit
+/// log, optionally only the records after the specified sequence #.
+fn audit_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let after = match args.next() {
+        Some(seq) => Some(seq.parse::<u32>()?),
+        None => None,
+    };
+    let mut copy_region = unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+    match kata_security_get_audit_log(after, &mut copy_region) {
+        Ok(records) => {
+            for r in records {
+                writeln!(
+                    output,
+                    "{:>5} {:>10}ms badge {} {:?} {} {}",
+                    r.seq, r.timestamp_ms, r.badge, r.event, r.bundle_id, r.detail
+                )?;
+            }
+        }
+        Err(status) => {
+            writeln!(output, "Get audit log failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
 /// Implements a "builtins" command that lists the contents of the built-in cpio archive.
 fn builtins_command(
     _args: &mut dyn Iterator<Item = &str>,
@@ -500,6 +604,36 @@ fn uninstall_command(
     Ok(())
 }
 
+/// Implements a "factory_reset" command that stops all applications,
+/// uninstalls all packages, and erases all stored data. The user must
+/// confirm the operation since it cannot be undone.
+fn factory_reset_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    mut input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    output.write_str("Erase all applications and data? Type \"yes\" to confirm: ")?;
+    let mut line_reader = LineReader::new();
+    let confirmed = match line_reader.read_line(output, &mut input) {
+        Ok(answer) => answer == "yes",
+        Err(_) => false,
+    };
+    if !confirmed {
+        writeln!(output, "\nfactory_reset cancelled.")?;
+        return Ok(());
+    }
+    match kata_pkg_mgmt_factory_reset() {
+        Ok(_) => {
+            writeln!(output, "Factory reset complete.")?;
+        }
+        Err(status) => {
+            writeln!(output, "factory_reset failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
 fn start_command(
     args: &mut dyn Iterator<Item = &str>,
     _input: &mut dyn io::BufRead,
@@ -555,6 +689,56 @@ fn kvdelete_command(
     Ok(())
 }
 
+fn kvlist_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
+    let mut after: Option<String> = None;
+    loop {
+        match kata_security_list_keys(bundle_id, after.as_deref()) {
+            Ok((keys, more)) => {
+                for key in &keys {
+                    writeln!(output, "{}", key)?;
+                }
+                if !more || keys.is_empty() {
+                    break;
+                }
+                after = keys.last().cloned();
+            }
+            Err(status) => {
+                writeln!(output, "List keys failed: {:?}", status)?;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn kvusage_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
+    match kata_security_get_key_usage(bundle_id) {
+        Ok(usage) => {
+            writeln!(
+                output,
+                "{}: {}/{} keys, {}/{} bytes",
+                bundle_id, usage.keys, usage.max_keys, usage.bytes, usage.max_bytes
+            )?;
+        }
+        Err(status) => {
+            writeln!(output, "Get key usage failed: {:?}", status)?;
+        }
+    }
+    Ok(())
+}
+
 fn kvread_command(
     args: &mut dyn Iterator<Item = &str>,
     _input: &mut dyn io::BufRead,
@@ -563,11 +747,13 @@ fn kvread_command(
 ) -> Result<(), CommandError> {
     let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
     let key = args.next().ok_or(CommandError::BadArgs)?;
-    let mut keyval = [0u8; kata_security_interface::KEY_VALUE_DATA_SIZE];
-    match kata_security_read_key(bundle_id, key, &mut keyval) {
-        Ok(_) => {
-            writeln!(output, "Read key \"{}\" = {:?}.", key, keyval)?;
-        }
+    let mut keyval = vec![0u8; KEY_VALUE_DATA_SIZE];
+    let mut copy_region = unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+    match kata_security_read_key(bundle_id, key, &mut keyval, &mut copy_region) {
+        Ok(value) => match core::str::from_utf8(value) {
+            Ok(value) => writeln!(output, "Read key \"{}\" = {:?}.", key, value)?,
+            Err(_) => writeln!(output, "Read key \"{}\" = {:?}.", key, value)?,
+        },
         Err(status) => {
             writeln!(output, "Read key \"{}\" failed: {:?}", key, status)?;
         }
@@ -584,7 +770,8 @@ fn kvwrite_command(
     let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
     let key = args.next().ok_or(CommandError::BadArgs)?;
     let value = args.collect::<Vec<&str>>().join(" ");
-    match kata_security_write_key(bundle_id, key, value.as_bytes()) {
+    let mut copy_region = unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+    match kata_security_write_key(bundle_id, key, value.as_bytes(), &mut copy_region) {
         Ok(_) => {
             writeln!(output, "Write key \"{}\" = {:?}.", key, value)?;
         }
@@ -607,6 +794,102 @@ fn mdebug_command(
     Ok(())
 }
 
+fn mleaks(output: &mut dyn io::Write, trace: &MemoryAllocTrace) -> Result<(), CommandError> {
+    if !trace.enabled {
+        writeln!(output, "tracing is off, use \"mleaks on\"")?;
+        return Ok(());
+    }
+    writeln!(output, "{:<8} {:<20} {:<12} objects", "seq", "client", "owner")?;
+    for record in &trace.records {
+        let objs: Vec<String> = record
+            .objs
+            .iter()
+            .map(|(type_, count)| format!("{:?} x{}", type_, count))
+            .collect();
+        writeln!(
+            output,
+            "{:<8} {:<20} {:<12} {}",
+            record.seq,
+            format!("{}({})", client_name(record.badge), record.badge),
+            record.owner,
+            objs.join(", "),
+        )?;
+    }
+    writeln!(
+        output,
+        "{} requests outstanding, checkpoint {}",
+        trace.records.len(),
+        trace.checkpoint
+    )?;
+    if trace.dropped > 0 {
+        writeln!(output, "{} requests not traced (trace full)", trace.dropped)?;
+    }
+    Ok(())
+}
+
+// Traces MemoryManager allocations to find leaks:
+//   mleaks on|off                enable or disable tracing
+//   mleaks mark                  print a checkpoint
+//   mleaks [<since> [<until>]]   list allocations requested between two
+//                                checkpoints that are still outstanding
+// For example, to find what a start/stop cycle leaked take a checkpoint
+// before and after the cycle and list the allocations between them.
+fn mleaks_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let since = match args.next() {
+        Some(arg @ ("on" | "off")) => {
+            if let Err(status) = kata_memory_set_tracing(arg == "on") {
+                writeln!(output, "mleaks failed: {:?}", status)?;
+            }
+            return Ok(());
+        }
+        Some("mark") => {
+            match kata_memory_checkpoint() {
+                Ok(checkpoint) => writeln!(output, "checkpoint {}", checkpoint)?,
+                Err(status) => writeln!(output, "mleaks failed: {:?}", status)?,
+            }
+            return Ok(());
+        }
+        Some(since) => since.parse::<usize>()?,
+        None => 0,
+    };
+    let until = match args.next() {
+        Some(until) => until.parse::<usize>()?,
+        None => usize::MAX,
+    };
+    match kata_memory_allocations(since, until) {
+        Ok(trace) => mleaks(output, &trace)?,
+        Err(status) => writeln!(output, "mleaks failed: {:?}", status)?,
+    }
+    Ok(())
+}
+
+// Sets or clears the limit on memory allocated by a MemoryManager client:
+//   mlimit <badge> <bytes>|off [<owner>]
+// where <owner> names the bundle a client allocates for (e.g. when the
+// ProcessManager starts an application).
+fn mlimit_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let badge = args.next().ok_or(CommandError::BadArgs)?.parse::<usize>()?;
+    let limit_bytes = match args.next().ok_or(CommandError::BadArgs)? {
+        "off" => None,
+        bytes => Some(bytes.parse::<usize>()?),
+    };
+    let owner = args.next().unwrap_or("");
+    if let Err(status) = kata_memory_set_limit(badge, owner, limit_bytes) {
+        writeln!(output, "mlimit failed: {:?}", status)?;
+    }
+    Ok(())
+}
+
 fn mstats(output: &mut dyn io::Write, stats: &MemoryManagerStats) -> Result<(), CommandError> {
     writeln!(
         output,
@@ -618,15 +901,50 @@ fn mstats(output: &mut dyn io::Write, stats: &MemoryManagerStats) -> Result<(),
         "{} objs in-use, {} objs requested",
         stats.allocated_objs, stats.total_requested_objs
     )?;
+    writeln!(output, "{} device bytes in-use", stats.device_allocated_bytes)?;
+    Ok(())
+}
+
+fn mstats_clients(
+    output: &mut dyn io::Write,
+    clients: &[MemoryClientStats],
+) -> Result<(), CommandError> {
+    writeln!(
+        output,
+        "{:<20} {:<12} {:>10} {:>6} {:>10} {:>10} {:>6}",
+        "client", "owner", "bytes", "objs", "peak", "limit", "denied"
+    )?;
+    for client in clients {
+        let limit = match client.limit_bytes {
+            Some(limit_bytes) => format!("{}", limit_bytes),
+            None => String::from("-"),
+        };
+        writeln!(
+            output,
+            "{:<20} {:<12} {:>10} {:>6} {:>10} {:>10} {:>6}",
+            format!("{}({})", client_name(client.badge), client.badge),
+            client.owner,
+            client.allocated_bytes,
+            client.allocated_objs,
+            client.peak_bytes,
+            limit,
+            client.quota_exceeded,
+        )?;
+    }
     Ok(())
 }
 
 fn mstats_command(
-    _args: &mut dyn Iterator<Item = &str>,
+    args: &mut dyn Iterator<Item = &str>,
     _input: &mut dyn io::BufRead,
     output: &mut dyn io::Write,
     _builtin_cpio: &[u8],
 ) -> Result<(), CommandError> {
+    let verbose = match args.next() {
+        Some("-v") => true,
+        Some(_) => return Err(CommandError::BadArgs),
+        None => false,
+    };
     match kata_memory_stats() {
         Ok(stats) => {
             mstats(output, &stats)?;
@@ -635,6 +953,16 @@ fn mstats_command(
             writeln!(output, "stats failed: {:?}", status)?;
         }
     }
+    if verbose {
+        match kata_memory_client_stats() {
+            Ok(clients) => {
+                mstats_clients(output, &clients)?;
+            }
+            Err(status) => {
+                writeln!(output, "client stats failed: {:?}", status)?;
+            }
+        }
+    }
     Ok(())
 }
 
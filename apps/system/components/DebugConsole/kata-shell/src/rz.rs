@@ -21,7 +21,7 @@ use core::cmp;
 use core::ptr;
 use crc::crc32;
 use crc::Hasher32;
-use kata_memory_interface::kata_frame_alloc;
+use kata_memory_interface::kata_large_frame_alloc;
 use kata_memory_interface::ObjDescBundle;
 use kata_os_common::sel4_sys;
 
@@ -45,13 +45,15 @@ impl From<UploadError> for io::Error {
     fn from(_err: UploadError) -> io::Error { io::Error }
 }
 
-// TODO(sleffler): use ObjDesc::size_bytes
-const PAGE_SIZE: usize = 1 << seL4_PageBits;
+// Size of the UPLOAD window. Frames are allocated to fill the window;
+// the copyregion holds one small page so that is what we get, but a
+// window sized & aligned for a large page would get large pages.
+const UPLOAD_SIZE: usize = 1 << seL4_PageBits;
 
 extern "C" {
     static SELF_CNODE: seL4_CPtr;
     static SELF_VSPACE_ROOT: seL4_CPtr;
-    static mut UPLOAD: [u8; PAGE_SIZE];
+    static mut UPLOAD: [u8; UPLOAD_SIZE];
 }
 
 pub struct Upload {
@@ -78,9 +80,7 @@ impl Upload {
         }
     }
     pub fn crc32(&self) -> u32 { self.digest.sum32() }
-    pub fn len(&self) -> usize {
-        (self.frames.count() * PAGE_SIZE) - (self.mapped_bytes - self.next_free)
-    }
+    pub fn len(&self) -> usize { self.frames.size_bytes() - (self.mapped_bytes - self.next_free) }
     pub fn finish(&mut self) { self.unmap_current_frame().expect("finish"); }
     pub fn frames(&self) -> &ObjDescBundle { &self.frames }
 
@@ -100,10 +100,12 @@ impl Upload {
 
     // Expand storage and map the new frame into our VSpace.
     fn expand_and_map(&mut self) -> Result<(), UploadError> {
-        let new_page = kata_frame_alloc(PAGE_SIZE).map_err(|_| UploadError::MallocFailed)?;
+        let new_page =
+            kata_large_frame_alloc(UPLOAD_SIZE).map_err(|_| UploadError::MallocFailed)?;
         // Verify the new frame is in the same CNode as previous.
         assert_eq!(new_page.cnode, self.frames.cnode);
         assert_eq!(new_page.depth, self.frames.depth);
+        assert_eq!(new_page.count(), 1);
         self.frames.objs.push(new_page.objs[0]);
 
         let frame = &self.frames.objs.last().unwrap();
@@ -121,7 +123,7 @@ impl Upload {
             )
         }
         .map_err(|_| UploadError::PageMapFailed)?;
-        self.mapped_bytes = PAGE_SIZE;
+        self.mapped_bytes = frame.frame_bytes().unwrap();
         self.next_free = 0;
         Ok(())
     }
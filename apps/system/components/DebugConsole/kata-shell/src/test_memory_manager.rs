@@ -27,6 +27,7 @@ use kata_memory_interface::*;
 use kata_os_common::sel4_sys;
 
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_LargePageBits;
 use sel4_sys::seL4_MinSchedContextBits;
 use sel4_sys::seL4_ObjectType::*;
 use sel4_sys::seL4_SmallPageObject;
@@ -123,7 +124,7 @@ fn obj_alloc_command(
     check_alloc(output, "notification", kata_notification_alloc());
     check_alloc(output, "cnode", kata_cnode_alloc(5)); // NB: 32 slots
     check_alloc(output, "frame", kata_frame_alloc(4096));
-    //    check_alloc(output, "large frame",  kata_frame_alloc(1024*1024));
+    check_alloc(output, "large frame", kata_large_frame_alloc(1 << seL4_LargePageBits));
     check_alloc(output, "page table", kata_page_table_alloc());
 
     #[cfg(feature = "CONFIG_KERNEL_MCS")]
@@ -198,5 +199,18 @@ fn obj_alloc_command(
         }
     }
 
+    // Large pages plus a small page for the remainder.
+    match kata_large_frame_alloc_in_cnode((1 << seL4_LargePageBits) + 4096) {
+        Ok(frames) => {
+            writeln!(output, "kata_large_frame_alloc_in_cnode ok: {:?}", frames)?;
+            if let Err(e) = kata_object_free_in_cnode(&frames) {
+                writeln!(output, "kata_object_free_in_cnode failed: {:?}", e)?;
+            }
+        }
+        Err(e) => {
+            writeln!(output, "kata_large_frame_alloc_in_cnode failed: {:?}", e)?;
+        }
+    }
+
     Ok(writeln!(output, "All tests passed!")?)
 }
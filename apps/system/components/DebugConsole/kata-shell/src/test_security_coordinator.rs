@@ -15,17 +15,34 @@
 //! SecurityCoordinator shell test commands
 
 extern crate alloc;
+use crate::mstats;
 use crate::CmdFn;
 use crate::CommandError;
 use crate::HashMap;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::Write;
+use core::ptr;
 
 use kata_io as io;
-use kata_memory_interface::kata_object_free_in_cnode;
+use kata_memory_interface::kata_cnode_alloc;
+use kata_memory_interface::kata_frame_alloc;
+use kata_memory_interface::kata_memory_stats;
+use kata_os_common::copyregion::CopyRegion;
 use kata_os_common::cspace_slot::CSpaceSlot;
+use kata_os_common::sel4_sys;
+use kata_os_common::slot_allocator::KATA_CSPACE_SLOTS;
+use kata_proc_interface::kata_pkg_mgmt_install;
 use kata_security_interface::*;
 
+use sel4_sys::seL4_CNode_Delete;
+use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_WordBits;
+
+extern "C" {
+    static SELF_CNODE: seL4_CPtr;
+}
+
 pub fn add_cmds(cmds: &mut HashMap<&str, CmdFn>) {
     cmds.extend([
         ("scecho", scecho_command as CmdFn),
@@ -33,10 +50,39 @@ pub fn add_cmds(cmds: &mut HashMap<&str, CmdFn>) {
         ("get_manifest", get_manifest_command as CmdFn),
         ("load_application", load_application_command as CmdFn),
         ("load_model", load_model_command as CmdFn),
+        ("random", random_command as CmdFn),
+        ("test_install_rejected", test_install_rejected_command as CmdFn),
         ("test_mailbox", test_mailbox_command as CmdFn),
     ]);
 }
 
+/// Implements a "random" command that dumps random bytes (16 by default)
+/// from the SecurityCoordinator's DRBG.
+fn random_command(
+    args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let len = match args.next() {
+        Some(len) => len.parse::<usize>()?,
+        None => 16,
+    };
+    let mut data = vec![0u8; len];
+    match kata_security_get_random(&mut data) {
+        Ok(_) => {
+            for line in data.chunks(16) {
+                for b in line {
+                    write!(output, "{:02x}", b)?;
+                }
+                writeln!(output)?;
+            }
+        }
+        Err(status) => writeln!(output, "GetRandom failed: {:?}", status)?,
+    }
+    Ok(())
+}
+
 /// Implements an "scecho" command that sends arguments to the Security Core's echo service.
 fn scecho_command(
     args: &mut dyn Iterator<Item = &str>,
@@ -73,7 +119,9 @@ fn get_manifest_command(
     _builtin_cpio: &[u8],
 ) -> Result<(), CommandError> {
     let bundle_id = args.next().ok_or(CommandError::BadArgs)?;
-    match kata_security_get_manifest(bundle_id) {
+    let mut copy_region =
+        unsafe { CopyRegion::new(ptr::addr_of_mut!(crate::KEY_VALUE[0]), crate::PAGE_SIZE) };
+    match kata_security_get_manifest(bundle_id, &mut copy_region) {
         Ok(manifest) => writeln!(output, "{}", manifest)?,
         Err(status) => writeln!(output, "GetManifest failed: {:?}", status)?,
     }
@@ -91,8 +139,8 @@ fn load_application_command(
     match kata_security_load_application(bundle_id, &container_slot) {
         Ok(frames) => {
             container_slot.release(); // NB: take ownership
-            writeln!(output, "{:?}", &frames)?;
-            let _ = kata_object_free_in_cnode(&frames);
+            writeln!(output, "{:?} ({} shared)", &frames.frames, frames.shared_count())?;
+            let _ = kata_security_release_image(&frames);
         }
         Err(status) => writeln!(output, "LoadApplication failed: {:?}", status)?,
     }
@@ -111,8 +159,8 @@ fn load_model_command(
     match kata_security_load_model(bundle_id, model_id, &container_slot) {
         Ok(frames) => {
             container_slot.release(); // NB: take ownership
-            writeln!(output, "{:?}", &frames)?;
-            let _ = kata_object_free_in_cnode(&frames);
+            writeln!(output, "{:?} ({} shared)", &frames.frames, frames.shared_count())?;
+            let _ = kata_security_release_image(&frames);
         }
         Err(status) => writeln!(output, "LoadApplication failed: {:?}", status)?,
     }
@@ -135,3 +183,39 @@ fn test_mailbox_command(
     }
     Ok(())
 }
+
+/// Implements a "test_install_rejected" command that installs an unsigned
+/// package and checks that it is rejected and its memory reclaimed.
+fn test_install_rejected_command(
+    _args: &mut dyn Iterator<Item = &str>,
+    _input: &mut dyn io::BufRead,
+    output: &mut dyn io::Write,
+    _builtin_cpio: &[u8],
+) -> Result<(), CommandError> {
+    let before_stats = kata_memory_stats().expect("before stats");
+    mstats(output, &before_stats)?;
+
+    // NB: zero-filled pages; there is no signature trailer
+    let mut pkg_contents = kata_frame_alloc(8192).map_err(|_| CommandError::Memory)?;
+    let cnode_depth = pkg_contents.count_log2();
+    let cnode = kata_cnode_alloc(cnode_depth).map_err(|_| CommandError::Memory)?;
+    pkg_contents
+        .move_objects_from_toplevel(cnode.objs[0].cptr, cnode_depth as u8)
+        .map_err(|_| CommandError::Memory)?;
+    let result = kata_pkg_mgmt_install(&pkg_contents);
+    // The SecurityCoordinator owns (and has freed) the cnode; drop our cap.
+    unsafe {
+        KATA_CSPACE_SLOTS.free(cnode.objs[0].cptr, 1);
+        seL4_CNode_Delete(SELF_CNODE, cnode.objs[0].cptr, seL4_WordBits as u8)
+            .expect("test_install_rejected");
+    }
+    writeln!(output, "install -> {:?}", result)?;
+    assert!(result.is_err());
+
+    let after_stats = kata_memory_stats().expect("after stats");
+    mstats(output, &after_stats)?;
+    assert_eq!(before_stats.allocated_bytes, after_stats.allocated_bytes);
+    assert_eq!(before_stats.allocated_objs, after_stats.allocated_objs);
+
+    Ok(writeln!(output, "All tests passed!")?)
+}
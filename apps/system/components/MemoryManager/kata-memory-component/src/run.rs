@@ -18,20 +18,28 @@
 #![no_std]
 #![allow(clippy::missing_safety_doc)]
 
+extern crate alloc;
+
+use alloc::string::String;
 use core::ops::Range;
 use core::slice;
 use kata_memory_interface::MemoryManagerError;
 use kata_memory_interface::MemoryManagerInterface;
+use kata_memory_interface::MemoryPressure;
 use kata_memory_interface::ObjDescBundle;
+use kata_memory_interface::RawMemoryClientsData;
 use kata_memory_interface::RawMemoryStatsData;
+use kata_memory_interface::RawMemoryTraceData;
+use kata_memory_interface::DEBUG_CONSOLE_BADGE;
 use kata_memory_manager::KataMemoryManager;
 use kata_os_common::camkes::Camkes;
 use kata_os_common::sel4_sys;
-use log::info;
+use log::{info, trace};
 
 use sel4_sys::seL4_BootInfo;
 use sel4_sys::seL4_CNode_Delete;
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_Word;
 
 static mut CAMKES: Camkes = Camkes::new("MemoryManager");
 
@@ -42,6 +50,18 @@ extern "C" {
     // Each CAmkES-component has a CNode setup at a well-known top-level slot.
     // We re-use that slot to receive CNode caps passed with alloc & free requests.
     static MEMORY_RECV_CNODE: seL4_CPtr;
+
+    fn memory_get_sender_id() -> seL4_Word;
+
+    fn pressure_get_sender_id() -> seL4_Word;
+    fn pressure_emit(badge: seL4_Word);
+}
+
+// Signals pressure subscribers that have seen a threshold crossed.
+unsafe fn signal_pressure() {
+    for badge in KATA_MEMORY.take_pressure_signals() {
+        pressure_emit(badge);
+    }
 }
 
 #[no_mangle]
@@ -49,7 +69,8 @@ pub unsafe extern "C" fn pre_init() {
     // NB: set to max; the LoggerInterface will filter
     CAMKES.init_logger(log::LevelFilter::Trace);
 
-    static mut HEAP_MEMORY: [u8; 8 * 1024] = [0; 8 * 1024];
+    // NB: includes room for allocation tracing when enabled
+    static mut HEAP_MEMORY: [u8; 24 * 1024] = [0; 24 * 1024];
     CAMKES.init_allocator(&mut HEAP_MEMORY);
 
     extern "C" {
@@ -115,12 +136,13 @@ pub unsafe extern "C" fn memory_alloc(
 
             bundle.cnode = recv_path.1;
             // NB: bundle.depth should reflect the received cnode
-            KATA_MEMORY.alloc(&bundle).into()
+            KATA_MEMORY.alloc(&bundle, memory_get_sender_id()).into()
         }
         Err(_) => MemoryManagerError::MmeDeserializeFailed,
     };
     // NB: must clear ReceivePath for next request
     CAMKES.clear_recv_path();
+    signal_pressure();
     ret_status
 }
 
@@ -141,7 +163,62 @@ pub unsafe extern "C" fn memory_free(
 
             bundle.cnode = recv_path.1;
             // NB: bundle.depth should reflect the received cnode
-            KATA_MEMORY.free(&bundle).into()
+            KATA_MEMORY.free(&bundle, memory_get_sender_id()).into()
+        }
+        Err(_) => MemoryManagerError::MmeDeserializeFailed,
+    };
+    // NB: must clear ReceivePath for next request
+    CAMKES.clear_recv_path();
+    signal_pressure();
+    ret_status
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memory_device_alloc(
+    c_raw_data_len: u32,
+    c_raw_data: *const u8,
+) -> MemoryManagerError {
+    let recv_path = CAMKES.get_current_recv_path();
+    // NB: make sure noone clobbers the setup done in memory__init
+    CAMKES.assert_recv_path();
+
+    let raw_slice = slice::from_raw_parts(c_raw_data, c_raw_data_len as usize);
+    let ret_status = match postcard::from_bytes::<(seL4_Word, ObjDescBundle)>(raw_slice) {
+        Ok((paddr, mut bundle)) => {
+            // We must have a CNode for returning allocated objects.
+            Camkes::debug_assert_slot_cnode("memory_device_alloc", &recv_path);
+
+            bundle.cnode = recv_path.1;
+            // NB: bundle.depth should reflect the received cnode
+            KATA_MEMORY
+                .device_alloc(&bundle, paddr, memory_get_sender_id())
+                .into()
+        }
+        Err(_) => MemoryManagerError::MmeDeserializeFailed,
+    };
+    // NB: must clear ReceivePath for next request
+    CAMKES.clear_recv_path();
+    ret_status
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memory_device_free(
+    c_raw_data_len: u32,
+    c_raw_data: *const u8,
+) -> MemoryManagerError {
+    let recv_path = CAMKES.get_current_recv_path();
+    // NB: make sure noone clobbers the setup done in memory__init
+    CAMKES.assert_recv_path();
+
+    let raw_slice = slice::from_raw_parts(c_raw_data, c_raw_data_len as usize);
+    let ret_status = match postcard::from_bytes::<ObjDescBundle>(raw_slice) {
+        Ok(mut bundle) => {
+            // We must have a CNode for returning allocated objects.
+            Camkes::debug_assert_slot_cnode("memory_device_free", &recv_path);
+
+            bundle.cnode = recv_path.1;
+            // NB: bundle.depth should reflect the received cnode
+            KATA_MEMORY.device_free(&bundle).into()
         }
         Err(_) => MemoryManagerError::MmeDeserializeFailed,
     };
@@ -172,6 +249,143 @@ pub unsafe extern "C" fn memory_stats(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn memory_set_owner(
+    c_raw_data_len: u32,
+    c_raw_data: *const u8,
+) -> MemoryManagerError {
+    let recv_path = CAMKES.get_current_recv_path();
+    // NB: make sure noone clobbers the setup done in memory__init
+    CAMKES.assert_recv_path();
+    Camkes::debug_assert_slot_empty("memory_set_owner", &recv_path);
+
+    let raw_slice = slice::from_raw_parts(c_raw_data, c_raw_data_len as usize);
+    match postcard::from_bytes::<String>(raw_slice) {
+        Ok(owner) => KATA_MEMORY.set_owner(memory_get_sender_id(), &owner).into(),
+        Err(_) => MemoryManagerError::MmeDeserializeFailed,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memory_set_limit(
+    c_raw_data_len: u32,
+    c_raw_data: *const u8,
+) -> MemoryManagerError {
+    let recv_path = CAMKES.get_current_recv_path();
+    // NB: make sure noone clobbers the setup done in memory__init
+    CAMKES.assert_recv_path();
+    Camkes::debug_assert_slot_empty("memory_set_limit", &recv_path);
+
+    // Limits are administrative; only the DebugConsole may set them.
+    let client = memory_get_sender_id();
+    if client != DEBUG_CONSOLE_BADGE {
+        trace!("set_limit denied for badge {}", client);
+        return MemoryManagerError::MmePermissionDenied;
+    }
+    let raw_slice = slice::from_raw_parts(c_raw_data, c_raw_data_len as usize);
+    match postcard::from_bytes::<(seL4_Word, String, Option<usize>)>(raw_slice) {
+        Ok((badge, owner, limit_bytes)) => KATA_MEMORY.set_limit(badge, &owner, limit_bytes).into(),
+        Err(_) => MemoryManagerError::MmeDeserializeFailed,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memory_client_stats(
+    c_raw_resp_data: *mut RawMemoryClientsData,
+) -> MemoryManagerError {
+    let recv_path = CAMKES.get_current_recv_path();
+    // NB: make sure noone clobbers the setup done in memory__init
+    CAMKES.assert_recv_path();
+    Camkes::debug_assert_slot_empty("memory_client_stats", &recv_path);
+
+    match KATA_MEMORY.client_stats() {
+        Ok(stats) => match postcard::to_slice(&stats, &mut (*c_raw_resp_data)[..]) {
+            Ok(_) => MemoryManagerError::MmeSuccess,
+            Err(_) => MemoryManagerError::MmeSerializeFailed,
+        },
+        Err(e) => e.into(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memory_set_tracing(
+    c_raw_data_len: u32,
+    c_raw_data: *const u8,
+) -> MemoryManagerError {
+    let recv_path = CAMKES.get_current_recv_path();
+    // NB: make sure noone clobbers the setup done in memory__init
+    CAMKES.assert_recv_path();
+    Camkes::debug_assert_slot_empty("memory_set_tracing", &recv_path);
+
+    // Tracing is a debugging aid; only the DebugConsole may control it.
+    let client = memory_get_sender_id();
+    if client != DEBUG_CONSOLE_BADGE {
+        trace!("set_tracing denied for badge {}", client);
+        return MemoryManagerError::MmePermissionDenied;
+    }
+    let raw_slice = slice::from_raw_parts(c_raw_data, c_raw_data_len as usize);
+    match postcard::from_bytes::<bool>(raw_slice) {
+        Ok(enable) => KATA_MEMORY.set_tracing(enable).into(),
+        Err(_) => MemoryManagerError::MmeDeserializeFailed,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memory_allocations(
+    c_raw_data_len: u32,
+    c_raw_data: *const u8,
+    c_raw_resp_data: *mut RawMemoryTraceData,
+) -> MemoryManagerError {
+    let recv_path = CAMKES.get_current_recv_path();
+    // NB: make sure noone clobbers the setup done in memory__init
+    CAMKES.assert_recv_path();
+    Camkes::debug_assert_slot_empty("memory_allocations", &recv_path);
+
+    let raw_slice = slice::from_raw_parts(c_raw_data, c_raw_data_len as usize);
+    match postcard::from_bytes::<(usize, usize)>(raw_slice) {
+        Ok((since, until)) => match KATA_MEMORY.allocations(since, until) {
+            // NB: fails if the records do not fit; the caller can narrow the range
+            Ok(trace) => match postcard::to_slice(&trace, &mut (*c_raw_resp_data)[..]) {
+                Ok(_) => MemoryManagerError::MmeSuccess,
+                Err(_) => MemoryManagerError::MmeSerializeFailed,
+            },
+            Err(e) => e.into(),
+        },
+        Err(_) => MemoryManagerError::MmeDeserializeFailed,
+    }
+}
+
+// MemoryPressureInterface glue stubs.
+
+#[no_mangle]
+pub unsafe extern "C" fn pressure_subscribe(
+    c_raw_data_len: u32,
+    c_raw_data: *const u8,
+) -> MemoryManagerError {
+    let raw_slice = slice::from_raw_parts(c_raw_data, c_raw_data_len as usize);
+    let ret_status = match postcard::from_bytes::<(usize, usize)>(raw_slice) {
+        Ok((low_water_bytes, critical_bytes)) => KATA_MEMORY
+            .subscribe(pressure_get_sender_id(), low_water_bytes, critical_bytes)
+            .into(),
+        Err(_) => MemoryManagerError::MmeDeserializeFailed,
+    };
+    signal_pressure();
+    ret_status
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pressure_unsubscribe() -> MemoryManagerError {
+    KATA_MEMORY.unsubscribe(pressure_get_sender_id()).into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pressure_level() -> MemoryPressure {
+    // NB: callers that have not subscribed see no pressure
+    KATA_MEMORY
+        .pressure(pressure_get_sender_id())
+        .unwrap_or(MemoryPressure::PressureNormal)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn memory_debug() -> MemoryManagerError {
     let recv_path = CAMKES.get_current_recv_path();
@@ -18,9 +18,11 @@
 #![allow(dead_code)]
 
 extern crate alloc;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt;
+use core::ops::Range;
 use kata_os_common::camkes::Camkes;
 use kata_os_common::sel4_sys;
 use kata_os_common::slot_allocator;
@@ -30,12 +32,16 @@ use serde::{Deserialize, Serialize};
 use sel4_sys::seL4_CNode_Move;
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_Error;
+use sel4_sys::seL4_LargePageBits;
+use sel4_sys::seL4_LargePageObject;
+use sel4_sys::seL4_NBWait;
 use sel4_sys::seL4_ObjectType;
 use sel4_sys::seL4_ObjectType::*;
 use sel4_sys::seL4_PageBits;
 use sel4_sys::seL4_PageTableObject;
 use sel4_sys::seL4_Result;
 use sel4_sys::seL4_SmallPageObject;
+use sel4_sys::seL4_Word;
 use sel4_sys::seL4_WordBits;
 
 use slot_allocator::KATA_CSPACE_SLOTS;
@@ -67,7 +73,10 @@ extern "C" {
 // allocated objects; e.g. map page frames into a VSpace, bind endpoints
 // to irq's, configure TCB slots, etc.
 //
-// TODO(sleffler): support setting fixed physical address for drivers
+// Device-backed memory (e.g. MMIO regions) is requested with page frame
+// descriptors and the physical address of the first frame; subsequent
+// frames follow contiguously in physical memory.
+//
 // TODO(sleffler): maybe allocate associated resources like endpoint #'s?
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct ObjDesc {
@@ -135,6 +144,15 @@ impl ObjDesc {
     pub fn can_combine(&self, other: &ObjDesc) -> bool {
         self.type_ == other.type_ && self.cptr + self.count == other.cptr
     }
+
+    // Returns the size of each page frame described, or None if the
+    // objects are not page frames.
+    pub fn frame_bytes(&self) -> Option<usize> {
+        match self.type_ {
+            seL4_SmallPageObject | seL4_LargePageObject => self.type_.size_bits().map(|x| 1 << x),
+            _ => None,
+        }
+    }
 }
 
 // ObjDescBundle holds a collection of ObjDesc's and their associated
@@ -203,6 +221,37 @@ impl ObjDescBundle {
             .flat_map(|od| od.cptr..(od.cptr + od.retype_count()))
     }
 
+    // Returns an iterator that enumerates each page frame's seL4_CPtr and
+    // size in bytes. Frames may be of mixed sizes; objects that are not
+    // frames are skipped.
+    pub fn frame_iter(&self) -> impl Iterator<Item = (seL4_CPtr, usize)> + '_ {
+        self.objs.iter().flat_map(|od| {
+            let frame_bytes = od.frame_bytes().unwrap_or(0);
+            let count = if frame_bytes > 0 {
+                od.retype_count()
+            } else {
+                0
+            };
+            (od.cptr..(od.cptr + count)).map(move |cptr| (cptr, frame_bytes))
+        })
+    }
+
+    // Returns the page frame holding byte |offset| of the frames laid out
+    // back-to-back as (cptr, offset of the frame, frame size in bytes).
+    pub fn frame_at(&self, offset: usize) -> Option<(seL4_CPtr, usize, usize)> {
+        let mut frame_off = 0;
+        for od in &self.objs {
+            let frame_bytes = od.frame_bytes()?;
+            let size_bytes = frame_bytes * od.retype_count();
+            if offset < frame_off + size_bytes {
+                let index = (offset - frame_off) / frame_bytes;
+                return Some((od.cptr + index, frame_off + index * frame_bytes, frame_bytes));
+            }
+            frame_off += size_bytes;
+        }
+        None
+    }
+
     // Move objects to dynamically-allocated slots in the top-level
     // CNode and mutate the Object Descriptor with the new cptr's.
     // TODO(sleffler) make generic (requires supplying slot allocator)?
@@ -288,7 +337,11 @@ pub enum MemoryError {
     ObjCountInvalid = 0, // Too many objects requested
     ObjTypeInvalid,      // Request with invalid object type
     ObjCapInvalid,       // Request with invalid cptr XXX
+    ObjAddrInvalid,      // Request with unavailable physical address
     CapAllocFailed,
+    QuotaExceeded,    // Request exceeds the client's limit
+    PermissionDenied, // Client may not make the request
+    ThresholdInvalid, // Pressure thresholds out of order
     UnknownMemoryError,
     // Generic errors.
     AllocFailed,
@@ -313,6 +366,9 @@ pub struct MemoryManagerStats {
     // Space required for operation of the MemoryManager service.
     pub overhead_bytes: usize,
 
+    // Current device-backed space committed to allocations.
+    pub device_allocated_bytes: usize,
+
     // Current number of seL4 objects allocated.
     pub allocated_objs: usize,
 
@@ -326,13 +382,154 @@ pub struct MemoryManagerStats {
     pub out_of_memory: usize,
 }
 
+// Badges of the MemoryManager's clients. These follow the order of the
+// multi_memory connection in system.camkes.
+pub const DEBUG_CONSOLE_BADGE: seL4_Word = 1;
+pub const PROCESS_MANAGER_BADGE: seL4_Word = 2;
+pub const SECURITY_COORDINATOR_BADGE: seL4_Word = 3;
+pub const SDK_RUNTIME_BADGE: seL4_Word = 4;
+pub const ML_COORDINATOR_BADGE: seL4_Word = 5;
+
+// Returns a printable name for the client with |badge|.
+pub fn client_name(badge: seL4_Word) -> &'static str {
+    match badge {
+        DEBUG_CONSOLE_BADGE => "DebugConsole",
+        PROCESS_MANAGER_BADGE => "ProcessManager",
+        SECURITY_COORDINATOR_BADGE => "SecurityCoordinator",
+        SDK_RUNTIME_BADGE => "SDKRuntime",
+        ML_COORDINATOR_BADGE => "MlCoordinator",
+        _ => "unknown",
+    }
+}
+
+// Device memory each client may allocate with kata_device_alloc, as
+// (badge, physical address range); requests must lie inside a range
+// listed for the requestor. MMIO regions assigned in system.camkes are
+// mapped by the capDL loader and never reach the MemoryManager so they
+// are not listed.
+// TODO(sleffler): add ranges as drivers move to kata_device_alloc
+pub const DEVICE_ACCESS: &[(seL4_Word, Range<seL4_Word>)] = &[];
+
+pub const RAW_MEMORY_CLIENTS_DATA_SIZE: usize = 2048;
+pub type RawMemoryClientsData = [u8; RAW_MEMORY_CLIENTS_DATA_SIZE];
+
+// Memory usage attributed to a client. A client is identified by its
+// badge and, when the client allocates on behalf of someone else (e.g. the
+// ProcessManager starting a bundle), the owner it has set.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MemoryClientStats {
+    pub badge: seL4_Word,
+    // Owner for the usage; empty for the client itself.
+    pub owner: String,
+
+    // Current space committed to allocations.
+    pub allocated_bytes: usize,
+
+    // Current number of seL4 objects allocated.
+    pub allocated_objs: usize,
+
+    // Largest value of |allocated_bytes|.
+    pub peak_bytes: usize,
+
+    // Limit on |allocated_bytes| (if any).
+    pub limit_bytes: Option<usize>,
+
+    // Alloc requests rejected because of |limit_bytes|.
+    pub quota_exceeded: usize,
+}
+
+// Memory pressure seen by a subscriber, from comparing the free space
+// against the thresholds it registered.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub enum MemoryPressure {
+    PressureNormal = 0, // Free space above the low-water threshold
+    PressureLow,        // Free space at or below the low-water threshold
+    PressureCritical,   // Free space at or below the critical threshold
+}
+
+pub const RAW_MEMORY_TRACE_DATA_SIZE: usize = 2048;
+pub type RawMemoryTraceData = [u8; RAW_MEMORY_TRACE_DATA_SIZE];
+
+// An alloc request with objects that have not been freed. Frees are
+// matched to the client's most recent request with objects of the same
+// type so a record may be partly freed.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MemoryAllocRecord {
+    // Sequence number of the alloc request.
+    pub seq: usize,
+    pub badge: seL4_Word,
+    // Owner set by the client when allocating; empty for the client itself.
+    pub owner: String,
+    // Outstanding objects by type.
+    pub objs: Vec<(seL4_ObjectType, usize)>,
+}
+
+// Traced allocations returned by kata_memory_allocations.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MemoryAllocTrace {
+    // Whether tracing is enabled.
+    pub enabled: bool,
+
+    // Sequence number the next alloc request will get; use this as a
+    // checkpoint for later queries.
+    pub checkpoint: usize,
+
+    // Alloc requests not recorded because the trace was full.
+    pub dropped: usize,
+
+    pub records: Vec<MemoryAllocRecord>,
+}
+
 // Objects are potentially batched with caps to allocated objects returned
 // in the container slots specified by the |bundle] objects.
 pub trait MemoryManagerInterface {
-    fn alloc(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryError>;
-    fn free(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryError>;
+    // NB: |client| is the badge of the requestor; usage is charged to
+    //   it (and any owner it has set) and credited back on free to
+    //   whoever was charged.
+    fn alloc(&mut self, bundle: &ObjDescBundle, client: seL4_Word) -> Result<(), MemoryError>;
+    fn free(&mut self, bundle: &ObjDescBundle, client: seL4_Word) -> Result<(), MemoryError>;
+    // Like alloc & free but for page frames backed by device memory
+    // starting at physical address |paddr|. The range must be granted to
+    // |client| in DEVICE_ACCESS. Frames are located on free by the address
+    // their capability reports.
+    fn device_alloc(
+        &mut self,
+        bundle: &ObjDescBundle,
+        paddr: seL4_Word,
+        client: seL4_Word,
+    ) -> Result<(), MemoryError>;
+    fn device_free(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryError>;
     fn stats(&self) -> Result<MemoryManagerStats, MemoryError>;
     fn debug(&self) -> Result<(), MemoryError>;
+    // Charges future requests from |client| to |owner| (empty to clear).
+    fn set_owner(&mut self, client: seL4_Word, owner: &str) -> Result<(), MemoryError>;
+    // Sets or clears the limit for |client| acting for |owner|.
+    fn set_limit(
+        &mut self,
+        client: seL4_Word,
+        owner: &str,
+        limit_bytes: Option<usize>,
+    ) -> Result<(), MemoryError>;
+    fn client_stats(&self) -> Result<Vec<MemoryClientStats>, MemoryError>;
+    // Registers |client| to be signaled when free space crosses
+    // |low_water_bytes| or |critical_bytes| (in either direction).
+    // NB: |client| is the badge on the pressure connection.
+    fn subscribe(
+        &mut self,
+        client: seL4_Word,
+        low_water_bytes: usize,
+        critical_bytes: usize,
+    ) -> Result<(), MemoryError>;
+    fn unsubscribe(&mut self, client: seL4_Word) -> Result<(), MemoryError>;
+    // Returns the memory pressure relative to |client|'s thresholds.
+    fn pressure(&self, client: seL4_Word) -> Result<MemoryPressure, MemoryError>;
+    // Enables or disables allocation tracing. Disabling discards what
+    // has been recorded.
+    fn set_tracing(&mut self, enable: bool) -> Result<(), MemoryError>;
+    // Returns the traced allocations outstanding from alloc requests with
+    // sequence numbers in [since, until).
+    fn allocations(&self, since: usize, until: usize) -> Result<MemoryAllocTrace, MemoryError>;
 }
 
 // Public version of MemoryError presented over rpc interface.
@@ -346,7 +543,11 @@ pub enum MemoryManagerError {
     MmeObjCountInvalid,
     MmeObjTypeInvalid,
     MmeObjCapInvalid,
+    MmeObjAddrInvalid,
     MmeCapAllocFailed,
+    MmeQuotaExceeded,
+    MmePermissionDenied,
+    MmeThresholdInvalid,
     MmeSerializeFailed,
     MmeDeserializeFailed,
     MmeUnknownError,
@@ -360,7 +561,11 @@ impl From<MemoryError> for MemoryManagerError {
             MemoryError::ObjCountInvalid => MemoryManagerError::MmeObjCountInvalid,
             MemoryError::ObjTypeInvalid => MemoryManagerError::MmeObjTypeInvalid,
             MemoryError::ObjCapInvalid => MemoryManagerError::MmeObjCapInvalid,
+            MemoryError::ObjAddrInvalid => MemoryManagerError::MmeObjAddrInvalid,
             MemoryError::CapAllocFailed => MemoryManagerError::MmeCapAllocFailed,
+            MemoryError::QuotaExceeded => MemoryManagerError::MmeQuotaExceeded,
+            MemoryError::PermissionDenied => MemoryManagerError::MmePermissionDenied,
+            MemoryError::ThresholdInvalid => MemoryManagerError::MmeThresholdInvalid,
             MemoryError::AllocFailed => MemoryManagerError::MmeAllocFailed,
             MemoryError::FreeFailed => MemoryManagerError::MmeFreeFailed,
             _ => MemoryManagerError::MmeUnknownError,
@@ -607,6 +812,54 @@ pub fn kata_frame_alloc_in_cnode(space_bytes: usize) -> Result<ObjDescBundle, Me
     }
 }
 
+// Returns object descriptors for |space_bytes| of page frames using large
+// pages where possible and small pages for any remainder. The cptr's are
+// consecutive starting at zero.
+fn large_frame_descs(space_bytes: usize) -> Vec<ObjDesc> {
+    fn howmany(value: usize, unit: usize) -> usize { (value + (unit - 1)) / unit }
+    let nlarge = space_bytes >> seL4_LargePageBits;
+    let nsmall = howmany(space_bytes - (nlarge << seL4_LargePageBits), 1 << seL4_PageBits);
+    let mut objs = Vec::new();
+    let mut cptr = 0;
+    // NB: split requests to stay under the Retype "fanout" limit
+    for (type_, mut count) in [
+        (seL4_LargePageObject, nlarge),
+        (seL4_SmallPageObject, nsmall),
+    ] {
+        while count > 0 {
+            let n = core::cmp::min(count, 256);
+            objs.push(ObjDesc::new(type_, n, cptr));
+            cptr += n;
+            count -= n;
+        }
+    }
+    objs
+}
+
+// Wrapper for allocating page frames using large pages where possible
+// (see kata_frame_alloc). Note large pages can only be mapped at an
+// address (and into a region) aligned to their size.
+#[inline]
+pub fn kata_large_frame_alloc(space_bytes: usize) -> Result<ObjDescBundle, MemoryManagerError> {
+    let mut objs = ObjDescBundle::new(
+        unsafe { MEMORY_RECV_CNODE },
+        unsafe { MEMORY_RECV_CNODE_DEPTH },
+        large_frame_descs(space_bytes),
+    );
+    kata_object_alloc(&objs)?;
+    objs.move_objects_to_toplevel()
+        .map_err(|_| MemoryManagerError::MmeObjCapInvalid)?;
+    Ok(objs)
+}
+
+// Like kata_large_frame_alloc but also create a CNode to hold the frames.
+#[inline]
+pub fn kata_large_frame_alloc_in_cnode(
+    space_bytes: usize,
+) -> Result<ObjDescBundle, MemoryManagerError> {
+    kata_object_alloc_in_cnode(large_frame_descs(space_bytes))
+}
+
 #[inline]
 pub fn kata_page_table_alloc() -> Result<ObjDescBundle, MemoryManagerError> {
     let mut objs = ObjDescBundle::new(
@@ -674,6 +927,107 @@ pub fn kata_object_free_toplevel(objs: &ObjDescBundle) -> Result<(), MemoryManag
     kata_object_free(&objs_mut)
 }
 
+// Allocates the page frames specified in |request| from device memory
+// starting at physical address |paddr|. The capabilities are stored in
+// |request|.cnode which is assumed to be a CNode with sufficient capacity.
+#[inline]
+pub fn kata_device_alloc(
+    paddr: seL4_Word,
+    request: &ObjDescBundle,
+) -> Result<(), MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryManager component is named "memory".
+        fn memory_device_alloc(c_request_len: u32, c_request_data: *const u8)
+            -> MemoryManagerError;
+    }
+    trace!("kata_device_alloc {:#x} {}", paddr, request);
+    let raw_data = &mut [0u8; RAW_OBJ_DESC_DATA_SIZE];
+    postcard::to_slice(&(paddr, request), &mut raw_data[..])
+        .map_err(|_| MemoryManagerError::MmeSerializeFailed)?;
+    unsafe {
+        // NB: see kata_object_alloc
+        sel4_sys::debug_assert_slot_cnode!(request.cnode);
+        let _cleanup = Camkes::set_request_cap(request.cnode);
+
+        memory_device_alloc(raw_data.len() as u32, raw_data.as_ptr()).into()
+    }
+}
+
+// Allocates |space_bytes| of device memory starting at physical address
+// |paddr| as small pages. The capabilities are stored in a new CNode
+// allocated with sufficient capacity (as kata_frame_alloc_in_cnode).
+#[inline]
+pub fn kata_device_frame_alloc_in_cnode(
+    paddr: seL4_Word,
+    space_bytes: usize,
+) -> Result<ObjDescBundle, MemoryManagerError> {
+    fn howmany(value: usize, unit: usize) -> usize { (value + (unit - 1)) / unit }
+    fn next_log2(value: usize) -> usize {
+        // NB: BITS & leading_zeros return u32
+        (1 + usize::BITS - usize::leading_zeros(value)) as usize
+    }
+    let npages = howmany(space_bytes, 1 << seL4_PageBits);
+    // NB: split the request to stay under the Retype "fanout" limit
+    let mut objs = Vec::new();
+    let mut cptr = 0;
+    while cptr < npages {
+        let count = core::cmp::min(npages - cptr, 256);
+        objs.push(ObjDesc::new(seL4_SmallPageObject, count, cptr));
+        cptr += count;
+    }
+    let cnode_depth = next_log2(npages);
+
+    // Request a top-level CNode.
+    let cnode = kata_cnode_alloc(cnode_depth)?;
+
+    // Now construct the request for |objs| with |cnode| as the container.
+    let request = ObjDescBundle::new(cnode.objs[0].cptr, cnode_depth as u8, objs);
+    match kata_device_alloc(paddr, &request) {
+        Err(e) => {
+            kata_object_free_toplevel(&cnode).expect("cnode free");
+            Err(e)
+        }
+        Ok(_) => Ok(request),
+    }
+}
+
+#[inline]
+pub fn kata_device_free(request: &ObjDescBundle) -> Result<(), MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryManager component is named "memory".
+        fn memory_device_free(c_data_len: u32, c_data: *const u8) -> MemoryManagerError;
+    }
+    trace!("kata_device_free {}", request);
+    let raw_data = &mut [0u8; RAW_OBJ_DESC_DATA_SIZE];
+    postcard::to_slice(request, &mut raw_data[..])
+        .map_err(|_| MemoryManagerError::MmeSerializeFailed)?;
+    unsafe {
+        // NB: see kata_object_free
+        sel4_sys::debug_assert_slot_cnode!(request.cnode);
+        let _cleanup = Camkes::set_request_cap(request.cnode);
+
+        memory_device_free(raw_data.len() as u32, raw_data.as_ptr()).into()
+    }
+}
+
+// Free device frames allocated with kata_device_frame_alloc_in_cnode and
+// then the container that holds them.
+#[inline]
+pub fn kata_device_free_in_cnode(request: &ObjDescBundle) -> Result<(), MemoryManagerError> {
+    let cnode_obj = ObjDescBundle::new(
+        unsafe { SELF_CNODE },
+        seL4_WordBits as u8,
+        vec![ObjDesc::new(
+            /*type=*/ seL4_CapTableObject,
+            /*count=*/ request.depth as usize,
+            /*cptr=*/ request.cnode,
+        )],
+    );
+    kata_device_free(request)?;
+    // No way to recover if this fails..
+    kata_object_free_toplevel(&cnode_obj)
+}
+
 #[inline]
 pub fn kata_memory_stats() -> Result<MemoryManagerStats, MemoryManagerError> {
     extern "C" {
@@ -691,6 +1045,162 @@ pub fn kata_memory_stats() -> Result<MemoryManagerStats, MemoryManagerError> {
     }
 }
 
+// Charges subsequent allocations by the caller to |owner| (e.g. a bundle
+// id) until cleared with an empty string. Frees are credited to whoever
+// was charged for the objects; the owner set only guides the choice for
+// objects other than page frames, which cannot be located.
+#[inline]
+pub fn kata_memory_set_owner(owner: &str) -> Result<(), MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryManager component is named "memory".
+        fn memory_set_owner(c_request_len: u32, c_request_data: *const u8) -> MemoryManagerError;
+    }
+    let raw_data = &mut [0u8; RAW_OBJ_DESC_DATA_SIZE];
+    postcard::to_slice(owner, &mut raw_data[..])
+        .map_err(|_| MemoryManagerError::MmeSerializeFailed)?;
+    unsafe { memory_set_owner(raw_data.len() as u32, raw_data.as_ptr()) }.into()
+}
+
+// Sets (or with None clears) the limit on memory allocated by the client
+// with |badge| acting for |owner| (empty for the client itself).
+#[inline]
+pub fn kata_memory_set_limit(
+    badge: seL4_Word,
+    owner: &str,
+    limit_bytes: Option<usize>,
+) -> Result<(), MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryManager component is named "memory".
+        fn memory_set_limit(c_request_len: u32, c_request_data: *const u8) -> MemoryManagerError;
+    }
+    let raw_data = &mut [0u8; RAW_OBJ_DESC_DATA_SIZE];
+    postcard::to_slice(&(badge, owner, limit_bytes), &mut raw_data[..])
+        .map_err(|_| MemoryManagerError::MmeSerializeFailed)?;
+    unsafe { memory_set_limit(raw_data.len() as u32, raw_data.as_ptr()) }.into()
+}
+
+#[inline]
+pub fn kata_memory_client_stats() -> Result<Vec<MemoryClientStats>, MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryManager component is named "memory".
+        fn memory_client_stats(c_data: *mut RawMemoryClientsData) -> MemoryManagerError;
+    }
+    let raw_data = &mut [0u8; RAW_MEMORY_CLIENTS_DATA_SIZE];
+    match unsafe { memory_client_stats(raw_data as *mut _) } {
+        MemoryManagerError::MmeSuccess => {
+            let stats = postcard::from_bytes::<Vec<MemoryClientStats>>(raw_data)
+                .map_err(|_| MemoryManagerError::MmeDeserializeFailed)?;
+            Ok(stats)
+        }
+        status => Err(status),
+    }
+}
+
+// Enables or disables tracing of alloc requests. While enabled every
+// request is recorded until its objects are freed.
+#[inline]
+pub fn kata_memory_set_tracing(enable: bool) -> Result<(), MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryManager component is named "memory".
+        fn memory_set_tracing(c_request_len: u32, c_request_data: *const u8) -> MemoryManagerError;
+    }
+    let raw_data = &mut [0u8; RAW_OBJ_DESC_DATA_SIZE];
+    postcard::to_slice(&enable, &mut raw_data[..])
+        .map_err(|_| MemoryManagerError::MmeSerializeFailed)?;
+    unsafe { memory_set_tracing(raw_data.len() as u32, raw_data.as_ptr()) }.into()
+}
+
+// Returns the traced allocations still outstanding that were requested
+// between the checkpoints |since| and |until|. The result's checkpoint
+// marks the present so, for example, what a start/stop cycle leaked is
+// found by taking a checkpoint before and after and querying the range.
+#[inline]
+pub fn kata_memory_allocations(
+    since: usize,
+    until: usize,
+) -> Result<MemoryAllocTrace, MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryManager component is named "memory".
+        fn memory_allocations(
+            c_request_len: u32,
+            c_request_data: *const u8,
+            c_raw_resp_data: *mut RawMemoryTraceData,
+        ) -> MemoryManagerError;
+    }
+    let raw_request = &mut [0u8; RAW_OBJ_DESC_DATA_SIZE];
+    postcard::to_slice(&(since, until), &mut raw_request[..])
+        .map_err(|_| MemoryManagerError::MmeSerializeFailed)?;
+    let raw_data = &mut [0u8; RAW_MEMORY_TRACE_DATA_SIZE];
+    match unsafe {
+        memory_allocations(raw_request.len() as u32, raw_request.as_ptr(), raw_data as *mut _)
+    } {
+        MemoryManagerError::MmeSuccess => {
+            let trace = postcard::from_bytes::<MemoryAllocTrace>(raw_data)
+                .map_err(|_| MemoryManagerError::MmeDeserializeFailed)?;
+            Ok(trace)
+        }
+        status => Err(status),
+    }
+}
+
+// Returns a checkpoint for use with kata_memory_allocations.
+#[inline]
+pub fn kata_memory_checkpoint() -> Result<usize, MemoryManagerError> {
+    // NB: an empty range returns no records
+    kata_memory_allocations(usize::MAX, usize::MAX).map(|trace| trace.checkpoint)
+}
+
+// Registers the caller for memory pressure notifications. The caller's
+// pressure notification is signaled each time free space crosses either
+// threshold; use kata_memory_pressure to get the new level. Registering
+// again replaces the thresholds.
+#[inline]
+pub fn kata_memory_subscribe(
+    low_water_bytes: usize,
+    critical_bytes: usize,
+) -> Result<(), MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryPressureInterface is named "pressure".
+        fn pressure_subscribe(c_request_len: u32, c_request_data: *const u8) -> MemoryManagerError;
+    }
+    let raw_data = &mut [0u8; RAW_OBJ_DESC_DATA_SIZE];
+    postcard::to_slice(&(low_water_bytes, critical_bytes), &mut raw_data[..])
+        .map_err(|_| MemoryManagerError::MmeSerializeFailed)?;
+    unsafe { pressure_subscribe(raw_data.len() as u32, raw_data.as_ptr()) }.into()
+}
+
+#[inline]
+pub fn kata_memory_unsubscribe() -> Result<(), MemoryManagerError> {
+    extern "C" {
+        // NB: this assumes the MemoryPressureInterface is named "pressure".
+        fn pressure_unsubscribe() -> MemoryManagerError;
+    }
+    unsafe { pressure_unsubscribe() }.into()
+}
+
+// Returns the current memory pressure relative to the caller's thresholds.
+#[inline]
+pub fn kata_memory_pressure() -> MemoryPressure {
+    extern "C" {
+        // NB: this assumes the MemoryPressureInterface is named "pressure".
+        fn pressure_level() -> MemoryPressure;
+    }
+    unsafe { pressure_level() }
+}
+
+// Checks (without blocking) for a memory pressure notification. Returns
+// true if the pressure level changed since the last check.
+#[inline]
+pub fn kata_memory_pressure_poll() -> bool {
+    extern "C" {
+        // NB: this assumes the MemoryPressureInterface is named "pressure".
+        fn pressure_notification() -> seL4_CPtr;
+    }
+    let mut badge: seL4_Word = 0;
+    unsafe { seL4_NBWait(pressure_notification(), &mut badge) };
+    badge != 0
+}
+
 #[inline]
 pub fn kata_memory_debug() -> Result<(), MemoryManagerError> {
     extern "C" {
@@ -2,14 +2,21 @@
 
 #![cfg_attr(not(test), no_std)]
 
+extern crate alloc;
+
+use alloc::vec::Vec;
 use core::ops::Range;
+use kata_memory_interface::MemoryAllocTrace;
+use kata_memory_interface::MemoryClientStats;
 use kata_memory_interface::MemoryError;
 use kata_memory_interface::MemoryManagerInterface;
 use kata_memory_interface::MemoryManagerStats;
+use kata_memory_interface::MemoryPressure;
 use kata_memory_interface::ObjDescBundle;
 use kata_os_common::sel4_sys;
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_UntypedDesc;
+use sel4_sys::seL4_Word;
 use spin::Mutex;
 
 mod memory_manager;
@@ -35,17 +42,91 @@ impl KataMemoryManager {
     pub fn init(&self, ut_slots: Range<seL4_CPtr>, untypeds: &[seL4_UntypedDesc]) {
         *self.manager.lock() = Some(MemoryManager::new(ut_slots, untypeds));
     }
+
+    // Returns the badges of pressure subscribers to signal.
+    pub fn take_pressure_signals(&self) -> Vec<seL4_Word> {
+        self.manager
+            .lock()
+            .as_mut()
+            .unwrap()
+            .take_pressure_signals()
+    }
 }
 // These just lock accesses and handle the necessary indirection.
 impl MemoryManagerInterface for KataMemoryManager {
-    fn alloc(&mut self, objs: &ObjDescBundle) -> Result<(), MemoryError> {
-        self.manager.lock().as_mut().unwrap().alloc(objs)
+    fn alloc(&mut self, objs: &ObjDescBundle, client: seL4_Word) -> Result<(), MemoryError> {
+        self.manager.lock().as_mut().unwrap().alloc(objs, client)
+    }
+    fn free(&mut self, objs: &ObjDescBundle, client: seL4_Word) -> Result<(), MemoryError> {
+        self.manager.lock().as_mut().unwrap().free(objs, client)
     }
-    fn free(&mut self, objs: &ObjDescBundle) -> Result<(), MemoryError> {
-        self.manager.lock().as_mut().unwrap().free(objs)
+    fn device_alloc(
+        &mut self,
+        objs: &ObjDescBundle,
+        paddr: seL4_Word,
+        client: seL4_Word,
+    ) -> Result<(), MemoryError> {
+        self.manager
+            .lock()
+            .as_mut()
+            .unwrap()
+            .device_alloc(objs, paddr, client)
+    }
+    fn device_free(&mut self, objs: &ObjDescBundle) -> Result<(), MemoryError> {
+        self.manager.lock().as_mut().unwrap().device_free(objs)
     }
     fn stats(&self) -> Result<MemoryManagerStats, MemoryError> {
         self.manager.lock().as_ref().unwrap().stats()
     }
     fn debug(&self) -> Result<(), MemoryError> { self.manager.lock().as_ref().unwrap().debug() }
+    fn set_owner(&mut self, client: seL4_Word, owner: &str) -> Result<(), MemoryError> {
+        self.manager
+            .lock()
+            .as_mut()
+            .unwrap()
+            .set_owner(client, owner)
+    }
+    fn set_limit(
+        &mut self,
+        client: seL4_Word,
+        owner: &str,
+        limit_bytes: Option<usize>,
+    ) -> Result<(), MemoryError> {
+        self.manager
+            .lock()
+            .as_mut()
+            .unwrap()
+            .set_limit(client, owner, limit_bytes)
+    }
+    fn client_stats(&self) -> Result<Vec<MemoryClientStats>, MemoryError> {
+        self.manager.lock().as_ref().unwrap().client_stats()
+    }
+    fn subscribe(
+        &mut self,
+        client: seL4_Word,
+        low_water_bytes: usize,
+        critical_bytes: usize,
+    ) -> Result<(), MemoryError> {
+        self.manager
+            .lock()
+            .as_mut()
+            .unwrap()
+            .subscribe(client, low_water_bytes, critical_bytes)
+    }
+    fn unsubscribe(&mut self, client: seL4_Word) -> Result<(), MemoryError> {
+        self.manager.lock().as_mut().unwrap().unsubscribe(client)
+    }
+    fn pressure(&self, client: seL4_Word) -> Result<MemoryPressure, MemoryError> {
+        self.manager.lock().as_ref().unwrap().pressure(client)
+    }
+    fn set_tracing(&mut self, enable: bool) -> Result<(), MemoryError> {
+        self.manager.lock().as_mut().unwrap().set_tracing(enable)
+    }
+    fn allocations(&self, since: usize, until: usize) -> Result<MemoryAllocTrace, MemoryError> {
+        self.manager
+            .lock()
+            .as_ref()
+            .unwrap()
+            .allocations(since, until)
+    }
 }
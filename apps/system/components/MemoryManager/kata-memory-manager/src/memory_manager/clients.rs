@@ -0,0 +1,389 @@
+//! Per-client memory accounting
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use kata_memory_interface::MemoryClientStats;
+use kata_memory_interface::MemoryError;
+use kata_os_common::sel4_sys;
+
+use sel4_sys::seL4_ObjectType;
+use sel4_sys::seL4_Word;
+
+// Max number of accounting records. Records for owners are dropped when
+// they hold nothing (and have no limit) so this only needs to cover what
+// is live. When full, usage is charged to the client itself.
+pub const MAX_CLIENT_RECORDS: usize = 32;
+
+// Page frames allocated together and the client (and owner) charged.
+struct FrameRun {
+    last_paddr: seL4_Word, // Physical address of run end
+    frame_bytes: usize,
+    live_frames: usize,
+    badge: seL4_Word,
+    owner: String,
+}
+
+// Outstanding objects (other than frames) of one type & size and the
+// client (and owner) charged.
+struct ObjCharge {
+    type_: seL4_ObjectType,
+    obj_bytes: usize,
+    count: usize,
+    badge: seL4_Word,
+    owner: String,
+}
+
+// ClientTable attributes memory usage to clients. A client is identified
+// by the badge its requests arrive with plus the owner it has set (if any)
+// to charge usage on behalf of someone else (e.g. a bundle).
+//
+// Frees are credited to whoever was charged for the object, not to the
+// client doing the free, so objects may be handed between components.
+// Page frames are located by their physical address. Other objects
+// cannot be identified (see mod.rs) so a free is matched against the
+// outstanding objects of the same type & size, preferring those charged
+// to the freeing client & its current owner; totals are exact but which
+// owner is credited is a best guess when several hold such objects.
+pub struct ClientTable {
+    records: Vec<MemoryClientStats>,
+    owners: Vec<(seL4_Word, String)>,      // Current owner by badge
+    frames: BTreeMap<seL4_Word, FrameRun>, // Live frames by run start
+    objs: Vec<ObjCharge>,                  // Live non-frame objects
+}
+impl ClientTable {
+    pub fn new() -> Self {
+        ClientTable {
+            records: Vec::new(),
+            owners: Vec::new(),
+            frames: BTreeMap::new(),
+            objs: Vec::new(),
+        }
+    }
+
+    // Charges subsequent requests from |badge| to |owner|; an empty
+    // |owner| charges the client itself.
+    pub fn set_owner(&mut self, badge: seL4_Word, owner: &str) {
+        self.owners.retain(|(b, _)| *b != badge);
+        if !owner.is_empty() {
+            self.owners.push((badge, String::from(owner)));
+        }
+    }
+
+    // Returns the owner currently set for |badge|.
+    pub fn owner(&self, badge: seL4_Word) -> &str {
+        self.owners
+            .iter()
+            .find(|(b, _)| *b == badge)
+            .map_or("", |(_, owner)| owner.as_str())
+    }
+
+    fn find(&self, badge: seL4_Word, owner: &str) -> Option<usize> {
+        self.records
+            .iter()
+            .position(|r| r.badge == badge && r.owner == owner)
+    }
+
+    // Returns the record to charge for |badge| & |owner|, creating it if
+    // needed. Falls back to the client's own record if the table is full.
+    fn record(&mut self, badge: seL4_Word, owner: &str) -> &mut MemoryClientStats {
+        let index = match self.find(badge, owner) {
+            Some(index) => index,
+            None if self.records.len() < MAX_CLIENT_RECORDS || owner.is_empty() => {
+                self.records.push(MemoryClientStats {
+                    badge,
+                    owner: String::from(owner),
+                    ..Default::default()
+                });
+                self.records.len() - 1
+            }
+            None => return self.record(badge, ""),
+        };
+        &mut self.records[index]
+    }
+
+    // Checks that |badge| may allocate another |size_bytes|. Rejections
+    // are counted against the client.
+    pub fn check(&mut self, badge: seL4_Word, size_bytes: usize) -> Result<(), MemoryError> {
+        let owner = String::from(self.owner(badge));
+        let record = self.record(badge, &owner);
+        match record.limit_bytes {
+            Some(limit) if record.allocated_bytes.saturating_add(size_bytes) > limit => {
+                record.quota_exceeded += 1;
+                Err(MemoryError::QuotaExceeded)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Records an allocation by |badge| and returns the badge & owner
+    // charged.
+    fn charge(&mut self, badge: seL4_Word, size_bytes: usize, objs: usize) -> (seL4_Word, String) {
+        let owner = String::from(self.owner(badge));
+        let record = self.record(badge, &owner);
+        record.allocated_bytes += size_bytes;
+        record.allocated_objs += objs;
+        if record.allocated_bytes > record.peak_bytes {
+            record.peak_bytes = record.allocated_bytes;
+        }
+        (record.badge, record.owner.clone())
+    }
+
+    // Records the allocation by |badge| of |count| frames of |frame_bytes|
+    // placed back-to-back starting at |paddr|.
+    pub fn charge_frames(
+        &mut self,
+        badge: seL4_Word,
+        paddr: seL4_Word,
+        frame_bytes: usize,
+        count: usize,
+    ) {
+        let (badge, owner) = self.charge(badge, frame_bytes * count, count);
+        self.frames.insert(
+            paddr,
+            FrameRun {
+                last_paddr: paddr + frame_bytes * count,
+                frame_bytes,
+                live_frames: count,
+                badge,
+                owner,
+            },
+        );
+    }
+
+    // Records the allocation by |badge| of |count| objects of |type_|
+    // (other than frames) that are |obj_bytes| each.
+    pub fn charge_objs(
+        &mut self,
+        badge: seL4_Word,
+        type_: seL4_ObjectType,
+        obj_bytes: usize,
+        count: usize,
+    ) {
+        let (badge, owner) = self.charge(badge, obj_bytes * count, count);
+        match self.objs.iter_mut().find(|c| {
+            c.type_ == type_ && c.obj_bytes == obj_bytes && c.badge == badge && c.owner == owner
+        }) {
+            Some(charge) => charge.count += count,
+            None => self.objs.push(ObjCharge {
+                type_,
+                obj_bytes,
+                count,
+                badge,
+                owner,
+            }),
+        }
+    }
+
+    // Credits the free of |size_bytes| to |badge| & |owner|.
+    fn credit(&mut self, badge: seL4_Word, owner: &str, size_bytes: usize) {
+        if let Some(index) = self.find(badge, owner) {
+            let record = &mut self.records[index];
+            record.allocated_bytes -= size_bytes;
+            record.allocated_objs -= 1;
+            self.prune();
+        }
+    }
+
+    // Records the free of the frame at |paddr|. Returns the badge & owner
+    // credited or None if the frame was not charged to anyone.
+    pub fn credit_frame(&mut self, paddr: seL4_Word) -> Option<(seL4_Word, String)> {
+        let (&base, run) = self.frames.range_mut(..=paddr).next_back()?;
+        if paddr >= run.last_paddr {
+            return None;
+        }
+        run.live_frames -= 1;
+        let (badge, owner, frame_bytes) = (run.badge, run.owner.clone(), run.frame_bytes);
+        if run.live_frames == 0 {
+            self.frames.remove(&base);
+        }
+        self.credit(badge, &owner, frame_bytes);
+        Some((badge, owner))
+    }
+
+    // Records the free by |badge| (if known) of an object of |type_| that
+    // is |obj_bytes| (other than a frame). Returns the badge & owner
+    // credited or None if no such object was charged to anyone.
+    pub fn credit_obj(
+        &mut self,
+        badge: Option<seL4_Word>,
+        type_: seL4_ObjectType,
+        obj_bytes: usize,
+    ) -> Option<(seL4_Word, String)> {
+        let index = {
+            let owner = badge.map(|b| self.owner(b));
+            let candidates = || {
+                self.objs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.type_ == type_ && c.obj_bytes == obj_bytes)
+            };
+            candidates()
+                .find(|(_, c)| Some(c.badge) == badge && Some(c.owner.as_str()) == owner)
+                .or_else(|| candidates().find(|(_, c)| Some(c.badge) == badge))
+                .or_else(|| candidates().next())
+                .map(|(index, _)| index)?
+        };
+        let charge = &mut self.objs[index];
+        charge.count -= 1;
+        let (badge, owner) = (charge.badge, charge.owner.clone());
+        if charge.count == 0 {
+            self.objs.swap_remove(index);
+        }
+        self.credit(badge, &owner, obj_bytes);
+        Some((badge, owner))
+    }
+
+    // Sets or clears the limit for |badge| acting for |owner|.
+    pub fn set_limit(&mut self, badge: seL4_Word, owner: &str, limit_bytes: Option<usize>) {
+        self.record(badge, owner).limit_bytes = limit_bytes;
+        self.prune();
+    }
+
+    // Drops owner records that no longer hold anything of interest.
+    fn prune(&mut self) {
+        self.records.retain(|r| {
+            r.owner.is_empty()
+                || r.allocated_bytes > 0
+                || r.allocated_objs > 0
+                || r.limit_bytes.is_some()
+        });
+    }
+
+    pub fn stats(&self) -> Vec<MemoryClientStats> { self.records.clone() }
+}
+impl Default for ClientTable {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kata_memory_interface::MemoryError as me;
+    use sel4_sys::seL4_EndpointObject;
+    use sel4_sys::seL4_TCBObject;
+
+    const PAGE: usize = 4096;
+    const TCB: usize = 512;
+
+    fn usage(table: &ClientTable, badge: seL4_Word, owner: &str) -> Option<(usize, usize)> {
+        table
+            .stats()
+            .iter()
+            .find(|r| r.badge == badge && r.owner == owner)
+            .map(|r| (r.allocated_bytes, r.allocated_objs))
+    }
+
+    #[test]
+    fn test_charge_credit() {
+        let mut table = ClientTable::new();
+        table.charge_frames(1, 0x1000, PAGE, 1);
+        table.charge_frames(2, 0x2000, PAGE, 2);
+        table.charge_objs(1, seL4_TCBObject, TCB, 1);
+        assert_eq!(usage(&table, 1, ""), Some((PAGE + TCB, 2)));
+        assert_eq!(usage(&table, 2, ""), Some((2 * PAGE, 2)));
+
+        assert_eq!(table.credit_frame(0x1000), Some((1, String::new())));
+        assert_eq!(
+            table.credit_obj(Some(1), seL4_TCBObject, TCB),
+            Some((1, String::new()))
+        );
+        assert_eq!(usage(&table, 1, ""), Some((0, 0)));
+        // Peak is kept.
+        assert_eq!(table.stats()[0].peak_bytes, PAGE + TCB);
+
+        // Frees of objects not charged to anyone are ignored.
+        assert_eq!(table.credit_frame(0x1000), None);
+        assert_eq!(table.credit_frame(0x4000), None);
+        assert_eq!(table.credit_obj(Some(2), seL4_TCBObject, TCB), None);
+        assert_eq!(usage(&table, 2, ""), Some((2 * PAGE, 2)));
+    }
+
+    #[test]
+    fn test_credit_charged_client() {
+        let mut table = ClientTable::new();
+        table.charge_frames(1, 0x1000, PAGE, 4);
+        table.charge_objs(1, seL4_EndpointObject, 16, 1);
+
+        // Frees by another client are credited to the client charged.
+        assert_eq!(table.credit_frame(0x3000), Some((1, String::new())));
+        assert_eq!(
+            table.credit_obj(Some(2), seL4_EndpointObject, 16),
+            Some((1, String::new()))
+        );
+        assert_eq!(usage(&table, 1, ""), Some((3 * PAGE, 3)));
+        assert_eq!(usage(&table, 2, ""), None);
+
+        // Objects are matched by size as well as type.
+        assert_eq!(table.credit_obj(Some(1), seL4_EndpointObject, 32), None);
+    }
+
+    #[test]
+    fn test_owner() {
+        let mut table = ClientTable::new();
+        table.set_owner(2, "fubar");
+        assert_eq!(table.owner(2), "fubar");
+        assert_eq!(table.owner(1), "");
+        table.charge_frames(2, 0x1000, PAGE, 1);
+        table.charge_objs(2, seL4_TCBObject, TCB, 1);
+        table.set_owner(2, "");
+        table.charge_objs(2, seL4_TCBObject, TCB, 1);
+        assert_eq!(usage(&table, 2, "fubar"), Some((PAGE + TCB, 2)));
+        assert_eq!(usage(&table, 2, ""), Some((TCB, 1)));
+
+        // Frames are credited to the owner charged whatever is set now.
+        assert_eq!(table.credit_frame(0x1000), Some((2, String::from("fubar"))));
+        // Other objects prefer the owner that is set.
+        assert_eq!(
+            table.credit_obj(Some(2), seL4_TCBObject, TCB),
+            Some((2, String::new()))
+        );
+        table.set_owner(2, "fubar");
+        assert_eq!(
+            table.credit_obj(Some(2), seL4_TCBObject, TCB),
+            Some((2, String::from("fubar")))
+        );
+        // An owner record is dropped once it holds nothing.
+        assert_eq!(usage(&table, 2, "fubar"), None);
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut table = ClientTable::new();
+        table.set_limit(4, "", Some(2 * PAGE));
+        assert!(table.check(4, 2 * PAGE).is_ok());
+        table.charge_frames(4, 0x1000, PAGE, 2);
+        assert_eq!(table.check(4, 1), Err(me::QuotaExceeded));
+        assert_eq!(table.stats()[0].quota_exceeded, 1);
+
+        // Limits on an owner apply only to that owner and are kept
+        // before the owner allocates anything.
+        table.set_limit(2, "fubar", Some(PAGE));
+        assert_eq!(usage(&table, 2, "fubar"), Some((0, 0)));
+        assert!(table.check(2, 2 * PAGE).is_ok());
+        table.set_owner(2, "fubar");
+        assert_eq!(table.check(2, 2 * PAGE), Err(me::QuotaExceeded));
+
+        // Clearing the limit lifts the restriction.
+        table.set_limit(4, "", None);
+        assert!(table.check(4, 1 << 20).is_ok());
+    }
+
+    #[test]
+    fn test_full_table() {
+        let mut table = ClientTable::new();
+        for i in 0..MAX_CLIENT_RECORDS {
+            table.set_owner(2, &i.to_string());
+            table.charge_frames(2, i * PAGE, PAGE, 1);
+        }
+        // No room for another owner; usage goes to the client itself.
+        table.set_owner(2, "overflow");
+        let paddr = MAX_CLIENT_RECORDS * PAGE;
+        table.charge_frames(2, paddr, PAGE, 1);
+        assert_eq!(usage(&table, 2, "overflow"), None);
+        assert_eq!(usage(&table, 2, ""), Some((PAGE, 1)));
+        // ..and is credited back there.
+        assert_eq!(table.credit_frame(paddr), Some((2, String::new())));
+        assert_eq!(usage(&table, 2, ""), Some((0, 0)));
+    }
+}
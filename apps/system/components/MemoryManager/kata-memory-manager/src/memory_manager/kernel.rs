@@ -0,0 +1,131 @@
+//! Kernel operations used by the MemoryManager
+
+use kata_os_common::camkes::seL4_CPath;
+use kata_os_common::sel4_sys;
+use kata_os_common::slot_allocator;
+
+use sel4_sys::seL4_CNode_Delete;
+use sel4_sys::seL4_CNode_Move;
+use sel4_sys::seL4_CNode_Revoke;
+use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_ObjectType;
+use sel4_sys::seL4_Page_GetAddress;
+use sel4_sys::seL4_Result;
+use sel4_sys::seL4_Untyped_Describe;
+use sel4_sys::seL4_Untyped_Retype;
+use sel4_sys::seL4_Word;
+use sel4_sys::seL4_WordBits;
+
+use slot_allocator::KATA_CSPACE_SLOTS;
+
+extern "C" {
+    static SELF_CNODE: seL4_CPtr;
+}
+
+// Untyped object state as reported by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UntypedState {
+    pub size_bits: usize,       // Log2 size of the untyped object
+    pub remaining_bytes: usize, // Space past the watermark
+}
+
+// KernelOps is the set of kernel services the MemoryManager depends on.
+// Slots passed without a CNode are in our top-level CNode. Sel4Kernel
+// issues the real system calls; the unit tests substitute a simulation
+// (see sim.rs) so the allocator logic can be exercised on the host.
+pub trait KernelOps {
+    // Our top-level CNode.
+    fn self_cnode(&self) -> seL4_CPtr;
+
+    // Allocates/frees a slot in our top-level CNode.
+    fn alloc_slot(&mut self) -> Option<seL4_CPtr>;
+    fn free_slot(&mut self, slot: seL4_CPtr);
+
+    // Retypes |count| objects of |type_| from untyped |ut| into slots
+    // starting at |offset| in the CNode |root|.
+    fn untyped_retype(
+        &mut self,
+        ut: seL4_CPtr,
+        type_: seL4_ObjectType,
+        size_bits: usize,
+        root: seL4_CPtr,
+        offset: seL4_CPtr,
+        count: usize,
+    ) -> seL4_Result;
+    fn untyped_describe(&self, ut: seL4_CPtr) -> UntypedState;
+
+    // Deletes all caps derived from |slot|.
+    fn revoke(&mut self, slot: seL4_CPtr) -> seL4_Result;
+    fn delete(&mut self, path: &seL4_CPath) -> seL4_Result;
+    // Moves the cap at |path| to |slot|. This fails if |path| is empty
+    // (e.g. the object was already freed).
+    fn take_cap(&mut self, path: &seL4_CPath, slot: seL4_CPtr) -> seL4_Result;
+    // Returns the physical address of the page frame at |slot|.
+    fn frame_paddr(&self, slot: seL4_CPtr) -> seL4_Word;
+
+    fn top_level_path(&self, slot: seL4_CPtr) -> seL4_CPath {
+        (self.self_cnode(), slot, seL4_WordBits)
+    }
+}
+
+// Sel4Kernel is the KernelOps used when running on seL4.
+pub struct Sel4Kernel;
+impl KernelOps for Sel4Kernel {
+    fn self_cnode(&self) -> seL4_CPtr { unsafe { SELF_CNODE } }
+
+    fn alloc_slot(&mut self) -> Option<seL4_CPtr> { unsafe { KATA_CSPACE_SLOTS.alloc(1) } }
+    fn free_slot(&mut self, slot: seL4_CPtr) { unsafe { KATA_CSPACE_SLOTS.free(slot, 1) } }
+
+    fn untyped_retype(
+        &mut self,
+        ut: seL4_CPtr,
+        type_: seL4_ObjectType,
+        size_bits: usize,
+        root: seL4_CPtr,
+        offset: seL4_CPtr,
+        count: usize,
+    ) -> seL4_Result {
+        unsafe {
+            seL4_Untyped_Retype(
+                ut,
+                /*type=*/ type_.into(),
+                /*size_bits=*/ size_bits,
+                /*root=*/ root,
+                /*node_index=*/ 0, // Ignored 'cuz depth is zero
+                /*node_depth=*/ 0, // NB: store in cnode
+                /*node_offset=*/ offset,
+                /*num_objects=*/ count,
+            )
+        }
+    }
+    fn untyped_describe(&self, ut: seL4_CPtr) -> UntypedState {
+        let info = unsafe { seL4_Untyped_Describe(ut) };
+        UntypedState {
+            size_bits: info.sizeBits,
+            remaining_bytes: info.remainingBytes,
+        }
+    }
+
+    fn revoke(&mut self, slot: seL4_CPtr) -> seL4_Result {
+        let path = self.top_level_path(slot);
+        unsafe { seL4_CNode_Revoke(path.0, path.1, path.2 as u8) }
+    }
+    fn delete(&mut self, path: &seL4_CPath) -> seL4_Result {
+        unsafe { seL4_CNode_Delete(path.0, path.1, path.2 as u8) }
+    }
+    fn take_cap(&mut self, path: &seL4_CPath, slot: seL4_CPtr) -> seL4_Result {
+        unsafe {
+            seL4_CNode_Move(
+                /*dest_root=*/ SELF_CNODE,
+                /*dest_index=*/ slot,
+                /*dest_depth=*/ seL4_WordBits as u8,
+                /*src_root=*/ path.0,
+                /*src_index=*/ path.1,
+                /*src_depth=*/ path.2 as u8,
+            )
+        }
+    }
+    fn frame_paddr(&self, slot: seL4_CPtr) -> seL4_Word {
+        unsafe { seL4_Page_GetAddress(slot) }.paddr
+    }
+}
@@ -1,36 +1,55 @@
 //! Kata OS global memory management support
 
 extern crate alloc;
+use alloc::vec::Vec;
 use core::ops::Range;
+use kata_memory_interface::MemoryAllocTrace;
+use kata_memory_interface::MemoryClientStats;
 use kata_memory_interface::MemoryError;
 use kata_memory_interface::MemoryManagerInterface;
 use kata_memory_interface::MemoryManagerStats;
+use kata_memory_interface::MemoryPressure;
 use kata_memory_interface::ObjDesc;
 use kata_memory_interface::ObjDescBundle;
-use kata_os_common::camkes::{seL4_CPath, Camkes};
+use kata_memory_interface::DEVICE_ACCESS;
 use kata_os_common::sel4_sys;
 use log::{debug, error, info, trace, warn};
 use smallvec::SmallVec;
 
-use sel4_sys::seL4_CNode_Delete;
-use sel4_sys::seL4_CNode_Revoke;
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_Error;
+use sel4_sys::seL4_LargePageObject;
+use sel4_sys::seL4_MinUntypedBits;
+use sel4_sys::seL4_ObjectType;
 use sel4_sys::seL4_Result;
+use sel4_sys::seL4_SmallPageObject;
 use sel4_sys::seL4_UntypedDesc;
-use sel4_sys::seL4_Untyped_Describe;
-use sel4_sys::seL4_Untyped_Retype;
+use sel4_sys::seL4_UntypedObject;
 use sel4_sys::seL4_Word;
 
-fn delete_path(path: &seL4_CPath) -> seL4_Result {
-    unsafe { seL4_CNode_Delete(path.0, path.1, path.2 as u8) }
-}
-fn revoke_cap(cptr: seL4_CPtr) -> seL4_Result {
-    let path = Camkes::top_level_path(cptr);
-    unsafe { seL4_CNode_Revoke(path.0, path.1, path.2 as u8) }
-}
-fn untyped_describe(cptr: seL4_CPtr) -> seL4_Untyped_Describe {
-    unsafe { seL4_Untyped_Describe(cptr) }
+mod clients;
+use clients::ClientTable;
+
+mod kernel;
+use kernel::{KernelOps, Sel4Kernel};
+
+mod policy;
+use policy::{DefaultSlabPolicy, SlabInfo, SlabPolicy};
+
+mod pressure;
+use pressure::PressureMonitor;
+
+#[cfg(test)]
+mod sim;
+
+mod tracer;
+use tracer::AllocTracer;
+
+// Page frames are the only objects whose capability reveals where they
+// live so they are the only objects that can be attributed to a slab on
+// free.
+fn is_frame(type_: seL4_ObjectType) -> bool {
+    matches!(type_, seL4_SmallPageObject | seL4_LargePageObject)
 }
 
 // SmallVec capacity for untyped memory slabs. There are two instances;
@@ -43,41 +62,134 @@ const UNTYPED_SLAB_CAPACITY: usize = 64; // # slabs kept inline
 // The MemoryManager supports allocating & freeing seL4 objects that are
 // instantiated from UntypedMemory "slabs". Allocation causes untyped memory
 // to be converted to concrete types. Freeing deletes the specified capabilities
-// and updates the bookkeeping. Note that a free only releases the specified
-// cap; if there are dups or derived objects the memory will not be returned
-// to the untyped slab from which it was allocated and the bookkeeping done
-// here will be out of sync with the kernel.
-// TODO(sleffler): support device-backed memory objects
+// and updates the bookkeeping.
+//
+// Each slab tracks the page frames that are live. Frames are located on
+// free by their physical address and when the last frame in a slab is
+// freed the slab is revoked. This deletes any dups or derived caps a client
+// failed to return so the space is reusable. Other objects cannot be
+// located so the slab selection policy (see policy.rs) keeps them apart
+// from frames and any slab holding one is "pinned"; pinned slabs are
+// never revoked (the kernel still reuses their space once all objects are
+// deleted). Slabs partly consumed before we started are pinned for the
+// same reason.
+//
+// Device-backed memory (e.g. MMIO regions) is only handed out as page
+// frames at a caller-specified physical address. The kernel always retypes
+// from the start of the free space in an untyped object so to reach an
+// address we first carve the space below it into child untyped objects.
+// The children are added to the device slabs so their memory remains
+// available for later requests.
 #[derive(Debug)]
 struct UntypedSlab {
-    pub _size_bits: usize,      // NB: only used to sort
-    pub free_bytes: usize,      // Available space in slab
-    pub _base_paddr: seL4_Word, // Physical address of slab start
-    pub _last_paddr: seL4_Word, // Physical address of slab end
-    pub cptr: seL4_CPtr,        // seL4 untyped object
+    pub _size_bits: usize,     // NB: only used to sort
+    pub free_bytes: usize,     // Available space in slab
+    pub base_paddr: seL4_Word, // Physical address of slab start
+    pub last_paddr: seL4_Word, // Physical address of slab end
+    pub cptr: seL4_CPtr,       // seL4 untyped object
+    pub live_bytes: usize,     // Space held by live frames
+    pub live_frames: usize,    // # live frames
+    pub pinned: bool,          // Slab must not be revoked
 }
 impl UntypedSlab {
     fn new(ut: &seL4_UntypedDesc, free_bytes: usize, cptr: seL4_CPtr) -> Self {
         UntypedSlab {
             _size_bits: ut.size_bits(),
             free_bytes,
-            _base_paddr: ut.paddr,
-            _last_paddr: ut.paddr + (1 << ut.size_bits()),
+            base_paddr: ut.paddr,
+            last_paddr: ut.paddr + (1 << ut.size_bits()),
             cptr,
+            live_bytes: 0,
+            live_frames: 0,
+            pinned: false,
+        }
+    }
+
+    // Returns whether |paddr| is inside the slab.
+    fn contains(&self, paddr: seL4_Word) -> bool {
+        self.base_paddr <= paddr && paddr < self.last_paddr
+    }
+
+    // Returns the physical address where the next object will be placed.
+    fn next_paddr<K: KernelOps>(&self, kernel: &K) -> seL4_Word {
+        if self.live_frames == 0 && !self.pinned {
+            // NB: the kernel resets an untyped with no children on the next
+            //   retype; until then it reports the space used before the reset
+            self.base_paddr
+        } else {
+            self.last_paddr - kernel.untyped_describe(self.cptr).remaining_bytes
         }
     }
+
+    // Records the release of a frame of |size_bytes|. Returns true when
+    // the slab holds no more objects and can be revoked.
+    fn release_frame(&mut self, size_bytes: usize) -> bool {
+        self.live_bytes -= size_bytes;
+        self.live_frames -= 1;
+        self.live_frames == 0 && !self.pinned
+    }
+
+    // Refreshes the tracked free space from the kernel.
+    fn sync_free_bytes<K: KernelOps>(&mut self, kernel: &K) {
+        if self.live_frames > 0 || self.pinned {
+            // NB: otherwise the kernel may not yet have reset the slab
+            self.free_bytes = kernel.untyped_describe(self.cptr).remaining_bytes;
+        }
+    }
+
+    // Revokes the slab so all of its space is available. The caller must
+    // ensure no live objects remain.
+    fn reclaim<K: KernelOps>(&mut self, kernel: &mut K) {
+        trace!("reclaim untyped slab {}", self.cptr);
+        if let Err(e) = kernel.revoke(self.cptr) {
+            warn!("Revoke of untyped slab {} failed: {:?}", self.cptr, e);
+            return;
+        }
+        self.free_bytes = self.last_paddr - self.base_paddr;
+    }
+
+    // Returns whether [paddr, paddr + size_bytes) is unused space in
+    // the slab.
+    fn has_free_range<K: KernelOps>(
+        &self,
+        kernel: &K,
+        paddr: seL4_Word,
+        size_bytes: usize,
+    ) -> bool {
+        self.base_paddr <= paddr
+            && paddr + size_bytes <= self.last_paddr
+            && self.next_paddr(kernel) <= paddr
+    }
 }
-pub struct MemoryManager {
+impl SlabInfo for UntypedSlab {
+    fn size_bytes(&self) -> usize { self.last_paddr - self.base_paddr }
+    fn free_bytes(&self) -> usize {
+        if self.live_frames == 0 && !self.pinned {
+            // NB: the kernel resets the slab on the next retype (see next_paddr)
+            self.size_bytes()
+        } else {
+            self.free_bytes
+        }
+    }
+    fn pinned(&self) -> bool { self.pinned }
+}
+pub struct MemoryManager<K: KernelOps = Sel4Kernel> {
+    kernel: K, // Kernel services
+
     untypeds: SmallVec<[UntypedSlab; UNTYPED_SLAB_CAPACITY]>,
-    _device_untypeds: SmallVec<[UntypedSlab; UNTYPED_SLAB_CAPACITY]>,
-    cur_untyped: usize,
-    _cur_device_untyped: usize,
+    device_untypeds: SmallVec<[UntypedSlab; UNTYPED_SLAB_CAPACITY]>,
+    policy: DefaultSlabPolicy, // Slab selection
 
     total_bytes: usize,     // Total available space
     allocated_bytes: usize, // Amount of space currently allocated
     requested_bytes: usize, // Amount of space allocated over all time
     overhead_bytes: usize,
 
+    device_allocated_bytes: usize, // Device memory currently allocated
+
+    // Device memory each client may allocate (see DEVICE_ACCESS).
+    device_access: &'static [(seL4_Word, Range<seL4_Word>)],
+
     allocated_objs: usize, // # seL4 objects currently allocated
     requested_objs: usize, // # seL4 objects allocated over all time
 
@@ -87,6 +199,12 @@ pub struct MemoryManager {
     // Alloc requests failed due to lack of untyped memory (NB: may be
     // due to fragmentation of untyped slabs).
     out_of_memory: usize,
+
+    clients: ClientTable, // Usage by client
+
+    pressure: PressureMonitor, // Low-memory notification subscribers
+
+    tracer: AllocTracer, // Outstanding allocations (when tracing)
 }
 
 fn _howmany(value: usize, unit: usize) -> usize { value + (unit - 1) / unit }
@@ -95,57 +213,110 @@ fn _round_up(value: usize, align: usize) -> usize { _howmany(value, align) * ali
 // Log2 bits to bytes.
 fn l2tob(size_bits: usize) -> usize { 1 << size_bits }
 
-impl MemoryManager {
+// Creates the objects for each of |objs| using |retype|, which returns
+// where they were placed (e.g. the index of the slab used). A request is
+// all-or-nothing: if any descriptor fails the objects created for the
+// preceding descriptors are released with |undo| (most recent first) and
+// the error is returned. On success the placement of each descriptor is
+// returned. Both |retype| and |undo| are passed |ctx| so they may share
+// mutable state.
+fn alloc_all<C, T, R, U>(
+    ctx: &mut C,
+    objs: &[ObjDesc],
+    mut retype: R,
+    mut undo: U,
+) -> Result<Vec<T>, MemoryError>
+where
+    R: FnMut(&mut C, &ObjDesc) -> Result<T, MemoryError>,
+    U: FnMut(&mut C, &ObjDesc),
+{
+    let mut slabs = Vec::with_capacity(objs.len());
+    for od in objs {
+        match retype(ctx, od) {
+            Ok(placed) => slabs.push(placed),
+            Err(e) => {
+                for done in objs[..slabs.len()].iter().rev() {
+                    undo(ctx, done);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(slabs)
+}
+
+impl MemoryManager<Sel4Kernel> {
     // Creates a new MemoryManager instance. The allocator is seeded
     // from the untyped memory descriptors.
     pub fn new(slots: Range<seL4_CPtr>, untypeds: &[seL4_UntypedDesc]) -> Self {
+        Self::with_kernel(Sel4Kernel, slots, untypeds)
+    }
+}
+
+impl<K: KernelOps> MemoryManager<K> {
+    // Creates a new MemoryManager instance that uses |kernel| for
+    // kernel services.
+    pub fn with_kernel(kernel: K, slots: Range<seL4_CPtr>, untypeds: &[seL4_UntypedDesc]) -> Self {
         assert!(!untypeds.is_empty());
         assert_eq!(slots.end - slots.start, untypeds.len());
         let mut m = MemoryManager {
+            kernel,
             untypeds: SmallVec::new(),
-            _device_untypeds: SmallVec::new(),
-            cur_untyped: 0,
-            _cur_device_untyped: 0,
+            device_untypeds: SmallVec::new(),
+            policy: DefaultSlabPolicy::new(0),
 
             total_bytes: 0,
             allocated_bytes: 0,
             requested_bytes: 0,
             overhead_bytes: 0,
 
+            device_allocated_bytes: 0,
+            device_access: DEVICE_ACCESS,
+
             allocated_objs: 0,
             requested_objs: 0,
 
             untyped_slab_too_small: 0,
             out_of_memory: 0,
+
+            clients: ClientTable::new(),
+
+            pressure: PressureMonitor::new(),
+
+            tracer: AllocTracer::new(),
         };
         for (ut_index, ut) in untypeds.iter().enumerate() {
             #[cfg(feature = "CONFIG_NOISY_UNTYPEDS")]
             log::info!("slot {} {:?}", slots.start + ut_index, ut);
             let slab_size = l2tob(ut.size_bits());
             if ut.is_device() {
-                m._device_untypeds
+                m.device_untypeds
                     .push(UntypedSlab::new(ut, slab_size, slots.start + ut_index));
             } else {
                 if ut.is_tainted() {
-                    revoke_cap(slots.start + ut_index).expect("revoke untyped");
+                    m.kernel
+                        .revoke(slots.start + ut_index)
+                        .expect("revoke untyped");
                 }
                 // NB: must get current state of ut as it will reflect resources
                 //   allocated before we run.
-                let info = untyped_describe(slots.start + ut_index);
-                assert_eq!(info.sizeBits, ut.size_bits());
+                let info = m.kernel.untyped_describe(slots.start + ut_index);
+                assert_eq!(info.size_bits, ut.size_bits());
 
                 // We only have the remainder available for allocations.
-                m.untypeds
-                    .push(UntypedSlab::new(ut, info.remainingBytes, slots.start + ut_index));
-                m.total_bytes += info.remainingBytes;
+                let mut slab = UntypedSlab::new(ut, info.remaining_bytes, slots.start + ut_index);
+                slab.pinned = info.remaining_bytes != slab_size;
+                m.untypeds.push(slab);
+                m.total_bytes += info.remaining_bytes;
 
                 // Use overhead to track memory allocated out of our control.
-                m.overhead_bytes += slab_size - info.remainingBytes;
+                m.overhead_bytes += slab_size - info.remaining_bytes;
             }
         }
         // Sort non-device slabs by descending amount of free space.
         m.untypeds
             .sort_unstable_by(|a, b| b.free_bytes.cmp(&a.free_bytes));
+        m.policy = DefaultSlabPolicy::new(m.untypeds.len());
         m
     }
 
@@ -159,6 +330,8 @@ impl MemoryManager {
     pub fn total_requested_space(&self) -> usize { self.requested_bytes }
     // Current allocated space out of our control.
     pub fn overhead_space(&self) -> usize { self.overhead_bytes }
+    // Current allocated device-backed space.
+    pub fn device_allocated_space(&self) -> usize { self.device_allocated_bytes }
 
     // Current allocated objects
     pub fn allocated_objs(&self) -> usize { self.allocated_objs }
@@ -168,25 +341,275 @@ impl MemoryManager {
     pub fn untyped_slab_too_small(&self) -> usize { self.untyped_slab_too_small }
     pub fn out_of_memory(&self) -> usize { self.out_of_memory }
 
-    fn retype_untyped(free_untyped: seL4_CPtr, root: seL4_CPtr, obj: &ObjDesc) -> seL4_Result {
-        unsafe {
-            seL4_Untyped_Retype(
-                free_untyped,
-                /*type=*/ obj.type_.into(),
-                /*size_bits=*/ obj.retype_size_bits().unwrap(),
-                /*root=*/ root,
-                /*node_index=*/ 0, // Ignored 'cuz depth is zero
-                /*node_depth=*/ 0, // NB: store in cnode
-                /*node_offset=*/ obj.cptr,
-                /*num_objects=*/ obj.retype_count(),
-            )
+    // Returns the badges of pressure subscribers to signal; each has seen
+    // free space cross one of its thresholds since the last call.
+    pub fn take_pressure_signals(&mut self) -> Vec<seL4_Word> { self.pressure.take_pending() }
+
+    fn retype_untyped(
+        kernel: &mut K,
+        free_untyped: seL4_CPtr,
+        root: seL4_CPtr,
+        obj: &ObjDesc,
+    ) -> seL4_Result {
+        kernel.untyped_retype(
+            free_untyped,
+            obj.type_,
+            obj.retype_size_bits().unwrap(),
+            root,
+            obj.cptr,
+            obj.retype_count(),
+        )
+    }
+
+    // Returns the total size of the frames in |bundle| if they can be
+    // placed back-to-back starting at |paddr|.
+    fn device_size_bytes(bundle: &ObjDescBundle, paddr: seL4_Word) -> Result<usize, MemoryError> {
+        if bundle.is_empty() {
+            return Err(MemoryError::ObjCountInvalid);
+        }
+        let mut size_bytes: usize = 0;
+        for od in &bundle.objs {
+            if !matches!(od.type_, seL4_SmallPageObject | seL4_LargePageObject) {
+                return Err(MemoryError::ObjTypeInvalid);
+            }
+            // NB: the kernel places each frame on a size-aligned boundary
+            let frame_bytes = l2tob(od.retype_size_bits().unwrap());
+            let frame_paddr = paddr
+                .checked_add(size_bytes)
+                .ok_or(MemoryError::ObjAddrInvalid)?;
+            if frame_paddr % frame_bytes != 0 {
+                return Err(MemoryError::ObjAddrInvalid);
+            }
+            size_bytes += od.size_bytes().unwrap();
+        }
+        paddr
+            .checked_add(size_bytes)
+            .ok_or(MemoryError::ObjAddrInvalid)?;
+        Ok(size_bytes)
+    }
+
+    // Carves the free space below |paddr| in device slab |ut_index| into
+    // child untyped objects so the next object retyped from the slab lands
+    // at |paddr|. Each child is the largest naturally-aligned untyped that
+    // fits in what remains of the gap.
+    fn carve_device_untyped(
+        &mut self,
+        ut_index: usize,
+        paddr: seL4_Word,
+    ) -> Result<(), MemoryError> {
+        let parent = self.device_untypeds[ut_index].cptr;
+        let mut next_paddr = self.device_untypeds[ut_index].next_paddr(&self.kernel);
+        while next_paddr < paddr {
+            let gap_bits = (usize::BITS - 1 - (paddr - next_paddr).leading_zeros()) as usize;
+            let size_bits = core::cmp::min(next_paddr.trailing_zeros() as usize, gap_bits);
+            assert!(size_bits >= seL4_MinUntypedBits);
+
+            let slot = self
+                .kernel
+                .alloc_slot()
+                .ok_or(MemoryError::CapAllocFailed)?;
+            let self_cnode = self.kernel.self_cnode();
+            if let Err(e) = self.kernel.untyped_retype(
+                parent,
+                seL4_UntypedObject,
+                size_bits,
+                self_cnode,
+                slot,
+                1,
+            ) {
+                self.kernel.free_slot(slot);
+                error!("Carve of device untyped {} failed: {:?}", parent, e);
+                return Err(MemoryError::UnknownMemoryError);
+            }
+            trace!(
+                "carve [{:#x}, {:#x}) from {}",
+                next_paddr,
+                next_paddr + l2tob(size_bits),
+                parent
+            );
+            self.device_untypeds.push(UntypedSlab {
+                _size_bits: size_bits,
+                free_bytes: l2tob(size_bits),
+                base_paddr: next_paddr,
+                last_paddr: next_paddr + l2tob(size_bits),
+                cptr: slot,
+                live_bytes: 0,
+                live_frames: 0,
+                pinned: false,
+            });
+            // NB: revoking the parent would delete the children
+            self.device_untypeds[ut_index].pinned = true;
+            next_paddr += l2tob(size_bits);
         }
+        Ok(())
     }
 
-    fn delete_caps(root: seL4_CPtr, depth: u8, od: &ObjDesc) -> seL4_Result {
+    // Retypes |od| from the first slab with enough space and returns the
+    // slab's index and, for frames, the physical address of the first
+    // frame. Frames are taken from the largest slabs, searching forward,
+    // while other objects search backward from the smallest.
+    fn retype_from_slabs(
+        &mut self,
+        root: seL4_CPtr,
+        od: &ObjDesc,
+    ) -> Result<(usize, seL4_Word), MemoryError> {
+        let frames = is_frame(od.type_);
+        let count = od.retype_count();
+        let obj_bytes = od
+            .size_bytes()
+            .ok_or(MemoryError::ObjTypeInvalid)?
+            .checked_div(count)
+            .ok_or(MemoryError::ObjCountInvalid)?;
+        for ut_index in self
+            .policy
+            .order(&self.untypeds[..], obj_bytes, count, frames)
+        {
+            // NB: we don't check slots are available (the kernel will tell us).
+            // NB: we don't allocate ASIDPool objects but if we did it
+            //   would fail because it needs to map to an UntypedObject
+            match Self::retype_untyped(&mut self.kernel, self.untypeds[ut_index].cptr, root, od) {
+                Ok(_) => {
+                    let ut = &mut self.untypeds[ut_index];
+                    // NB: the objects end at the slab's new watermark
+                    let paddr = if frames {
+                        ut.last_paddr
+                            - self.kernel.untyped_describe(ut.cptr).remaining_bytes
+                            - obj_bytes * count
+                    } else {
+                        0
+                    };
+                    // NB: the space is consumed even if the objects are
+                    //   released because a later part of the request fails
+                    ut.free_bytes =
+                        policy::free_after(ut.size_bytes(), ut.free_bytes(), obj_bytes, count)
+                            .unwrap_or(0);
+                    self.policy.used(ut_index, frames);
+                    return Ok((ut_index, paddr));
+                }
+                Err(seL4_Error::seL4_NotEnoughMemory) => {
+                    // This untyped does not have enough available space,
+                    // try the next slab until we exhaust all candidates.
+                    self.untyped_slab_too_small += 1;
+                    self.untypeds[ut_index].sync_free_bytes(&self.kernel);
+                    debug!("Untyped slab {} too small", ut_index);
+                }
+                Err(e) => {
+                    // Should not happen.
+                    error!("Allocation request failed (retype returned {:?})", e);
+                    return Err(MemoryError::UnknownMemoryError);
+                }
+            }
+        }
+        self.out_of_memory += 1;
+        debug!("Allocation request failed (out of space)");
+        Err(MemoryError::AllocFailed)
+    }
+
+    // Deletes the objects in |bundle| and updates the bookkeeping. Each cap
+    // is first moved to |scratch| so requests for caps that are not present
+    // (e.g. a double free) are ignored; this also lets us ask a frame
+    // where it lives. Objects released from general memory (i.e. not
+    // device memory) are credited to the client that was charged for them
+    // (see clients.rs); |client| is the requestor (if known).
+    fn free_objs(
+        &mut self,
+        bundle: &ObjDescBundle,
+        client: Option<seL4_Word>,
+    ) -> Result<(), MemoryError> {
+        let scratch = self
+            .kernel
+            .alloc_slot()
+            .ok_or(MemoryError::CapAllocFailed)?;
+        for od in &bundle.objs {
+            if od.retype_count() == 0 {
+                continue;
+            }
+            let obj_bytes = od.size_bytes().ok_or(MemoryError::ObjTypeInvalid)? / od.retype_count();
+            for offset in 0..od.retype_count() {
+                let path = (bundle.cnode, od.cptr + offset, bundle.depth as usize);
+                if let Err(e) = self.kernel.take_cap(&path, scratch) {
+                    warn!("FREE {:?} failed: od {:?} error {:?}", &path, od, e);
+                    continue;
+                }
+                let paddr = if is_frame(od.type_) {
+                    Some(self.kernel.frame_paddr(scratch))
+                } else {
+                    None
+                };
+                let scratch_path = self.kernel.top_level_path(scratch);
+                if let Err(e) = self.kernel.delete(&scratch_path) {
+                    warn!("DELETE {:?} failed: od {:?} error {:?}", &path, od, e);
+                    continue;
+                }
+                let released = match paddr {
+                    Some(paddr) => self.release_frame(paddr, obj_bytes),
+                    None => self.release_obj(obj_bytes),
+                };
+                if released {
+                    let charged = match paddr {
+                        Some(paddr) => self.clients.credit_frame(paddr),
+                        None => self.clients.credit_obj(client, od.type_, obj_bytes),
+                    };
+                    if let Some((badge, owner)) = charged {
+                        self.tracer.free(badge, &owner, od.type_);
+                    }
+                }
+            }
+        }
+        self.kernel.free_slot(scratch);
+        Ok(())
+    }
+
+    // Records the release of a frame of |size_bytes| at |paddr| against
+    // the slab it was allocated from and revokes the slab if now empty.
+    // Returns true if the frame was general (not device) memory.
+    fn release_frame(&mut self, paddr: seL4_Word, size_bytes: usize) -> bool {
+        if let Some(ut) = self
+            .untypeds
+            .iter_mut()
+            .find(|ut| ut.contains(paddr) && ut.live_frames > 0)
+        {
+            if ut.release_frame(size_bytes) {
+                ut.reclaim(&mut self.kernel);
+            }
+            self.allocated_bytes -= size_bytes;
+            self.allocated_objs -= 1;
+            true
+        } else if let Some(ut) = self
+            .device_untypeds
+            .iter_mut()
+            .filter(|ut| ut.contains(paddr) && ut.live_frames > 0)
+            // NB: carved slabs are inside their parent; take the innermost
+            .min_by_key(|ut| ut.last_paddr - ut.base_paddr)
+        {
+            if ut.release_frame(size_bytes) {
+                ut.reclaim(&mut self.kernel);
+            }
+            self.device_allocated_bytes -= size_bytes;
+            false
+        } else {
+            // Not allocated by us (e.g. a frame setup before we started).
+            debug!("Free of untracked frame {:#x}", paddr);
+            false
+        }
+    }
+
+    // Records the release of a non-frame object of |size_bytes|.
+    fn release_obj(&mut self, size_bytes: usize) -> bool {
+        if size_bytes <= self.allocated_bytes && self.allocated_objs > 0 {
+            self.allocated_bytes -= size_bytes;
+            self.allocated_objs -= 1;
+            true
+        } else {
+            debug!("Underflow on free of {} bytes", size_bytes);
+            false
+        }
+    }
+
+    fn delete_caps(kernel: &mut K, root: seL4_CPtr, depth: u8, od: &ObjDesc) -> seL4_Result {
         for offset in 0..od.retype_count() {
             let path = (root, od.cptr + offset, depth as usize);
-            if let Err(e) = delete_path(&path) {
+            if let Err(e) = kernel.delete(&path) {
                 warn!("DELETE {:?} failed: od {:?} error {:?}", &path, od, e);
             }
         }
@@ -194,53 +617,52 @@ impl MemoryManager {
     }
 }
 
-impl MemoryManagerInterface for MemoryManager {
-    fn alloc(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryError> {
-        trace!("alloc {:?}", bundle);
+impl<K: KernelOps> MemoryManagerInterface for MemoryManager<K> {
+    fn alloc(&mut self, bundle: &ObjDescBundle, client: seL4_Word) -> Result<(), MemoryError> {
+        trace!("alloc {:?} client {}", bundle, client);
+
+        let mut request_bytes: usize = 0;
+        for od in &bundle.objs {
+            request_bytes = od
+                .size_bytes()
+                .and_then(|size_bytes| request_bytes.checked_add(size_bytes))
+                .ok_or(MemoryError::ObjTypeInvalid)?;
+        }
+        self.clients.check(client, request_bytes)?;
 
-        // TODO(sleffler): split by device vs no-device (or allow mixing)
-        let first_ut = self.cur_untyped;
-        let mut ut_index = first_ut;
+        // NB: device-backed memory is only available through device_alloc
+        // TODO(sleffler): maybe check size_bytes() against untyped slab?
+        //    (we depend on the kernel for now)
+        let slabs = alloc_all(
+            self,
+            &bundle.objs,
+            |mm, od| mm.retype_from_slabs(bundle.cnode, od),
+            |mm, od| {
+                let _ = Self::delete_caps(&mut mm.kernel, bundle.cnode, bundle.depth, od);
+            },
+        )?;
 
+        // NB: bookkeeping is done only once the whole request succeeds
         let mut allocated_bytes: usize = 0;
         let mut allocated_objs: usize = 0;
-
-        for od in &bundle.objs {
-            // NB: we don't check slots are available (the kernel will tell us).
-            // TODO(sleffler): maybe check size_bytes() against untyped slab?
-            //    (we depend on the kernel for now)
-            while let Err(e) =
-                // NB: we don't allocate ASIDPool objects but if we did it
-                //   would fail because it needs to map to an UntypedObject
-                MemoryManager::retype_untyped(
-                    self.untypeds[ut_index].cptr,
-                    bundle.cnode,
-                    od,
-                )
-            {
-                if e != seL4_Error::seL4_NotEnoughMemory {
-                    // Should not happen.
-                    // TODO(sleffler): reclaim allocations
-                    error!("Allocation request failed (retype returned {:?})", e);
-                    return Err(MemoryError::UnknownMemoryError);
-                }
-                // This untyped does not have enough available space, try
-                // the next slab until we exhaust all slabs. This is the best
-                // we can do without per-slab bookkeeping.
-                self.untyped_slab_too_small += 1;
-                ut_index = (ut_index + 1) % self.untypeds.len();
-                debug!("Advance to untyped slab {}", ut_index);
-                if ut_index == first_ut {
-                    // TODO(sleffler): reclaim allocations
-                    self.out_of_memory += 1;
-                    debug!("Allocation request failed (out of space)");
-                    return Err(MemoryError::AllocFailed);
-                }
+        for (od, &(ut_index, paddr)) in bundle.objs.iter().zip(slabs.iter()) {
+            let size_bytes = od.size_bytes().unwrap();
+            let count = od.retype_count();
+            let ut = &mut self.untypeds[ut_index];
+            if is_frame(od.type_) {
+                ut.live_bytes += size_bytes;
+                ut.live_frames += count;
+                self.clients
+                    .charge_frames(client, paddr, size_bytes / count, count);
+            } else {
+                ut.pinned = true;
+                self.clients
+                    .charge_objs(client, od.type_, size_bytes / count, count);
             }
+
             allocated_objs += od.retype_count();
-            allocated_bytes += od.size_bytes().unwrap();
+            allocated_bytes += size_bytes;
         }
-        self.cur_untyped = ut_index;
 
         self.allocated_bytes += allocated_bytes;
         self.allocated_objs += allocated_objs;
@@ -249,35 +671,88 @@ impl MemoryManagerInterface for MemoryManager {
         self.requested_objs += allocated_objs;
         self.requested_bytes += allocated_bytes;
 
+        self.tracer
+            .alloc(client, self.clients.owner(client), &bundle.objs);
+        self.pressure.update(self.free_space());
+
         Ok(())
     }
-    fn free(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryError> {
-        trace!("free {:?}", bundle);
+    fn free(&mut self, bundle: &ObjDescBundle, client: seL4_Word) -> Result<(), MemoryError> {
+        trace!("free {:?} client {}", bundle, client);
 
-        for od in &bundle.objs {
-            // TODO(sleffler): support leaving objects so client can do bulk
-            //   reclaim on exit (maybe require cptr != 0)
-            if MemoryManager::delete_caps(bundle.cnode, bundle.depth, od).is_ok() {
-                // NB: atm we do not do per-untyped bookkeeping so just track
-                //   global stats.
-                // TODO(sleffler): temp workaround for bad bookkeeping / client mis-handling
-                let size_bytes = od.size_bytes().ok_or(MemoryError::ObjTypeInvalid)?;
-                if size_bytes <= self.allocated_bytes {
-                    self.allocated_bytes -= size_bytes;
-                    self.allocated_objs -= od.retype_count();
-                } else {
-                    debug!("Underflow on free of {:?}", od);
-                }
-            }
+        // TODO(sleffler): support leaving objects so client can do bulk
+        //   reclaim on exit (maybe require cptr != 0)
+        self.free_objs(bundle, Some(client))?;
+        self.pressure.update(self.free_space());
+        Ok(())
+    }
+    fn device_alloc(
+        &mut self,
+        bundle: &ObjDescBundle,
+        paddr: seL4_Word,
+        client: seL4_Word,
+    ) -> Result<(), MemoryError> {
+        trace!("device_alloc {:#x} {:?} client {}", paddr, bundle, client);
+
+        let size_bytes = Self::device_size_bytes(bundle, paddr)?;
+        if !self.device_access.iter().any(|(badge, range)| {
+            *badge == client && range.start <= paddr && paddr + size_bytes <= range.end
+        }) {
+            trace!("device_alloc denied for badge {}", client);
+            return Err(MemoryError::PermissionDenied);
         }
+        // NB: carving leaves at most one slab with the range available;
+        //   requests that straddle slabs are not supported
+        let ut_index = self
+            .device_untypeds
+            .iter()
+            .position(|ut| ut.has_free_range(&self.kernel, paddr, size_bytes))
+            .ok_or_else(|| {
+                debug!("Device range [{:#x}, {:#x}) unavailable", paddr, paddr + size_bytes);
+                MemoryError::ObjAddrInvalid
+            })?;
+        self.carve_device_untyped(ut_index, paddr)?;
+
+        let ut_cptr = self.device_untypeds[ut_index].cptr;
+        // NB: on failure the carved space is not reclaimed, only the frames
+        alloc_all(
+            &mut self.kernel,
+            &bundle.objs,
+            |kernel, od| {
+                Self::retype_untyped(kernel, ut_cptr, bundle.cnode, od)
+                    .map(|_| ut_index)
+                    .map_err(|e| {
+                        error!("Device allocation request failed (retype returned {:?})", e);
+                        MemoryError::AllocFailed
+                    })
+            },
+            |kernel, od| {
+                let _ = Self::delete_caps(kernel, bundle.cnode, bundle.depth, od);
+            },
+        )?;
+        let ut = &mut self.device_untypeds[ut_index];
+        ut.live_bytes += size_bytes;
+        ut.live_frames += bundle.count();
+        self.device_allocated_bytes += size_bytes;
+
         Ok(())
     }
+    fn device_free(&mut self, bundle: &ObjDescBundle) -> Result<(), MemoryError> {
+        trace!("device_free {:?}", bundle);
+
+        if !bundle.objs.iter().all(|od| is_frame(od.type_)) {
+            return Err(MemoryError::ObjTypeInvalid);
+        }
+        // NB: free_objs locates each frame by the address its cap reports
+        self.free_objs(bundle, None)
+    }
     fn stats(&self) -> Result<MemoryManagerStats, MemoryError> {
         Ok(MemoryManagerStats {
             allocated_bytes: self.allocated_space(),
             free_bytes: self.free_space(),
             total_requested_bytes: self.total_requested_space(),
             overhead_bytes: self.overhead_space(),
+            device_allocated_bytes: self.device_allocated_space(),
 
             allocated_objs: self.allocated_objs(),
             total_requested_objs: self.total_requested_objs(),
@@ -287,17 +762,499 @@ impl MemoryManagerInterface for MemoryManager {
         })
     }
     fn debug(&self) -> Result<(), MemoryError> {
-        // TODO(sleffler): only shows !device slabs
         for ut in &self.untypeds {
-            let info = untyped_describe(ut.cptr);
-            let size = l2tob(info.sizeBits);
+            let info = self.kernel.untyped_describe(ut.cptr);
+            let size = l2tob(info.size_bits);
+            info!(
+                "[{}] allocated {} free {} frames {} ({} bytes){}",
+                ut.cptr,
+                size - info.remaining_bytes,
+                info.remaining_bytes,
+                ut.live_frames,
+                ut.live_bytes,
+                if ut.pinned { " pinned" } else { "" },
+            );
+        }
+        for ut in &self.device_untypeds {
+            let info = self.kernel.untyped_describe(ut.cptr);
             info!(
-                "[{}] allocated {} free {}",
+                "[{}] device [{:#x}, {:#x}) next {:#x} frames {}{}",
                 ut.cptr,
-                size - info.remainingBytes,
-                info.remainingBytes,
+                ut.base_paddr,
+                ut.last_paddr,
+                ut.last_paddr - info.remaining_bytes,
+                ut.live_frames,
+                if ut.pinned { " pinned" } else { "" },
             );
         }
         Ok(())
     }
+    fn set_owner(&mut self, client: seL4_Word, owner: &str) -> Result<(), MemoryError> {
+        trace!("set_owner {} {:?}", client, owner);
+        self.clients.set_owner(client, owner);
+        Ok(())
+    }
+    fn set_limit(
+        &mut self,
+        client: seL4_Word,
+        owner: &str,
+        limit_bytes: Option<usize>,
+    ) -> Result<(), MemoryError> {
+        trace!("set_limit {} {:?} {:?}", client, owner, limit_bytes);
+        self.clients.set_limit(client, owner, limit_bytes);
+        Ok(())
+    }
+    fn client_stats(&self) -> Result<Vec<MemoryClientStats>, MemoryError> {
+        Ok(self.clients.stats())
+    }
+    fn subscribe(
+        &mut self,
+        client: seL4_Word,
+        low_water_bytes: usize,
+        critical_bytes: usize,
+    ) -> Result<(), MemoryError> {
+        trace!(
+            "subscribe {} low {} critical {}",
+            client,
+            low_water_bytes,
+            critical_bytes
+        );
+        let free_bytes = self.free_space();
+        self.pressure
+            .subscribe(client, low_water_bytes, critical_bytes, free_bytes)
+    }
+    fn unsubscribe(&mut self, client: seL4_Word) -> Result<(), MemoryError> {
+        trace!("unsubscribe {}", client);
+        self.pressure.unsubscribe(client);
+        Ok(())
+    }
+    fn pressure(&self, client: seL4_Word) -> Result<MemoryPressure, MemoryError> {
+        Ok(self.pressure.level(client, self.free_space()))
+    }
+    fn set_tracing(&mut self, enable: bool) -> Result<(), MemoryError> {
+        trace!("set_tracing {}", enable);
+        self.tracer.set_enabled(enable);
+        Ok(())
+    }
+    fn allocations(&self, since: usize, until: usize) -> Result<MemoryAllocTrace, MemoryError> {
+        Ok(self.tracer.trace(since, until))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kata_memory_interface::MemoryError as me;
+    use sel4_sys::seL4_LargePageBits;
+    use sel4_sys::seL4_PageBits;
+    use sel4_sys::seL4_TCBObject;
+    use sel4_sys::seL4_WordBits;
+    use sim::{SimKernel, SIM_SELF_CNODE};
+
+    const CLIENT_CNODE: seL4_CPtr = 2; // NB: SimKernel accepts any cptr
+    const PAGE: usize = 1 << seL4_PageBits;
+    const UT_PADDR: seL4_Word = 0x8000_0000;
+    const DEVICE_PADDR: seL4_Word = 0x1000_0000;
+
+    fn request() -> Vec<ObjDesc> {
+        vec![
+            ObjDesc::new(seL4_TCBObject, 1, 0),
+            ObjDesc::new(seL4_SmallPageObject, 4, 1),
+            ObjDesc::new(seL4_SmallPageObject, 2, 5),
+            ObjDesc::new(seL4_TCBObject, 1, 7),
+        ]
+    }
+
+    #[test]
+    fn test_alloc_all() {
+        let objs = request();
+        let mut undone = Vec::new();
+        let slabs =
+            alloc_all(&mut (), &objs, |_, od| Ok(od.cptr % 2), |_, od| undone.push(od.cptr));
+        assert_eq!(slabs, Ok(vec![0, 1, 1, 1]));
+        assert!(undone.is_empty());
+    }
+
+    #[test]
+    fn test_alloc_all_rollback() {
+        let objs = request();
+        // Fail at each descriptor in turn; everything created before the
+        // failure must be undone, most recent first, and nothing else.
+        for fail_at in 0..objs.len() {
+            let mut created = Vec::new();
+            let mut undone = Vec::new();
+            let result = alloc_all(
+                &mut (),
+                &objs,
+                |_, od| {
+                    if od.cptr == objs[fail_at].cptr {
+                        return Err(me::AllocFailed);
+                    }
+                    created.push(od.cptr);
+                    Ok(0)
+                },
+                |_, od| undone.push(od.cptr),
+            );
+            assert_eq!(result, Err(me::AllocFailed));
+            created.reverse();
+            assert_eq!(undone, created);
+            assert_eq!(undone.len(), fail_at);
+        }
+    }
+
+    #[test]
+    fn test_alloc_all_error_passthrough() {
+        let objs = request();
+        let mut undone = 0;
+        assert_eq!(
+            alloc_all::<_, usize, _, _>(
+                &mut (),
+                &objs,
+                |_, _| Err(me::UnknownMemoryError),
+                |_, _| undone += 1
+            ),
+            Err(me::UnknownMemoryError)
+        );
+        assert_eq!(undone, 0);
+    }
+
+    // Returns a MemoryManager on a SimKernel seeded with |untypeds|. Each
+    // (index, bytes) in |used| marks space consumed before we start.
+    fn sim_manager(
+        untypeds: &[seL4_UntypedDesc],
+        used: &[(usize, usize)],
+    ) -> MemoryManager<SimKernel> {
+        let mut kernel = SimKernel::new(untypeds);
+        let slots = kernel.untyped_slots();
+        for &(index, used_bytes) in used {
+            kernel.set_watermark(slots.start + index, used_bytes);
+        }
+        MemoryManager::with_kernel(kernel, slots, untypeds)
+    }
+
+    fn bundle(objs: Vec<ObjDesc>) -> ObjDescBundle {
+        ObjDescBundle::new(CLIENT_CNODE, seL4_WordBits as u8, objs)
+    }
+    fn pages(count: usize, cptr: seL4_CPtr) -> ObjDescBundle {
+        bundle(vec![ObjDesc::new(seL4_SmallPageObject, count, cptr)])
+    }
+
+    #[test]
+    fn test_sim_alloc_free() {
+        let mut mm = sim_manager(&[seL4_UntypedDesc::new(UT_PADDR, 16, false, false)], &[]);
+        assert_eq!(mm.total_available_space(), 16 * PAGE);
+
+        let frames = pages(4, 0);
+        assert!(mm.alloc(&frames, 1).is_ok());
+        for index in 0..4 {
+            assert_eq!(
+                mm.kernel.object_at(CLIENT_CNODE, index),
+                Some((seL4_SmallPageObject, UT_PADDR + index * PAGE))
+            );
+        }
+        assert_eq!(mm.allocated_space(), 4 * PAGE);
+        assert_eq!(mm.free_space(), 12 * PAGE);
+        assert_eq!(mm.allocated_objs(), 4);
+
+        // Occupied slots are refused by the kernel.
+        assert_eq!(mm.alloc(&frames, 1), Err(me::UnknownMemoryError));
+        assert_eq!(mm.allocated_objs(), 4);
+
+        assert!(mm.free(&frames, 1).is_ok());
+        assert_eq!(mm.kernel.cap_count(CLIENT_CNODE), 0);
+        assert_eq!(mm.allocated_space(), 0);
+        assert_eq!(mm.allocated_objs(), 0);
+        assert_eq!(mm.total_requested_space(), 4 * PAGE);
+        assert_eq!(mm.total_requested_objs(), 4);
+        // The empty slab was revoked.
+        let slot = mm.kernel.untyped_slots().start;
+        assert_eq!(mm.kernel.untyped_describe(slot).remaining_bytes, 16 * PAGE);
+
+        // A double free is ignored.
+        assert!(mm.free(&frames, 1).is_ok());
+        assert_eq!(mm.allocated_objs(), 0);
+        // Only the untyped remains in our CNode (no scratch slot leaked).
+        assert_eq!(mm.kernel.cap_count(SIM_SELF_CNODE), 1);
+    }
+
+    #[test]
+    fn test_sim_slab_exhaustion() {
+        let mut mm = sim_manager(
+            &[
+                seL4_UntypedDesc::new(UT_PADDR, 14, false, false),
+                seL4_UntypedDesc::new(UT_PADDR + 4 * PAGE, 14, false, false),
+            ],
+            &[],
+        );
+        assert!(mm.alloc(&pages(4, 0), 1).is_ok());
+        assert!(mm.alloc(&pages(4, 4), 1).is_ok());
+        assert_eq!(mm.free_space(), 0);
+        assert_eq!(mm.alloc(&pages(1, 8), 1), Err(me::AllocFailed));
+        assert_eq!(mm.out_of_memory(), 1);
+
+        // A request that does not fit fails as a whole.
+        assert!(mm.free(&pages(4, 0), 1).is_ok());
+        let request = bundle(vec![
+            ObjDesc::new(seL4_SmallPageObject, 2, 8),
+            ObjDesc::new(seL4_SmallPageObject, 4, 10),
+        ]);
+        assert_eq!(mm.alloc(&request, 1), Err(me::AllocFailed));
+        assert_eq!(mm.out_of_memory(), 2);
+        assert_eq!(mm.kernel.cap_count(CLIENT_CNODE), 4);
+        assert_eq!(mm.allocated_space(), 4 * PAGE);
+
+        // The space used by the failed request is recovered.
+        assert!(mm.alloc(&pages(4, 0), 1).is_ok());
+        assert_eq!(mm.free_space(), 0);
+    }
+
+    #[test]
+    fn test_sim_device_memory() {
+        let mut mm = sim_manager(
+            &[
+                seL4_UntypedDesc::new(UT_PADDR, 16, false, false),
+                seL4_UntypedDesc::new(DEVICE_PADDR, 20, true, false),
+            ],
+            &[],
+        );
+        mm.device_access = &[(1, DEVICE_PADDR..DEVICE_PADDR + 8 * PAGE)];
+        // Device memory is not counted as available.
+        assert_eq!(mm.total_available_space(), 16 * PAGE);
+
+        let paddr = DEVICE_PADDR + 5 * PAGE;
+        assert!(mm.device_alloc(&pages(1, 0), paddr, 1).is_ok());
+        assert_eq!(
+            mm.kernel.object_at(CLIENT_CNODE, 0),
+            Some((seL4_SmallPageObject, paddr))
+        );
+        assert_eq!(mm.device_allocated_space(), PAGE);
+        assert_eq!(mm.allocated_space(), 0);
+
+        // General allocations never come from device memory.
+        assert!(mm.alloc(&pages(1, 1), 1).is_ok());
+        assert_eq!(
+            mm.kernel.object_at(CLIENT_CNODE, 1),
+            Some((seL4_SmallPageObject, UT_PADDR))
+        );
+
+        // Device requests must be frames in unused device space.
+        let tcb = bundle(vec![ObjDesc::new(seL4_TCBObject, 1, 2)]);
+        assert_eq!(mm.device_alloc(&tcb, DEVICE_PADDR, 1), Err(me::ObjTypeInvalid));
+        assert_eq!(mm.device_alloc(&pages(1, 2), paddr, 1), Err(me::ObjAddrInvalid));
+
+        // The space carved out below the first frame remains available.
+        assert!(mm
+            .device_alloc(&pages(1, 2), DEVICE_PADDR + PAGE, 1)
+            .is_ok());
+        assert_eq!(
+            mm.kernel.object_at(CLIENT_CNODE, 2),
+            Some((seL4_SmallPageObject, DEVICE_PADDR + PAGE))
+        );
+        assert_eq!(mm.device_allocated_space(), 2 * PAGE);
+
+        assert_eq!(mm.device_free(&tcb), Err(me::ObjTypeInvalid));
+        assert!(mm.device_free(&pages(1, 0)).is_ok());
+        assert!(mm.device_free(&pages(1, 2)).is_ok());
+        assert_eq!(mm.device_allocated_space(), 0);
+        assert_eq!(mm.allocated_space(), PAGE);
+        assert_eq!(mm.kernel.cap_count(CLIENT_CNODE), 1);
+    }
+
+    #[test]
+    fn test_sim_device_access() {
+        let mut mm = sim_manager(
+            &[
+                seL4_UntypedDesc::new(UT_PADDR, 16, false, false),
+                seL4_UntypedDesc::new(DEVICE_PADDR, 20, true, false),
+            ],
+            &[],
+        );
+        // Nothing is granted by default.
+        assert_eq!(
+            mm.device_alloc(&pages(1, 0), DEVICE_PADDR, 1),
+            Err(me::PermissionDenied)
+        );
+
+        mm.device_access = &[(1, DEVICE_PADDR + PAGE..DEVICE_PADDR + 3 * PAGE)];
+        // Only the client granted the range may allocate from it.
+        assert_eq!(
+            mm.device_alloc(&pages(1, 0), DEVICE_PADDR + PAGE, 2),
+            Err(me::PermissionDenied)
+        );
+        // Requests must lie entirely inside the range.
+        assert_eq!(
+            mm.device_alloc(&pages(1, 0), DEVICE_PADDR, 1),
+            Err(me::PermissionDenied)
+        );
+        assert_eq!(
+            mm.device_alloc(&pages(3, 0), DEVICE_PADDR + PAGE, 1),
+            Err(me::PermissionDenied)
+        );
+        assert_eq!(mm.device_alloc(&pages(1, 0), UT_PADDR, 1), Err(me::PermissionDenied));
+        assert_eq!(mm.kernel.cap_count(CLIENT_CNODE), 0);
+
+        assert!(mm
+            .device_alloc(&pages(2, 0), DEVICE_PADDR + PAGE, 1)
+            .is_ok());
+        assert_eq!(mm.device_allocated_space(), 2 * PAGE);
+    }
+
+    #[test]
+    fn test_sim_tainted_untyped() {
+        let mm = sim_manager(
+            &[
+                seL4_UntypedDesc::new(UT_PADDR, 16, false, true),
+                seL4_UntypedDesc::new(UT_PADDR + 16 * PAGE, 16, false, false),
+            ],
+            &[(0, 2 * PAGE), (1, 2 * PAGE)],
+        );
+        // The tainted untyped is revoked so all of it is available; space
+        // used in the other is overhead.
+        let slots = mm.kernel.untyped_slots();
+        assert_eq!(mm.kernel.untyped_describe(slots.start).remaining_bytes, 16 * PAGE);
+        assert_eq!(mm.kernel.untyped_describe(slots.start + 1).remaining_bytes, 14 * PAGE);
+        assert_eq!(mm.total_available_space(), 30 * PAGE);
+        assert_eq!(mm.overhead_space(), 2 * PAGE);
+        assert!(mm
+            .untypeds
+            .iter()
+            .any(|ut| ut.pinned && ut.free_bytes == 14 * PAGE));
+    }
+
+    #[test]
+    fn test_sim_stats() {
+        let mut mm = sim_manager(&[seL4_UntypedDesc::new(UT_PADDR, 16, false, false)], &[]);
+        let tcb = ObjDesc::new(seL4_TCBObject, 1, 0);
+        let tcb_bytes = tcb.size_bytes().unwrap();
+        let objs = bundle(vec![tcb, ObjDesc::new(seL4_SmallPageObject, 2, 1)]);
+        assert!(mm.alloc(&objs, 1).is_ok());
+        assert!(mm.alloc(&pages(1, 3), 2).is_ok());
+
+        let stats = mm.stats().unwrap();
+        assert_eq!(stats.allocated_bytes, tcb_bytes + 3 * PAGE);
+        assert_eq!(stats.free_bytes, 16 * PAGE - stats.allocated_bytes);
+        assert_eq!(stats.allocated_objs, 4);
+        assert_eq!(stats.total_requested_objs, 4);
+        assert_eq!(stats.out_of_memory, 0);
+
+        let clients = mm.client_stats().unwrap();
+        let client = |badge: seL4_Word| clients.iter().find(|c| c.badge == badge).unwrap();
+        assert_eq!(client(1).allocated_bytes, tcb_bytes + 2 * PAGE);
+        assert_eq!(client(1).allocated_objs, 3);
+        assert_eq!(client(2).allocated_bytes, PAGE);
+        assert_eq!(client(2).allocated_objs, 1);
+
+        assert!(mm.free(&objs, 1).is_ok());
+        let stats = mm.stats().unwrap();
+        assert_eq!(stats.allocated_bytes, PAGE);
+        assert_eq!(stats.allocated_objs, 1);
+        assert_eq!(stats.total_requested_bytes, tcb_bytes + 3 * PAGE);
+        assert_eq!(stats.total_requested_objs, 4);
+        let clients = mm.client_stats().unwrap();
+        let client = clients.iter().find(|c| c.badge == 1).unwrap();
+        assert_eq!(client.allocated_bytes, 0);
+        assert_eq!(client.allocated_objs, 0);
+    }
+
+    #[test]
+    fn test_sim_free_by_other_client() {
+        let mut mm = sim_manager(&[seL4_UntypedDesc::new(UT_PADDR, 16, false, false)], &[]);
+        let tcb = ObjDesc::new(seL4_TCBObject, 1, 0);
+        let tcb_bytes = tcb.size_bytes().unwrap();
+        assert!(mm.set_owner(2, "bundle").is_ok());
+        assert!(mm
+            .alloc(&bundle(vec![tcb, ObjDesc::new(seL4_SmallPageObject, 2, 1)]), 2)
+            .is_ok());
+        assert!(mm.set_owner(2, "").is_ok());
+        assert!(mm.alloc(&pages(1, 3), 3).is_ok());
+
+        // Frees are credited to whoever was charged, not the requestor.
+        assert!(mm.free(&pages(2, 1), 1).is_ok());
+        assert!(mm.free(&pages(1, 3), 2).is_ok());
+        let usage = |mm: &MemoryManager<SimKernel>, badge: seL4_Word, owner: &str| {
+            mm.client_stats()
+                .unwrap()
+                .iter()
+                .find(|c| c.badge == badge && c.owner == owner)
+                .map(|c| (c.allocated_bytes, c.allocated_objs))
+        };
+        assert_eq!(usage(&mm, 2, "bundle"), Some((tcb_bytes, 1)));
+        assert_eq!(usage(&mm, 3, ""), Some((0, 0)));
+        assert_eq!(usage(&mm, 1, ""), None);
+
+        assert!(mm.free(&bundle(vec![tcb]), 1).is_ok());
+        assert_eq!(usage(&mm, 2, "bundle"), None);
+        assert_eq!(mm.allocated_space(), 0);
+    }
+
+    #[test]
+    fn test_sim_large_pages() {
+        const LARGE: usize = 1 << seL4_LargePageBits;
+        let mut mm = sim_manager(
+            &[seL4_UntypedDesc::new(
+                UT_PADDR,
+                seL4_LargePageBits + 1,
+                false,
+                false,
+            )],
+            &[],
+        );
+        assert!(mm.alloc(&pages(1, 0), 1).is_ok());
+
+        // The large page is placed on a large-page boundary past the small one.
+        let large = bundle(vec![ObjDesc::new(seL4_LargePageObject, 1, 1)]);
+        assert!(mm.alloc(&large, 1).is_ok());
+        assert_eq!(
+            mm.kernel.object_at(CLIENT_CNODE, 1),
+            Some((seL4_LargePageObject, UT_PADDR + LARGE))
+        );
+        assert_eq!(mm.allocated_space(), PAGE + LARGE);
+        assert_eq!(mm.allocated_objs(), 2);
+
+        // No room remains for a second large page.
+        let again = bundle(vec![ObjDesc::new(seL4_LargePageObject, 1, 2)]);
+        assert_eq!(mm.alloc(&again, 1), Err(me::AllocFailed));
+
+        assert!(mm.free(&large, 1).is_ok());
+        assert_eq!(mm.kernel.object_at(CLIENT_CNODE, 1), None);
+        assert_eq!(mm.allocated_space(), PAGE);
+        assert_eq!(mm.allocated_objs(), 1);
+        let stats = mm.client_stats().unwrap();
+        let client = stats.iter().find(|c| c.badge == 1).unwrap();
+        assert_eq!((client.allocated_bytes, client.allocated_objs), (PAGE, 1));
+    }
+
+    #[test]
+    fn test_sim_tracing() {
+        let mut mm = sim_manager(&[seL4_UntypedDesc::new(UT_PADDR, 16, false, false)], &[]);
+        assert!(mm.alloc(&pages(1, 0), 1).is_ok());
+        assert!(mm.set_tracing(true).is_ok());
+        let before = mm.allocations(0, 0).unwrap().checkpoint;
+
+        let objs = bundle(vec![
+            ObjDesc::new(seL4_TCBObject, 1, 1),
+            ObjDesc::new(seL4_SmallPageObject, 2, 2),
+        ]);
+        assert!(mm.alloc(&objs, 1).is_ok());
+        assert!(mm.free(&pages(1, 2), 1).is_ok());
+        // A double free is not counted.
+        assert!(mm.free(&pages(1, 2), 1).is_ok());
+        let after = mm.allocations(0, 0).unwrap().checkpoint;
+
+        let trace = mm.allocations(before, after).unwrap();
+        assert!(trace.enabled);
+        assert_eq!(trace.records.len(), 1);
+        assert_eq!(trace.records[0].badge, 1);
+        assert_eq!(
+            trace.records[0].objs,
+            vec![(seL4_TCBObject, 1), (seL4_SmallPageObject, 1)]
+        );
+
+        let objs = bundle(vec![
+            ObjDesc::new(seL4_TCBObject, 1, 1),
+            ObjDesc::new(seL4_SmallPageObject, 1, 3),
+        ]);
+        assert!(mm.free(&objs, 1).is_ok());
+        assert!(mm.allocations(0, usize::MAX).unwrap().records.is_empty());
+    }
 }
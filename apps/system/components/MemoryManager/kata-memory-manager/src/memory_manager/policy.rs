@@ -0,0 +1,326 @@
+//! Untyped slab selection policies
+
+use alloc::vec::Vec;
+
+// What a policy knows about a slab.
+pub trait SlabInfo {
+    fn size_bytes(&self) -> usize; // Total space in slab
+    fn free_bytes(&self) -> usize; // Tracked space available
+    fn pinned(&self) -> bool; // Holds objects that cannot be located on free
+}
+
+// Returns the space left in a slab of |size_bytes| with |free_bytes|
+// available after retyping |count| objects of |obj_bytes| each, or None
+// if they do not fit. The kernel places objects at the next free address
+// aligned to the object size; slabs are aligned to their size so this can
+// be done with offsets.
+pub fn free_after(
+    size_bytes: usize,
+    free_bytes: usize,
+    obj_bytes: usize,
+    count: usize,
+) -> Option<usize> {
+    let used = size_bytes.checked_sub(free_bytes)?;
+    let start = used.checked_add(obj_bytes - 1)? & !(obj_bytes - 1);
+    let end = start.checked_add(obj_bytes.checked_mul(count)?)?;
+    size_bytes.checked_sub(end)
+}
+
+// A SlabPolicy decides which slab(s) to retype objects from. Whatever
+// the policy says the kernel has the final word; a slab that turns out to
+// be too small costs a failed retype after which the next slab is tried.
+pub trait SlabPolicy {
+    fn new(nslabs: usize) -> Self;
+    // Returns the slabs to try for |count| objects of |obj_bytes| each,
+    // most preferred first. |frames| is set for page frames.
+    fn order<S: SlabInfo>(
+        &self,
+        slabs: &[S],
+        obj_bytes: usize,
+        count: usize,
+        frames: bool,
+    ) -> Vec<usize>;
+    // Notes slab |index| satisfied a request.
+    fn used(&mut self, index: usize, frames: bool);
+}
+
+// RoundRobin walks the slabs from wherever the last request was satisfied
+// without regard for free space; frames move forward from the largest
+// slab and other objects move backward from the smallest. Every slab too
+// small for a request costs a failed retype and small objects land in
+// large slabs.
+pub struct RoundRobin {
+    cur_frames: usize, // Next slab for frames
+    cur_kobjs: usize,  // Next slab for non-frame objects
+}
+impl SlabPolicy for RoundRobin {
+    fn new(nslabs: usize) -> Self {
+        RoundRobin {
+            cur_frames: 0,
+            cur_kobjs: nslabs.saturating_sub(1),
+        }
+    }
+    fn order<S: SlabInfo>(
+        &self,
+        slabs: &[S],
+        _obj_bytes: usize,
+        _count: usize,
+        frames: bool,
+    ) -> Vec<usize> {
+        let nslabs = slabs.len();
+        (0..nslabs)
+            .map(|i| {
+                if frames {
+                    (self.cur_frames + i) % nslabs
+                } else {
+                    (self.cur_kobjs + nslabs - i) % nslabs
+                }
+            })
+            .collect()
+    }
+    fn used(&mut self, index: usize, frames: bool) {
+        if frames {
+            self.cur_frames = index;
+        } else {
+            self.cur_kobjs = index;
+        }
+    }
+}
+
+// BestFit uses the tracked free space to pick the slab that will have the
+// least space left after a request. Small requests thereby fill small or
+// mostly-used slabs and large slabs are kept intact for large requests.
+// Objects are also grouped by kind: non-frame objects prefer slabs that
+// are already pinned and frames prefer slabs that are not, so as few slabs
+// as possible are pinned.
+//
+// Tracked free space is exact except for pinned slabs: once their objects
+// are all deleted the kernel resets them on the next retype but we cannot
+// tell when that happens. So pinned slabs that appear too small (but that
+// would fit the request were they reset) are tried last, largest first.
+pub struct BestFit;
+impl SlabPolicy for BestFit {
+    fn new(_nslabs: usize) -> Self { BestFit }
+    fn order<S: SlabInfo>(
+        &self,
+        slabs: &[S],
+        obj_bytes: usize,
+        count: usize,
+        frames: bool,
+    ) -> Vec<usize> {
+        let mut fits = Vec::new();
+        let mut rest = Vec::new();
+        for (index, slab) in slabs.iter().enumerate() {
+            match free_after(slab.size_bytes(), slab.free_bytes(), obj_bytes, count) {
+                Some(left) => fits.push(((frames == slab.pinned(), left), index)),
+                None if slab.pinned()
+                    && free_after(slab.size_bytes(), slab.size_bytes(), obj_bytes, count)
+                        .is_some() =>
+                {
+                    rest.push((slab.free_bytes(), index))
+                }
+                None => {}
+            }
+        }
+        fits.sort_unstable();
+        rest.sort_unstable_by(|a, b| b.cmp(a));
+        fits.iter()
+            .map(|(_, index)| *index)
+            .chain(rest.iter().map(|(_, index)| *index))
+            .collect()
+    }
+    fn used(&mut self, _index: usize, _frames: bool) {}
+}
+
+#[cfg(feature = "CONFIG_SLAB_ROUND_ROBIN")]
+pub type DefaultSlabPolicy = RoundRobin;
+#[cfg(not(feature = "CONFIG_SLAB_ROUND_ROBIN"))]
+pub type DefaultSlabPolicy = BestFit;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: usize = 4096;
+
+    #[derive(Clone)]
+    struct SimSlab {
+        size_bytes: usize,
+        free_bytes: usize,
+        pinned: bool,
+        live: usize, // # objects in slab
+    }
+    impl SimSlab {
+        fn new(size_bits: usize) -> Self {
+            SimSlab {
+                size_bytes: 1 << size_bits,
+                free_bytes: 1 << size_bits,
+                pinned: false,
+                live: 0,
+            }
+        }
+    }
+    impl SlabInfo for SimSlab {
+        fn size_bytes(&self) -> usize { self.size_bytes }
+        fn free_bytes(&self) -> usize { self.free_bytes }
+        fn pinned(&self) -> bool { self.pinned }
+    }
+
+    #[test]
+    fn test_free_after() {
+        assert_eq!(free_after(8 * PAGE, 8 * PAGE, PAGE, 8), Some(0));
+        assert_eq!(free_after(8 * PAGE, 8 * PAGE, PAGE, 9), None);
+        // Alignment padding counts against the slab.
+        assert_eq!(free_after(8 * PAGE, 8 * PAGE - 16, PAGE, 1), Some(6 * PAGE));
+        assert_eq!(free_after(8 * PAGE, 8 * PAGE - 16, PAGE, 7), Some(0));
+        assert_eq!(free_after(8 * PAGE, 8 * PAGE - 16, PAGE, 8), None);
+        assert_eq!(free_after(8 * PAGE, 0, 16, 1), None);
+        assert_eq!(free_after(8 * PAGE, 8 * PAGE, PAGE, usize::MAX), None);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let slabs = vec![SimSlab::new(14), SimSlab::new(13), SimSlab::new(12)];
+        let mut policy = RoundRobin::new(slabs.len());
+        assert_eq!(policy.order(&slabs, PAGE, 1, true), vec![0, 1, 2]);
+        assert_eq!(policy.order(&slabs, 16, 1, false), vec![2, 1, 0]);
+        policy.used(1, true);
+        policy.used(1, false);
+        assert_eq!(policy.order(&slabs, PAGE, 1, true), vec![1, 2, 0]);
+        assert_eq!(policy.order(&slabs, 16, 1, false), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_best_fit() {
+        let mut slabs = vec![SimSlab::new(16), SimSlab::new(14), SimSlab::new(12)];
+        let policy = BestFit::new(slabs.len());
+        // Smallest slab that holds the request first; slabs that cannot
+        // hold it are skipped.
+        assert_eq!(policy.order(&slabs, PAGE, 1, true), vec![2, 1, 0]);
+        assert_eq!(policy.order(&slabs, PAGE, 2, true), vec![1, 0]);
+        assert_eq!(policy.order(&slabs, PAGE, 16, true), vec![0]);
+        assert!(policy.order(&slabs, PAGE, 17, true).is_empty());
+
+        // Non-frame objects go to pinned slabs when possible...
+        slabs[0].pinned = true;
+        slabs[0].free_bytes -= PAGE;
+        assert_eq!(policy.order(&slabs, 16, 1, false), vec![0, 2, 1]);
+        // ...and frames avoid them.
+        assert_eq!(policy.order(&slabs, PAGE, 2, true), vec![1, 0]);
+
+        // A full slab is skipped unless it is pinned (in which case the
+        // kernel may have reset it) and then it is tried last.
+        slabs[2].free_bytes = 0;
+        assert_eq!(policy.order(&slabs, PAGE, 1, true), vec![1, 0]);
+        slabs[2].pinned = true;
+        assert_eq!(policy.order(&slabs, PAGE, 1, true), vec![1, 0, 2]);
+        assert_eq!(policy.order(&slabs, PAGE, 2, true), vec![1, 0]);
+    }
+
+    // Results of running a workload against a simulated slab set.
+    #[derive(Debug, Default)]
+    struct BenchStats {
+        requests: usize,
+        failed_retypes: usize,  // Retypes rejected by the "kernel"
+        wasted_retypes: usize,  // Failed retypes for requests satisfied
+        failed_requests: usize, // Requests no slab could satisfy
+        pinned_slabs: usize,
+        free_bytes: usize,
+        largest_free: usize, // Largest free space in a single slab
+    }
+
+    // Runs a synthetic workload of bundle-like allocations (kernel objects
+    // plus batches of frames, some large) interleaved with frees against
+    // |slabs| using |policy|. The "kernel" places objects at the aligned
+    // watermark and resets a slab once it holds no objects; as in the
+    // MemoryManager unpinned slabs are reclaimed when emptied while the
+    // tracked space of pinned slabs is left stale.
+    fn bench<P: SlabPolicy>(mut slabs: Vec<SimSlab>, steps: usize) -> BenchStats {
+        const MAX_LIVE: usize = 64;
+        let mut policy = P::new(slabs.len());
+        let mut live: Vec<(usize, usize)> = Vec::new(); // (slab, count)
+        let mut stats = BenchStats::default();
+        let mut seed: u64 = 0x5eed;
+        let mut rand = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % n
+        };
+        for _ in 0..steps {
+            // NB: cap live allocations to keep the slabs ~60% used
+            if live.is_empty() || (live.len() < MAX_LIVE && rand(100) < 50) {
+                let (obj_bytes, count, frames) = match rand(100) {
+                    0..=29 => ([16, 2048, PAGE, 8192][rand(4)], 1, false),
+                    30..=94 => (PAGE, 1 + rand(32), true),
+                    _ => (PAGE, 256, true), // e.g. a model image
+                };
+                stats.requests += 1;
+                let mut placed = false;
+                let mut failed = 0;
+                for index in policy.order(&slabs, obj_bytes, count, frames) {
+                    let slab = &mut slabs[index];
+                    if slab.live == 0 {
+                        slab.free_bytes = slab.size_bytes;
+                    }
+                    match free_after(slab.size_bytes, slab.free_bytes, obj_bytes, count) {
+                        Some(free_bytes) => {
+                            slab.free_bytes = free_bytes;
+                            slab.live += count;
+                            slab.pinned |= !frames;
+                            policy.used(index, frames);
+                            live.push((index, count));
+                            placed = true;
+                            break;
+                        }
+                        None => failed += 1,
+                    }
+                }
+                stats.failed_retypes += failed;
+                if placed {
+                    stats.wasted_retypes += failed;
+                } else {
+                    stats.failed_requests += 1;
+                }
+            } else {
+                let (index, count) = live.swap_remove(rand(live.len()));
+                let slab = &mut slabs[index];
+                slab.live -= count;
+                if slab.live == 0 && !slab.pinned {
+                    slab.free_bytes = slab.size_bytes;
+                }
+            }
+        }
+        for slab in &slabs {
+            let free_bytes = if slab.live == 0 {
+                slab.size_bytes
+            } else {
+                slab.free_bytes
+            };
+            stats.free_bytes += free_bytes;
+            stats.largest_free = stats.largest_free.max(free_bytes);
+            stats.pinned_slabs += slab.pinned as usize;
+        }
+        stats
+    }
+
+    // Compares the policies on a slab set resembling what the rootserver
+    // hands us. Run with --nocapture to see the numbers.
+    #[test]
+    fn bench_slab_policies() {
+        let mut slabs = Vec::new();
+        for size_bits in [22, 21, 20, 20, 18, 18, 16, 16, 16, 14, 14, 12, 12] {
+            slabs.push(SimSlab::new(size_bits));
+        }
+        const STEPS: usize = 20_000;
+        let round_robin = bench::<RoundRobin>(slabs.clone(), STEPS);
+        let best_fit = bench::<BestFit>(slabs, STEPS);
+        for (name, stats) in [("round-robin", &round_robin), ("best-fit", &best_fit)] {
+            println!("{:<12} {:?}", name, stats);
+        }
+        // NB: failed requests are dominated by the workload, not the policy
+        assert!(best_fit.failed_retypes < round_robin.failed_retypes);
+        assert!(best_fit.wasted_retypes < round_robin.wasted_retypes);
+        assert!(best_fit.largest_free > round_robin.largest_free);
+    }
+}
@@ -0,0 +1,173 @@
+//! Memory pressure notifications
+
+use alloc::vec::Vec;
+use kata_memory_interface::MemoryError;
+use kata_memory_interface::MemoryPressure;
+use kata_os_common::sel4_sys::seL4_Word;
+
+// Returns the pressure for |free_bytes| given a subscriber's thresholds.
+fn pressure_level(
+    free_bytes: usize,
+    low_water_bytes: usize,
+    critical_bytes: usize,
+) -> MemoryPressure {
+    if free_bytes <= critical_bytes {
+        MemoryPressure::PressureCritical
+    } else if free_bytes <= low_water_bytes {
+        MemoryPressure::PressureLow
+    } else {
+        MemoryPressure::PressureNormal
+    }
+}
+
+struct Subscriber {
+    badge: seL4_Word, // Badge on the pressure connection
+    low_water_bytes: usize,
+    critical_bytes: usize,
+    level: MemoryPressure, // Level last signaled
+}
+
+// PressureMonitor tracks subscribers' thresholds and decides who must be
+// signaled as free space changes. Signals are collected rather than sent
+// so the caller can deliver them outside any locks.
+pub struct PressureMonitor {
+    subscribers: Vec<Subscriber>,
+    pending: Vec<seL4_Word>, // Badges to signal
+}
+impl PressureMonitor {
+    pub fn new() -> Self {
+        PressureMonitor {
+            subscribers: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    // Registers (or updates) the thresholds for |badge|. A subscriber that
+    // is already under pressure is signaled immediately.
+    pub fn subscribe(
+        &mut self,
+        badge: seL4_Word,
+        low_water_bytes: usize,
+        critical_bytes: usize,
+        free_bytes: usize,
+    ) -> Result<(), MemoryError> {
+        if critical_bytes > low_water_bytes {
+            return Err(MemoryError::ThresholdInvalid);
+        }
+        self.unsubscribe(badge);
+        let level = pressure_level(free_bytes, low_water_bytes, critical_bytes);
+        self.subscribers.push(Subscriber {
+            badge,
+            low_water_bytes,
+            critical_bytes,
+            level,
+        });
+        if level != MemoryPressure::PressureNormal {
+            self.signal(badge);
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, badge: seL4_Word) {
+        self.subscribers.retain(|s| s.badge != badge);
+        self.pending.retain(|&b| b != badge);
+    }
+
+    // Returns the pressure |badge| sees at |free_bytes|; callers that have
+    // not subscribed see no pressure.
+    pub fn level(&self, badge: seL4_Word, free_bytes: usize) -> MemoryPressure {
+        self.subscribers
+            .iter()
+            .find(|s| s.badge == badge)
+            .map_or(MemoryPressure::PressureNormal, |s| {
+                pressure_level(free_bytes, s.low_water_bytes, s.critical_bytes)
+            })
+    }
+
+    // Re-evaluates each subscriber at |free_bytes|, queueing a signal
+    // for those whose level changed.
+    pub fn update(&mut self, free_bytes: usize) {
+        let mut changed = Vec::new();
+        for s in &mut self.subscribers {
+            let level = pressure_level(free_bytes, s.low_water_bytes, s.critical_bytes);
+            if level != s.level {
+                s.level = level;
+                changed.push(s.badge);
+            }
+        }
+        for badge in changed {
+            self.signal(badge);
+        }
+    }
+
+    fn signal(&mut self, badge: seL4_Word) {
+        // NB: notifications coalesce so one pending signal is enough
+        if !self.pending.contains(&badge) {
+            self.pending.push(badge);
+        }
+    }
+
+    // Returns the badges to signal, clearing the list.
+    pub fn take_pending(&mut self) -> Vec<seL4_Word> { core::mem::take(&mut self.pending) }
+}
+impl Default for PressureMonitor {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kata_memory_interface::MemoryError as me;
+    use kata_memory_interface::MemoryPressure::*;
+
+    const LOW: usize = 64 * 1024;
+    const CRITICAL: usize = 16 * 1024;
+
+    #[test]
+    fn test_thresholds() {
+        let mut monitor = PressureMonitor::new();
+        assert_eq!(monitor.subscribe(1, CRITICAL, LOW, LOW), Err(me::ThresholdInvalid));
+        assert!(monitor.subscribe(1, LOW, CRITICAL, 1 << 20).is_ok());
+        assert_eq!(monitor.level(1, LOW + 1), PressureNormal);
+        assert_eq!(monitor.level(1, LOW), PressureLow);
+        assert_eq!(monitor.level(1, CRITICAL), PressureCritical);
+        assert_eq!(monitor.level(1, 0), PressureCritical);
+        // Not subscribed.
+        assert_eq!(monitor.level(2, 0), PressureNormal);
+    }
+
+    #[test]
+    fn test_crossings() {
+        let mut monitor = PressureMonitor::new();
+        monitor.subscribe(1, LOW, CRITICAL, 1 << 20).unwrap();
+        monitor.subscribe(2, 2 * LOW, CRITICAL, 1 << 20).unwrap();
+        assert!(monitor.take_pending().is_empty());
+
+        // Only subscriber 2 crosses its low-water mark.
+        monitor.update(2 * LOW - 1);
+        assert_eq!(monitor.take_pending(), vec![2]);
+        // No change, no signal.
+        monitor.update(2 * LOW - 2);
+        assert!(monitor.take_pending().is_empty());
+        // Both go critical.
+        monitor.update(CRITICAL);
+        assert_eq!(monitor.take_pending(), vec![1, 2]);
+        // Recovering is signaled too.
+        monitor.update(1 << 20);
+        assert_eq!(monitor.take_pending(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_subscribe_under_pressure() {
+        let mut monitor = PressureMonitor::new();
+        monitor.subscribe(1, LOW, CRITICAL, LOW).unwrap();
+        assert_eq!(monitor.take_pending(), vec![1]);
+
+        // Unsubscribing drops any pending signal.
+        monitor.update(CRITICAL);
+        monitor.unsubscribe(1);
+        assert!(monitor.take_pending().is_empty());
+        monitor.update(0);
+        assert!(monitor.take_pending().is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+//! Simulated kernel for exercising the MemoryManager on the host
+
+use super::is_frame;
+use super::kernel::{KernelOps, UntypedState};
+use super::l2tob;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+use kata_os_common::camkes::seL4_CPath;
+use kata_os_common::sel4_sys;
+
+use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_CapTableObject;
+use sel4_sys::seL4_Error;
+use sel4_sys::seL4_ObjectType;
+use sel4_sys::seL4_Result;
+use sel4_sys::seL4_SchedContextObject;
+use sel4_sys::seL4_SlotBits;
+use sel4_sys::seL4_UntypedDesc;
+use sel4_sys::seL4_UntypedObject;
+use sel4_sys::seL4_Word;
+
+// Our top-level CNode. Other CNodes (e.g. those named in an ObjDescBundle)
+// are not modeled as objects; any cptr can be used to name one.
+pub const SIM_SELF_CNODE: seL4_CPtr = 1;
+const FIRST_UNTYPED_SLOT: seL4_CPtr = 16;
+
+#[derive(Debug, Clone, Copy)]
+enum SimObject {
+    Untyped {
+        paddr: seL4_Word,
+        size_bits: usize,
+        is_device: bool,
+        watermark: usize, // Offset of the first free byte
+    },
+    Object {
+        type_: seL4_ObjectType,
+        paddr: seL4_Word,
+    },
+}
+
+#[derive(Debug)]
+struct SimCap {
+    id: usize,             // Unique id used to track derivation
+    parent: Option<usize>, // Cap this one was derived from
+    obj: SimObject,
+}
+
+// Returns the space occupied by an object of |type_| retyped with |size_bits|.
+fn object_bytes(type_: seL4_ObjectType, size_bits: usize) -> usize {
+    match type_ {
+        seL4_UntypedObject | seL4_SchedContextObject => l2tob(size_bits),
+        seL4_CapTableObject => l2tob(size_bits + seL4_SlotBits),
+        _ => l2tob(type_.size_bits().expect("object size")),
+    }
+}
+
+// SimKernel models the parts of seL4 the MemoryManager uses: CNode slots
+// (keyed by (cnode, index)), capability derivation for revoke, and the
+// untyped watermark. Like the kernel, an untyped is reset to its start on
+// the next retype once it has no children and describe reports the stale
+// watermark until then. Device untypeds only yield frames and untypeds.
+pub struct SimKernel {
+    caps: BTreeMap<(seL4_CPtr, seL4_CPtr), SimCap>,
+    next_id: usize,
+    untyped_slots: Range<seL4_CPtr>,
+    next_slot: seL4_CPtr,       // Next never-used slot in SIM_SELF_CNODE
+    free_slots: Vec<seL4_CPtr>, // Slots returned by free_slot
+}
+impl SimKernel {
+    // Creates a kernel with an untyped object for each of |untypeds|;
+    // they occupy untyped_slots() in our top-level CNode.
+    pub fn new(untypeds: &[seL4_UntypedDesc]) -> Self {
+        let end = FIRST_UNTYPED_SLOT + untypeds.len();
+        let mut kernel = SimKernel {
+            caps: BTreeMap::new(),
+            next_id: 0,
+            untyped_slots: FIRST_UNTYPED_SLOT..end,
+            next_slot: end,
+            free_slots: Vec::new(),
+        };
+        for (slot, ut) in kernel.untyped_slots.clone().zip(untypeds) {
+            kernel.insert(
+                (SIM_SELF_CNODE, slot),
+                None,
+                SimObject::Untyped {
+                    paddr: ut.paddr,
+                    size_bits: ut.size_bits(),
+                    is_device: ut.is_device(),
+                    watermark: 0,
+                },
+            );
+        }
+        kernel
+    }
+
+    pub fn untyped_slots(&self) -> Range<seL4_CPtr> { self.untyped_slots.clone() }
+
+    // Marks the first |used_bytes| of the untyped at |slot| as consumed
+    // without creating any objects (e.g. by a prior owner).
+    pub fn set_watermark(&mut self, slot: seL4_CPtr, used_bytes: usize) {
+        match self.caps.get_mut(&(SIM_SELF_CNODE, slot)) {
+            Some(SimCap {
+                obj: SimObject::Untyped { watermark, .. },
+                ..
+            }) => *watermark = used_bytes,
+            _ => panic!("slot {} is not an untyped", slot),
+        }
+    }
+
+    // Returns the type & physical address of the object at |index| in |cnode|.
+    pub fn object_at(
+        &self,
+        cnode: seL4_CPtr,
+        index: seL4_CPtr,
+    ) -> Option<(seL4_ObjectType, seL4_Word)> {
+        self.caps.get(&(cnode, index)).map(|cap| match cap.obj {
+            SimObject::Untyped { paddr, .. } => (seL4_UntypedObject, paddr),
+            SimObject::Object { type_, paddr } => (type_, paddr),
+        })
+    }
+
+    // Returns the number of slots in |cnode| holding a cap.
+    pub fn cap_count(&self, cnode: seL4_CPtr) -> usize {
+        self.caps.keys().filter(|(c, _)| *c == cnode).count()
+    }
+
+    fn insert(&mut self, at: (seL4_CPtr, seL4_CPtr), parent: Option<usize>, obj: SimObject) {
+        let id = self.next_id;
+        self.next_id += 1;
+        assert!(self.caps.insert(at, SimCap { id, parent, obj }).is_none());
+    }
+
+    fn has_children(&self, id: usize) -> bool {
+        self.caps.values().any(|cap| cap.parent == Some(id))
+    }
+}
+impl KernelOps for SimKernel {
+    fn self_cnode(&self) -> seL4_CPtr { SIM_SELF_CNODE }
+
+    fn alloc_slot(&mut self) -> Option<seL4_CPtr> {
+        self.free_slots.pop().or_else(|| {
+            self.next_slot += 1;
+            Some(self.next_slot - 1)
+        })
+    }
+    fn free_slot(&mut self, slot: seL4_CPtr) {
+        assert!(!self.caps.contains_key(&(SIM_SELF_CNODE, slot)));
+        self.free_slots.push(slot);
+    }
+
+    fn untyped_retype(
+        &mut self,
+        ut: seL4_CPtr,
+        type_: seL4_ObjectType,
+        size_bits: usize,
+        root: seL4_CPtr,
+        offset: seL4_CPtr,
+        count: usize,
+    ) -> seL4_Result {
+        let (id, paddr, ut_bits, is_device, watermark) = match self.caps.get(&(SIM_SELF_CNODE, ut))
+        {
+            Some(SimCap {
+                id,
+                obj:
+                    SimObject::Untyped {
+                        paddr,
+                        size_bits,
+                        is_device,
+                        watermark,
+                    },
+                ..
+            }) => (*id, *paddr, *size_bits, *is_device, *watermark),
+            _ => return Err(seL4_Error::seL4_InvalidCapability),
+        };
+        if is_device && type_ != seL4_UntypedObject && !is_frame(type_) {
+            return Err(seL4_Error::seL4_InvalidArgument);
+        }
+        if count == 0 {
+            return Err(seL4_Error::seL4_RangeError);
+        }
+        if (offset..offset + count).any(|index| self.caps.contains_key(&(root, index))) {
+            return Err(seL4_Error::seL4_DeleteFirst);
+        }
+        let obj_bytes = object_bytes(type_, size_bits);
+        let used = if self.has_children(id) { watermark } else { 0 };
+        let start = (used + obj_bytes - 1) & !(obj_bytes - 1);
+        let end = start + count * obj_bytes;
+        if end > l2tob(ut_bits) {
+            return Err(seL4_Error::seL4_NotEnoughMemory);
+        }
+        if let Some(SimCap {
+            obj: SimObject::Untyped { watermark, .. },
+            ..
+        }) = self.caps.get_mut(&(SIM_SELF_CNODE, ut))
+        {
+            *watermark = end;
+        }
+        for i in 0..count {
+            let obj_paddr = paddr + start + i * obj_bytes;
+            let obj = if type_ == seL4_UntypedObject {
+                SimObject::Untyped {
+                    paddr: obj_paddr,
+                    size_bits,
+                    is_device,
+                    watermark: 0,
+                }
+            } else {
+                SimObject::Object {
+                    type_,
+                    paddr: obj_paddr,
+                }
+            };
+            self.insert((root, offset + i), Some(id), obj);
+        }
+        Ok(())
+    }
+    fn untyped_describe(&self, ut: seL4_CPtr) -> UntypedState {
+        match self.caps.get(&(SIM_SELF_CNODE, ut)) {
+            Some(SimCap {
+                obj:
+                    SimObject::Untyped {
+                        size_bits,
+                        watermark,
+                        ..
+                    },
+                ..
+            }) => UntypedState {
+                size_bits: *size_bits,
+                remaining_bytes: l2tob(*size_bits) - watermark,
+            },
+            _ => panic!("slot {} is not an untyped", ut),
+        }
+    }
+
+    fn revoke(&mut self, slot: seL4_CPtr) -> seL4_Result {
+        let id = self
+            .caps
+            .get(&(SIM_SELF_CNODE, slot))
+            .ok_or(seL4_Error::seL4_FailedLookup)?
+            .id;
+        // Collect all descendants, then delete them.
+        let mut doomed = alloc::vec![id];
+        loop {
+            let more: Vec<usize> = self
+                .caps
+                .values()
+                .filter(|cap| cap.parent.map_or(false, |p| doomed.contains(&p)))
+                .filter(|cap| !doomed.contains(&cap.id))
+                .map(|cap| cap.id)
+                .collect();
+            if more.is_empty() {
+                break;
+            }
+            doomed.extend(more);
+        }
+        self.caps
+            .retain(|_, cap| cap.id == id || !doomed.contains(&cap.id));
+        if let Some(SimCap {
+            obj: SimObject::Untyped { watermark, .. },
+            ..
+        }) = self.caps.get_mut(&(SIM_SELF_CNODE, slot))
+        {
+            *watermark = 0;
+        }
+        Ok(())
+    }
+    fn delete(&mut self, path: &seL4_CPath) -> seL4_Result {
+        // NB: deleting an empty slot succeeds
+        if let Some(cap) = self.caps.remove(&(path.0, path.1)) {
+            // Children move up to the deleted cap's parent.
+            for child in self.caps.values_mut() {
+                if child.parent == Some(cap.id) {
+                    child.parent = cap.parent;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn take_cap(&mut self, path: &seL4_CPath, slot: seL4_CPtr) -> seL4_Result {
+        if self.caps.contains_key(&(SIM_SELF_CNODE, slot)) {
+            return Err(seL4_Error::seL4_DeleteFirst);
+        }
+        let cap = self
+            .caps
+            .remove(&(path.0, path.1))
+            .ok_or(seL4_Error::seL4_FailedLookup)?;
+        self.caps.insert((SIM_SELF_CNODE, slot), cap);
+        Ok(())
+    }
+    fn frame_paddr(&self, slot: seL4_CPtr) -> seL4_Word {
+        match self.object_at(SIM_SELF_CNODE, slot) {
+            Some((type_, paddr)) if is_frame(type_) => paddr,
+            _ => panic!("slot {} is not a frame", slot),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Allocation tracing for leak detection
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use kata_memory_interface::MemoryAllocRecord;
+use kata_memory_interface::MemoryAllocTrace;
+use kata_memory_interface::ObjDesc;
+use kata_os_common::sel4_sys;
+
+use sel4_sys::seL4_ObjectType;
+use sel4_sys::seL4_Word;
+
+// Max number of outstanding alloc requests recorded. Requests past this
+// are only counted (see MemoryAllocTrace::dropped).
+pub const MAX_TRACE_RECORDS: usize = 64;
+
+// AllocTracer records alloc requests until their objects are freed. Every
+// request gets a sequence number whether or not tracing is enabled so
+// sequence numbers can be used as checkpoints. Objects cannot in general
+// be identified on free (see mod.rs) so a free is matched against the
+// most recent request by the same client (and owner) with an object of
+// the same type; the counts are exact but which request a leak is
+// blamed on is a best guess. Frees of objects allocated before tracing
+// was enabled are matched too so they can hide leaks; enable tracing
+// before the allocations of interest.
+pub struct AllocTracer {
+    enabled: bool,
+    next_seq: usize,
+    dropped: usize,
+    records: Vec<MemoryAllocRecord>, // Ordered by seq
+}
+impl AllocTracer {
+    pub fn new() -> Self {
+        AllocTracer {
+            enabled: false,
+            next_seq: 0,
+            dropped: 0,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enable: bool) {
+        if !enable {
+            self.records.clear();
+            self.dropped = 0;
+        }
+        self.enabled = enable;
+    }
+
+    // Records an alloc request by |badge| for |owner| of |objs|.
+    pub fn alloc(&mut self, badge: seL4_Word, owner: &str, objs: &[ObjDesc]) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if !self.enabled {
+            return;
+        }
+        if self.records.len() >= MAX_TRACE_RECORDS {
+            self.dropped += 1;
+            return;
+        }
+        let mut counts: Vec<(seL4_ObjectType, usize)> = Vec::new();
+        for od in objs {
+            match counts.iter_mut().find(|(type_, _)| *type_ == od.type_) {
+                Some((_, count)) => *count += od.retype_count(),
+                None => counts.push((od.type_, od.retype_count())),
+            }
+        }
+        counts.retain(|(_, count)| *count > 0);
+        if counts.is_empty() {
+            return;
+        }
+        self.records.push(MemoryAllocRecord {
+            seq,
+            badge,
+            owner: String::from(owner),
+            objs: counts,
+        });
+    }
+
+    // Records the free of an object of |type_| by |badge| for |owner|.
+    pub fn free(&mut self, badge: seL4_Word, owner: &str, type_: seL4_ObjectType) {
+        if !self.enabled {
+            return;
+        }
+        let found = self
+            .records
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .find_map(|(index, r)| {
+                if r.badge != badge || r.owner != owner {
+                    return None;
+                }
+                r.objs
+                    .iter_mut()
+                    .find(|(t, count)| *t == type_ && *count > 0)
+                    .map(|(_, count)| (index, count))
+            });
+        if let Some((index, count)) = found {
+            *count -= 1;
+            let r = &mut self.records[index];
+            r.objs.retain(|(_, count)| *count > 0);
+            if r.objs.is_empty() {
+                self.records.remove(index);
+            }
+        }
+    }
+
+    // Returns the records for requests with sequence numbers in [since, until).
+    pub fn trace(&self, since: usize, until: usize) -> MemoryAllocTrace {
+        MemoryAllocTrace {
+            enabled: self.enabled,
+            checkpoint: self.next_seq,
+            dropped: self.dropped,
+            records: self
+                .records
+                .iter()
+                .filter(|r| since <= r.seq && r.seq < until)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+impl Default for AllocTracer {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sel4_sys::seL4_SmallPageObject;
+    use sel4_sys::seL4_TCBObject;
+
+    fn request() -> Vec<ObjDesc> {
+        vec![
+            ObjDesc::new(seL4_TCBObject, 1, 0),
+            ObjDesc::new(seL4_SmallPageObject, 4, 1),
+            ObjDesc::new(seL4_SmallPageObject, 2, 5),
+        ]
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut tracer = AllocTracer::new();
+        tracer.alloc(1, "", &request());
+        let trace = tracer.trace(0, usize::MAX);
+        assert!(!trace.enabled);
+        assert!(trace.records.is_empty());
+        // Sequence numbers advance regardless.
+        assert_eq!(trace.checkpoint, 1);
+    }
+
+    #[test]
+    fn test_alloc_free() {
+        let mut tracer = AllocTracer::new();
+        tracer.set_enabled(true);
+        tracer.alloc(1, "", &request());
+        let trace = tracer.trace(0, usize::MAX);
+        assert_eq!(trace.records.len(), 1);
+        assert_eq!(
+            trace.records[0].objs,
+            vec![(seL4_TCBObject, 1), (seL4_SmallPageObject, 6)]
+        );
+
+        // Frees by someone else or of other types are not matched.
+        tracer.free(2, "", seL4_TCBObject);
+        tracer.free(1, "app", seL4_TCBObject);
+        tracer.free(1, "", seL4_TCBObject);
+        tracer.free(1, "", seL4_TCBObject);
+        assert_eq!(
+            tracer.trace(0, usize::MAX).records[0].objs,
+            vec![(seL4_SmallPageObject, 6)]
+        );
+        for _ in 0..6 {
+            tracer.free(1, "", seL4_SmallPageObject);
+        }
+        assert!(tracer.trace(0, usize::MAX).records.is_empty());
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let mut tracer = AllocTracer::new();
+        tracer.set_enabled(true);
+        tracer.alloc(1, "", &request());
+        let before = tracer.trace(usize::MAX, usize::MAX).checkpoint;
+
+        // A start/stop cycle that frees all but one page.
+        tracer.alloc(2, "app", &request());
+        tracer.alloc(2, "app", &[ObjDesc::new(seL4_TCBObject, 1, 0)]);
+        for _ in 0..2 {
+            tracer.free(2, "app", seL4_TCBObject);
+        }
+        for _ in 0..5 {
+            tracer.free(2, "app", seL4_SmallPageObject);
+        }
+        let after = tracer.trace(usize::MAX, usize::MAX).checkpoint;
+        tracer.alloc(2, "app", &request());
+
+        let leaks = tracer.trace(before, after).records;
+        assert_eq!(leaks.len(), 1);
+        assert_eq!(leaks[0].seq, before);
+        assert_eq!(leaks[0].owner, "app");
+        assert_eq!(leaks[0].objs, vec![(seL4_SmallPageObject, 1)]);
+        assert_eq!(tracer.trace(0, usize::MAX).records.len(), 3);
+
+        // Disabling discards the records.
+        tracer.set_enabled(false);
+        assert!(tracer.trace(0, usize::MAX).records.is_empty());
+    }
+
+    #[test]
+    fn test_full() {
+        let mut tracer = AllocTracer::new();
+        tracer.set_enabled(true);
+        for _ in 0..MAX_TRACE_RECORDS + 2 {
+            tracer.alloc(1, "", &request());
+        }
+        let trace = tracer.trace(0, usize::MAX);
+        assert_eq!(trace.records.len(), MAX_TRACE_RECORDS);
+        assert_eq!(trace.dropped, 2);
+        assert_eq!(trace.checkpoint, MAX_TRACE_RECORDS + 2);
+    }
+}
@@ -42,8 +42,10 @@ pub unsafe extern "C" fn mlcoord__init() { ML_COORD.lock().init(); }
 
 #[no_mangle]
 pub unsafe extern "C" fn run() {
+    ML_COORD.lock().subscribe_memory_pressure();
     loop {
         timer_service_wait();
+        ML_COORD.lock().check_memory_pressure();
         let completed = timer_service_completed_timers();
 
         for i in 0..31 {
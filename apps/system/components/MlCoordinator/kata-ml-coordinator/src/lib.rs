@@ -19,7 +19,10 @@
 extern crate alloc;
 
 use alloc::vec::Vec;
-use kata_memory_interface::kata_object_free_in_cnode;
+use kata_memory_interface::kata_memory_pressure;
+use kata_memory_interface::kata_memory_pressure_poll;
+use kata_memory_interface::kata_memory_subscribe;
+use kata_memory_interface::MemoryPressure;
 use kata_ml_interface::MlCoordError;
 use kata_ml_shared::*;
 use kata_ml_support::image_manager::ImageManager;
@@ -44,8 +47,15 @@ struct LoadableModel {
 struct Statistics {
     load_failures: u32,
     already_queued: u32,
+    low_memory: u32,
 }
 
+// Free memory thresholds for MemoryManager pressure notifications. Idle
+// images are evicted when memory is low; images are not loaded (which
+// requires staging them in memory) when memory is critical.
+const LOW_WATER_BYTES: usize = 256 * 1024;
+const CRITICAL_BYTES: usize = 128 * 1024;
+
 pub struct MLCoordinator {
     /// The currently running model, if any.
     running_model: Option<ImageId>,
@@ -58,6 +68,8 @@ pub struct MLCoordinator {
     /// The image manager is responsible for tracking, loading, and unloading
     /// images.
     image_manager: ImageManager,
+    /// Memory pressure last reported by the MemoryManager.
+    memory_pressure: MemoryPressure,
     statistics: Statistics,
 }
 
@@ -74,9 +86,11 @@ impl MLCoordinator {
             models: [INIT_NONE; MAX_MODELS],
             execution_queue: Vec::new(),
             image_manager: ImageManager::new(),
+            memory_pressure: MemoryPressure::PressureNormal,
             statistics: Statistics {
                 load_failures: 0,
                 already_queued: 0,
+                low_memory: 0,
             },
         }
     }
@@ -88,6 +102,46 @@ impl MLCoordinator {
         self.image_manager.init();
     }
 
+    /// Registers for memory pressure notifications. This must be done
+    /// once other components are running (i.e. not from init).
+    pub fn subscribe_memory_pressure(&mut self) {
+        if let Err(e) = kata_memory_subscribe(LOW_WATER_BYTES, CRITICAL_BYTES) {
+            error!("Memory pressure subscribe failed: {:?}", e);
+        }
+        self.memory_pressure = kata_memory_pressure();
+    }
+
+    /// Checks for a memory pressure notification and, if memory is short,
+    /// evicts the images of models that are not running.
+    pub fn check_memory_pressure(&mut self) {
+        if !kata_memory_pressure_poll() {
+            return;
+        }
+        self.memory_pressure = kata_memory_pressure();
+        if self.memory_pressure == MemoryPressure::PressureNormal {
+            return;
+        }
+        warn!("Memory pressure {:?}, evicting idle images", self.memory_pressure);
+        for model in self.models.iter().flatten() {
+            if self.running_model.as_ref() != Some(&model.id)
+                && self.image_manager.unload_image(&model.id)
+            {
+                info!("Evicted {}:{}", &model.id.bundle_id, &model.id.model_id);
+            }
+        }
+    }
+
+    // Returns true if there is enough memory to load an image.
+    fn can_load_image(&mut self) -> bool {
+        self.check_memory_pressure();
+        if self.memory_pressure == MemoryPressure::PressureCritical {
+            warn!("Memory critical, not loading image");
+            self.statistics.low_memory += 1;
+            return false;
+        }
+        true
+    }
+
     // Validates the image by ensuring it has all the required loadable
     // sections and that it fits into the TCM. Returns a tuple of
     // |(on_flash_sizes, in_memory_sizes)|.
@@ -96,7 +150,7 @@ impl MLCoordinator {
         match kata_security_load_model(&id.bundle_id, &id.model_id, &container_slot) {
             Ok(model_frames) => {
                 container_slot.release(); // NB: take ownership
-                let mut image = BundleImage::new(&model_frames);
+                let mut image = BundleImage::new(&model_frames.frames);
 
                 let mut on_flash_sizes = ImageSizes::default();
                 let mut in_memory_sizes = ImageSizes::default();
@@ -140,7 +194,7 @@ impl MLCoordinator {
                 }
 
                 drop(image);
-                let _ = kata_object_free_in_cnode(&model_frames);
+                let _ = kata_security_release_image(&model_frames);
 
                 Some((on_flash_sizes, in_memory_sizes))
             }
@@ -159,9 +213,14 @@ impl MLCoordinator {
         }
 
         let next_idx = self.execution_queue.remove(0);
+        let id = &self.models[next_idx].as_ref().expect("Model get fail").id;
+        let loaded = self.image_manager.is_loaded(id);
+        if !loaded && !self.can_load_image() {
+            return Err(MlCoordError::LoadModelFailed);
+        }
         let model = self.models[next_idx].as_ref().expect("Model get fail");
 
-        if !self.image_manager.is_loaded(&model.id) {
+        if !loaded {
             // Loads |model_id| associated with |bundle_id| from the
             // SecurityCoordinator. The data are returned as unmapped
             // page frames in a CNode container left in |container_slot|.
@@ -173,7 +232,7 @@ impl MLCoordinator {
             {
                 Ok(model_frames) => {
                     container_slot.release(); // NB: take ownership
-                    let mut image = BundleImage::new(&model_frames);
+                    let mut image = BundleImage::new(&model_frames.frames);
 
                     // Ask the image manager to make enough room and get
                     // the address to write to.
@@ -226,7 +285,7 @@ impl MLCoordinator {
                         .commit_image(model.id.clone(), model.in_memory_sizes);
 
                     drop(image);
-                    let _ = kata_object_free_in_cnode(&model_frames);
+                    let _ = kata_security_release_image(&model_frames);
                 }
                 Err(e) => {
                     error!(
@@ -305,6 +364,10 @@ impl MLCoordinator {
             .position(|m| m.is_none())
             .ok_or(MlCoordError::NoModelSlotsLeft)?;
 
+        // NB: validating the image requires loading it
+        if !self.can_load_image() {
+            return Err(MlCoordError::LoadModelFailed);
+        }
         let (on_flash_sizes, in_memory_sizes) =
             self.validate_image(&id).ok_or(MlCoordError::InvalidImage)?;
 
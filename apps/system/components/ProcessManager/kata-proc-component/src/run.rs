@@ -110,6 +110,14 @@ pub unsafe extern "C" fn pkg_mgmt_uninstall(
     ret_status
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn pkg_mgmt_factory_reset() -> ProcessManagerError {
+    match KATA_PROC.factory_reset() {
+        Ok(_) => ProcessManagerError::Success,
+        Err(e) => e,
+    }
+}
+
 // ProcessControlInterface glue stubs.
 #[no_mangle]
 pub unsafe extern "C" fn proc_ctrl_start(
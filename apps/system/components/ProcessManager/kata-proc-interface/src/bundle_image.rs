@@ -15,12 +15,15 @@
 //! Kata OS Bundle image loader.
 
 use core::cmp;
-use core::mem::size_of;
 use core::ops::Range;
 use core::ptr;
 use kata_memory_interface::ObjDescBundle;
 use kata_os_common::cspace_slot::CSpaceSlot;
 use kata_os_common::sel4_sys;
+use kata_package_signature::SECTION_HEADER_SIZE;
+use kata_package_signature::SECTION_MAGIC;
+use kata_package_signature::SECTION_MANIFEST;
+use kata_package_signature::SIGNATURE_MAGIC;
 use log::{error, trace};
 
 use sel4_sys::seL4_CPtr;
@@ -35,12 +38,12 @@ use io::Read;
 use io::Seek;
 use kata_io as io;
 
-// TODO(sleffler): use ObjDesc::size_bytes and support multiple page sizes
-const PAGE_SIZE: usize = 1 << seL4_PageBits;
+// Size of the BUNDLE_IMAGE window; frames larger than this cannot be mapped.
+const BUNDLE_IMAGE_SIZE: usize = 1 << seL4_PageBits;
 
 extern "C" {
     static SELF_VSPACE_ROOT: seL4_CPtr;
-    static mut BUNDLE_IMAGE: [u8; PAGE_SIZE];
+    static mut BUNDLE_IMAGE: [u8; BUNDLE_IMAGE_SIZE];
 }
 
 #[derive(Debug)]
@@ -58,7 +61,7 @@ impl From<seL4_Error> for BundleImageError {
     fn from(_err: seL4_Error) -> BundleImageError { BundleImageError::CapMoveFailed }
 }
 
-// On-disk header format.
+// On-disk header format (SECTION_HEADER_SIZE bytes, big-endian).
 #[repr(packed)]
 #[allow(dead_code)]
 struct SectionHeader {
@@ -72,7 +75,6 @@ struct SectionHeader {
     pad: u32,   // <ignore, reserved for future use>
     crc32: u32, // CRC32 of the data that follows
 }
-const SECTION_MAGIC: u64 = 0x0405_1957_1014_1955;
 
 const SECTION_READ: u32 = 0x1; // Data are readable
 const SECTION_WRITE: u32 = 0x2; // Data are writeable
@@ -148,18 +150,29 @@ impl<'a> BundleImage<'a> {
 
     // Read the current section header and setup to advance to the next
     // section on the next call. This is used in lieu of an iterator to
-    // avoid BundleImage borrow issues.
+    // avoid BundleImage borrow issues. Manifest sections (checked by the
+    // SecurityCoordinator at install) are skipped.
     // XXX change to Result so errors are visible
     pub fn next_section(&mut self) -> Option<BundleImageSection> {
+        loop {
+            let hdr = self.read_section()?;
+            if (hdr.flags & SECTION_MANIFEST) == 0 {
+                return Some(hdr);
+            }
+        }
+    }
+
+    fn read_section(&mut self) -> Option<BundleImageSection> {
         self.seek(io::SeekFrom::Start(self.next_section as u64))
             .ok()?;
-        let raw_data = &mut [0u8; size_of::<SectionHeader>()];
+        let raw_data = &mut [0u8; SECTION_HEADER_SIZE];
         self.read_exact(raw_data).ok()?;
         let magic = u64::from_be_bytes(raw_data[0..8].try_into().unwrap());
         if magic != SECTION_MAGIC {
             // NB: happens when the image does not end on a page boundary,
-            //   check magic as a hack to detect this
-            if magic != 0 {
+            //   check magic as a hack to detect this; a signed package
+            //   ends with a signature trailer (verified at install)
+            if magic != 0 && magic != SIGNATURE_MAGIC {
                 error!(
                     "Invalid magic number at offset {} expected 0x{:x} got 0x{:x}",
                     self.next_section, SECTION_MAGIC, magic
@@ -215,59 +228,56 @@ impl<'a> BundleImage<'a> {
     // Map the frame containing self.|cur_pos| into our VSpace.
     fn map_next_frame(&mut self) -> Result<(), BundleImageError> {
         assert_eq!(self.cur_frame, None);
-        let mut od_off: u64 = 0; // Running byte offset to start of current ObjDesc
-                                 // n^2 in ObjDesc, track last frame
-        for od in &self.frames.objs {
-            // TODO(sleffler): maybe move page index logic to ObjDesc
-            let size_bytes = od.size_bytes().unwrap() as u64;
-            if od_off <= self.cur_pos && self.cur_pos < od_off + size_bytes {
-                // The frame is in this ObjDesc, calculate the page index.
-                let index = ((self.cur_pos - od_off) / (PAGE_SIZE as u64)) as usize;
-                assert!(index < od.retype_count());
+        // NB: n^2 in ObjDesc, track last frame
+        let (cptr, frame_off, frame_bytes) =
+            self.frames.frame_at(self.cur_pos as usize).ok_or_else(|| {
+                error!("No page at offset {}", self.cur_pos);
+                BundleImageError::PageNotFound
+            })?;
+        if frame_bytes > BUNDLE_IMAGE_SIZE {
+            // NB: a large page needs a suitably sized & aligned window
+            error!("Frame at offset {} too large: {} bytes", self.cur_pos, frame_bytes);
+            return Err(BundleImageError::PageMapFailed);
+        }
 
-                // Bounce through the top-level CNode.
-                sel4_sys::debug_assert_slot_empty!(
-                    self.bounce.slot,
-                    "{}: expected slot {:?} empty but has cap type {:?}",
-                    "map_next_frame",
-                    self.bounce.slot,
-                    sel4_sys::cap_identify(self.bounce.slot)
-                );
-                self.bounce
-                    .move_to(self.frames.cnode, od.cptr + index, self.frames.depth)
-                    .map_err(|_| BundleImageError::CapMoveFailed)?;
+        // Bounce through the top-level CNode.
+        sel4_sys::debug_assert_slot_empty!(
+            self.bounce.slot,
+            "{}: expected slot {:?} empty but has cap type {:?}",
+            "map_next_frame",
+            self.bounce.slot,
+            sel4_sys::cap_identify(self.bounce.slot)
+        );
+        self.bounce
+            .move_to(self.frames.cnode, cptr, self.frames.depth)
+            .map_err(|_| BundleImageError::CapMoveFailed)?;
 
-                // Map the page into our VSpace
-                // TODO(sleffler): if this fails maybe undo move_to
-                sel4_sys::debug_assert_slot_frame!(
-                    self.bounce.slot,
-                    "{}: expected frame in slot {:?} but has cap type {:?}",
-                    "map_next_frame",
-                    self.bounce.slot,
-                    sel4_sys::cap_identify(self.bounce.slot)
-                );
-                unsafe {
-                    seL4_Page_Map(
-                        /*sel4_page=*/ self.bounce.slot,
-                        /*seL4_pd=*/ SELF_VSPACE_ROOT,
-                        /*vaddr=*/ self.mapped_page as usize,
-                        seL4_CapRights::new(
-                            /*grant_reply=*/ 0, /*grant=*/ 0, /*read=1*/ 1,
-                            /*write=*/ 0,
-                        ),
-                        seL4_Default_VMAttributes,
-                    )
-                }
-                .map_err(|_| BundleImageError::PageMapFailed)?;
-                self.cur_frame = Some(od.cptr + index);
-                self.mapped_bytes = PAGE_SIZE;
-                self.bytes_read = ((self.cur_pos - od_off) % (PAGE_SIZE as u64)) as usize;
-                return Ok(());
-            }
-            od_off += size_bytes;
+        // Map the page into our VSpace
+        // TODO(sleffler): if this fails maybe undo move_to
+        sel4_sys::debug_assert_slot_frame!(
+            self.bounce.slot,
+            "{}: expected frame in slot {:?} but has cap type {:?}",
+            "map_next_frame",
+            self.bounce.slot,
+            sel4_sys::cap_identify(self.bounce.slot)
+        );
+        unsafe {
+            seL4_Page_Map(
+                /*sel4_page=*/ self.bounce.slot,
+                /*seL4_pd=*/ SELF_VSPACE_ROOT,
+                /*vaddr=*/ self.mapped_page as usize,
+                seL4_CapRights::new(
+                    /*grant_reply=*/ 0, /*grant=*/ 0, /*read=1*/ 1,
+                    /*write=*/ 0,
+                ),
+                seL4_Default_VMAttributes,
+            )
         }
-        error!("No page at offset {}", self.cur_pos);
-        Err(BundleImageError::PageNotFound)
+        .map_err(|_| BundleImageError::PageMapFailed)?;
+        self.cur_frame = Some(cptr);
+        self.mapped_bytes = frame_bytes;
+        self.bytes_read = (self.cur_pos as usize) - frame_off;
+        Ok(())
     }
 }
 impl<'a> Drop for BundleImage<'a> {
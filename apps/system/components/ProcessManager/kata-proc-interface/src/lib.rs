@@ -91,9 +91,14 @@ pub enum ProcessManagerError {
     DeserializeError,
     SerializeError,
     ObjCapInvalid,
+    PackageUnsigned,
+    PackageSignatureInvalid,
+    PackageVersionRollback,
+    LowMemory, // Not enough free memory to start
     // Generic errors, mostly for unit tests.
     InstallFailed,
     UninstallFailed,
+    FactoryResetFailed,
     StartFailed,
     StopFailed,
     // TODO(sleffler): for use if/when ProcessManagerInterface grows
@@ -107,6 +112,7 @@ pub enum ProcessManagerError {
 pub trait ProcessManagerInterface {
     fn install(&mut self, pkg_contents: &ObjDescBundle) -> Result<String, ProcessManagerError>;
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError>;
+    fn factory_reset(&mut self) -> Result<(), ProcessManagerError>;
     fn start(
         &mut self,
         bundle: &Bundle,
@@ -124,6 +130,9 @@ pub trait ProcessManagerInterface {
 pub trait PackageManagementInterface {
     fn install(&mut self, pkg_contents: &ObjDescBundle) -> Result<String, ProcessManagerError>;
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError>;
+    // Stops all applications, uninstalls all packages, and erases all
+    // stored data.
+    fn factory_reset(&mut self) -> Result<(), ProcessManagerError>;
 }
 
 pub trait ProcessControlInterface {
@@ -154,8 +163,14 @@ impl From<SecurityRequestError> for ProcessManagerError {
             SecurityRequestError::SrePackageBufferLenInvalid => {
                 ProcessManagerError::PackageBufferLenInvalid
             }
+            SecurityRequestError::SrePackageUnsigned => ProcessManagerError::PackageUnsigned,
+            SecurityRequestError::SrePackageSignatureInvalid => {
+                ProcessManagerError::PackageSignatureInvalid
+            }
+            SecurityRequestError::SreVersionRollback => ProcessManagerError::PackageVersionRollback,
             SecurityRequestError::SreInstallFailed => ProcessManagerError::InstallFailed,
             SecurityRequestError::SreUninstallFailed => ProcessManagerError::UninstallFailed,
+            SecurityRequestError::SreFactoryResetFailed => ProcessManagerError::FactoryResetFailed,
             // NB: other errors "cannot happen" so just return something unique
             _ => ProcessManagerError::UnknownError,
         }
@@ -230,6 +245,17 @@ pub fn kata_pkg_mgmt_uninstall(bundle_id: &str) -> Result<(), ProcessManagerErro
     unsafe { pkg_mgmt_uninstall(cstr.as_ptr()) }.into()
 }
 
+// Returns the system to its freshly-flashed state: every application is
+// stopped, every package is uninstalled, and all stored data is erased.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_pkg_mgmt_factory_reset() -> Result<(), ProcessManagerError> {
+    extern "C" {
+        fn pkg_mgmt_factory_reset() -> ProcessManagerError;
+    }
+    unsafe { pkg_mgmt_factory_reset() }.into()
+}
+
 #[inline]
 #[allow(dead_code)]
 pub fn kata_proc_ctrl_start(bundle_id: &str) -> Result<(), ProcessManagerError> {
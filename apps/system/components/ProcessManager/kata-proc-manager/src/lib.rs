@@ -19,6 +19,10 @@
 extern crate alloc;
 use alloc::boxed::Box;
 use alloc::string::String;
+use kata_memory_interface::kata_memory_pressure;
+use kata_memory_interface::kata_memory_pressure_poll;
+use kata_memory_interface::kata_memory_subscribe;
+use kata_memory_interface::MemoryPressure;
 use kata_memory_interface::ObjDescBundle;
 use kata_os_common::cspace_slot::CSpaceSlot;
 use kata_proc_interface::Bundle;
@@ -28,10 +32,11 @@ use kata_proc_interface::PackageManagementInterface;
 use kata_proc_interface::ProcessControlInterface;
 use kata_proc_interface::ProcessManagerError;
 use kata_proc_interface::ProcessManagerInterface;
+use kata_security_interface::kata_security_factory_reset;
 use kata_security_interface::kata_security_install;
 use kata_security_interface::kata_security_load_application;
 use kata_security_interface::kata_security_uninstall;
-use log::trace;
+use log::{trace, warn};
 use spin::Mutex;
 
 mod sel4bundle;
@@ -59,7 +64,9 @@ impl KataProcManager {
     }
 
     // Finishes the setup started by empty():
-    pub fn init(&self) { *self.manager.lock() = Some(ProcessManager::new(KataManagerInterface)); }
+    pub fn init(&self) {
+        *self.manager.lock() = Some(ProcessManager::new(KataManagerInterface::new()));
+    }
 
     // Returns the bundle capacity.
     pub fn capacity(&self) -> usize { self.manager.lock().as_ref().unwrap().capacity() }
@@ -72,6 +79,9 @@ impl PackageManagementInterface for KataProcManager {
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError> {
         self.manager.lock().as_mut().unwrap().uninstall(bundle_id)
     }
+    fn factory_reset(&mut self) -> Result<(), ProcessManagerError> {
+        self.manager.lock().as_mut().unwrap().factory_reset()
+    }
 }
 impl ProcessControlInterface for KataProcManager {
     fn start(&mut self, bundle_id: &str) -> Result<(), ProcessManagerError> {
@@ -88,7 +98,35 @@ impl ProcessControlInterface for KataProcManager {
     }
 }
 
-struct KataManagerInterface;
+// Free memory thresholds for MemoryManager pressure notifications. New
+// applications are not started when memory is critical.
+const LOW_WATER_BYTES: usize = 512 * 1024;
+const CRITICAL_BYTES: usize = 256 * 1024;
+
+struct KataManagerInterface {
+    pressure: Option<MemoryPressure>, // None until subscribed
+}
+impl KataManagerInterface {
+    fn new() -> Self { KataManagerInterface { pressure: None } }
+
+    // Returns the current memory pressure. The level is cached and only
+    // re-fetched when the MemoryManager signals a change. Subscribing is
+    // deferred to first use since it cannot be done during setup.
+    fn memory_pressure(&mut self) -> MemoryPressure {
+        let level = match self.pressure {
+            Some(level) if !kata_memory_pressure_poll() => level,
+            Some(_) => kata_memory_pressure(),
+            None => {
+                if let Err(e) = kata_memory_subscribe(LOW_WATER_BYTES, CRITICAL_BYTES) {
+                    warn!("Memory pressure subscribe failed: {:?}", e);
+                }
+                kata_memory_pressure()
+            }
+        };
+        self.pressure = Some(level);
+        level
+    }
+}
 impl ProcessManagerInterface for KataManagerInterface {
     fn install(&mut self, pkg_contents: &ObjDescBundle) -> Result<String, ProcessManagerError> {
         trace!("ProcessManagerInterface::install pkg_contents {}", pkg_contents);
@@ -111,12 +149,29 @@ impl ProcessManagerInterface for KataManagerInterface {
         // This is handled by the SecurityCoordinator.
         Ok(kata_security_uninstall(bundle_id)?)
     }
+    fn factory_reset(&mut self) -> Result<(), ProcessManagerError> {
+        trace!("ProcessManagerInterface::factory_reset");
+
+        // NB: the caller has already stopped all applications
+
+        // This is handled by the SecurityCoordinator.
+        Ok(kata_security_factory_reset()?)
+    }
     fn start(
         &mut self,
         bundle: &Bundle,
     ) -> Result<Box<dyn BundleImplInterface>, ProcessManagerError> {
         trace!("ProcessManagerInterface::start {:?}", bundle);
 
+        match self.memory_pressure() {
+            MemoryPressure::PressureCritical => {
+                warn!("Memory critical, not starting {}", &bundle.app_id);
+                return Err(ProcessManagerError::LowMemory);
+            }
+            MemoryPressure::PressureLow => warn!("Memory low, starting {}", &bundle.app_id),
+            MemoryPressure::PressureNormal => {}
+        }
+
         // Design doc says:
         // 1. Ask security core for application footprint with SizeBuffer
         // 2. Ask security core for manifest (maybe piggyback on SizeBuffer)
@@ -134,8 +189,8 @@ impl ProcessManagerInterface for KataManagerInterface {
         //       access
         // What we do atm is:
         // 1. Ask SecurityCoordinator to return the application contents to load.
-        //    Data are delivered as a read-only ObjDescBundle ready to copy into
-        //    the VSpace.
+        //    Data are delivered as ImageFrames ready to copy into the VSpace;
+        //    read-only pages may be shared with the installed package.
         // 2. Do 4+6 with BundleImplInterface::start.
 
         // TODO(sleffler): awkward container_slot ownership
@@ -118,6 +118,26 @@ impl PackageManagementInterface for ProcessManager {
         // NB: the hashmap is ephemeral so always call through to the manager
         self.manager.uninstall(bundle_id)
     }
+
+    fn factory_reset(&mut self) -> Result<(), ProcessManagerError> {
+        trace!("factory_reset");
+
+        // Stop all applications before their packages are removed. If
+        // one cannot be stopped nothing is erased.
+        for (bundle_id, bundle) in self.bundles.iter_mut() {
+            if bundle.state == BundleState::Running {
+                trace!("factory_reset: stop {}", bundle_id);
+                self.manager
+                    .stop(bundle.bundle_impl.as_deref_mut().unwrap())?;
+                bundle.state = BundleState::Stopped;
+                bundle.bundle_impl = None;
+            }
+        }
+        self.bundles.clear();
+        // NB: the manager removes all packages, including any not
+        //   known to the (ephemeral) hashmap
+        self.manager.factory_reset()
+    }
 }
 
 impl ProcessControlInterface for ProcessManager {
@@ -233,6 +253,10 @@ mod tests {
                 None => Err(ProcessManagerError::BundleNotFound),
             }
         }
+        fn factory_reset(&mut self) -> Result<(), pme> {
+            self.bundles.clear();
+            Ok(())
+        }
         fn start(&mut self, bundle: &Bundle) -> Result<Box<dyn BundleImplInterface>, pme> {
             assert!(self.bundles.contains_key(&bundle.app_id));
             Ok(Box::new(FakeBundleImpl))
@@ -282,6 +306,27 @@ mod tests {
         assert!(mgr.uninstall(&bundle_id).is_ok());
     }
 
+    #[test]
+    fn test_factory_reset() {
+        let fake = tests::FakeManager::new();
+        let mut mgr = ProcessManager::new(fake);
+
+        let pkg_buffer = [0u8; 1024];
+        let bid1 = mgr.install(pkg_buffer.as_ptr(), pkg_buffer.len()).unwrap();
+        let slice = &pkg_buffer[1..];
+        let bid2 = mgr.install(slice.as_ptr(), slice.len()).unwrap();
+        assert!(mgr.start(&bid1).is_ok());
+
+        // Running bundles are stopped and everything is removed.
+        assert!(mgr.factory_reset().is_ok());
+        assert_eq!(mgr.get_running_bundles().unwrap().len(), 0);
+        assert_eq!(mgr.start(&bid1).err(), Some(pme::BundleNotFound));
+        assert_eq!(mgr.uninstall(&bid2).err(), Some(pme::BundleNotFound));
+
+        // Resetting an empty system is harmless.
+        assert!(mgr.factory_reset().is_ok());
+    }
+
     #[test]
     fn test_spill() {
         let fake = tests::FakeManager::new();
@@ -25,9 +25,10 @@ use core::cmp;
 use core::mem::size_of;
 use core::ptr;
 use kata_memory_interface::kata_cnode_alloc;
+use kata_memory_interface::kata_memory_set_owner;
 use kata_memory_interface::kata_object_alloc_in_toplevel;
 use kata_memory_interface::kata_object_free;
-use kata_memory_interface::kata_object_free_in_cnode;
+use kata_memory_interface::kata_object_free_toplevel;
 use kata_memory_interface::ObjDesc;
 use kata_memory_interface::ObjDescBundle;
 use kata_os_common::copyregion::CopyRegion;
@@ -40,6 +41,8 @@ use kata_proc_interface::BundleImplInterface;
 use kata_proc_interface::ProcessManagerError;
 use kata_sdk_manager::kata_sdk_manager_get_endpoint;
 use kata_sdk_manager::kata_sdk_manager_release_endpoint;
+use kata_security_interface::kata_security_release_image;
+use kata_security_interface::ImageFrames;
 use log::{debug, error, info, trace};
 
 use io::Read;
@@ -168,8 +171,9 @@ const FRAME_SLOT: usize = STACK_SLOT + STACK_COUNT;
 // NB: FRAME_SLOT count is based on the BundleImage
 
 pub struct seL4BundleImpl {
-    // Application binary pages ordered by virtual address.
-    bundle_frames: ObjDescBundle,
+    // Application binary pages ordered by virtual address. Some pages
+    // may be shared (read-only) with the SecurityCoordinator.
+    bundle_frames: ImageFrames,
 
     // Dynamically allocated CSpace contents; these start out in our
     // top-level CNode but are then moved to cspace_root.
@@ -206,21 +210,34 @@ pub struct seL4BundleImpl {
     sc_data: seL4_Word,
     sc_period: u64,
 }
+
+// Runs |f| with MemoryManager usage charged to |owner|. Accounting is
+// advisory so failing to set the owner is not fatal.
+fn charged_to<T>(owner: &str, f: impl FnOnce() -> T) -> T {
+    if let Err(e) = kata_memory_set_owner(owner) {
+        debug!("Cannot charge memory to {}: {:?}", owner, e);
+    }
+    let result = f();
+    let _ = kata_memory_set_owner("");
+    result
+}
+
 impl seL4BundleImpl {
-    pub fn new(
-        bundle: &Bundle,
-        bundle_frames: &ObjDescBundle,
-    ) -> Result<Self, ProcessManagerError> {
-        trace!("seL4BundleImpl::new {:?} bundle_frames {}", bundle, bundle_frames);
+    pub fn new(bundle: &Bundle, bundle_frames: &ImageFrames) -> Result<Self, ProcessManagerError> {
+        trace!(
+            "seL4BundleImpl::new {:?} bundle_frames {}",
+            bundle,
+            bundle_frames.frames
+        );
 
-        sel4_sys::debug_assert_slot_cnode!(bundle_frames.cnode);
+        sel4_sys::debug_assert_slot_cnode!(bundle_frames.frames.cnode);
 
         // TODO(sleffler): parse/extract from manifest to construct BundleImpl
 
         // Calculate how many pages are needed and
         // (while we're here) the entry point.
         let (nframes, first_vaddr, entry_point) =
-            seL4BundleImpl::preprocess_bundle_image(bundle_frames);
+            seL4BundleImpl::preprocess_bundle_image(&bundle_frames.frames);
         if entry_point.is_none() {
             info!(
                 "Bundle {} has no entry point, using 0x{:x}",
@@ -250,41 +267,45 @@ impl seL4BundleImpl {
         // TODO(sleffler): maybe construct the vec to avoid mismatches
         // TODO(sleffler): the toplevel CNode has a fixed size which
         //   can overflow when nframes is non-trivial
-        let dynamic_objs = kata_object_alloc_in_toplevel(vec![
-            // control/main-thread TCB
-            ObjDesc::new(seL4_TCBObject, 1, TCB_SLOT),
-            // fault redirect to SDK/ProcessManager
-            ObjDesc::new(seL4_EndpointObject, 1, FAULT_EP_SLOT),
-            // SchedContext for main thread
-            ObjDesc::new(seL4_SchedContextObject, seL4_MinSchedContextBits, SCHED_CONTEXT_SLOT),
-            // VSpace root (PD)
-            ObjDesc::new(seL4_PageTableObject, 1, PD_SLOT),
-            // VSpace page table (PT)
-            ObjDesc::new(seL4_PageTableObject, 1, PT_SLOT),
-            // IPC buffer frame
-            ObjDesc::new(seL4_SmallPageObject, 1, IPCBUFFER_SLOT),
-            // Frame for SDK RPC parameters
-            ObjDesc::new(seL4_SmallPageObject, 1, SDK_FRAME_SLOT),
-            // Stack frames (guard frames are unpopulated PT slots)
-            ObjDesc::new(seL4_SmallPageObject, STACK_COUNT, STACK_SLOT),
-            // Page frames for application binary.
-            ObjDesc::new(seL4_SmallPageObject, nframes, FRAME_SLOT),
-        ])
-        .map_err(|_| ProcessManagerError::StartFailed)?;
-
-        // Allocate the top-level CNode that will hold |dynamic_objs|.
-        let cspace_root_depth = dynamic_objs.count_log2();
-        let cspace_root = match kata_cnode_alloc(cspace_root_depth) {
-            Err(e) => {
-                error!("seL4BundleImpl::new: cnode alloc failed: {:?}", e);
-                info!("seL4BundleImpl::new: dynamic objects: {:?}", &dynamic_objs);
-                if let Err(e) = kata_object_free(&dynamic_objs) {
-                    error!("seL4BundleImpl::new: freeing dynamic_objs returned {:?}", e);
+        // NB: usage is charged to the bundle rather than the ProcessManager
+        let (dynamic_objs, cspace_root) = charged_to(&bundle.app_id, || {
+            let dynamic_objs = kata_object_alloc_in_toplevel(vec![
+                // control/main-thread TCB
+                ObjDesc::new(seL4_TCBObject, 1, TCB_SLOT),
+                // fault redirect to SDK/ProcessManager
+                ObjDesc::new(seL4_EndpointObject, 1, FAULT_EP_SLOT),
+                // SchedContext for main thread
+                ObjDesc::new(seL4_SchedContextObject, seL4_MinSchedContextBits, SCHED_CONTEXT_SLOT),
+                // VSpace root (PD)
+                ObjDesc::new(seL4_PageTableObject, 1, PD_SLOT),
+                // VSpace page table (PT)
+                ObjDesc::new(seL4_PageTableObject, 1, PT_SLOT),
+                // IPC buffer frame
+                ObjDesc::new(seL4_SmallPageObject, 1, IPCBUFFER_SLOT),
+                // Frame for SDK RPC parameters
+                ObjDesc::new(seL4_SmallPageObject, 1, SDK_FRAME_SLOT),
+                // Stack frames (guard frames are unpopulated PT slots)
+                ObjDesc::new(seL4_SmallPageObject, STACK_COUNT, STACK_SLOT),
+                // Page frames for application binary.
+                ObjDesc::new(seL4_SmallPageObject, nframes, FRAME_SLOT),
+            ])
+            .map_err(|_| ProcessManagerError::StartFailed)?;
+
+            // Allocate the top-level CNode that will hold |dynamic_objs|.
+            let cspace_root_depth = dynamic_objs.count_log2();
+            let cspace_root = match kata_cnode_alloc(cspace_root_depth) {
+                Err(e) => {
+                    error!("seL4BundleImpl::new: cnode alloc failed: {:?}", e);
+                    info!("seL4BundleImpl::new: dynamic objects: {:?}", &dynamic_objs);
+                    if let Err(e) = kata_object_free(&dynamic_objs) {
+                        error!("seL4BundleImpl::new: freeing dynamic_objs returned {:?}", e);
+                    }
+                    return Err(ProcessManagerError::StartFailed);
                 }
-                return Err(ProcessManagerError::StartFailed);
-            }
-            Ok(cnode) => cnode,
-        };
+                Ok(cnode) => cnode,
+            };
+            Ok((dynamic_objs, cspace_root))
+        })?;
 
         // XXX setup fault endpoint (allocate id)
         // XXX setup temporal fault endpoint (allocate id)
@@ -362,7 +383,7 @@ impl seL4BundleImpl {
         //   dynamic_objs, so page_frames 1 past STACK_SLOT. To be fixed when
         //   dynamic_objs is constructed directly and we have const indices.
         let page_frames = &self.dynamic_objs.objs[STACK_SLOT + 1];
-        let bundle_frames = &self.bundle_frames;
+        let bundle_frames = &self.bundle_frames.frames;
 
         // Map application pages. The |page_frames| are in the top-level
         // CNode but unmapped. We temporarily map them in a copy region
@@ -658,10 +679,20 @@ impl BundleImplInterface for seL4BundleImpl {
         self.suspend()?;
         kata_sdk_manager_release_endpoint(&self.tcb_name)
             .map_err(|_| ProcessManagerError::StopFailed)?;
-        kata_object_free_in_cnode(&self.bundle_frames)
-            .map_err(|_| ProcessManagerError::StopFailed)?;
-        kata_object_free_in_cnode(&self.dynamic_objs)
+        kata_security_release_image(&self.bundle_frames)
             .map_err(|_| ProcessManagerError::StopFailed)?;
+        // NB: the MemoryManager credits the bundle charged in new(); setting
+        //   the owner steers objects it cannot locate (e.g. the TCB)
+        charged_to(&self.tcb_name, || {
+            // NB: init_cspace moves dynamic_objs into cspace_root
+            let result = if self.dynamic_objs.cnode == self.cspace_root.objs[0].cptr {
+                kata_object_free(&self.dynamic_objs)
+            } else {
+                kata_object_free_toplevel(&self.dynamic_objs)
+            };
+            result.and_then(|_| kata_object_free_toplevel(&self.cspace_root))
+        })
+        .map_err(|_| ProcessManagerError::StopFailed)?;
         self.cap_tcb = CSpaceSlot::new(); // NB: force drop
                                           // XXX delete any other local caps
         Ok(())
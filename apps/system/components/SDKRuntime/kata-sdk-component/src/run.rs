@@ -51,12 +51,15 @@ use sdk_interface::SDKError;
 use sdk_interface::SDKRuntimeError;
 use sdk_interface::SDKRuntimeInterface;
 use sdk_interface::SDKRuntimeRequest;
-use sdk_interface::SDKRUNTIME_REQUEST_DATA_SIZE;
+use sdk_interface::CRYPTO_DATA_SIZE;
+use sdk_interface::RANDOM_DATA_SIZE;
+use sdk_interface::SDKRUNTIME_PARAMS_SIZE;
 
 use sel4_sys::seL4_CNode_Delete;
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_CapRights;
 use sel4_sys::seL4_EndpointObject;
+use sel4_sys::seL4_GetMR;
 use sel4_sys::seL4_MessageInfo;
 use sel4_sys::seL4_PageBits;
 use sel4_sys::seL4_Recv;
@@ -167,16 +170,20 @@ pub unsafe extern "C" fn run() -> ! {
         // outbound capability. To guard against this clear the field here
         // (so it happens for both calls) with clear_request_cap().
         Camkes::clear_request_cap();
+        // NB: fetch the request length before the ipc buffer is re-used
+        //   (e.g. by the syscall to map the frame)
+        let request_len = if info.get_length() > 0 {
+            core::cmp::min(seL4_GetMR(0) as usize, SDKRUNTIME_PARAMS_SIZE)
+        } else {
+            0
+        };
         // Map the frame with RPC parameters and process the request.
         if copy_region.map(recv_path.1).is_ok() {
-            // The request token is passed in the MessageInfo label field.
-            // Any request-specific parameters are serialized in the first
-            // half of the page, with the second half reserved for reply data.
-            // We might consider sending a request length out-of-band (like
-            // the request token) to enable variable page splitting.
-            let (request_slice, reply_slice) = copy_region
-                .as_mut()
-                .split_at_mut(SDKRUNTIME_REQUEST_DATA_SIZE);
+            // The request token is passed in the MessageInfo label field
+            // and the length of the request-specific parameters in the first
+            // message register. Parameters are serialized at the front of
+            // the page, with the remainder used for reply data.
+            let (request_slice, reply_slice) = copy_region.as_mut().split_at_mut(request_len);
             let request_slice = &*request_slice; // NB: immutable alias
 
             let app_id = sdk_runtime_badge as SDKAppId; // XXX safe?
@@ -192,6 +199,27 @@ pub unsafe extern "C" fn run() -> ! {
                 Ok(SDKRuntimeRequest::DeleteKey) => {
                     delete_key_request(app_id, request_slice, reply_slice)
                 }
+                Ok(SDKRuntimeRequest::ListKeys) => {
+                    list_keys_request(app_id, request_slice, reply_slice)
+                }
+                Ok(SDKRuntimeRequest::GetRandom) => {
+                    get_random_request(app_id, request_slice, reply_slice)
+                }
+                Ok(SDKRuntimeRequest::CryptoGenerate) => {
+                    crypto_generate_request(app_id, request_slice, reply_slice)
+                }
+                Ok(SDKRuntimeRequest::CryptoDelete) => {
+                    crypto_delete_request(app_id, request_slice, reply_slice)
+                }
+                Ok(
+                    op @ (SDKRuntimeRequest::CryptoSign
+                    | SDKRuntimeRequest::CryptoMac
+                    | SDKRuntimeRequest::CryptoEncrypt
+                    | SDKRuntimeRequest::CryptoDecrypt),
+                ) => crypto_op_request(op, app_id, request_slice, reply_slice),
+                Ok(SDKRuntimeRequest::CryptoVerify) => {
+                    crypto_verify_request(app_id, request_slice, reply_slice)
+                }
                 Err(_) => {
                     // TODO(b/254286176): possible ddos
                     error!("Unknown RPC request {}", info.get_label());
@@ -275,10 +303,7 @@ fn write_key_request(
 ) -> Result<(), SDKError> {
     let request = postcard::from_bytes::<sdk_interface::WriteKeyRequest>(request_slice)
         .map_err(deserialize_failure)?;
-    // NB: the serialized data are variable length so copy to convert
-    let mut keyval = [0u8; sdk_interface::KEY_VALUE_DATA_SIZE];
-    keyval[..request.value.len()].copy_from_slice(request.value);
-    unsafe { KATA_SDK.write_key(app_id, request.key, &keyval) }
+    unsafe { KATA_SDK.write_key(app_id, request.key, request.value) }
 }
 
 fn delete_key_request(
@@ -291,6 +316,114 @@ fn delete_key_request(
     unsafe { KATA_SDK.delete_key(app_id, request.key) }
 }
 
+fn list_keys_request(
+    app_id: SDKAppId,
+    request_slice: &[u8],
+    reply_slice: &mut [u8],
+) -> Result<(), SDKError> {
+    let request = postcard::from_bytes::<sdk_interface::ListKeysRequest>(request_slice)
+        .map_err(deserialize_failure)?;
+    let (keys, more) = unsafe { KATA_SDK.list_keys(app_id, request.after)? };
+    let _ = postcard::to_slice(
+        &sdk_interface::ListKeysResponse {
+            keys: keys.iter().map(|k| k.as_str()).collect(),
+            more,
+        },
+        reply_slice,
+    )
+    .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn get_random_request(
+    app_id: SDKAppId,
+    request_slice: &[u8],
+    reply_slice: &mut [u8],
+) -> Result<(), SDKError> {
+    let request = postcard::from_bytes::<sdk_interface::GetRandomRequest>(request_slice)
+        .map_err(deserialize_failure)?;
+    let mut data = [0u8; RANDOM_DATA_SIZE];
+    let data = data
+        .get_mut(..request.len)
+        .ok_or(SDKError::GetRandomFailed)?;
+    unsafe { KATA_SDK.get_random(app_id, data)? };
+    let _ = postcard::to_slice(&sdk_interface::GetRandomResponse { data }, reply_slice)
+        .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn crypto_generate_request(
+    app_id: SDKAppId,
+    request_slice: &[u8],
+    reply_slice: &mut [u8],
+) -> Result<(), SDKError> {
+    let request = postcard::from_bytes::<sdk_interface::CryptoGenerateRequest>(request_slice)
+        .map_err(deserialize_failure)?;
+    let public_key = unsafe { KATA_SDK.crypto_generate(app_id, request.name, request.key_type)? };
+    let _ = postcard::to_slice(
+        &sdk_interface::CryptoGenerateResponse {
+            public_key: &public_key,
+        },
+        reply_slice,
+    )
+    .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn crypto_delete_request(
+    app_id: SDKAppId,
+    request_slice: &[u8],
+    _reply_slice: &mut [u8],
+) -> Result<(), SDKError> {
+    let request = postcard::from_bytes::<sdk_interface::CryptoDeleteRequest>(request_slice)
+        .map_err(deserialize_failure)?;
+    unsafe { KATA_SDK.crypto_delete(app_id, request.name) }
+}
+
+// Handles CryptoSign, CryptoMac, CryptoEncrypt & CryptoDecrypt.
+fn crypto_op_request(
+    op: SDKRuntimeRequest,
+    app_id: SDKAppId,
+    request_slice: &[u8],
+    reply_slice: &mut [u8],
+) -> Result<(), SDKError> {
+    let request = postcard::from_bytes::<sdk_interface::CryptoOpRequest>(request_slice)
+        .map_err(deserialize_failure)?;
+    if request.data.len() > CRYPTO_DATA_SIZE {
+        return Err(SDKError::CryptoFailed);
+    }
+    let (name, data) = (request.name, request.data);
+    let result = unsafe {
+        match op {
+            SDKRuntimeRequest::CryptoSign => KATA_SDK.crypto_sign(app_id, name, data),
+            SDKRuntimeRequest::CryptoMac => KATA_SDK.crypto_mac(app_id, name, data),
+            SDKRuntimeRequest::CryptoEncrypt => KATA_SDK.crypto_encrypt(app_id, name, data),
+            SDKRuntimeRequest::CryptoDecrypt => KATA_SDK.crypto_decrypt(app_id, name, data),
+            _ => Err(SDKError::UnknownRequest),
+        }
+    }?;
+    let _ = postcard::to_slice(&sdk_interface::CryptoOpResponse { result: &result }, reply_slice)
+        .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn crypto_verify_request(
+    app_id: SDKAppId,
+    request_slice: &[u8],
+    reply_slice: &mut [u8],
+) -> Result<(), SDKError> {
+    let request = postcard::from_bytes::<sdk_interface::CryptoVerifyRequest>(request_slice)
+        .map_err(deserialize_failure)?;
+    if request.data.len() > CRYPTO_DATA_SIZE {
+        return Err(SDKError::CryptoFailed);
+    }
+    let valid =
+        unsafe { KATA_SDK.crypto_verify(app_id, request.name, request.data, request.signature)? };
+    let _ = postcard::to_slice(&sdk_interface::CryptoVerifyResponse { valid }, reply_slice)
+        .map_err(serialize_failure)?;
+    Ok(())
+}
+
 // SDKManager RPC handling; these arrive via CAmkES so have a C linkage.
 
 #[no_mangle]
@@ -15,12 +15,15 @@
 #![cfg_attr(not(test), no_std)]
 #![feature(build_hasher_simple_hash_one)]
 
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use kata_os_common::camkes::seL4_CPath;
 use kata_os_common::sel4_sys;
 use kata_sdk_manager::SDKManagerError;
 use kata_sdk_manager::SDKManagerInterface;
 use sdk_interface::error::SDKError;
-use sdk_interface::KeyValueData;
+use sdk_interface::CryptoKeyType;
 use sdk_interface::SDKAppId;
 use sdk_interface::SDKRuntimeInterface;
 use spin::Mutex;
@@ -82,7 +85,7 @@ impl SDKRuntimeInterface for KataSDKRuntime {
             .unwrap()
             .read_key(app_id, key, keyval)
     }
-    fn write_key(&self, app_id: SDKAppId, key: &str, value: &KeyValueData) -> Result<(), SDKError> {
+    fn write_key(&self, app_id: SDKAppId, key: &str, value: &[u8]) -> Result<(), SDKError> {
         self.runtime
             .lock()
             .as_ref()
@@ -96,4 +99,92 @@ impl SDKRuntimeInterface for KataSDKRuntime {
             .unwrap()
             .delete_key(app_id, key)
     }
+    fn list_keys(
+        &self,
+        app_id: SDKAppId,
+        after: Option<&str>,
+    ) -> Result<(Vec<String>, bool), SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .list_keys(app_id, after)
+    }
+    fn get_random(&self, app_id: SDKAppId, buf: &mut [u8]) -> Result<(), SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .get_random(app_id, buf)
+    }
+    fn crypto_generate(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        key_type: CryptoKeyType,
+    ) -> Result<Vec<u8>, SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .crypto_generate(app_id, name, key_type)
+    }
+    fn crypto_delete(&self, app_id: SDKAppId, name: &str) -> Result<(), SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .crypto_delete(app_id, name)
+    }
+    fn crypto_sign(&self, app_id: SDKAppId, name: &str, data: &[u8]) -> Result<Vec<u8>, SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .crypto_sign(app_id, name, data)
+    }
+    fn crypto_verify(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .crypto_verify(app_id, name, data, signature)
+    }
+    fn crypto_mac(&self, app_id: SDKAppId, name: &str, data: &[u8]) -> Result<Vec<u8>, SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .crypto_mac(app_id, name, data)
+    }
+    fn crypto_encrypt(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .crypto_encrypt(app_id, name, data)
+    }
+    fn crypto_decrypt(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SDKError> {
+        self.runtime
+            .lock()
+            .as_ref()
+            .unwrap()
+            .crypto_decrypt(app_id, name, data)
+    }
 }
@@ -12,25 +12,54 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::hash::BuildHasher;
+use core::mem::size_of;
+use core::ptr;
 use hashbrown::HashMap;
 use kata_os_common::camkes::seL4_CPath;
+use kata_os_common::copyregion::CopyRegion;
 use kata_os_common::cspace_slot::CSpaceSlot;
 use kata_os_common::sel4_sys;
 use kata_sdk_manager::SDKManagerError;
 use kata_sdk_manager::SDKManagerInterface;
+use kata_security_interface::kata_security_crypto_decrypt;
+use kata_security_interface::kata_security_crypto_delete;
+use kata_security_interface::kata_security_crypto_encrypt;
+use kata_security_interface::kata_security_crypto_generate;
+use kata_security_interface::kata_security_crypto_mac;
+use kata_security_interface::kata_security_crypto_sign;
+use kata_security_interface::kata_security_crypto_verify;
 use kata_security_interface::kata_security_delete_key;
+use kata_security_interface::kata_security_get_random;
+use kata_security_interface::kata_security_list_keys;
 use kata_security_interface::kata_security_read_key;
 use kata_security_interface::kata_security_write_key;
+use kata_security_interface::CryptoKeyType as SecurityKeyType;
+use kata_security_interface::SecurityRequestError;
 use log::{error, info};
 use sdk_interface::error::SDKError;
-use sdk_interface::KeyValueData;
+use sdk_interface::CryptoKeyType;
 use sdk_interface::SDKAppId;
 use sdk_interface::SDKRuntimeInterface;
 use smallstr::SmallString;
 
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_CapRights;
+use sel4_sys::seL4_PageBits;
+use sel4_sys::seL4_Word;
+
+const PAGE_SIZE: usize = 1 << seL4_PageBits;
+
+extern "C" {
+    // Copyregion for passing large key values to/from SecurityCoordinator.
+    static mut KEY_VALUE: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
+}
+
+fn key_value_region() -> CopyRegion {
+    unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) }
+}
 
 // App capacity before spillover to the heap; should be the max concurrent
 // started apps. Set very small because we expect, at least initially, that
@@ -55,6 +84,15 @@ impl SDKRuntimeState {
     }
 }
 
+// Maps a SecurityCoordinator error for a crypto request to an SDKError.
+fn crypto_error(err: SecurityRequestError) -> SDKError {
+    match err {
+        SecurityRequestError::SreKeyNotFound => SDKError::CryptoKeyNotFound,
+        SecurityRequestError::SreQuotaExceeded => SDKError::QuotaExceeded,
+        _ => SDKError::CryptoFailed,
+    }
+}
+
 /// Kata OS SDK support for third-party applications, Rust core.
 ///
 /// This is the actual Rust implementation of the SDK runtime component. Here's
@@ -160,21 +198,22 @@ impl SDKRuntimeInterface for SDKRuntime {
         keyval: &'a mut [u8],
     ) -> Result<&'a [u8], SDKError> {
         match self.apps.get(&app_id) {
-            Some(app) => {
-                kata_security_read_key(&app.id, key, keyval)
-                    .map_err(|_| SDKError::ReadKeyFailed)?; // XXX
-                Ok(keyval)
-            }
+            Some(app) => kata_security_read_key(&app.id, key, keyval, &mut key_value_region())
+                .map_err(|_| SDKError::ReadKeyFailed), // XXX
             None => Err(SDKError::InvalidBadge),
         }
     }
 
     /// Writes |value| for the specified |key| in the app's private key-value store.
-    fn write_key(&self, app_id: SDKAppId, key: &str, value: &KeyValueData) -> Result<(), SDKError> {
+    fn write_key(&self, app_id: SDKAppId, key: &str, value: &[u8]) -> Result<(), SDKError> {
         match self.apps.get(&app_id) {
             Some(app) => {
-                kata_security_write_key(&app.id, key, value)
-                    .map_err(|_| SDKError::WriteKeyFailed)?; // XXX
+                kata_security_write_key(&app.id, key, value, &mut key_value_region()).map_err(
+                    |e| match e {
+                        SecurityRequestError::SreQuotaExceeded => SDKError::QuotaExceeded,
+                        _ => SDKError::WriteKeyFailed, // XXX
+                    },
+                )?;
                 Ok(())
             }
             None => Err(SDKError::InvalidBadge),
@@ -191,4 +230,115 @@ impl SDKRuntimeInterface for SDKRuntime {
             None => Err(SDKError::InvalidBadge),
         }
     }
+
+    /// Returns a page of the keys in the app's private key-value store.
+    fn list_keys(
+        &self,
+        app_id: SDKAppId,
+        after: Option<&str>,
+    ) -> Result<(Vec<String>, bool), SDKError> {
+        match self.apps.get(&app_id) {
+            // NB: the bundle id comes from the badge so an app can only
+            //   list its own keys
+            Some(app) => {
+                kata_security_list_keys(&app.id, after).map_err(|_| SDKError::ListKeysFailed)
+            }
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// Fills |buf| with random bytes from the SecurityCoordinator.
+    fn get_random(&self, app_id: SDKAppId, buf: &mut [u8]) -> Result<(), SDKError> {
+        match self.apps.get(&app_id) {
+            Some(_) => kata_security_get_random(buf).map_err(|_| SDKError::GetRandomFailed),
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// Generates a key held for the app; the key material stays in the
+    /// SecurityCoordinator.
+    fn crypto_generate(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        key_type: CryptoKeyType,
+    ) -> Result<Vec<u8>, SDKError> {
+        let key_type = match key_type {
+            CryptoKeyType::Ed25519 => SecurityKeyType::Ed25519,
+            CryptoKeyType::HmacSha256 => SecurityKeyType::HmacSha256,
+            CryptoKeyType::Aes256GcmSiv => SecurityKeyType::Aes256GcmSiv,
+        };
+        match self.apps.get(&app_id) {
+            Some(app) => {
+                kata_security_crypto_generate(&app.id, name, key_type).map_err(crypto_error)
+            }
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// Deletes one of the app's keys.
+    fn crypto_delete(&self, app_id: SDKAppId, name: &str) -> Result<(), SDKError> {
+        match self.apps.get(&app_id) {
+            Some(app) => kata_security_crypto_delete(&app.id, name).map_err(crypto_error),
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// Signs |data| with one of the app's keys.
+    fn crypto_sign(&self, app_id: SDKAppId, name: &str, data: &[u8]) -> Result<Vec<u8>, SDKError> {
+        match self.apps.get(&app_id) {
+            Some(app) => kata_security_crypto_sign(&app.id, name, data).map_err(crypto_error),
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// Checks a signature or MAC made with one of the app's keys.
+    fn crypto_verify(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SDKError> {
+        match self.apps.get(&app_id) {
+            Some(app) => {
+                kata_security_crypto_verify(&app.id, name, data, signature).map_err(crypto_error)
+            }
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// MACs |data| with one of the app's keys.
+    fn crypto_mac(&self, app_id: SDKAppId, name: &str, data: &[u8]) -> Result<Vec<u8>, SDKError> {
+        match self.apps.get(&app_id) {
+            Some(app) => kata_security_crypto_mac(&app.id, name, data).map_err(crypto_error),
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// Encrypts |data| with one of the app's keys.
+    fn crypto_encrypt(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SDKError> {
+        match self.apps.get(&app_id) {
+            Some(app) => kata_security_crypto_encrypt(&app.id, name, data).map_err(crypto_error),
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
+
+    /// Decrypts |data| with one of the app's keys.
+    fn crypto_decrypt(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SDKError> {
+        match self.apps.get(&app_id) {
+            Some(app) => kata_security_crypto_decrypt(&app.id, name, data).map_err(crypto_error),
+            None => Err(SDKError::InvalidBadge),
+        }
+    }
 }
@@ -25,6 +25,11 @@ pub enum SDKError {
     ReadKeyFailed,
     WriteKeyFailed,
     DeleteKeyFailed,
+    ListKeysFailed,
+    QuotaExceeded,
+    GetRandomFailed,
+    CryptoFailed,
+    CryptoKeyNotFound,
     MapPageFailed,
     UnknownRequest,
     UnknownResponse,
@@ -47,6 +52,11 @@ pub enum SDKRuntimeError {
     SDKReadKeyFailed,
     SDKWriteKeyFailed,
     SDKDeleteKeyFailed,
+    SDKListKeysFailed,
+    SDKQuotaExceeded,
+    SDKGetRandomFailed,
+    SDKCryptoFailed,
+    SDKCryptoKeyNotFound,
     SDKMapPageFailed,
     SDKUnknownRequest,
     SDKUnknownResponse,
@@ -63,6 +73,11 @@ impl From<SDKError> for SDKRuntimeError {
             SDKError::ReadKeyFailed => SDKRuntimeError::SDKReadKeyFailed,
             SDKError::WriteKeyFailed => SDKRuntimeError::SDKWriteKeyFailed,
             SDKError::DeleteKeyFailed => SDKRuntimeError::SDKDeleteKeyFailed,
+            SDKError::ListKeysFailed => SDKRuntimeError::SDKListKeysFailed,
+            SDKError::QuotaExceeded => SDKRuntimeError::SDKQuotaExceeded,
+            SDKError::GetRandomFailed => SDKRuntimeError::SDKGetRandomFailed,
+            SDKError::CryptoFailed => SDKRuntimeError::SDKCryptoFailed,
+            SDKError::CryptoKeyNotFound => SDKRuntimeError::SDKCryptoKeyNotFound,
             SDKError::MapPageFailed => SDKRuntimeError::SDKMapPageFailed,
             SDKError::UnknownRequest => SDKRuntimeError::SDKUnknownRequest,
             SDKError::UnknownResponse => SDKRuntimeError::SDKUnknownResponse,
@@ -89,6 +104,11 @@ impl From<SDKRuntimeError> for Result<(), SDKError> {
             SDKRuntimeError::SDKReadKeyFailed => Err(SDKError::ReadKeyFailed),
             SDKRuntimeError::SDKWriteKeyFailed => Err(SDKError::WriteKeyFailed),
             SDKRuntimeError::SDKDeleteKeyFailed => Err(SDKError::DeleteKeyFailed),
+            SDKRuntimeError::SDKListKeysFailed => Err(SDKError::ListKeysFailed),
+            SDKRuntimeError::SDKQuotaExceeded => Err(SDKError::QuotaExceeded),
+            SDKRuntimeError::SDKGetRandomFailed => Err(SDKError::GetRandomFailed),
+            SDKRuntimeError::SDKCryptoFailed => Err(SDKError::CryptoFailed),
+            SDKRuntimeError::SDKCryptoKeyNotFound => Err(SDKError::CryptoKeyNotFound),
             SDKRuntimeError::SDKMapPageFailed => Err(SDKError::DeleteKeyFailed),
             SDKRuntimeError::SDKUnknownRequest => Err(SDKError::UnknownRequest),
             SDKRuntimeError::SDKUnknownResponse => Err(SDKError::UnknownResponse),
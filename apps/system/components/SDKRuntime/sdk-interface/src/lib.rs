@@ -16,11 +16,15 @@
 
 #![cfg_attr(not(test), no_std)]
 
+extern crate alloc;
+
 pub mod error;
 
 pub use error::SDKError;
 pub use error::SDKRuntimeError;
 
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::{Deserialize, Serialize};
 
@@ -29,6 +33,8 @@ use sel4_sys::seL4_Call;
 use sel4_sys::seL4_MessageInfo;
 use sel4_sys::seL4_PageBits;
 use sel4_sys::seL4_SetCap;
+use sel4_sys::seL4_SetMR;
+use sel4_sys::seL4_Word;
 
 const PAGE_SIZE: usize = 1 << seL4_PageBits;
 
@@ -47,8 +53,12 @@ extern "C" {
 // these against being able to handle large amounts of data.
 // XXX do sensor frames need to be passed & are they too big?
 
+// Requests and replies share the frame; the size of the serialized request
+// is passed in the first message register so the server can split the
+// frame accordingly (e.g. a large key value in either direction).
+// NB: KEY_VALUE_DATA_SIZE is chosen so a max-size value plus key fits.
 // pub for server-side logic
-pub const SDKRUNTIME_REQUEST_DATA_SIZE: usize = PAGE_SIZE / 2;
+pub const SDKRUNTIME_PARAMS_SIZE: usize = PAGE_SIZE;
 
 /// Application identity derived from seL4 Endpoint badge setup when
 /// the application is started by ProcessManager.
@@ -57,9 +67,10 @@ pub const SDKRUNTIME_REQUEST_DATA_SIZE: usize = PAGE_SIZE / 2;
 ///     on 64-bit platforms these are 64-bits.
 pub type SDKAppId = usize;
 
-// TODO(sleffler): temp constraint on value part of key-value pairs
+/// Max size of the value part of key-value pairs. Values are stored with
+/// their actual length; KeyValueData is a buffer large enough for any value.
 // TOOD(sleffler): dup's security coordinator but we don't want a dependency
-pub const KEY_VALUE_DATA_SIZE: usize = 100;
+pub const KEY_VALUE_DATA_SIZE: usize = 3 * 1024;
 pub type KeyValueData = [u8; KEY_VALUE_DATA_SIZE];
 
 /// SDKRuntimeRequest::Ping
@@ -95,6 +106,85 @@ pub struct DeleteKeyRequest<'a> {
     pub key: &'a str,
 }
 
+/// SDKRuntimeRequest::ListKeys
+#[derive(Serialize, Deserialize)]
+pub struct ListKeysRequest<'a> {
+    pub after: Option<&'a str>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ListKeysResponse<'a> {
+    #[serde(borrow)]
+    pub keys: Vec<&'a str>,
+    pub more: bool,
+}
+
+/// SDKRuntimeRequest::GetRandom
+#[derive(Serialize, Deserialize)]
+pub struct GetRandomRequest {
+    pub len: usize, // NB: at most RANDOM_DATA_SIZE
+}
+#[derive(Serialize, Deserialize)]
+pub struct GetRandomResponse<'a> {
+    pub data: &'a [u8],
+}
+
+/// Max random bytes returned by one GetRandom request; sdk_get_random
+/// issues multiple requests for larger buffers.
+pub const RANDOM_DATA_SIZE: usize = 1024;
+
+/// Types of keys held for an application by the SecurityCoordinator.
+/// The key material never leaves the SecurityCoordinator.
+// TODO(sleffler): dup's security coordinator but we don't want a dependency
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CryptoKeyType {
+    Ed25519,      // Sign & verify
+    HmacSha256,   // MAC & verify
+    Aes256GcmSiv, // Encrypt & decrypt
+}
+
+/// Max bytes of data passed to a crypto operation.
+pub const CRYPTO_DATA_SIZE: usize = 1024;
+
+/// SDKRuntimeRequest::CryptoGenerate
+#[derive(Serialize, Deserialize)]
+pub struct CryptoGenerateRequest<'a> {
+    pub name: &'a str,
+    pub key_type: CryptoKeyType,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CryptoGenerateResponse<'a> {
+    pub public_key: &'a [u8],
+}
+
+/// SDKRuntimeRequest::CryptoDelete
+#[derive(Serialize, Deserialize)]
+pub struct CryptoDeleteRequest<'a> {
+    pub name: &'a str,
+}
+
+/// SDKRuntimeRequest::CryptoSign, CryptoMac, CryptoEncrypt & CryptoDecrypt
+#[derive(Serialize, Deserialize)]
+pub struct CryptoOpRequest<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+#[derive(Serialize, Deserialize)]
+pub struct CryptoOpResponse<'a> {
+    pub result: &'a [u8],
+}
+
+/// SDKRuntimeRequest::CryptoVerify
+#[derive(Serialize, Deserialize)]
+pub struct CryptoVerifyRequest<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+    pub signature: &'a [u8],
+}
+#[derive(Serialize, Deserialize)]
+pub struct CryptoVerifyResponse {
+    pub valid: bool,
+}
+
 /// SDKRequest token sent over the seL4 IPC interface. We need repr(seL4_Word)
 /// but cannot use that so use the implied usize type instead.
 #[repr(usize)]
@@ -104,8 +194,19 @@ pub enum SDKRuntimeRequest {
     Log,      // Log message: [msg: &str]
 
     ReadKey,   // Read key: [key: &str, &mut [u8]] -> value: &[u8]
-    WriteKey,  // Write key: [key: &str, value: &KeyValueData]
+    WriteKey,  // Write key: [key: &str, value: &[u8]]
     DeleteKey, // Delete key: [key: &str]
+    ListKeys,  // List keys: [after: Option<&str>] -> keys: Vec<&str>, more: bool
+
+    GetRandom, // Random bytes: [len: usize] -> data: &[u8]
+
+    CryptoGenerate, // Generate key: [name: &str, key_type] -> public_key: &[u8]
+    CryptoDelete,   // Delete key: [name: &str]
+    CryptoSign,     // Sign: [name: &str, data: &[u8]] -> signature: &[u8]
+    CryptoVerify,   // Verify: [name: &str, data: &[u8], signature: &[u8]] -> valid: bool
+    CryptoMac,      // MAC: [name: &str, data: &[u8]] -> mac: &[u8]
+    CryptoEncrypt,  // Encrypt: [name: &str, data: &[u8]] -> ciphertext: &[u8]
+    CryptoDecrypt,  // Decrypt: [name: &str, data: &[u8]] -> plaintext: &[u8]
 }
 
 /// Rust interface for the SDKRuntime.
@@ -127,7 +228,7 @@ pub trait SDKRuntimeInterface {
     fn log(&self, app_id: SDKAppId, msg: &str) -> Result<(), SDKError>;
 
     /// Returns any value for the specified |key| in the app's  private key-value store.
-    /// Data are written to |keyval| and returned as a slice.
+    /// Data are written to |keyval| and returned as a slice of the value's length.
     fn read_key<'a>(
         &self,
         app_id: SDKAppId,
@@ -136,16 +237,72 @@ pub trait SDKRuntimeInterface {
     ) -> Result<&'a [u8], SDKError>;
 
     /// Writes |value| for the specified |key| in the app's private key-value store.
-    fn write_key(&self, app_id: SDKAppId, key: &str, value: &KeyValueData) -> Result<(), SDKError>;
+    fn write_key(&self, app_id: SDKAppId, key: &str, value: &[u8]) -> Result<(), SDKError>;
 
     /// Deletes the specified |key| in the app's private key-value store.
     fn delete_key(&self, app_id: SDKAppId, key: &str) -> Result<(), SDKError>;
+
+    /// Returns one page of the keys (in sorted order) that follow |after| in
+    /// the app's private key-value store, and whether more keys remain.
+    fn list_keys(
+        &self,
+        app_id: SDKAppId,
+        after: Option<&str>,
+    ) -> Result<(Vec<String>, bool), SDKError>;
+
+    /// Fills |buf| (at most RANDOM_DATA_SIZE bytes) with random bytes.
+    fn get_random(&self, app_id: SDKAppId, buf: &mut [u8]) -> Result<(), SDKError>;
+
+    /// Generates a |key_type| key |name| held for the app and returns the
+    /// public key (empty for symmetric keys).
+    fn crypto_generate(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        key_type: CryptoKeyType,
+    ) -> Result<Vec<u8>, SDKError>;
+
+    /// Deletes the app's key |name|.
+    fn crypto_delete(&self, app_id: SDKAppId, name: &str) -> Result<(), SDKError>;
+
+    /// Returns the signature of |data| made with the app's Ed25519 key |name|.
+    fn crypto_sign(&self, app_id: SDKAppId, name: &str, data: &[u8]) -> Result<Vec<u8>, SDKError>;
+
+    /// Returns whether |signature| is a signature (or MAC) of |data| made
+    /// with the app's key |name|.
+    fn crypto_verify(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SDKError>;
+
+    /// Returns the MAC of |data| made with the app's HMAC key |name|.
+    fn crypto_mac(&self, app_id: SDKAppId, name: &str, data: &[u8]) -> Result<Vec<u8>, SDKError>;
+
+    /// Returns |data| encrypted with the app's AES key |name|.
+    fn crypto_encrypt(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SDKError>;
+
+    /// Returns the plaintext of |data| encrypted with the app's AES key |name|.
+    fn crypto_decrypt(
+        &self,
+        app_id: SDKAppId,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SDKError>;
 }
 
 /// Rust client-side request processing. Note there is no CAmkES stub to
 /// call; everything is done here. A single page frame is attached to the
-/// IPC buffer with request parameters in the first half and return values
-/// in the second half. Requests must have an SDKRequestHeader written to
+/// IPC buffer with request parameters at the front and return values in
+/// the remainder; the size of the request parameters is passed in the first
+/// message register. Requests must have an SDKRequestHeader written to
 /// the label field of the MessageInfo. Responses must have an SDKRuntimeError
 /// written to the label field of the reply. For the moment this uses
 /// postcard for serde work; this may change in the future (e.g. to flatbuffers).
@@ -164,26 +321,29 @@ fn sdk_request<'a, S: Serialize, D: Deserialize<'a>>(
     request: SDKRuntimeRequest,
     request_args: &S,
 ) -> Result<D, SDKRuntimeError> {
-    let params_slice = unsafe { core::slice::from_raw_parts_mut(KATA_SDK_PARAMS, PAGE_SIZE) };
-
-    // NB: server-side must do the same split
-    let (request_slice, reply_slice) = params_slice.split_at_mut(SDKRUNTIME_REQUEST_DATA_SIZE);
-    reply_slice.fill(0); // XXX paranoid, could zero-pad request too
+    let params_slice =
+        unsafe { core::slice::from_raw_parts_mut(KATA_SDK_PARAMS, SDKRUNTIME_PARAMS_SIZE) };
 
     // Encode request arguments.
-    let _ = postcard::to_slice(request_args, request_slice)
-        .map_err(|_| SDKRuntimeError::SDKSerializeFailed)?;
+    let request_len = postcard::to_slice(request_args, params_slice)
+        .map_err(|_| SDKRuntimeError::SDKSerializeFailed)?
+        .len();
+
+    // NB: server-side must do the same split
+    let (_, reply_slice) = params_slice.split_at_mut(request_len);
+    reply_slice.fill(0); // XXX paranoid
 
     // Attach params & call the SDKRuntime; then wait (block) for a reply.
     unsafe {
         seL4_SetCap(0, KATA_SDK_FRAME);
+        seL4_SetMR(0, request_len as seL4_Word);
         let info = seL4_Call(
             KATA_SDK_ENDPOINT,
             seL4_MessageInfo::new(
                 /*label=*/ request.into(),
                 /*capsUnrapped=*/ 0,
                 /*extraCaps=*/ 1,
-                /*length=*/ 0,
+                /*length=*/ 1,
             ),
         );
         seL4_SetCap(0, 0);
@@ -218,7 +378,8 @@ pub fn sdk_log(msg: &str) -> Result<(), SDKRuntimeError> {
     )
 }
 
-/// Rust client-side wrapper for the read key method.
+/// Rust client-side wrapper for the read key method. The value is
+/// copied to |keyval| and the returned slice has the length written.
 // TODO(sleffler): _mut variant?
 #[inline]
 #[allow(dead_code)]
@@ -227,6 +388,9 @@ pub fn sdk_read_key<'a>(key: &str, keyval: &'a mut [u8]) -> Result<&'a [u8], SDK
         SDKRuntimeRequest::ReadKey,
         &ReadKeyRequest { key },
     )?;
+    let keyval = keyval
+        .get_mut(..response.value.len())
+        .ok_or(SDKRuntimeError::SDKReadKeyFailed)?;
     keyval.copy_from_slice(response.value);
     Ok(keyval)
 }
@@ -244,3 +408,118 @@ pub fn sdk_write_key(key: &str, value: &[u8]) -> Result<(), SDKRuntimeError> {
 pub fn sdk_delete_key(key: &str) -> Result<(), SDKRuntimeError> {
     sdk_request::<DeleteKeyRequest, ()>(SDKRuntimeRequest::DeleteKey, &DeleteKeyRequest { key })
 }
+
+/// Rust client-side wrapper for the list keys method. Returns one page of
+/// keys that follow |after| (all keys if None) and whether more remain;
+/// pass the last key returned to get the next page.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_list_keys(after: Option<&str>) -> Result<(Vec<String>, bool), SDKRuntimeError> {
+    let response = sdk_request::<ListKeysRequest, ListKeysResponse>(
+        SDKRuntimeRequest::ListKeys,
+        &ListKeysRequest { after },
+    )?;
+    Ok((response.keys.iter().map(|k| k.to_string()).collect(), response.more))
+}
+
+/// Rust client-side wrapper for the get random method. Fills |buf| with
+/// random bytes drawn from the SecurityCoordinator's DRBG.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_get_random(buf: &mut [u8]) -> Result<(), SDKRuntimeError> {
+    for chunk in buf.chunks_mut(RANDOM_DATA_SIZE) {
+        let response = sdk_request::<GetRandomRequest, GetRandomResponse>(
+            SDKRuntimeRequest::GetRandom,
+            &GetRandomRequest { len: chunk.len() },
+        )?;
+        if response.data.len() != chunk.len() {
+            return Err(SDKRuntimeError::SDKGetRandomFailed);
+        }
+        chunk.copy_from_slice(response.data);
+    }
+    Ok(())
+}
+
+/// Rust client-side wrapper for the crypto generate method. Returns the
+/// public key (empty for symmetric keys).
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_crypto_generate(
+    name: &str,
+    key_type: CryptoKeyType,
+) -> Result<Vec<u8>, SDKRuntimeError> {
+    let response = sdk_request::<CryptoGenerateRequest, CryptoGenerateResponse>(
+        SDKRuntimeRequest::CryptoGenerate,
+        &CryptoGenerateRequest { name, key_type },
+    )?;
+    Ok(response.public_key.to_vec())
+}
+
+/// Rust client-side wrapper for the crypto delete method.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_crypto_delete(name: &str) -> Result<(), SDKRuntimeError> {
+    sdk_request::<CryptoDeleteRequest, ()>(
+        SDKRuntimeRequest::CryptoDelete,
+        &CryptoDeleteRequest { name },
+    )
+}
+
+// Applies key |name| to |data| (at most CRYPTO_DATA_SIZE bytes) for
+// |request| and returns the result.
+fn sdk_crypto_op(
+    request: SDKRuntimeRequest,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, SDKRuntimeError> {
+    let response =
+        sdk_request::<CryptoOpRequest, CryptoOpResponse>(request, &CryptoOpRequest { name, data })?;
+    Ok(response.result.to_vec())
+}
+
+/// Rust client-side wrapper for the crypto sign method.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_crypto_sign(name: &str, data: &[u8]) -> Result<Vec<u8>, SDKRuntimeError> {
+    sdk_crypto_op(SDKRuntimeRequest::CryptoSign, name, data)
+}
+
+/// Rust client-side wrapper for the crypto verify method.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_crypto_verify(
+    name: &str,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, SDKRuntimeError> {
+    let response = sdk_request::<CryptoVerifyRequest, CryptoVerifyResponse>(
+        SDKRuntimeRequest::CryptoVerify,
+        &CryptoVerifyRequest {
+            name,
+            data,
+            signature,
+        },
+    )?;
+    Ok(response.valid)
+}
+
+/// Rust client-side wrapper for the crypto mac method.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_crypto_mac(name: &str, data: &[u8]) -> Result<Vec<u8>, SDKRuntimeError> {
+    sdk_crypto_op(SDKRuntimeRequest::CryptoMac, name, data)
+}
+
+/// Rust client-side wrapper for the crypto encrypt method.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_crypto_encrypt(name: &str, data: &[u8]) -> Result<Vec<u8>, SDKRuntimeError> {
+    sdk_crypto_op(SDKRuntimeRequest::CryptoEncrypt, name, data)
+}
+
+/// Rust client-side wrapper for the crypto decrypt method.
+#[inline]
+#[allow(dead_code)]
+pub fn sdk_crypto_decrypt(name: &str, data: &[u8]) -> Result<Vec<u8>, SDKRuntimeError> {
+    sdk_crypto_op(SDKRuntimeRequest::CryptoDecrypt, name, data)
+}
@@ -0,0 +1,364 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kata OS attestation reports.
+//!
+//! An attestation report describes the software a device is running so
+//! a remote verifier (e.g. the fleet backend) can check it. A report is
+//! text with one record per line:
+//!
+//!   KataOS attestation report v1
+//!   system=<system version>
+//!   nonce=<hex>
+//!   bundle=<bundle id> version=<version> sha256=<hex>
+//!   ...
+//!
+//! There is one bundle line for each installed bundle, in bundle id
+//! order. The sha256 is the signed digest of the bundle's package (see
+//! kata-package-signature) so it can be matched against the digest
+//! computed when the package was signed. The nonce is chosen by the
+//! verifier to show the report is fresh. Whitespace and control
+//! characters in values are replaced with '_' so a report always parses.
+//!
+//! The report is signed with the device's Ed25519 attestation key. The
+//! signature covers the report digest: a SHA-256 over a domain-separation
+//! string followed by the report text.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use ed25519_compact::{PublicKey, Signature};
+use sha2::{Digest, Sha256};
+
+pub const REPORT_HEADER: &str = "KataOS attestation report v1";
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+pub const DIGEST_SIZE: usize = 32;
+pub type ReportDigest = [u8; DIGEST_SIZE];
+
+// Domain separation for the report digest; bump on format changes.
+const DIGEST_CONTEXT: &[u8] = b"kata-os attestation v1";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AttestationError {
+    Malformed,     // Not a well-formed report
+    UnknownSigner, // Signed with a key not in the trusted set
+    BadSignature,  // Signature does not match report contents
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BundleRecord {
+    pub bundle_id: String,
+    pub version: String,
+    pub package_digest: [u8; DIGEST_SIZE],
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    pub system_version: String,
+    pub nonce: Vec<u8>,
+    pub bundles: Vec<BundleRecord>,
+}
+impl Report {
+    pub fn new(system_version: &str, nonce: &[u8]) -> Self {
+        Report {
+            system_version: sanitize(system_version),
+            nonce: nonce.to_vec(),
+            bundles: Vec::new(),
+        }
+    }
+
+    pub fn add_bundle(
+        &mut self,
+        bundle_id: &str,
+        version: &str,
+        package_digest: &[u8; DIGEST_SIZE],
+    ) {
+        self.bundles.push(BundleRecord {
+            bundle_id: sanitize(bundle_id),
+            version: sanitize(version),
+            package_digest: *package_digest,
+        });
+    }
+
+    // Returns the report text; bundles are sorted by bundle id.
+    pub fn encode(&self) -> String {
+        let mut bundles: Vec<&BundleRecord> = self.bundles.iter().collect();
+        bundles.sort_by(|a, b| a.bundle_id.cmp(&b.bundle_id));
+        let mut text = String::from(REPORT_HEADER);
+        text.push_str("\nsystem=");
+        text.push_str(&self.system_version);
+        text.push_str("\nnonce=");
+        push_hex(&mut text, &self.nonce);
+        for bundle in bundles {
+            text.push_str("\nbundle=");
+            text.push_str(&bundle.bundle_id);
+            text.push_str(" version=");
+            text.push_str(&bundle.version);
+            text.push_str(" sha256=");
+            push_hex(&mut text, &bundle.package_digest);
+        }
+        text.push('\n');
+        text
+    }
+
+    // Parses report |text| as produced by encode.
+    pub fn parse(text: &str) -> Result<Self, AttestationError> {
+        let mut lines = text.lines();
+        if lines.next() != Some(REPORT_HEADER) {
+            return Err(AttestationError::Malformed);
+        }
+        let system_version = lines
+            .next()
+            .and_then(|l| l.strip_prefix("system="))
+            .ok_or(AttestationError::Malformed)?;
+        let nonce = lines
+            .next()
+            .and_then(|l| l.strip_prefix("nonce="))
+            .and_then(parse_hex)
+            .ok_or(AttestationError::Malformed)?;
+        let mut report = Report {
+            system_version: String::from(system_version),
+            nonce,
+            bundles: Vec::new(),
+        };
+        for line in lines {
+            report
+                .bundles
+                .push(parse_bundle(line).ok_or(AttestationError::Malformed)?);
+        }
+        Ok(report)
+    }
+}
+
+fn parse_bundle(line: &str) -> Option<BundleRecord> {
+    let mut fields = line.split(' ');
+    let bundle_id = fields.next()?.strip_prefix("bundle=")?;
+    let version = fields.next()?.strip_prefix("version=")?;
+    let digest = parse_hex(fields.next()?.strip_prefix("sha256=")?)?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(BundleRecord {
+        bundle_id: String::from(bundle_id),
+        version: String::from(version),
+        package_digest: digest.try_into().ok()?,
+    })
+}
+
+// Replaces characters that would break the report format.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn push_hex(text: &mut String, data: &[u8]) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    for b in data {
+        text.push(HEX[(b >> 4) as usize] as char);
+        text.push(HEX[(b & 0xf) as usize] as char);
+    }
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let text = text.as_bytes();
+    if text.len() & 1 != 0 {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| Some((digit(pair[0])? << 4) | digit(pair[1])?))
+        .collect()
+}
+
+// Returns the digest signed for |report|.
+pub fn report_digest(report: &[u8]) -> ReportDigest {
+    let mut hasher = Sha256::new();
+    hasher.update(DIGEST_CONTEXT);
+    hasher.update(report);
+    hasher.finalize().into()
+}
+
+// Signs |digest| with the Ed25519 key derived from |seed|. Returns the
+// public key and signature. Used by the security core (or its stand-ins).
+pub fn sign_digest(
+    seed: &[u8; ed25519_compact::Seed::BYTES],
+    digest: &ReportDigest,
+) -> ([u8; PUBLIC_KEY_SIZE], [u8; SIGNATURE_SIZE]) {
+    let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(*seed));
+    (*key_pair.pk, *key_pair.sk.sign(digest, None))
+}
+
+// Returns the Ed25519 public key derived from |seed|.
+pub fn public_key(seed: &[u8; ed25519_compact::Seed::BYTES]) -> [u8; PUBLIC_KEY_SIZE] {
+    *ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(*seed)).pk
+}
+
+// Checks |signature| of |report| was made by |public_key| and that the
+// key is one of |trusted|. On success returns the parsed report; the
+// caller must still check the nonce is the one it sent.
+pub fn verify(
+    report: &str,
+    public_key: &[u8],
+    signature: &[u8],
+    trusted: &[[u8; PUBLIC_KEY_SIZE]],
+) -> Result<Report, AttestationError> {
+    let public_key = trusted
+        .iter()
+        .find(|k| k[..] == *public_key)
+        .ok_or(AttestationError::UnknownSigner)?;
+    let signature = Signature::from_slice(signature).map_err(|_| AttestationError::BadSignature)?;
+    PublicKey::new(*public_key)
+        .verify(report_digest(report.as_bytes()), &signature)
+        .map_err(|_| AttestationError::BadSignature)?;
+    Report::parse(report)
+}
+
+// Well-known seed for the development attestation key. Reports signed
+// with this key prove nothing; it is for builds without a device key
+// and for host tests.
+pub const TEST_ATTESTATION_SEED: [u8; 32] = *b"kata-os test device attestation!";
+pub const TEST_ATTESTATION_PUBLIC_KEY: [u8; PUBLIC_KEY_SIZE] = [
+    0x68, 0x91, 0x8d, 0xbe, 0x9c, 0xf2, 0x1f, 0x98, 0x0d, 0x14, 0xc0, 0x27, 0x6e, 0x1e, 0xd2, 0xb3,
+    0x48, 0xae, 0x51, 0x5a, 0x00, 0x0b, 0x3d, 0xc0, 0xe8, 0x8b, 0x37, 0x36, 0xee, 0x8a, 0x0c, 0x76,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_key() {
+        assert_eq!(public_key(&TEST_ATTESTATION_SEED), TEST_ATTESTATION_PUBLIC_KEY);
+    }
+
+    fn report() -> Report {
+        let mut report = Report::new("1.2.3", &[0xde, 0xad, 0xbe, 0xef]);
+        report.add_bundle("zzz.last", "7", &[0x11; DIGEST_SIZE]);
+        report.add_bundle("com.example.hello", "1.0", &[0xa5; DIGEST_SIZE]);
+        report
+    }
+
+    fn sign(text: &str, seed: &[u8; 32]) -> ([u8; PUBLIC_KEY_SIZE], [u8; SIGNATURE_SIZE]) {
+        sign_digest(seed, &report_digest(text.as_bytes()))
+    }
+
+    #[test]
+    fn test_encode() {
+        let text = report().encode();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some(REPORT_HEADER));
+        assert_eq!(lines.next(), Some("system=1.2.3"));
+        assert_eq!(lines.next(), Some("nonce=deadbeef"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("bundle=com.example.hello version=1.0 sha256=a5a5"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("bundle=zzz.last version=7 sha256=1111"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut expected = report();
+        expected
+            .bundles
+            .sort_by(|a, b| a.bundle_id.cmp(&b.bundle_id));
+        assert_eq!(Report::parse(&report().encode()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let mut report = Report::new("dev build", &[]);
+        report.add_bundle("a\nbundle=evil", "1 2", &[0; DIGEST_SIZE]);
+        let parsed = Report::parse(&report.encode()).unwrap();
+        assert_eq!(parsed.system_version, "dev_build");
+        assert_eq!(parsed.bundles[0].bundle_id, "a_bundle=evil");
+        assert_eq!(parsed.bundles[0].version, "1_2");
+    }
+
+    #[test]
+    fn test_malformed() {
+        let text = report().encode();
+        for bad in [
+            String::new(),
+            text.replacen("v1", "v2", 1),
+            text.replacen("nonce=deadbeef", "nonce=deadbee", 1),
+            text.replacen("nonce=deadbeef", "nonce=xx", 1),
+            text.replacen("sha256=a5", "sha256=", 1),
+            text.replacen(" version=1.0", "", 1),
+            text + "junk\n",
+        ] {
+            assert_eq!(Report::parse(&bad).unwrap_err(), AttestationError::Malformed);
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let text = report().encode();
+        let (pk, sig) = sign(&text, &TEST_ATTESTATION_SEED);
+        let parsed = verify(&text, &pk, &sig, &[TEST_ATTESTATION_PUBLIC_KEY]).unwrap();
+        assert_eq!(parsed.nonce, [0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn test_tampered_report() {
+        let text = report().encode();
+        let (pk, sig) = sign(&text, &TEST_ATTESTATION_SEED);
+        let tampered = text.replacen("version=7", "version=8", 1);
+        assert_eq!(
+            verify(&tampered, &pk, &sig, &[TEST_ATTESTATION_PUBLIC_KEY]).unwrap_err(),
+            AttestationError::BadSignature
+        );
+    }
+
+    #[test]
+    fn test_tampered_signature() {
+        let text = report().encode();
+        let (pk, mut sig) = sign(&text, &TEST_ATTESTATION_SEED);
+        sig[0] ^= 1;
+        assert_eq!(
+            verify(&text, &pk, &sig, &[TEST_ATTESTATION_PUBLIC_KEY]).unwrap_err(),
+            AttestationError::BadSignature
+        );
+        assert_eq!(
+            verify(&text, &pk, &sig[1..], &[TEST_ATTESTATION_PUBLIC_KEY]).unwrap_err(),
+            AttestationError::BadSignature
+        );
+    }
+
+    #[test]
+    fn test_unknown_signer() {
+        let text = report().encode();
+        let (pk, sig) = sign(&text, b"some other key that is not known");
+        assert_eq!(
+            verify(&text, &pk, &sig, &[TEST_ATTESTATION_PUBLIC_KEY]).unwrap_err(),
+            AttestationError::UnknownSigner
+        );
+    }
+}
@@ -0,0 +1,313 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kata OS per-bundle cryptographic keys.
+//!
+//! A CryptoKey is a named secret held on behalf of a bundle; the secret
+//! never leaves the SecurityCoordinator (or the security core), only the
+//! results of operations with it. Every key type uses a 32-byte secret:
+//!
+//!   Ed25519       sign & verify; the secret is the Ed25519 seed
+//!   HmacSha256    MAC & verify
+//!   Aes256GcmSiv  encrypt & decrypt
+//!
+//! Keys are stored as a type byte followed by the secret (see to_bytes).
+//! Ciphertext is laid out as for kata-sealed-storage:
+//!
+//!   nonce: [u8; NONCE_SIZE]
+//!   ciphertext: [u8; plaintext.len()]
+//!   tag: [u8; TAG_SIZE]
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+use aes_gcm_siv::aead::{Aead, NewAead};
+use aes_gcm_siv::{Aes256GcmSiv, Key, Nonce};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use ed25519_compact::{KeyPair, Seed, Signature};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SECRET_SIZE: usize = 32;
+pub const KEY_BYTES_SIZE: usize = 1 + SECRET_SIZE;
+
+pub const PUBLIC_KEY_SIZE: usize = 32; // Ed25519
+pub const SIGNATURE_SIZE: usize = 64; // Ed25519
+pub const MAC_SIZE: usize = 32; // HMAC-SHA256
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+// Max keys held for one bundle.
+pub const MAX_BUNDLE_KEYS: usize = 8;
+// Max length of a key name.
+pub const MAX_NAME_LEN: usize = 64;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyType {
+    Ed25519 = 0,
+    HmacSha256,
+    Aes256GcmSiv,
+}
+impl TryFrom<u8> for KeyType {
+    type Error = CryptoError;
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(KeyType::Ed25519),
+            1 => Ok(KeyType::HmacSha256),
+            2 => Ok(KeyType::Aes256GcmSiv),
+            _ => Err(CryptoError::Malformed),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CryptoError {
+    NameInvalid,   // Key name empty or too long
+    Malformed,     // Stored key or ciphertext not well-formed
+    WrongKeyType,  // Operation not supported by the key type
+    DecryptFailed, // Wrong key or ciphertext modified
+}
+
+// Checks |name| is usable as a key name.
+pub fn check_name(name: &str) -> Result<(), CryptoError> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(CryptoError::NameInvalid);
+    }
+    Ok(())
+}
+
+pub struct CryptoKey {
+    key_type: KeyType,
+    secret: [u8; SECRET_SIZE],
+}
+impl CryptoKey {
+    // Returns a |key_type| key with |secret|; |secret| must come from a
+    // cryptographically secure source (e.g. the DRBG).
+    pub fn new(key_type: KeyType, secret: &[u8; SECRET_SIZE]) -> Self {
+        CryptoKey {
+            key_type,
+            secret: *secret,
+        }
+    }
+
+    // Decodes a key written by to_bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        if bytes.len() != KEY_BYTES_SIZE {
+            return Err(CryptoError::Malformed);
+        }
+        let mut secret = [0u8; SECRET_SIZE];
+        secret.copy_from_slice(&bytes[1..]);
+        Ok(CryptoKey {
+            key_type: KeyType::try_from(bytes[0])?,
+            secret,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; KEY_BYTES_SIZE] {
+        let mut bytes = [0u8; KEY_BYTES_SIZE];
+        bytes[0] = self.key_type as u8;
+        bytes[1..].copy_from_slice(&self.secret);
+        bytes
+    }
+
+    pub fn key_type(&self) -> KeyType { self.key_type }
+
+    fn key_pair(&self) -> Result<KeyPair, CryptoError> {
+        match self.key_type {
+            KeyType::Ed25519 => Ok(KeyPair::from_seed(Seed::new(self.secret))),
+            _ => Err(CryptoError::WrongKeyType),
+        }
+    }
+
+    fn hmac(&self) -> Result<HmacSha256, CryptoError> {
+        match self.key_type {
+            KeyType::HmacSha256 => {
+                Ok(HmacSha256::new_from_slice(&self.secret).expect("any key size"))
+            }
+            _ => Err(CryptoError::WrongKeyType),
+        }
+    }
+
+    fn cipher(&self) -> Result<Aes256GcmSiv, CryptoError> {
+        match self.key_type {
+            KeyType::Aes256GcmSiv => Ok(Aes256GcmSiv::new(Key::from_slice(&self.secret))),
+            _ => Err(CryptoError::WrongKeyType),
+        }
+    }
+
+    // Returns the public part of the key; this is empty for symmetric keys.
+    pub fn public_key(&self) -> Vec<u8> {
+        self.key_pair()
+            .map_or_else(|_| Vec::new(), |key_pair| key_pair.pk.to_vec())
+    }
+
+    // Returns the Ed25519 signature of |data|.
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        Ok(self.key_pair()?.sk.sign(data, None).to_vec())
+    }
+
+    // Returns the HMAC-SHA256 of |data|.
+    pub fn mac(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let mut mac = self.hmac()?;
+        mac.update(data);
+        Ok(mac.finalize().into_bytes().to_vec())
+    }
+
+    // Checks |signature| of |data| is a signature (Ed25519 keys) or MAC
+    // (HMAC keys) made with this key.
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, CryptoError> {
+        match self.key_type {
+            KeyType::Ed25519 => match Signature::from_slice(signature) {
+                Ok(signature) => Ok(self.key_pair()?.pk.verify(data, &signature).is_ok()),
+                Err(_) => Ok(false), // Wrong length
+            },
+            KeyType::HmacSha256 => {
+                let mut mac = self.hmac()?;
+                mac.update(data);
+                Ok(mac.verify_slice(signature).is_ok()) // NB: constant time
+            }
+            KeyType::Aes256GcmSiv => Err(CryptoError::WrongKeyType),
+        }
+    }
+
+    // Encrypts |plaintext| using |nonce|. The nonce should be random; a
+    // repeated nonce only reveals that the same plaintext was encrypted.
+    pub fn encrypt(
+        &self,
+        plaintext: &[u8],
+        nonce: &[u8; NONCE_SIZE],
+    ) -> Result<Vec<u8>, CryptoError> {
+        let ciphertext = self
+            .cipher()?
+            .encrypt(Nonce::from_slice(nonce), plaintext)
+            .map_err(|_| CryptoError::Malformed)?;
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    // Returns the plaintext of |sealed| (as returned by encrypt).
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let cipher = self.cipher()?;
+        if sealed.len() < NONCE_SIZE + TAG_SIZE {
+            return Err(CryptoError::Malformed);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::DecryptFailed)
+    }
+}
+impl Drop for CryptoKey {
+    fn drop(&mut self) { self.secret.fill(0); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; SECRET_SIZE] = [0x5a; SECRET_SIZE];
+    const NONCE: [u8; NONCE_SIZE] = [1; NONCE_SIZE];
+
+    #[test]
+    fn test_bytes_round_trip() {
+        for key_type in [KeyType::Ed25519, KeyType::HmacSha256, KeyType::Aes256GcmSiv] {
+            let key = CryptoKey::from_bytes(&CryptoKey::new(key_type, &SECRET).to_bytes()).unwrap();
+            assert_eq!(key.key_type(), key_type);
+            assert_eq!(key.secret, SECRET);
+        }
+        assert_eq!(
+            CryptoKey::from_bytes(&[0; SECRET_SIZE]).err(),
+            Some(CryptoError::Malformed)
+        );
+        let mut bytes = CryptoKey::new(KeyType::Ed25519, &SECRET).to_bytes();
+        bytes[0] = 9;
+        assert_eq!(CryptoKey::from_bytes(&bytes).err(), Some(CryptoError::Malformed));
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let key = CryptoKey::new(KeyType::Ed25519, &SECRET);
+        let signature = key.sign(b"data").unwrap();
+        assert_eq!(signature.len(), SIGNATURE_SIZE);
+        assert!(key.verify(b"data", &signature).unwrap());
+        assert!(!key.verify(b"other", &signature).unwrap());
+        assert!(!key.verify(b"data", &signature[1..]).unwrap());
+
+        // The public key checks signatures off-device.
+        let public_key = ed25519_compact::PublicKey::from_slice(&key.public_key()).unwrap();
+        assert!(public_key
+            .verify(b"data", &Signature::from_slice(&signature).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_mac_verify() {
+        let key = CryptoKey::new(KeyType::HmacSha256, &SECRET);
+        let mac = key.mac(b"data").unwrap();
+        assert_eq!(mac.len(), MAC_SIZE);
+        assert!(key.verify(b"data", &mac).unwrap());
+        assert!(!key.verify(b"other", &mac).unwrap());
+        assert!(key.public_key().is_empty());
+
+        // Different secrets give different MACs.
+        let other = CryptoKey::new(KeyType::HmacSha256, &[0; SECRET_SIZE]);
+        assert_ne!(other.mac(b"data").unwrap(), mac);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = CryptoKey::new(KeyType::Aes256GcmSiv, &SECRET);
+        let sealed = key.encrypt(b"secret value", &NONCE).unwrap();
+        assert_eq!(sealed.len(), NONCE_SIZE + 12 + TAG_SIZE);
+        assert!(!sealed.windows(6).any(|w| w == b"secret"));
+        assert_eq!(key.decrypt(&sealed).unwrap(), b"secret value");
+
+        let mut modified = sealed.clone();
+        modified[NONCE_SIZE] ^= 1;
+        assert_eq!(key.decrypt(&modified).err(), Some(CryptoError::DecryptFailed));
+        assert_eq!(key.decrypt(&sealed[..TAG_SIZE]).err(), Some(CryptoError::Malformed));
+
+        let other = CryptoKey::new(KeyType::Aes256GcmSiv, &[0; SECRET_SIZE]);
+        assert_eq!(other.decrypt(&sealed).err(), Some(CryptoError::DecryptFailed));
+    }
+
+    #[test]
+    fn test_wrong_key_type() {
+        let key = CryptoKey::new(KeyType::HmacSha256, &SECRET);
+        assert_eq!(key.sign(b"data").err(), Some(CryptoError::WrongKeyType));
+        assert_eq!(key.encrypt(b"data", &NONCE).err(), Some(CryptoError::WrongKeyType));
+        let key = CryptoKey::new(KeyType::Aes256GcmSiv, &SECRET);
+        assert_eq!(key.mac(b"data").err(), Some(CryptoError::WrongKeyType));
+        assert_eq!(
+            key.verify(b"data", &[0; MAC_SIZE]).err(),
+            Some(CryptoError::WrongKeyType)
+        );
+    }
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("signing").is_ok());
+        assert_eq!(check_name(""), Err(CryptoError::NameInvalid));
+        assert_eq!(
+            check_name(&"k".repeat(MAX_NAME_LEN + 1)),
+            Err(CryptoError::NameInvalid)
+        );
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kata OS deterministic random bit generator.
+//!
+//! HmacDrbg is the HMAC_DRBG construction of NIST SP 800-90A using
+//! HMAC-SHA256 (without prediction resistance). The generator is seeded
+//! from an external entropy source (the security core on hardware); it
+//! must be reseeded from that source every RESEED_INTERVAL requests.
+//! Given the same seed material the output is reproducible which is
+//! used for host-side testing.
+
+#![cfg_attr(not(test), no_std)]
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const OUTLEN: usize = 32; // SHA-256 output size (bytes)
+
+// Minimum entropy (bytes) for instantiate & reseed; this is the 256-bit
+// security strength of HMAC-SHA256.
+pub const MIN_ENTROPY_SIZE: usize = 32;
+
+// Max bytes returned by one generate request (SP 800-90A: 2^19 bits).
+pub const MAX_REQUEST_SIZE: usize = 1 << 16;
+
+// Requests allowed between reseeds. SP 800-90A permits 2^48; this is
+// much smaller so fresh entropy is mixed in regularly.
+pub const RESEED_INTERVAL: u64 = 1 << 16;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DrbgError {
+    EntropyTooShort, // Less than MIN_ENTROPY_SIZE bytes of entropy
+    RequestTooLarge, // More than MAX_REQUEST_SIZE bytes requested
+    ReseedRequired,  // RESEED_INTERVAL requests since the last (re)seed
+}
+
+pub struct HmacDrbg {
+    k: [u8; OUTLEN],
+    v: [u8; OUTLEN],
+    reseed_counter: u64,
+}
+impl HmacDrbg {
+    // Instantiates a generator from |entropy|, a |nonce| and an optional
+    // |personalization| string (may be empty).
+    pub fn new(entropy: &[u8], nonce: &[u8], personalization: &[u8]) -> Result<Self, DrbgError> {
+        if entropy.len() < MIN_ENTROPY_SIZE {
+            return Err(DrbgError::EntropyTooShort);
+        }
+        let mut drbg = HmacDrbg {
+            k: [0x00; OUTLEN],
+            v: [0x01; OUTLEN],
+            reseed_counter: 1,
+        };
+        drbg.update(&[entropy, nonce, personalization]);
+        Ok(drbg)
+    }
+
+    // Mixes fresh |entropy| and optional |additional| input into the state.
+    pub fn reseed(&mut self, entropy: &[u8], additional: &[u8]) -> Result<(), DrbgError> {
+        if entropy.len() < MIN_ENTROPY_SIZE {
+            return Err(DrbgError::EntropyTooShort);
+        }
+        self.update(&[entropy, additional]);
+        self.reseed_counter = 1;
+        Ok(())
+    }
+
+    // Returns whether the next generate request will be refused until
+    // the generator is reseeded.
+    pub fn needs_reseed(&self) -> bool { self.reseed_counter > RESEED_INTERVAL }
+
+    // Fills |out| with pseudo-random bytes; |additional| input (may be
+    // empty) is mixed in before and after.
+    pub fn generate(&mut self, out: &mut [u8], additional: &[u8]) -> Result<(), DrbgError> {
+        if out.len() > MAX_REQUEST_SIZE {
+            return Err(DrbgError::RequestTooLarge);
+        }
+        if self.needs_reseed() {
+            return Err(DrbgError::ReseedRequired);
+        }
+        if !additional.is_empty() {
+            self.update(&[additional]);
+        }
+        for chunk in out.chunks_mut(OUTLEN) {
+            self.v = self.hmac_v();
+            chunk.copy_from_slice(&self.v[..chunk.len()]);
+        }
+        self.update(&[additional]);
+        self.reseed_counter += 1;
+        Ok(())
+    }
+
+    // HMAC_DRBG_Update: |provided| is the concatenation of the slices.
+    fn update(&mut self, provided: &[&[u8]]) {
+        self.update_round(0x00, provided);
+        if provided.iter().any(|p| !p.is_empty()) {
+            self.update_round(0x01, provided);
+        }
+    }
+
+    fn update_round(&mut self, separator: u8, provided: &[&[u8]]) {
+        let mut mac = self.mac();
+        mac.update(&self.v);
+        mac.update(&[separator]);
+        for p in provided {
+            mac.update(p);
+        }
+        self.k = mac.finalize().into_bytes().into();
+        self.v = self.hmac_v();
+    }
+
+    // Returns HMAC(K, V).
+    fn hmac_v(&self) -> [u8; OUTLEN] {
+        let mut mac = self.mac();
+        mac.update(&self.v);
+        mac.finalize().into_bytes().into()
+    }
+
+    fn mac(&self) -> HmacSha256 { HmacSha256::new_from_slice(&self.k).expect("key length") }
+}
+impl Drop for HmacDrbg {
+    fn drop(&mut self) {
+        self.k.fill(0);
+        self.v.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn seq(range: core::ops::Range<u8>) -> Vec<u8> { range.collect() }
+
+    #[test]
+    fn test_nist_vector() {
+        // CAVP HMAC_DRBG SHA-256, no prediction resistance, no reseed,
+        // no personalization/additional input: count 0.
+        let mut drbg = HmacDrbg::new(
+            &unhex("ca851911349384bffe89de1cbdc46e6831e44d34a4fb935ee285dd14b71a7488"),
+            &unhex("659ba96c601dc69fc902940805ec0ca8"),
+            &[],
+        )
+        .unwrap();
+        let mut out = [0u8; 128];
+        drbg.generate(&mut out, &[]).unwrap();
+        drbg.generate(&mut out, &[]).unwrap();
+        assert_eq!(
+            out.to_vec(),
+            unhex(concat!(
+                "e528e9abf2dece54d47c7e75e5fe302149f817ea9fb4bee6f4199697d04d5b89",
+                "d54fbb978a15b5c443c9ec21036d2460b6f73ebad0dc2aba6e624abf07745bc1",
+                "07694bb7547bb0995f70de25d6b29e2d3011bb19d27676c07162c8b5ccde0668",
+                "961df86803482cb37ed6d5c0bb8d50cf1f50d476aa0458bdaba806f48be9dcb8",
+            ))
+        );
+    }
+
+    #[test]
+    fn test_reseed_and_additional_input() {
+        let mut drbg = HmacDrbg::new(&seq(0..32), &seq(32..48), b"kata").unwrap();
+        drbg.reseed(&seq(48..80), &[]).unwrap();
+        let mut out = [0u8; 64];
+        drbg.generate(&mut out, b"extra").unwrap();
+        assert_eq!(
+            out.to_vec(),
+            unhex(concat!(
+                "d928ff0d5b7ae3163d6b606ee9a4d4b3b1b7257b7de7a076970f33bf24b1b5cf",
+                "733eb0888905f8788e5cdb199e8547af316bcf7e19fe7d9faff3567e3a91543e",
+            ))
+        );
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let mut a = HmacDrbg::new(&seq(0..32), &[], &[]).unwrap();
+        let mut b = HmacDrbg::new(&seq(0..32), &[], &[]).unwrap();
+        let (mut out_a, mut out_b) = ([0u8; 40], [0u8; 40]);
+        a.generate(&mut out_a, &[]).unwrap();
+        b.generate(&mut out_b, &[]).unwrap();
+        assert_eq!(out_a, out_b);
+
+        // Successive requests differ.
+        b.generate(&mut out_b, &[]).unwrap();
+        assert_ne!(out_a, out_b);
+    }
+
+    #[test]
+    fn test_inputs_change_output() {
+        let generate = |drbg: &mut HmacDrbg, additional: &[u8]| {
+            let mut out = [0u8; 32];
+            drbg.generate(&mut out, additional).unwrap();
+            out
+        };
+        let base = generate(&mut HmacDrbg::new(&seq(0..32), &[], &[]).unwrap(), &[]);
+        assert_ne!(
+            base,
+            generate(&mut HmacDrbg::new(&seq(1..33), &[], &[]).unwrap(), &[])
+        );
+        assert_ne!(
+            base,
+            generate(&mut HmacDrbg::new(&seq(0..32), b"n", &[]).unwrap(), &[])
+        );
+        assert_ne!(
+            base,
+            generate(&mut HmacDrbg::new(&seq(0..32), &[], b"p").unwrap(), &[])
+        );
+        assert_ne!(
+            base,
+            generate(&mut HmacDrbg::new(&seq(0..32), &[], &[]).unwrap(), b"a")
+        );
+        let mut reseeded = HmacDrbg::new(&seq(0..32), &[], &[]).unwrap();
+        reseeded.reseed(&seq(0..32), &[]).unwrap();
+        assert_ne!(base, generate(&mut reseeded, &[]));
+    }
+
+    #[test]
+    fn test_reseed_required() {
+        let mut drbg = HmacDrbg::new(&seq(0..32), &[], &[]).unwrap();
+        let mut out = [0u8; 16];
+        drbg.reseed_counter = RESEED_INTERVAL;
+        assert!(!drbg.needs_reseed());
+        drbg.generate(&mut out, &[]).unwrap();
+        assert!(drbg.needs_reseed());
+        assert_eq!(drbg.generate(&mut out, &[]), Err(DrbgError::ReseedRequired));
+        drbg.reseed(&seq(32..64), &[]).unwrap();
+        assert!(drbg.generate(&mut out, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_bad_requests() {
+        assert_eq!(
+            HmacDrbg::new(&seq(0..31), &[], &[]).err(),
+            Some(DrbgError::EntropyTooShort)
+        );
+        let mut drbg = HmacDrbg::new(&seq(0..32), &[], &[]).unwrap();
+        assert_eq!(drbg.reseed(&[0; 8], &[]), Err(DrbgError::EntropyTooShort));
+        let mut out = vec![0u8; MAX_REQUEST_SIZE + 1];
+        assert_eq!(drbg.generate(&mut out, &[]), Err(DrbgError::RequestTooLarge));
+        assert!(drbg.generate(&mut out[..MAX_REQUEST_SIZE], &[]).is_ok());
+    }
+}
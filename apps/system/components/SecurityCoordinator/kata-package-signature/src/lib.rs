@@ -0,0 +1,498 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kata OS package signature support.
+//!
+//! A signed package is a BundleImage (a sequence of sections, each a
+//! fixed-size header followed by the section data) with a signature
+//! trailer appended after the last section:
+//!
+//!   magic: u64            SIGNATURE_MAGIC (big-endian)
+//!   public_key: [u8; 32]  Ed25519 public key of the signer
+//!   signature: [u8; 64]   Ed25519 signature of the package digest
+//!
+//! The package digest is a SHA-256 over a domain-separation string and,
+//! for each section in order, the raw section header (the "manifest"
+//! entry) followed by the SHA-256 of the section data (the member hash).
+//! Any bytes following the trailer (e.g. zero padding to a page boundary)
+//! are ignored.
+//!
+//! Verification is done incrementally with PackageVerifier so a package
+//! can be checked one page frame at a time.
+//!
+//! A package's manifest (bundle id, version, etc) is carried in a section
+//! marked SECTION_MANIFEST; being a section it is covered by the
+//! signature. Manifest sections are not loaded.
+
+#![cfg_attr(not(test), no_std)]
+
+use core::ops::Range;
+use ed25519_compact::{PublicKey, Signature};
+use sha2::{Digest, Sha256};
+
+// On-disk section format; also used by the BundleImage loader in
+// kata-proc-interface.
+pub const SECTION_MAGIC: u64 = 0x0405_1957_1014_1955;
+pub const SECTION_HEADER_SIZE: usize = 48;
+pub const SECTION_FLAGS_OFFSET: usize = 24;
+pub const SECTION_FSIZE_OFFSET: usize = 28;
+pub const SECTION_WRITE: u32 = 0x2; // Data are writeable
+pub const SECTION_MANIFEST: u32 = 0x10; // Data are the package manifest
+
+pub const SIGNATURE_MAGIC: u64 = 0x4b41_5441_5349_474e; // "KATASIGN"
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+pub const SIGNATURE_TRAILER_SIZE: usize = 8 + PUBLIC_KEY_SIZE + SIGNATURE_SIZE;
+
+pub const DIGEST_SIZE: usize = 32;
+pub type PackageDigest = [u8; DIGEST_SIZE];
+
+// Domain separation for the package digest; bump on format changes.
+const DIGEST_CONTEXT: &[u8] = b"kata-os package v1";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignatureError {
+    Unsigned,      // No signature trailer
+    Malformed,     // Truncated section or garbage where a header belongs
+    UnknownSigner, // Signed with a key not in the trusted set
+    BadSignature,  // Signature does not match package contents
+}
+
+// The fields of a section header needed to walk a package.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SectionInfo {
+    pub flags: u32,
+    pub fsize: usize, // Length of data that follows the header (bytes)
+}
+impl SectionInfo {
+    // Parses the raw section header |header|; returns None if it does
+    // not start with SECTION_MAGIC (e.g. padding or a signature trailer).
+    pub fn parse(header: &[u8; SECTION_HEADER_SIZE]) -> Option<Self> {
+        let be_u32 =
+            |offset: usize| u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap());
+        if u64::from_be_bytes(header[0..8].try_into().unwrap()) != SECTION_MAGIC {
+            return None;
+        }
+        Some(SectionInfo {
+            flags: be_u32(SECTION_FLAGS_OFFSET),
+            fsize: be_u32(SECTION_FSIZE_OFFSET) as usize,
+        })
+    }
+
+    pub fn is_write(&self) -> bool { (self.flags & SECTION_WRITE) != 0 }
+    pub fn is_manifest(&self) -> bool { (self.flags & SECTION_MANIFEST) != 0 }
+}
+
+// Returns the header of a manifest section holding |fsize| bytes.
+pub fn manifest_header(fsize: usize) -> [u8; SECTION_HEADER_SIZE] {
+    let mut header = [0u8; SECTION_HEADER_SIZE];
+    header[0..8].copy_from_slice(&SECTION_MAGIC.to_be_bytes());
+    header[SECTION_FLAGS_OFFSET..SECTION_FLAGS_OFFSET + 4]
+        .copy_from_slice(&SECTION_MANIFEST.to_be_bytes());
+    header[SECTION_FSIZE_OFFSET..SECTION_FSIZE_OFFSET + 4]
+        .copy_from_slice(&(fsize as u32).to_be_bytes());
+    header
+}
+
+// A public key trusted to sign packages.
+#[derive(Debug)]
+pub struct TrustedKey {
+    pub name: &'static str,
+    pub public_key: [u8; PUBLIC_KEY_SIZE],
+}
+
+enum State {
+    // Collecting a section header (or the signature trailer).
+    Header,
+    // Consuming section data; |usize| bytes remain.
+    Data(usize),
+    // Collecting the signature trailer.
+    Trailer,
+    // Trailer complete, remaining bytes are ignored.
+    Signed,
+    // End of sections without a trailer, remaining bytes are ignored.
+    Unsigned,
+    Failed(SignatureError),
+}
+
+// Incremental package parser & verifier. Feed the package contents
+// with update() then call verify() (or digest() to sign).
+pub struct PackageVerifier {
+    state: State,
+    package_hasher: Sha256,
+    section_hasher: Sha256,
+    buf: [u8; SIGNATURE_TRAILER_SIZE],
+    buf_len: usize,
+    sections: usize,
+    pos: usize,                     // Bytes consumed
+    manifest: Option<Range<usize>>, // Data of the first manifest section
+}
+impl Default for PackageVerifier {
+    fn default() -> Self { Self::new() }
+}
+impl PackageVerifier {
+    pub fn new() -> Self {
+        let mut package_hasher = Sha256::new();
+        package_hasher.update(DIGEST_CONTEXT);
+        PackageVerifier {
+            state: State::Header,
+            package_hasher,
+            section_hasher: Sha256::new(),
+            buf: [0u8; SIGNATURE_TRAILER_SIZE],
+            buf_len: 0,
+            sections: 0,
+            pos: 0,
+            manifest: None,
+        }
+    }
+
+    // Returns the number of complete sections processed.
+    pub fn sections(&self) -> usize { self.sections }
+
+    // Returns the location in the package of the manifest data, once the
+    // header of the first manifest section has been seen. NB: the data
+    // may not have been fed yet and |fsize| is not checked.
+    pub fn manifest_range(&self) -> Option<Range<usize>> { self.manifest.clone() }
+
+    // Appends |data| to buf until |want| bytes are collected. Returns the
+    // number of bytes consumed from |data|.
+    fn fill(&mut self, data: &[u8], want: usize) -> usize {
+        let n = core::cmp::min(want - self.buf_len, data.len());
+        self.buf[self.buf_len..self.buf_len + n].copy_from_slice(&data[..n]);
+        self.buf_len += n;
+        n
+    }
+
+    fn magic(&self) -> u64 { u64::from_be_bytes(self.buf[0..8].try_into().unwrap()) }
+
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), SignatureError> {
+        let start = self.pos;
+        let total = data.len();
+        self.pos += total;
+        while !data.is_empty() {
+            match self.state {
+                State::Header => {
+                    // Need the magic before we know what follows.
+                    let want = if self.buf_len < 8 {
+                        8
+                    } else {
+                        SECTION_HEADER_SIZE
+                    };
+                    let n = self.fill(data, want);
+                    data = &data[n..];
+                    if self.buf_len < 8 {
+                        continue;
+                    }
+                    match self.magic() {
+                        SECTION_MAGIC => {
+                            if self.buf_len < SECTION_HEADER_SIZE {
+                                continue;
+                            }
+                            let header: &[u8; SECTION_HEADER_SIZE] =
+                                self.buf[..SECTION_HEADER_SIZE].try_into().unwrap();
+                            self.package_hasher.update(header);
+                            let section = SectionInfo::parse(header).unwrap();
+                            let fsize = section.fsize;
+                            if section.is_manifest() && self.manifest.is_none() {
+                                let offset = start + (total - data.len());
+                                self.manifest = Some(offset..offset + fsize);
+                            }
+                            self.buf_len = 0;
+                            self.state = State::Data(fsize);
+                            if fsize == 0 {
+                                self.finish_section();
+                            }
+                        }
+                        SIGNATURE_MAGIC => self.state = State::Trailer,
+                        0 => self.state = State::Unsigned,
+                        _ => self.state = State::Failed(SignatureError::Malformed),
+                    }
+                }
+                State::Data(remaining) => {
+                    let n = core::cmp::min(remaining, data.len());
+                    self.section_hasher.update(&data[..n]);
+                    data = &data[n..];
+                    self.state = State::Data(remaining - n);
+                    if remaining == n {
+                        self.finish_section();
+                    }
+                }
+                State::Trailer => {
+                    let n = self.fill(data, SIGNATURE_TRAILER_SIZE);
+                    data = &data[n..];
+                    if self.buf_len == SIGNATURE_TRAILER_SIZE {
+                        self.state = State::Signed;
+                    }
+                }
+                State::Signed | State::Unsigned => break,
+                State::Failed(err) => return Err(err),
+            }
+        }
+        match self.state {
+            State::Failed(err) => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn finish_section(&mut self) {
+        let member_hash = self.section_hasher.finalize_reset();
+        self.package_hasher.update(member_hash);
+        self.sections += 1;
+        self.state = State::Header;
+    }
+
+    // Returns the digest to sign for the sections seen so far. The
+    // contents must end on a section boundary and any trailer present
+    // is ignored (so a signed package can be re-signed).
+    pub fn digest(&self) -> Result<PackageDigest, SignatureError> {
+        match self.state {
+            State::Header if self.buf_len == 0 => {}
+            State::Trailer | State::Signed | State::Unsigned => {}
+            State::Failed(err) => return Err(err),
+            _ => return Err(SignatureError::Malformed),
+        }
+        Ok(self.package_hasher.clone().finalize().into())
+    }
+
+    // Checks the signature trailer against |trusted| keys. On success
+    // returns the key that signed the package.
+    pub fn verify<'a>(&self, trusted: &'a [TrustedKey]) -> Result<&'a TrustedKey, SignatureError> {
+        match self.state {
+            State::Signed => {}
+            State::Header if self.buf_len == 0 => return Err(SignatureError::Unsigned),
+            State::Unsigned => return Err(SignatureError::Unsigned),
+            State::Failed(err) => return Err(err),
+            _ => return Err(SignatureError::Malformed),
+        }
+        let digest = self.digest()?;
+        let public_key = &self.buf[8..8 + PUBLIC_KEY_SIZE];
+        let signature = &self.buf[8 + PUBLIC_KEY_SIZE..SIGNATURE_TRAILER_SIZE];
+        let signer = trusted
+            .iter()
+            .find(|k| k.public_key == public_key)
+            .ok_or(SignatureError::UnknownSigner)?;
+        let signature =
+            Signature::from_slice(signature).map_err(|_| SignatureError::BadSignature)?;
+        PublicKey::new(signer.public_key)
+            .verify(digest, &signature)
+            .map_err(|_| SignatureError::BadSignature)?;
+        Ok(signer)
+    }
+}
+
+// Returns a signature trailer for |digest| signed with the Ed25519 key
+// derived from |seed|. Used by host tools & tests; the target only verifies.
+pub fn signature_trailer(
+    seed: &[u8; ed25519_compact::Seed::BYTES],
+    digest: &PackageDigest,
+) -> [u8; SIGNATURE_TRAILER_SIZE] {
+    let key_pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(*seed));
+    let signature = key_pair.sk.sign(digest, None);
+    let mut trailer = [0u8; SIGNATURE_TRAILER_SIZE];
+    trailer[0..8].copy_from_slice(&SIGNATURE_MAGIC.to_be_bytes());
+    trailer[8..8 + PUBLIC_KEY_SIZE].copy_from_slice(key_pair.pk.as_ref());
+    trailer[8 + PUBLIC_KEY_SIZE..].copy_from_slice(signature.as_ref());
+    trailer
+}
+
+// Returns the Ed25519 public key derived from |seed|.
+pub fn public_key(seed: &[u8; ed25519_compact::Seed::BYTES]) -> [u8; PUBLIC_KEY_SIZE] {
+    *ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(*seed)).pk
+}
+
+// Public half of the well-known development signing key (the seed is
+// known only to kata-sign-package & tests). Packages signed with it are
+// accepted only by builds that trust TEST_PUBLIC_KEY.
+pub const TEST_PUBLIC_KEY: [u8; PUBLIC_KEY_SIZE] = [
+    0xdd, 0x4d, 0x9d, 0xca, 0x5d, 0xd4, 0x28, 0x06, 0xee, 0x29, 0x57, 0xd9, 0x67, 0x7c, 0x4c, 0x03,
+    0x5a, 0x62, 0x40, 0x7f, 0x2b, 0xdc, 0xe5, 0x49, 0x60, 0x19, 0xb4, 0x6c, 0xcc, 0xf1, 0x62, 0x11,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Development signing key (also in kata-sign-package);
+    // test_public_key checks it matches TEST_PUBLIC_KEY.
+    const TEST_SIGNING_SEED: [u8; 32] = *b"kata-os test package signing key";
+
+    const TRUSTED: &[TrustedKey] = &[TrustedKey {
+        name: "test",
+        public_key: TEST_PUBLIC_KEY,
+    }];
+
+    fn section(vaddr: u64, data: &[u8]) -> Vec<u8> {
+        let mut s = Vec::new();
+        s.extend_from_slice(&SECTION_MAGIC.to_be_bytes());
+        s.extend_from_slice(&vaddr.to_be_bytes());
+        s.extend_from_slice(&0u64.to_be_bytes()); // entry
+        s.extend_from_slice(&0x1u32.to_be_bytes()); // flags
+        s.extend_from_slice(&(data.len() as u32).to_be_bytes()); // fsize
+        s.extend_from_slice(&(data.len() as u32).to_be_bytes()); // msize
+        s.extend_from_slice(&[0u8; 12]); // align, pad, crc32
+        assert_eq!(s.len(), SECTION_HEADER_SIZE);
+        s.extend_from_slice(data);
+        s
+    }
+
+    fn package() -> Vec<u8> {
+        let mut pkg = section(0x1000, b"text section contents");
+        pkg.extend(section(0x2000, &[]));
+        pkg.extend(section(0x3000, &[0x5a; 300]));
+        pkg
+    }
+
+    fn sign(pkg: &[u8], seed: &[u8; 32]) -> Vec<u8> {
+        let mut verifier = PackageVerifier::new();
+        verifier.update(pkg).unwrap();
+        let mut signed = pkg.to_vec();
+        signed.extend_from_slice(&signature_trailer(seed, &verifier.digest().unwrap()));
+        signed
+    }
+
+    // Feeds |pkg| in |chunk| size pieces followed by page padding.
+    fn verify_chunked(pkg: &[u8], chunk: usize) -> Result<&'static TrustedKey, SignatureError> {
+        let mut padded = pkg.to_vec();
+        padded.resize((pkg.len() + 4095) & !4095, 0);
+        let mut verifier = PackageVerifier::new();
+        for piece in padded.chunks(chunk) {
+            verifier.update(piece)?;
+        }
+        verifier.verify(TRUSTED)
+    }
+
+    #[test]
+    fn test_section_info() {
+        let mut pkg = section(0x1000, b"data");
+        pkg[SECTION_FLAGS_OFFSET + 3] |= SECTION_WRITE as u8;
+        let header: &[u8; SECTION_HEADER_SIZE] = pkg[..SECTION_HEADER_SIZE].try_into().unwrap();
+        let info = SectionInfo::parse(header).unwrap();
+        assert_eq!(info.fsize, 4);
+        assert!(info.is_write());
+        assert_eq!(SectionInfo::parse(&[0u8; SECTION_HEADER_SIZE]), None);
+    }
+
+    #[test]
+    fn test_manifest_range() {
+        let manifest = b"[Manifest]\nBundleId=test\n";
+        let mut pkg = package();
+        let offset = pkg.len() + SECTION_HEADER_SIZE;
+        pkg.extend_from_slice(&manifest_header(manifest.len()));
+        pkg.extend_from_slice(manifest);
+        let signed = sign(&pkg, &TEST_SIGNING_SEED);
+        for chunk in [1, 7, 48, 4096] {
+            let mut verifier = PackageVerifier::new();
+            for piece in signed.chunks(chunk) {
+                verifier.update(piece).unwrap();
+            }
+            assert_eq!(verifier.manifest_range(), Some(offset..offset + manifest.len()));
+            assert!(verifier.verify(TRUSTED).is_ok());
+        }
+
+        let mut verifier = PackageVerifier::new();
+        verifier.update(&package()).unwrap();
+        assert_eq!(verifier.manifest_range(), None);
+    }
+
+    #[test]
+    fn test_public_key() {
+        assert_eq!(public_key(&TEST_SIGNING_SEED), TEST_PUBLIC_KEY);
+    }
+
+    #[test]
+    fn test_signed() {
+        let signed = sign(&package(), &TEST_SIGNING_SEED);
+        for chunk in [1, 7, 48, 4096] {
+            assert_eq!(verify_chunked(&signed, chunk).unwrap().name, "test");
+        }
+    }
+
+    #[test]
+    fn test_unsigned() {
+        assert_eq!(
+            verify_chunked(&package(), 4096).unwrap_err(),
+            SignatureError::Unsigned
+        );
+        assert_eq!(verify_chunked(&[], 4096).unwrap_err(), SignatureError::Unsigned);
+    }
+
+    #[test]
+    fn test_tampered_data() {
+        let mut signed = sign(&package(), &TEST_SIGNING_SEED);
+        signed[SECTION_HEADER_SIZE + 3] ^= 1;
+        assert_eq!(
+            verify_chunked(&signed, 4096).unwrap_err(),
+            SignatureError::BadSignature
+        );
+    }
+
+    #[test]
+    fn test_tampered_header() {
+        let mut signed = sign(&package(), &TEST_SIGNING_SEED);
+        signed[15] ^= 1; // vaddr of first section
+        assert_eq!(
+            verify_chunked(&signed, 4096).unwrap_err(),
+            SignatureError::BadSignature
+        );
+    }
+
+    #[test]
+    fn test_tampered_signature() {
+        let mut signed = sign(&package(), &TEST_SIGNING_SEED);
+        let last = signed.len() - 1;
+        signed[last] ^= 1;
+        assert_eq!(
+            verify_chunked(&signed, 4096).unwrap_err(),
+            SignatureError::BadSignature
+        );
+    }
+
+    #[test]
+    fn test_unknown_signer() {
+        let signed = sign(&package(), b"some other key that is not known");
+        assert_eq!(
+            verify_chunked(&signed, 4096).unwrap_err(),
+            SignatureError::UnknownSigner
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let pkg = package();
+        let mut verifier = PackageVerifier::new();
+        verifier.update(&pkg[..pkg.len() - 1]).unwrap();
+        assert_eq!(verifier.digest().unwrap_err(), SignatureError::Malformed);
+        assert_eq!(verifier.verify(TRUSTED).unwrap_err(), SignatureError::Malformed);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let mut pkg = package();
+        pkg[0] ^= 0xff;
+        let mut verifier = PackageVerifier::new();
+        assert_eq!(verifier.update(&pkg).unwrap_err(), SignatureError::Malformed);
+    }
+
+    #[test]
+    fn test_resign() {
+        // Signing an already-signed package ignores the old trailer.
+        let signed = sign(&package(), b"some other key that is not known");
+        let mut verifier = PackageVerifier::new();
+        verifier.update(&signed).unwrap();
+        let mut resigned = package();
+        resigned
+            .extend_from_slice(&signature_trailer(&TEST_SIGNING_SEED, &verifier.digest().unwrap()));
+        assert!(verify_chunked(&resigned, 4096).is_ok());
+    }
+}
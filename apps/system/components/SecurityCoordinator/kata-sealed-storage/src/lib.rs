@@ -0,0 +1,224 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kata OS sealed (encrypted + authenticated) key-value data.
+//!
+//! Each bundle's values are sealed with AES-256-GCM-SIV under a key
+//! derived from the device root secret:
+//!
+//!   bundle_key = HKDF-SHA256(salt = KEY_CONTEXT, ikm = root_secret,
+//!                            info = bundle_id || 0 || epoch (u64 big-endian))
+//!
+//! The epoch is assigned when a bundle's key is created and forgotten
+//! when the bundle is uninstalled; a reinstalled bundle gets a new epoch
+//! so data left behind by an earlier install cannot be opened.
+//!
+//! A sealed value is laid out as:
+//!
+//!   nonce: [u8; NONCE_SIZE]
+//!   ciphertext: [u8; value.len()]
+//!   tag: [u8; TAG_SIZE]
+//!
+//! The key name is bound in as associated data so records cannot be
+//! swapped between keys (swapping between bundles fails because the
+//! bundle keys differ). GCM-SIV is used because nonces come from a
+//! counter that restarts at boot; a repeated nonce only reveals that
+//! the same value was written to the same key.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+use aes_gcm_siv::aead::{Aead, NewAead, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Key, Nonce};
+use alloc::vec::Vec;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+pub const ROOT_SECRET_SIZE: usize = 32;
+pub type RootSecret = [u8; ROOT_SECRET_SIZE];
+
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+// Bytes added to a value by sealing.
+pub const SEAL_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+// Domain separation for key derivation; bump on format changes.
+const KEY_CONTEXT: &[u8] = b"kata-os sealed storage v1";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SealError {
+    Malformed,  // Too short to be a sealed value
+    AuthFailed, // Wrong bundle/key or contents modified
+}
+
+// Returns the size of |value_len| bytes once sealed.
+pub const fn sealed_len(value_len: usize) -> usize { value_len + SEAL_OVERHEAD }
+
+// Per-bundle sealing key. The key material lives only as long as this
+// object; it is re-derived on demand from the root secret.
+pub struct SealingKey {
+    cipher: Aes256GcmSiv,
+}
+impl SealingKey {
+    pub fn derive(root_secret: &RootSecret, bundle_id: &str, epoch: u64) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(Some(KEY_CONTEXT), root_secret);
+        let mut key = [0u8; 32];
+        hkdf.expand_multi_info(&[bundle_id.as_bytes(), &[0], &epoch.to_be_bytes()], &mut key)
+            .expect("key length");
+        let cipher = Aes256GcmSiv::new(Key::from_slice(&key));
+        key.fill(0);
+        SealingKey { cipher }
+    }
+
+    // Seals |value| stored under |key| using |nonce|.
+    pub fn seal(&self, key: &str, value: &[u8], nonce: &[u8; NONCE_SIZE]) -> Vec<u8> {
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: value,
+                    aad: key.as_bytes(),
+                },
+            )
+            .expect("seal");
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    // Returns the value sealed under |key| in |sealed|.
+    pub fn open(&self, key: &str, sealed: &[u8]) -> Result<Vec<u8>, SealError> {
+        if sealed.len() < SEAL_OVERHEAD {
+            return Err(SealError::Malformed);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: key.as_bytes(),
+                },
+            )
+            .map_err(|_| SealError::AuthFailed)
+    }
+}
+
+// Generates nonces for sealing. Nonces are unique until the counter
+// is reset (e.g. by a reboot); see the note above on why that is ok.
+#[derive(Default)]
+pub struct NonceSequence {
+    next: u64,
+}
+impl NonceSequence {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn next_nonce(&mut self) -> [u8; NONCE_SIZE] {
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[NONCE_SIZE - 8..].copy_from_slice(&self.next.to_be_bytes());
+        self.next = self.next.wrapping_add(1);
+        nonce
+    }
+}
+
+// Well-known root secret for development builds and tests. Anything
+// sealed with this secret is readable by anyone with the source.
+pub const TEST_ROOT_SECRET: RootSecret = *b"kata-os test device root secret!";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(bundle_id: &str, epoch: u64) -> SealingKey {
+        SealingKey::derive(&TEST_ROOT_SECRET, bundle_id, epoch)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut nonces = NonceSequence::new();
+        let k = key("bundle", 0);
+        for value in [&b""[..], b"123", &[0x5a; 3 * 1024]] {
+            let sealed = k.seal("foo", value, &nonces.next_nonce());
+            assert_eq!(sealed.len(), sealed_len(value.len()));
+            assert_eq!(k.open("foo", &sealed).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_ciphertext_hides_value() {
+        let sealed = key("bundle", 0).seal("foo", b"secret value", &[0; NONCE_SIZE]);
+        assert!(!sealed.windows(6).any(|w| w == b"secret"));
+    }
+
+    #[test]
+    fn test_nonces_unique() {
+        let mut nonces = NonceSequence::new();
+        let k = key("bundle", 0);
+        let a = k.seal("foo", b"value", &nonces.next_nonce());
+        let b = k.seal("foo", b"value", &nonces.next_nonce());
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_wrong_key_name() {
+        // A record moved to a different key in the same bundle.
+        let k = key("bundle", 0);
+        let sealed = k.seal("foo", b"value", &[0; NONCE_SIZE]);
+        assert_eq!(k.open("bar", &sealed).unwrap_err(), SealError::AuthFailed);
+    }
+
+    #[test]
+    fn test_wrong_bundle() {
+        // A record moved to a different bundle.
+        let sealed = key("bundle", 0).seal("foo", b"value", &[0; NONCE_SIZE]);
+        assert_eq!(
+            key("other", 0).open("foo", &sealed).unwrap_err(),
+            SealError::AuthFailed
+        );
+    }
+
+    #[test]
+    fn test_wrong_epoch() {
+        // Data left from an earlier install of the same bundle.
+        let sealed = key("bundle", 0).seal("foo", b"value", &[0; NONCE_SIZE]);
+        assert_eq!(
+            key("bundle", 1).open("foo", &sealed).unwrap_err(),
+            SealError::AuthFailed
+        );
+    }
+
+    #[test]
+    fn test_wrong_root_secret() {
+        let sealed = key("bundle", 0).seal("foo", b"value", &[0; NONCE_SIZE]);
+        let other = SealingKey::derive(b"some other device's root secret!", "bundle", 0);
+        assert_eq!(other.open("foo", &sealed).unwrap_err(), SealError::AuthFailed);
+    }
+
+    #[test]
+    fn test_tampered() {
+        let k = key("bundle", 0);
+        let sealed = k.seal("foo", b"value", &[0; NONCE_SIZE]);
+        for i in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert_eq!(k.open("foo", &tampered).unwrap_err(), SealError::AuthFailed);
+        }
+        assert_eq!(
+            k.open("foo", &sealed[..SEAL_OVERHEAD - 1]).unwrap_err(),
+            SealError::Malformed
+        );
+    }
+}
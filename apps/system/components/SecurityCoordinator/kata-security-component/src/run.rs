@@ -18,24 +18,47 @@
 #![no_std]
 #![allow(clippy::missing_safety_doc)]
 
+extern crate alloc;
+use alloc::fmt;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr;
 use core::slice;
+use kata_memory_interface::kata_frame_alloc_in_cnode;
+use kata_memory_interface::kata_object_free_in_cnode;
 use kata_os_common::camkes::Camkes;
+use kata_os_common::copyregion::CopyRegion;
 use kata_os_common::cspace_slot::CSpaceSlot;
 use kata_os_common::sel4_sys;
+use kata_security_coordinator::access;
 use kata_security_coordinator::KATA_SECURITY;
 use kata_security_interface::*;
+use kata_timer_interface::timer_service_now_ms;
 use log::trace;
 
 use SecurityRequestError::*;
 
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_PageBits;
+use sel4_sys::seL4_Word;
+
+const PAGE_SIZE: usize = 1 << seL4_PageBits;
+
+extern "C" {
+    static mut KEY_VALUE: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
+    fn security_get_sender_id() -> seL4_Word;
+}
 
 static mut CAMKES: Camkes = Camkes::new("SecurityCoordinator");
 static mut SECURITY_RECV_SLOT: seL4_CPtr = 0;
 
 #[no_mangle]
 pub unsafe extern "C" fn pre_init() {
-    static mut HEAP_MEMORY: [u8; 8 * 1024] = [0; 8 * 1024];
+    // NB: the key-value store is held in the heap; it takes 96KiB
+    //   (see KEY_STORE_BUNDLES in kata-security-coordinator/src/storage.rs)
+    static mut HEAP_MEMORY: [u8; 128 * 1024] = [0; 128 * 1024];
     // NB: set to max; the LoggerInterface will filter
     CAMKES.pre_init(log::LevelFilter::Trace, &mut HEAP_MEMORY);
 
@@ -67,6 +90,81 @@ fn deserialize_failure(e: postcard::Error) -> SecurityRequestError {
     SreDeserializeFailed
 }
 
+// Records |event| in the audit log tagged with the badge of the
+// requesting component and the current time.
+fn audit(event: AuditEvent, bundle_id: &str, detail: &str) {
+    unsafe {
+        KATA_SECURITY.audit(AuditRecord {
+            seq: 0, // NB: assigned by the log
+            timestamp_ms: timer_service_now_ms(),
+            badge: security_get_sender_id(),
+            event,
+            bundle_id: bundle_id.to_string(),
+            detail: detail.to_string(),
+        })
+    }
+}
+
+// Returns the serialized request held in the SecurityPayload sent by
+// kata_security_request_large; a large request arrives in page frames.
+fn large_request(request_buffer: &[u8]) -> Result<Vec<u8>, SecurityRequestError> {
+    match postcard::from_bytes::<SecurityPayload>(request_buffer).map_err(deserialize_failure)? {
+        SecurityPayload::Inline(data) => Ok(data.to_vec()),
+        SecurityPayload::Frames(mut frames, len) => {
+            if len > frames.size_bytes() {
+                // NB: drop the container so the next request can receive a cap
+                unsafe { CAMKES.clear_recv_path() };
+                return Err(SreDeserializeFailed);
+            }
+            let recv_path = unsafe { CAMKES.get_current_recv_path() };
+            Camkes::debug_assert_slot_cnode("large_request", &recv_path);
+
+            // Move the container CNode so it's not clobbered; the frames
+            // belong to the client so our cap is deleted on return.
+            let container_slot = CSpaceSlot::new();
+            container_slot
+                .move_to(recv_path.0, recv_path.1, recv_path.2 as u8)
+                .map_err(|_| SreCapMoveFailed)?;
+            frames.cnode = container_slot.slot;
+
+            let mut data = vec![0u8; len];
+            let mut copy_region =
+                unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+            kata_copy_from_frames(&frames, &mut data, &mut copy_region)
+                .map_err(|_| SreDeserializeFailed)?;
+            Ok(data)
+        }
+    }
+}
+
+// Returns the serialized reply |data| to kata_security_request_large in
+// a SecurityPayload. A reply too large for the ipc buffer is returned in
+// page frames sized to fit; the client frees them.
+fn large_reply(data: &[u8], reply_buffer: &mut [u8]) -> Result<(), SecurityRequestError> {
+    if data.len() <= SECURITY_PAYLOAD_INLINE_SIZE {
+        let _ = postcard::to_slice(&SecurityPayload::Inline(data), reply_buffer)
+            .map_err(serialize_failure)?;
+        return Ok(());
+    }
+
+    let frames = kata_frame_alloc_in_cnode(data.len()).map_err(|_| SreCapAllocFailed)?;
+    let mut copy_region = unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+    if let Err(e) = kata_copy_to_frames(&frames, data, &mut copy_region)
+        .map_err(|_| SreSerializeFailed)
+        .and_then(|_| {
+            postcard::to_slice(&SecurityPayload::Frames(frames.clone(), data.len()), reply_buffer)
+                .map_err(serialize_failure)
+        })
+    {
+        let _ = kata_object_free_in_cnode(&frames);
+        return Err(e);
+    }
+    trace!("LARGE REPLY {} bytes -> {}", data.len(), frames);
+    // Cleanup allocated slot & mark cap for release after reply completes.
+    Camkes::set_reply_cap_release(frames.cnode);
+    Ok(())
+}
+
 fn echo_request(
     request_buffer: &[u8],
     reply_buffer: &mut [u8],
@@ -97,7 +195,24 @@ fn install_request(
         .map_err(|_| SecurityRequestError::SreCapMoveFailed)?; // XXX expect?
     request.set_container_cap(container_slot.release());
 
-    let bundle_id = unsafe { KATA_SECURITY.install(&request.pkg_contents) }?;
+    // NB: install takes the package only on success; a rejected package
+    //   is reclaimed here so the frames & container are not leaked
+    let bundle_id = match unsafe { KATA_SECURITY.install(&request.pkg_contents) } {
+        Ok(bundle_id) => bundle_id,
+        Err(e) => {
+            if let Err(fe) = kata_object_free_in_cnode(&request.pkg_contents) {
+                trace!("install: free of {} failed: {:?}", request.pkg_contents, fe);
+            }
+            if matches!(
+                e,
+                SrePackageUnsigned | SrePackageSignatureInvalid | SreVersionRollback
+            ) {
+                audit(AuditEvent::InstallRejected, "", &fmt::format(format_args!("{:?}", e)));
+            }
+            return Err(e);
+        }
+    };
+    audit(AuditEvent::Install, &bundle_id, "");
     let _ = postcard::to_slice(
         &InstallResponse {
             bundle_id: &bundle_id,
@@ -116,7 +231,35 @@ fn uninstall_request(
         postcard::from_bytes::<UninstallRequest>(request_buffer).map_err(deserialize_failure)?;
 
     trace!("UNINSTALL {}", request.bundle_id);
-    unsafe { KATA_SECURITY.uninstall(request.bundle_id) }
+    unsafe { KATA_SECURITY.uninstall(request.bundle_id) }?;
+    audit(AuditEvent::Uninstall, request.bundle_id, "");
+    Ok(())
+}
+
+fn allow_rollback_request(
+    request_buffer: &[u8],
+    _reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request = postcard::from_bytes::<AllowRollbackRequest>(request_buffer)
+        .map_err(deserialize_failure)?;
+
+    trace!("ALLOW ROLLBACK {}", request.bundle_id);
+    unsafe { KATA_SECURITY.allow_rollback(request.bundle_id) }?;
+    audit(AuditEvent::AllowRollback, request.bundle_id, "");
+    Ok(())
+}
+
+fn factory_reset_request() -> Result<(), SecurityRequestError> {
+    trace!("FACTORY RESET");
+    let result = unsafe { KATA_SECURITY.factory_reset() };
+    // NB: the audit log was cleared, this is the first record; a failed
+    //   reset may have erased data so it is recorded too
+    let detail = result
+        .err()
+        .map(|e| fmt::format(format_args!("{:?}", e)))
+        .unwrap_or_default();
+    audit(AuditEvent::FactoryReset, "", &detail);
+    result
 }
 
 fn size_buffer_request(
@@ -137,19 +280,17 @@ fn get_manifest_request(
     request_buffer: &[u8],
     reply_buffer: &mut [u8],
 ) -> Result<(), SecurityRequestError> {
+    let request_data = large_request(request_buffer)?;
     let request =
-        postcard::from_bytes::<GetManifestRequest>(request_buffer).map_err(deserialize_failure)?;
+        postcard::from_bytes::<GetManifestRequest>(&request_data).map_err(deserialize_failure)?;
 
     trace!("GET MANIFEST bundle_id {}", request.bundle_id);
     let manifest = unsafe { KATA_SECURITY.get_manifest(request.bundle_id) }?;
-    let _ = postcard::to_slice(
-        &GetManifestResponse {
-            manifest: &manifest,
-        },
-        reply_buffer,
-    )
+    let reply_data = postcard::to_allocvec(&GetManifestResponse {
+        manifest: &manifest,
+    })
     .map_err(serialize_failure)?;
-    Ok(())
+    large_reply(&reply_data, reply_buffer)
 }
 
 fn load_application_request(
@@ -169,9 +310,13 @@ fn load_application_request(
         reply_buffer,
     )
     .map_err(serialize_failure)?;
-    trace!("LOAD APPLICATION -> {}", bundle_frames);
+    trace!(
+        "LOAD APPLICATION -> {} ({} shared)",
+        bundle_frames.frames,
+        bundle_frames.shared_count()
+    );
     // Cleanup allocated slot & mark cap for release after reply completes.
-    Camkes::set_reply_cap_release(bundle_frames.cnode);
+    Camkes::set_reply_cap_release(bundle_frames.frames.cnode);
     Ok(())
 }
 
@@ -183,17 +328,22 @@ fn load_model_request(
         postcard::from_bytes::<LoadModelRequest>(request_buffer).map_err(deserialize_failure)?;
 
     let model_frames = unsafe { KATA_SECURITY.load_model(request.bundle_id, request.model_id) }?;
+    audit(AuditEvent::LoadModel, request.bundle_id, request.model_id);
     // TODO(sleffler): maybe rearrange to eliminate clone
     let _ = postcard::to_slice(
-        &LoadApplicationResponse {
-            bundle_frames: model_frames.clone(),
+        &LoadModelResponse {
+            model_frames: model_frames.clone(),
         },
         reply_buffer,
     )
     .map_err(serialize_failure)?;
-    trace!("LOAD MODEL -> {}", model_frames);
+    trace!(
+        "LOAD MODEL -> {} ({} shared)",
+        model_frames.frames,
+        model_frames.shared_count()
+    );
     // Cleanup allocated slot & mark cap for release after reply completes.
-    Camkes::set_reply_cap_release(model_frames.cnode);
+    Camkes::set_reply_cap_release(model_frames.frames.cnode);
     Ok(())
 }
 
@@ -206,7 +356,38 @@ fn read_key_request(
 
     trace!("READ KEY bundle_id {} key {}", request.bundle_id, request.key);
     let value = unsafe { KATA_SECURITY.read_key(request.bundle_id, request.key) }?;
-    let _ = postcard::to_slice(&ReadKeyResponse { value }, reply_buffer).map_err(serialize_failure);
+    if value.len() <= KEY_VALUE_INLINE_SIZE {
+        let _ = postcard::to_slice(
+            &ReadKeyResponse {
+                value: SecurityPayload::Inline(&value),
+            },
+            reply_buffer,
+        )
+        .map_err(serialize_failure)?;
+        return Ok(());
+    }
+
+    // Too large for the ipc buffer, return the value in page frames.
+    let frames = kata_frame_alloc_in_cnode(value.len()).map_err(|_| SreCapAllocFailed)?;
+    let mut copy_region = unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+    if let Err(e) = kata_copy_to_frames(&frames, &value, &mut copy_region)
+        .map_err(|_| SreReadFailed)
+        .and_then(|_| {
+            postcard::to_slice(
+                &ReadKeyResponse {
+                    value: SecurityPayload::Frames(frames.clone(), value.len()),
+                },
+                reply_buffer,
+            )
+            .map_err(serialize_failure)
+        })
+    {
+        let _ = kata_object_free_in_cnode(&frames);
+        return Err(e);
+    }
+    trace!("READ KEY -> {}", frames);
+    // Cleanup allocated slot & mark cap for release after reply completes.
+    Camkes::set_reply_cap_release(frames.cnode);
     Ok(())
 }
 
@@ -217,16 +398,38 @@ fn write_key_request(
     let request =
         postcard::from_bytes::<WriteKeyRequest>(request_buffer).map_err(deserialize_failure)?;
 
-    trace!(
-        "WRITE KEY bundle_id {} key {} value {:?}",
-        request.bundle_id,
-        request.key,
-        request.value
-    );
-    // NB: the serialized data are variable length so copy to convert
-    let mut keyval = [0u8; KEY_VALUE_DATA_SIZE];
-    keyval[..request.value.len()].copy_from_slice(request.value);
-    unsafe { KATA_SECURITY.write_key(request.bundle_id, request.key, &keyval) }
+    trace!("WRITE KEY bundle_id {} key {}", request.bundle_id, request.key);
+    match request.value {
+        SecurityPayload::Inline(value) => {
+            unsafe { KATA_SECURITY.write_key(request.bundle_id, request.key, value) }?
+        }
+        SecurityPayload::Frames(mut frames, len) => {
+            if len > KEY_VALUE_DATA_SIZE {
+                // NB: drop the container so the next request can receive a cap
+                unsafe { CAMKES.clear_recv_path() };
+                return Err(SreValueInvalid);
+            }
+            let recv_path = unsafe { CAMKES.get_current_recv_path() };
+            Camkes::debug_assert_slot_cnode("write_key_request", &recv_path);
+
+            // Move the container CNode so it's not clobbered; the frames
+            // belong to the client so our cap is deleted on return.
+            let container_slot = CSpaceSlot::new();
+            container_slot
+                .move_to(recv_path.0, recv_path.1, recv_path.2 as u8)
+                .map_err(|_| SreCapMoveFailed)?;
+            frames.cnode = container_slot.slot;
+
+            let mut value = vec![0u8; len];
+            let mut copy_region =
+                unsafe { CopyRegion::new(ptr::addr_of_mut!(KEY_VALUE[0]), PAGE_SIZE) };
+            kata_copy_from_frames(&frames, &mut value, &mut copy_region)
+                .map_err(|_| SreValueInvalid)?;
+            unsafe { KATA_SECURITY.write_key(request.bundle_id, request.key, &value) }?
+        }
+    }
+    audit(AuditEvent::WriteKey, request.bundle_id, request.key);
+    Ok(())
 }
 
 fn delete_key_request(
@@ -237,7 +440,191 @@ fn delete_key_request(
         postcard::from_bytes::<DeleteKeyRequest>(request_buffer).map_err(deserialize_failure)?;
 
     trace!("DELETE KEY bundle_id {} key {}", request.bundle_id, request.key);
-    unsafe { KATA_SECURITY.delete_key(request.bundle_id, request.key) }
+    unsafe { KATA_SECURITY.delete_key(request.bundle_id, request.key) }?;
+    audit(AuditEvent::DeleteKey, request.bundle_id, request.key);
+    Ok(())
+}
+
+fn list_keys_request(
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request =
+        postcard::from_bytes::<ListKeysRequest>(request_buffer).map_err(deserialize_failure)?;
+
+    trace!("LIST KEYS bundle_id {} after {:?}", request.bundle_id, request.after);
+    let (keys, more) = unsafe { KATA_SECURITY.list_keys(request.bundle_id, request.after) }?;
+    let _ = postcard::to_slice(
+        &ListKeysResponse {
+            keys: keys.iter().map(|k| k.as_str()).collect(),
+            more,
+        },
+        reply_buffer,
+    )
+    .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn get_key_usage_request(
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request =
+        postcard::from_bytes::<GetKeyUsageRequest>(request_buffer).map_err(deserialize_failure)?;
+
+    trace!("GET KEY USAGE bundle_id {}", request.bundle_id);
+    let usage = unsafe { KATA_SECURITY.get_key_usage(request.bundle_id) }?;
+    let _ = postcard::to_slice(&usage, reply_buffer).map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn get_audit_log_request(
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request_data = large_request(request_buffer)?;
+    let request =
+        postcard::from_bytes::<GetAuditLogRequest>(&request_data).map_err(deserialize_failure)?;
+
+    trace!("GET AUDIT LOG after {:?}", request.after);
+    let records = unsafe { KATA_SECURITY.get_audit_log(request.after) }?;
+    let reply_data =
+        postcard::to_allocvec(&GetAuditLogResponse { records }).map_err(serialize_failure)?;
+    large_reply(&reply_data, reply_buffer)
+}
+
+fn get_random_request(
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request =
+        postcard::from_bytes::<GetRandomRequest>(request_buffer).map_err(deserialize_failure)?;
+
+    trace!("GET RANDOM len {}", request.len);
+    if request.len > SECURITY_RANDOM_MAX {
+        return Err(SreGetRandomFailed);
+    }
+    let mut data = [0u8; SECURITY_RANDOM_MAX];
+    let data = &mut data[..request.len];
+    unsafe { KATA_SECURITY.get_random(data) }?;
+    let _ =
+        postcard::to_slice(&GetRandomResponse { data }, reply_buffer).map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn crypto_generate_request(
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request = postcard::from_bytes::<CryptoGenerateRequest>(request_buffer)
+        .map_err(deserialize_failure)?;
+
+    trace!(
+        "CRYPTO GENERATE bundle_id {} name {} key_type {:?}",
+        request.bundle_id,
+        request.name,
+        request.key_type
+    );
+    let public_key = unsafe {
+        KATA_SECURITY.crypto_generate(request.bundle_id, request.name, request.key_type)
+    }?;
+    audit(AuditEvent::CryptoGenerate, request.bundle_id, request.name);
+    let _ = postcard::to_slice(
+        &CryptoGenerateResponse {
+            public_key: &public_key,
+        },
+        reply_buffer,
+    )
+    .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn crypto_delete_request(
+    request_buffer: &[u8],
+    _reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request =
+        postcard::from_bytes::<CryptoDeleteRequest>(request_buffer).map_err(deserialize_failure)?;
+
+    trace!("CRYPTO DELETE bundle_id {} name {}", request.bundle_id, request.name);
+    unsafe { KATA_SECURITY.crypto_delete(request.bundle_id, request.name) }?;
+    audit(AuditEvent::CryptoDelete, request.bundle_id, request.name);
+    Ok(())
+}
+
+// Handles SrCryptoSign, SrCryptoMac, SrCryptoEncrypt & SrCryptoDecrypt.
+fn crypto_op_request(
+    op: SecurityRequest,
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request =
+        postcard::from_bytes::<CryptoOpRequest>(request_buffer).map_err(deserialize_failure)?;
+
+    trace!(
+        "{:?} bundle_id {} name {} len {}",
+        op,
+        request.bundle_id,
+        request.name,
+        request.data.len()
+    );
+    if request.data.len() > CRYPTO_DATA_MAX {
+        return Err(SreValueInvalid);
+    }
+    let (bundle_id, name, data) = (request.bundle_id, request.name, request.data);
+    let result = unsafe {
+        match op {
+            SecurityRequest::SrCryptoSign => KATA_SECURITY.crypto_sign(bundle_id, name, data),
+            SecurityRequest::SrCryptoMac => KATA_SECURITY.crypto_mac(bundle_id, name, data),
+            SecurityRequest::SrCryptoEncrypt => KATA_SECURITY.crypto_encrypt(bundle_id, name, data),
+            SecurityRequest::SrCryptoDecrypt => KATA_SECURITY.crypto_decrypt(bundle_id, name, data),
+            _ => Err(SreCryptoFailed),
+        }
+    }?;
+    let _ = postcard::to_slice(&CryptoOpResponse { result: &result }, reply_buffer)
+        .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn crypto_verify_request(
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request =
+        postcard::from_bytes::<CryptoVerifyRequest>(request_buffer).map_err(deserialize_failure)?;
+
+    trace!("CRYPTO VERIFY bundle_id {} name {}", request.bundle_id, request.name);
+    if request.data.len() > CRYPTO_DATA_MAX {
+        return Err(SreValueInvalid);
+    }
+    let valid = unsafe {
+        KATA_SECURITY.crypto_verify(
+            request.bundle_id,
+            request.name,
+            request.data,
+            request.signature,
+        )
+    }?;
+    let _ = postcard::to_slice(&CryptoVerifyResponse { valid }, reply_buffer)
+        .map_err(serialize_failure)?;
+    Ok(())
+}
+
+fn get_attestation_report_request(
+    request_buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<(), SecurityRequestError> {
+    let request_data = large_request(request_buffer)?;
+    let request = postcard::from_bytes::<GetAttestationReportRequest>(&request_data)
+        .map_err(deserialize_failure)?;
+
+    trace!("GET ATTESTATION REPORT nonce {:02x?}", request.nonce);
+    if request.nonce.len() > ATTESTATION_NONCE_MAX {
+        return Err(SreAttestationFailed);
+    }
+    let report = unsafe { KATA_SECURITY.get_attestation_report(request.nonce) }?;
+    let reply_data = postcard::to_allocvec(&report).map_err(serialize_failure)?;
+    large_reply(&reply_data, reply_buffer)
 }
 
 fn test_mailbox_request() -> Result<(), SecurityRequestError> {
@@ -257,12 +644,25 @@ pub unsafe extern "C" fn security_request(
     c_request_buffer: *const u8,
     c_reply_buffer: *mut SecurityReplyData,
 ) -> SecurityRequestError {
+    if !access::is_allowed(security_get_sender_id(), c_request) {
+        trace!("{:?} denied for badge {}", c_request, security_get_sender_id());
+        audit(
+            AuditEvent::AccessDenied,
+            "",
+            &fmt::format(format_args!("{:?}", c_request)),
+        );
+        // Discard any capability passed with the request.
+        CAMKES.clear_recv_path();
+        return SrePermissionDenied;
+    }
     let request_buffer = slice::from_raw_parts(c_request_buffer, c_request_buffer_len as usize);
     let reply_buffer = &mut (*c_reply_buffer)[..];
     match c_request {
         SecurityRequest::SrEcho => echo_request(request_buffer, reply_buffer),
         SecurityRequest::SrInstall => install_request(request_buffer, reply_buffer),
         SecurityRequest::SrUninstall => uninstall_request(request_buffer, reply_buffer),
+        SecurityRequest::SrAllowRollback => allow_rollback_request(request_buffer, reply_buffer),
+        SecurityRequest::SrFactoryReset => factory_reset_request(),
         SecurityRequest::SrSizeBuffer => size_buffer_request(request_buffer, reply_buffer),
         SecurityRequest::SrGetManifest => get_manifest_request(request_buffer, reply_buffer),
         SecurityRequest::SrLoadApplication => {
@@ -272,6 +672,22 @@ pub unsafe extern "C" fn security_request(
         SecurityRequest::SrReadKey => read_key_request(request_buffer, reply_buffer),
         SecurityRequest::SrWriteKey => write_key_request(request_buffer, reply_buffer),
         SecurityRequest::SrDeleteKey => delete_key_request(request_buffer, reply_buffer),
+        SecurityRequest::SrListKeys => list_keys_request(request_buffer, reply_buffer),
+        SecurityRequest::SrGetKeyUsage => get_key_usage_request(request_buffer, reply_buffer),
+        SecurityRequest::SrGetAuditLog => get_audit_log_request(request_buffer, reply_buffer),
+        SecurityRequest::SrGetRandom => get_random_request(request_buffer, reply_buffer),
+        SecurityRequest::SrCryptoGenerate => crypto_generate_request(request_buffer, reply_buffer),
+        SecurityRequest::SrCryptoDelete => crypto_delete_request(request_buffer, reply_buffer),
+        SecurityRequest::SrCryptoSign
+        | SecurityRequest::SrCryptoMac
+        | SecurityRequest::SrCryptoEncrypt
+        | SecurityRequest::SrCryptoDecrypt => {
+            crypto_op_request(c_request, request_buffer, reply_buffer)
+        }
+        SecurityRequest::SrCryptoVerify => crypto_verify_request(request_buffer, reply_buffer),
+        SecurityRequest::SrGetAttestationReport => {
+            get_attestation_report_request(request_buffer, reply_buffer)
+        }
         SecurityRequest::SrTestMailbox => test_mailbox_request(),
         SecurityRequest::SrCapScan => capscan_request(),
     }
@@ -0,0 +1,111 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+
+// Returns the client component instances of |connection| in system.camkes,
+// in the order they appear (which is the order CAmkES assigns badges).
+fn connection_clients(camkes: &str, connection: &str) -> Vec<String> {
+    let mut clients = Vec::new();
+    let mut in_connection = false;
+    for line in camkes.lines() {
+        let line = line.split("//").next().unwrap().trim();
+        if !in_connection {
+            in_connection = line.starts_with("connection ")
+                && line.split_whitespace().nth(2) == Some(&format!("{}(", connection));
+            continue;
+        }
+        if let Some(from) = line.strip_prefix("from ") {
+            let instance = from.split('.').next().unwrap().trim();
+            clients.push(String::from(instance));
+        }
+        if line.ends_with(");") {
+            break;
+        }
+    }
+    clients
+}
+
+fn main() {
+    // Generate the client badges from the multi_security connection in
+    // system.camkes (see access.rs). KATA_SYSTEM_CAMKES overrides the path
+    // (e.g. when building outside the source tree).
+    println!("cargo:rerun-if-env-changed=KATA_SYSTEM_CAMKES");
+    let camkes_path = env::var("KATA_SYSTEM_CAMKES").unwrap_or_else(|_| {
+        format!("{}/../../../system.camkes", env::var("CARGO_MANIFEST_DIR").unwrap())
+    });
+    println!("cargo:rerun-if-changed={}", camkes_path);
+    let camkes =
+        fs::read_to_string(&camkes_path).unwrap_or_else(|e| panic!("{}: {}", camkes_path, e));
+    let clients = connection_clients(&camkes, "multi_security");
+    if clients.is_empty() {
+        panic!("{}: no multi_security connection clients", camkes_path);
+    }
+
+    // Generate the package signing keys to trust (see trusted_keys.rs):
+    // the release key, if KATA_PACKAGE_SIGNING_PUBKEY names a file holding
+    // its public key as 64 hex digits (kata-sign-package --public-key),
+    // and the well-known development key with the "test_signing_key"
+    // feature.
+    println!("cargo:rerun-if-env-changed=KATA_PACKAGE_SIGNING_PUBKEY");
+    let mut keys = Vec::new();
+    if let Ok(path) = env::var("KATA_PACKAGE_SIGNING_PUBKEY") {
+        println!("cargo:rerun-if-changed={}", path);
+        let text = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        let text = text.trim();
+        if text.len() != 64 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+            panic!("{}: expected 64 hex digits", path);
+        }
+        let bytes: Vec<String> = (0..32)
+            .map(|i| format!("0x{}", &text[2 * i..2 * i + 2]))
+            .collect();
+        keys.push(format!(
+            "TrustedKey {{ name: \"kata-release\", public_key: [{}] }}",
+            bytes.join(", ")
+        ));
+    }
+    if env::var("CARGO_FEATURE_TEST_SIGNING_KEY").is_ok() {
+        keys.push(String::from(
+            "TrustedKey { name: \"kata-test\", public_key: kata_package_signature::TEST_PUBLIC_KEY }",
+        ));
+    }
+    if keys.is_empty() {
+        // NB: trusted_keys.rs rejects this except for unit tests
+        println!("cargo:rustc-cfg=no_trusted_keys");
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_path = std::path::Path::new(&out_dir).join("badges.rs");
+    let mut out_file = fs::File::create(&out_path).unwrap();
+    for (index, instance) in clients.iter().enumerate() {
+        // NB: CAmkES numbers clients from 1
+        writeln!(
+            &mut out_file,
+            "pub const {}_BADGE: seL4_Word = {};",
+            instance.to_uppercase(),
+            index + 1
+        )
+        .unwrap();
+    }
+
+    let out_path = std::path::Path::new(&out_dir).join("trusted_keys.rs");
+    let mut out_file = fs::File::create(&out_path).unwrap();
+    writeln!(&mut out_file, "pub static TRUSTED_KEYS: &[TrustedKey] = &[").unwrap();
+    for key in keys {
+        writeln!(&mut out_file, "    {},", key).unwrap();
+    }
+    writeln!(&mut out_file, "];").unwrap();
+}
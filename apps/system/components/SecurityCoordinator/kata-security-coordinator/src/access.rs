@@ -0,0 +1,61 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Access control for SecurityCoordinator requests.
+//!
+//! Each component connected to the security interface is identified by
+//! the badge on its end of the connection and may issue only the
+//! requests granted to it here.
+
+use kata_os_common::sel4_sys::seL4_Word;
+use kata_security_interface::SecurityRequest;
+use kata_security_interface::SecurityRequest::*;
+
+// Badges of the multi_security connection clients, <INSTANCE>_BADGE for
+// each client component instance. These are generated by build.rs from
+// the order of the connection in system.camkes (CAmkES numbers clients
+// from 1); a client referenced below but missing from the connection
+// fails the build.
+include!(concat!(env!("OUT_DIR"), "/badges.rs"));
+
+// Returns whether the client with |badge| may issue |request|.
+// NB: the match is exhaustive so new requests must be added here.
+pub fn is_allowed(badge: seL4_Word, request: SecurityRequest) -> bool {
+    match request {
+        // Packages are managed only by the ProcessManager.
+        // NB: factory reset goes through the ProcessManager so running
+        //   applications are stopped first.
+        SrInstall | SrUninstall | SrFactoryReset => badge == PROCESS_MANAGER_BADGE,
+        // Overriding anti-rollback is an explicit (privileged) operator action.
+        SrAllowRollback => badge == DEBUG_CONSOLE_BADGE,
+        SrSizeBuffer | SrGetManifest | SrLoadApplication => {
+            matches!(badge, PROCESS_MANAGER_BADGE | DEBUG_CONSOLE_BADGE)
+        }
+        SrLoadModel => matches!(badge, ML_COORDINATOR_BADGE | DEBUG_CONSOLE_BADGE),
+        // The SDKRuntime maps each application to its bundle.
+        SrReadKey | SrWriteKey | SrDeleteKey | SrListKeys | SrGetKeyUsage => {
+            matches!(badge, SDK_RUNTIME_BADGE | DEBUG_CONSOLE_BADGE)
+        }
+        SrCryptoGenerate | SrCryptoDelete | SrCryptoSign | SrCryptoVerify | SrCryptoMac
+        | SrCryptoEncrypt | SrCryptoDecrypt => {
+            matches!(badge, SDK_RUNTIME_BADGE | DEBUG_CONSOLE_BADGE)
+        }
+        // Random bytes are available to every client.
+        SrGetRandom => true,
+        // TODO(sleffler): allow the component that talks to the fleet backend
+        SrGetAttestationReport => badge == DEBUG_CONSOLE_BADGE,
+        // Debug/test support.
+        SrEcho | SrGetAuditLog | SrTestMailbox | SrCapScan => badge == DEBUG_CONSOLE_BADGE,
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Attestation report support shared by the platform implementations.
+
+// Version of the running system; set by the build with KATA_SYSTEM_VERSION.
+pub const SYSTEM_VERSION: &str = match option_env!("KATA_SYSTEM_VERSION") {
+    Some(version) => version,
+    None => "dev",
+};
+
+// Max bytes of bundle ids fetched from the security core per request.
+#[cfg_attr(feature = "fake", allow(dead_code))]
+pub const BUNDLE_LIST_PAGE_BYTES: usize = 1024;
+
+// Returns the value of |name| in the [Manifest] section of |manifest|
+// ("" if not present).
+pub fn manifest_field<'a>(manifest: &'a str, name: &str) -> &'a str {
+    let mut in_manifest = false;
+    for line in manifest.lines().map(|l| l.trim()) {
+        if line.starts_with('[') {
+            in_manifest = line == "[Manifest]";
+            continue;
+        }
+        if !in_manifest {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+            if key.trim() == name {
+                return value.trim();
+            }
+        }
+    }
+    ""
+}
+
+// Returns the [Manifest] Version of |manifest| ("" if not present).
+pub fn manifest_version(manifest: &str) -> &str { manifest_field(manifest, "Version") }
@@ -0,0 +1,23 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Device attestation key; signs attestation reports.
+
+#[cfg(not(feature = "test_attestation_key"))]
+compile_error!("no device attestation key available; enable \"test_attestation_key\"");
+
+// TODO(sleffler): the sel4 platform signs with a key held by the security
+//   core; the fake has only the well-known development key
+#[cfg(feature = "test_attestation_key")]
+pub static ATTESTATION_SEED: [u8; 32] = kata_attestation::TEST_ATTESTATION_SEED;
@@ -0,0 +1,198 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Security audit log shared by the platform implementations.
+
+use crate::rollback::MAX_BUNDLE_ID_LEN;
+use crate::storage::KeyStore;
+use alloc::collections::VecDeque;
+use alloc::fmt;
+use alloc::string::String;
+use alloc::vec::Vec;
+use kata_security_interface::AuditRecord;
+use kata_storage::MAX_KEY_LEN;
+use kata_storage::RECORD_HEADER_SIZE;
+use log::warn;
+
+// Max records held; the oldest record is dropped to make room.
+pub const AUDIT_LOG_CAPACITY: usize = 32;
+
+// Namespace holding records written to a KeyStore, keyed by sequence # in hex so
+// keys sort in log order. NB: bundle ids never start with '.'.
+const AUDIT_NS: &str = ".audit";
+
+fn audit_key(seq: u32) -> String { fmt::format(format_args!("{:08x}", seq)) }
+const AUDIT_KEY_LEN: usize = 8;
+
+// Max bytes of a record's detail (room for a key name); longer details
+// are truncated, as are bundle ids longer than MAX_BUNDLE_ID_LEN.
+pub const MAX_AUDIT_DETAIL_LEN: usize = MAX_KEY_LEN;
+
+// Max serialized size of a record: the postcard varints at their widest
+// (seq, timestamp_ms, badge, event) and the length-prefixed strings.
+pub const MAX_AUDIT_RECORD_SIZE: usize =
+    5 + 10 + 10 + 1 + (1 + MAX_BUNDLE_ID_LEN) + (2 + MAX_AUDIT_DETAIL_LEN);
+
+// Space a full log takes in a KeyStore.
+pub const AUDIT_STORE_BYTES: usize = AUDIT_LOG_CAPACITY
+    * (RECORD_HEADER_SIZE + AUDIT_NS.len() + AUDIT_KEY_LEN + MAX_AUDIT_RECORD_SIZE);
+
+// Truncates |s| to at most |max_len| bytes on a char boundary.
+fn truncate(s: &mut String, max_len: usize) {
+    if s.len() > max_len {
+        let mut end = max_len;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+    }
+}
+
+// Append-only ring of AuditRecord's. When a KeyStore is supplied records
+// are also written there and reloaded with load(); otherwise the log is
+// held only in memory. NB: neither is persistent: the KeyStore is itself
+// RAM-backed (see storage.rs) so the log is lost on reboot.
+pub struct AuditLog {
+    records: VecDeque<AuditRecord>,
+    next_seq: u32,
+}
+impl Default for AuditLog {
+    fn default() -> Self { Self::new() }
+}
+impl AuditLog {
+    pub fn new() -> Self {
+        AuditLog {
+            records: VecDeque::with_capacity(AUDIT_LOG_CAPACITY),
+            next_seq: 0,
+        }
+    }
+
+    // Returns a log holding the records previously written to |store|.
+    // Records that cannot be decoded are dropped.
+    pub fn load(store: &KeyStore) -> Self {
+        let mut log = AuditLog::new();
+        for key in store.keys(AUDIT_NS) {
+            match store
+                .read(AUDIT_NS, key)
+                .ok()
+                .and_then(|value| postcard::from_bytes::<AuditRecord>(&value).ok())
+            {
+                Some(record) => {
+                    log.next_seq = record.seq.wrapping_add(1);
+                    log.push(record);
+                }
+                None => warn!("Audit record {} unreadable, dropped", key),
+            }
+        }
+        log
+    }
+
+    // Adds |record| to the ring, returning the record dropped (if any).
+    fn push(&mut self, record: AuditRecord) -> Option<AuditRecord> {
+        let evicted = if self.records.len() == AUDIT_LOG_CAPACITY {
+            self.records.pop_front()
+        } else {
+            None
+        };
+        self.records.push_back(record);
+        evicted
+    }
+
+    // Appends |record| (assigning its sequence #), dropping the oldest
+    // record if the log is full. A failure to write the record to |store|
+    // is logged but otherwise ignored; the record is kept in memory.
+    pub fn append(&mut self, mut record: AuditRecord, store: Option<&mut KeyStore>) {
+        truncate(&mut record.bundle_id, MAX_BUNDLE_ID_LEN);
+        truncate(&mut record.detail, MAX_AUDIT_DETAIL_LEN);
+        record.seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let value = postcard::to_allocvec(&record);
+        let seq = record.seq;
+        let evicted = self.push(record);
+        if let Some(store) = store {
+            // NB: remove the dropped record first to make room
+            if let Some(evicted) = evicted {
+                let _ = store.delete(AUDIT_NS, &audit_key(evicted.seq));
+            }
+            match value {
+                Ok(value) => {
+                    if let Err(e) = store.write(AUDIT_NS, &audit_key(seq), &value) {
+                        warn!("Audit record {} not stored: {:?}", seq, e);
+                    }
+                }
+                Err(e) => warn!("Audit record {} not serialized: {:?}", seq, e),
+            }
+        }
+    }
+
+    // Drops every record (the caller erases any KeyStore copy). Sequence
+    // #'s carry on so readers can tell the log was cleared.
+    pub fn clear(&mut self) { self.records.clear(); }
+
+    // Returns the records with a sequence # after |after| (all if None).
+    pub fn records_after(&self, after: Option<u32>) -> Vec<AuditRecord> {
+        self.records
+            .iter()
+            .filter(|r| after.map_or(true, |after| r.seq > after))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kata_security_interface::AuditEvent;
+
+    fn record(bundle_id: &str, detail: &str) -> AuditRecord {
+        AuditRecord {
+            seq: u32::MAX,
+            timestamp_ms: u64::MAX,
+            badge: !0,
+            event: AuditEvent::WriteKey,
+            bundle_id: String::from(bundle_id),
+            detail: String::from(detail),
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut log = AuditLog::new();
+        log.append(record("a", ""), None);
+        log.append(record("b", ""), None);
+        log.clear();
+        assert!(log.records_after(None).is_empty());
+        log.append(record("c", ""), None);
+        let records = log.records_after(None);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].seq, 2);
+    }
+
+    #[test]
+    fn test_record_size() {
+        let mut log = AuditLog::new();
+        // NB: multi-byte chars are not split
+        log.append(
+            record(&"é".repeat(MAX_BUNDLE_ID_LEN), &"x".repeat(MAX_AUDIT_DETAIL_LEN + 1)),
+            None,
+        );
+        let records = log.records_after(None);
+        assert_eq!(records[0].bundle_id, "é".repeat(MAX_BUNDLE_ID_LEN / 2));
+        assert_eq!(records[0].detail.len(), MAX_AUDIT_DETAIL_LEN);
+
+        let mut largest = records[0].clone();
+        largest.seq = u32::MAX;
+        assert!(postcard::to_allocvec(&largest).unwrap().len() <= MAX_AUDIT_RECORD_SIZE);
+    }
+}
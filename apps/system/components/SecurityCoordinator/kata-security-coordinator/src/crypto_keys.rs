@@ -0,0 +1,34 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-bundle crypto key support shared by the platform implementations.
+
+use kata_crypto_keys::{CryptoError, KeyType};
+use kata_security_interface::CryptoKeyType;
+use kata_security_interface::SecurityRequestError;
+
+pub fn key_type(key_type: CryptoKeyType) -> KeyType {
+    match key_type {
+        CryptoKeyType::Ed25519 => KeyType::Ed25519,
+        CryptoKeyType::HmacSha256 => KeyType::HmacSha256,
+        CryptoKeyType::Aes256GcmSiv => KeyType::Aes256GcmSiv,
+    }
+}
+
+pub fn crypto_error(err: CryptoError) -> SecurityRequestError {
+    match err {
+        CryptoError::NameInvalid => SecurityRequestError::SreKeyInvalid,
+        _ => SecurityRequestError::SreCryptoFailed,
+    }
+}
@@ -16,27 +16,71 @@
 
 extern crate alloc;
 use alloc::fmt;
-use alloc::string::{String, ToString};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp;
 use core::mem::size_of;
 use core::ptr;
 use hashbrown::HashMap;
-use kata_memory_interface::kata_frame_alloc_in_cnode;
+use kata_attestation::Report;
+use kata_crypto_keys::CryptoKey;
+use kata_drbg::HmacDrbg;
+use kata_memory_interface::kata_cnode_alloc;
+use kata_memory_interface::kata_object_alloc;
+use kata_memory_interface::kata_object_free;
 use kata_memory_interface::kata_object_free_in_cnode;
+use kata_memory_interface::kata_object_free_toplevel;
+use kata_memory_interface::ObjDesc;
 use kata_memory_interface::ObjDescBundle;
 use kata_os_common::copyregion::CopyRegion;
 use kata_os_common::cspace_slot::CSpaceSlot;
 use kata_os_common::sel4_sys;
+use kata_package_signature::PackageDigest;
+use kata_package_signature::PackageVerifier;
+use kata_package_signature::SectionInfo;
+use kata_package_signature::SECTION_HEADER_SIZE;
+use kata_sealed_storage::sealed_len;
 use kata_security_interface::*;
-use log::info;
+use kata_timer_interface::timer_service_now_ms;
+use log::{info, warn};
 
+use crate::attestation::{manifest_version, SYSTEM_VERSION};
+use crate::attestation_key::ATTESTATION_SEED;
+use crate::audit::AuditLog;
+use crate::crypto_keys;
+use crate::crypto_keys::crypto_error;
+use crate::page_mapper::PageMapper;
+use crate::rollback;
+use crate::rollback::ManifestCollector;
+use crate::root_secret::ROOT_SECRET;
+use crate::storage::{check_quota, key_usage, list_keys, storage_error};
+use crate::storage::{KeyQuota, SealedKeyStore};
+
+use sel4_sys::seL4_CNode_Copy;
+use sel4_sys::seL4_CNode_Revoke;
+use sel4_sys::seL4_CapRights;
 use sel4_sys::seL4_Error;
+use sel4_sys::seL4_ObjectType;
 use sel4_sys::seL4_PageBits;
 use sel4_sys::seL4_Word;
 
 const PAGE_SIZE: usize = 1 << seL4_PageBits;
 
+// Max frames retyped in one allocation (the kernel's Retype "fanout" limit).
+const MAX_FRAME_RUN: usize = 256;
+
+// Personalization string for the DRBG.
+const DRBG_PERSONALIZATION: &[u8] = b"KataOS fake SecurityCoordinator";
+
+// Returns input that varies from boot to boot (and call to call): the
+// time since boot. NB: the fake has no entropy source; this (together
+// with the device root secret) only keeps devices, boots & reseeds from
+// sharing a stream.
+fn boot_input() -> [u8; 8] { timer_service_now_ms().to_be_bytes() }
+
 extern "C" {
-    // Regions for deep_copy work.
+    // Regions for share_image work.
     static mut DEEP_COPY_SRC: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
     static mut DEEP_COPY_DEST: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
 }
@@ -45,58 +89,87 @@ struct BundleData {
     pkg_contents: ObjDescBundle,
     pkg_size: usize,
     manifest: String,
-    keys: HashMap<String, KeyValueData>,
+    signer: &'static str,      // Name of the trusted key that signed the package
+    pkg_digest: PackageDigest, // Signed digest of the package (for attestation)
+    key_quota: KeyQuota,       // Limits on key-value storage
 }
 impl BundleData {
-    fn new(pkg_contents: &ObjDescBundle) -> Self {
+    fn new(
+        pkg_contents: &ObjDescBundle,
+        manifest: String,
+        signer: &'static str,
+        pkg_digest: PackageDigest,
+    ) -> Self {
         BundleData {
             pkg_contents: pkg_contents.clone(),
             pkg_size: pkg_contents.size_bytes(),
-            manifest: String::from(
-                r##"
-# Comments like this
-[Manifest]
-BundleId=com.google.cerebra.hw.HelloWorld
-
-[Binaries]
-App=HelloWorldBin
-Model=NeuralNetworkName
-
-[Storage]
-Required=1
-"##,
-            ),
-            keys: HashMap::with_capacity(2),
+            signer,
+            pkg_digest,
+            key_quota: KeyQuota::from_manifest(&manifest),
+            manifest,
         }
     }
 }
 impl Drop for BundleData {
-    fn drop(&mut self) { let _ = kata_object_free_in_cnode(&self.pkg_contents); }
+    fn drop(&mut self) {
+        // Revoke the read-only capabilities handed out by share_image
+        // before the package frames are reclaimed.
+        let pkg = &self.pkg_contents;
+        for cptr in pkg.cptr_iter() {
+            if let Err(e) = unsafe { seL4_CNode_Revoke(pkg.cnode, cptr, pkg.depth) } {
+                warn!("Revoke of package frame {} failed: {:?}", cptr, e);
+            }
+        }
+        let _ = kata_object_free_in_cnode(pkg);
+    }
 }
 
 pub struct FakeSecurityCoordinator {
     bundles: HashMap<String, BundleData>,
+    keys: SealedKeyStore,
+    audit: AuditLog, // Written to |keys| (NB: RAM-backed, lost on reboot)
+    // Seeded on first use (the TimerService may not be up at init).
+    drbg: Option<HmacDrbg>,
 }
 impl Default for FakeSecurityCoordinator {
     fn default() -> Self { Self::new() }
 }
 impl FakeSecurityCoordinator {
     pub fn new() -> Self {
+        let keys = SealedKeyStore::new(&ROOT_SECRET);
         FakeSecurityCoordinator {
             bundles: HashMap::with_capacity(2),
+            audit: AuditLog::load(keys.store()),
+            keys,
+            drbg: None,
         }
     }
 
+    // Returns the DRBG, (re)seeding it as needed. The seed is the device
+    // root secret with the boot input as the nonce; a reseed mixes in the
+    // boot input taken then.
+    fn drbg(&mut self) -> Result<&mut HmacDrbg, SecurityRequestError> {
+        match self.drbg.as_mut() {
+            Some(drbg) if !drbg.needs_reseed() => {}
+            Some(drbg) => {
+                drbg.reseed(&ROOT_SECRET, &boot_input())
+                    .map_err(|_| SecurityRequestError::SreGetRandomFailed)?;
+            }
+            None => {
+                self.drbg = Some(
+                    HmacDrbg::new(&ROOT_SECRET, &boot_input(), DRBG_PERSONALIZATION)
+                        .map_err(|_| SecurityRequestError::SreGetRandomFailed)?,
+                );
+            }
+        }
+        Ok(self.drbg.as_mut().unwrap())
+    }
+
     fn get_bundle(&self, bundle_id: &str) -> Result<&BundleData, SecurityRequestError> {
         self.bundles
             .get(bundle_id)
             .map_or_else(|| Err(SecurityRequestError::SreBundleNotFound), Ok)
     }
-    fn get_bundle_mut(&mut self, bundle_id: &str) -> Result<&mut BundleData, SecurityRequestError> {
-        self.bundles
-            .get_mut(bundle_id)
-            .map_or_else(|| Err(SecurityRequestError::SreBundleNotFound), Ok)
-    }
     fn remove_bundle(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
         self.bundles
             .remove(bundle_id)
@@ -105,59 +178,236 @@ impl FakeSecurityCoordinator {
 }
 pub type KataSecurityCoordinatorInterface = FakeSecurityCoordinator;
 
-// Returns a deep copy (including seL4 objects) of |src|. The container
-// CNode is in the toplevel (allocated from the slot allocator).
-fn deep_copy(src: &ObjDescBundle) -> Result<ObjDescBundle, seL4_Error> {
-    let dest = kata_frame_alloc_in_cnode(src.size_bytes())
-        .map_err(|_| seL4_Error::seL4_NotEnoughMemory)?; // TODO(sleffler) From mapping
-    assert_eq!(src.count(), dest.count());
-    // Src top-level slot & copy region
+// Returns which frames of |pkg| hold writeable section data. These must be
+// copied on each load; all other frames (section headers, read-only data,
+// the signature trailer) can be shared with the installed package.
+fn writeable_frames(pkg: &ObjDescBundle) -> Result<Vec<bool>, seL4_Error> {
+    // Byte offset of each frame; frames may be of mixed sizes.
+    let mut starts = Vec::with_capacity(pkg.count());
+    let mut size = 0;
+    for (_, frame_bytes) in pkg.frame_iter() {
+        starts.push(size);
+        size += frame_bytes;
+    }
+    let frame_index = |offset: usize| starts.partition_point(|&start| start <= offset) - 1;
+
+    let mut writeable = vec![false; starts.len()];
+    let mut frames = PageMapper::new(
+        pkg,
+        CopyRegion::new(unsafe { ptr::addr_of_mut!(DEEP_COPY_SRC[0]) }, PAGE_SIZE),
+    );
+    let mut hdr = [0u8; SECTION_HEADER_SIZE];
+    let mut offset = 0;
+    while offset + SECTION_HEADER_SIZE <= size {
+        // NB: headers are not aligned and may straddle frames
+        for (i, byte) in hdr.iter_mut().enumerate() {
+            let index = frame_index(offset + i);
+            *byte = frames.page(index)?[offset + i - starts[index]];
+        }
+        let section = match SectionInfo::parse(&hdr) {
+            Some(section) => section,
+            None => break, // End of sections (padding or signature trailer)
+        };
+        let data = offset + SECTION_HEADER_SIZE;
+        let end = cmp::min(data + section.fsize, size);
+        if section.is_write() && data < end {
+            writeable[frame_index(data)..=frame_index(end - 1)].fill(true);
+        }
+        offset = data + section.fsize;
+    }
+    Ok(writeable)
+}
+
+// Returns the frames of |pkg| arranged as a loadable image. Frames holding
+// writeable data are copied; the rest are read-only capabilities derived
+// from the package frames that are revoked when the package is removed
+// (see BundleData::drop). The container CNode is in the toplevel
+// (allocated from the slot allocator).
+fn share_image(pkg: &ObjDescBundle) -> Result<ImageFrames, seL4_Error> {
+    fn next_log2(value: usize) -> usize {
+        // NB: BITS & leading_zeros return u32
+        (1 + usize::BITS - usize::leading_zeros(value)) as usize
+    }
+    let writeable = writeable_frames(pkg)?;
+    let types: Vec<seL4_ObjectType> = pkg
+        .objs
+        .iter()
+        .flat_map(|od| core::iter::repeat(od.type_).take(od.retype_count()))
+        .collect();
+    let npages = writeable.len();
+
+    // Describe the image as runs of private or shared frames in frame
+    // order; runs are split where the privacy or page size changes.
+    let depth = next_log2(npages);
+    let cnode = kata_cnode_alloc(depth).map_err(|_| seL4_Error::seL4_NotEnoughMemory)?;
+    let mut image = ImageFrames {
+        frames: ObjDescBundle::new(cnode.objs[0].cptr, depth as u8, Vec::new()),
+        private: Vec::new(),
+    };
+    let mut start = 0;
+    while start < npages {
+        let (private, type_) = (writeable[start], types[start]);
+        let end = writeable[start..]
+            .iter()
+            .zip(types[start..].iter())
+            .take(MAX_FRAME_RUN)
+            .position(|(&w, &t)| w != private || t != type_)
+            .map_or(cmp::min(npages, start + MAX_FRAME_RUN), |n| start + n);
+        let od = ObjDesc::new(type_, end - start, start);
+        if private {
+            image.private.push(od);
+        }
+        image.frames.objs.push(od);
+        start = end;
+    }
+    if !image.private.is_empty() {
+        if let Err(e) = kata_object_alloc(&image.private_frames()) {
+            warn!("share_image: alloc failed: {:?}", e);
+            let _ = kata_object_free_toplevel(&cnode);
+            return Err(seL4_Error::seL4_NotEnoughMemory);
+        }
+    }
+
+    // Fill in the image: copy private frames & derive shared frames.
+    let result = {
+        let mut src_pages = PageMapper::new(
+            pkg,
+            CopyRegion::new(unsafe { ptr::addr_of_mut!(DEEP_COPY_SRC[0]) }, PAGE_SIZE),
+        );
+        let mut dest_pages = PageMapper::new(
+            &image.frames,
+            CopyRegion::new(unsafe { ptr::addr_of_mut!(DEEP_COPY_DEST[0]) }, PAGE_SIZE),
+        );
+        let read_only = seL4_CapRights::new(
+            /*grant_reply=*/ 0, /*grant=*/ 0, /*read=*/ 1, /*write=*/ 0,
+        );
+        pkg.cptr_iter()
+            .zip(writeable.iter())
+            .enumerate()
+            .try_for_each(|(index, (src_cptr, &private))| {
+                if private {
+                    let src = src_pages.page(index)?;
+                    dest_pages.page(index)?.copy_from_slice(src);
+                    Ok(())
+                } else {
+                    unsafe {
+                        seL4_CNode_Copy(
+                            /*dest_root=*/ image.frames.cnode,
+                            /*dest_index=*/ index,
+                            /*dest_depth=*/ image.frames.depth,
+                            /*src_root=*/ pkg.cnode,
+                            /*src_index=*/ src_cptr,
+                            /*src_depth=*/ pkg.depth,
+                            read_only,
+                        )
+                    }
+                }
+            })
+    };
+    if let Err(e) = result {
+        // Return the private copies then the container; deleting the
+        // container also deletes the shared (derived) caps and its
+        // toplevel slot goes back to the slot allocator.
+        if !image.private.is_empty() {
+            let _ = kata_object_free(&image.private_frames());
+        }
+        let _ = kata_object_free_toplevel(&cnode);
+        return Err(e);
+    }
+    Ok(image)
+}
+
+// Feeds the contents of |pkg| through a PackageVerifier one frame at a
+// time, collecting the manifest on the way.
+fn scan_package(
+    pkg: &ObjDescBundle,
+    verifier: &mut PackageVerifier,
+    collector: &mut ManifestCollector,
+) -> Result<(), SecurityRequestError> {
     let src_slot = CSpaceSlot::new();
     let mut src_region = CopyRegion::new(unsafe { ptr::addr_of_mut!(DEEP_COPY_SRC[0]) }, PAGE_SIZE);
-    // Dest top-level slot & copy region
-    let dest_slot = CSpaceSlot::new();
-    let mut dest_region =
-        CopyRegion::new(unsafe { ptr::addr_of_mut!(DEEP_COPY_DEST[0]) }, PAGE_SIZE);
-    for (src_cptr, dest_cptr) in src.cptr_iter().zip(dest.cptr_iter()) {
-        // Map src & dest frames and copy data.
+    let mut offset = 0;
+    for (src_cptr, frame_bytes) in pkg.frame_iter() {
         src_slot
-            .dup_to(src.cnode, src_cptr, src.depth)
-            .and_then(|_| src_region.map(src_slot.slot))?;
-        dest_slot
-            .dup_to(dest.cnode, dest_cptr, dest.depth)
-            .and_then(|_| dest_region.map(dest_slot.slot))?;
-
-        unsafe {
-            ptr::copy_nonoverlapping(
-                src_region.as_ref().as_ptr(),
-                dest_region.as_mut().as_mut_ptr(),
-                PAGE_SIZE,
-            );
+            .dup_to(pkg.cnode, src_cptr, pkg.depth)
+            .and_then(|_| src_region.map_sized(src_slot.slot, frame_bytes))
+            .map_err(|_| SecurityRequestError::SreInstallFailed)?;
+        let result = verifier.update(src_region.as_ref());
+        let collected = collector.update(verifier, offset, src_region.as_ref());
+        src_region
+            .unmap()
+            .and_then(|_| src_slot.delete())
+            .map_err(|_| SecurityRequestError::SreInstallFailed)?;
+        collected?;
+        if result.is_err() {
+            break; // NB: verify reports the error
         }
-
-        // Unmap & clear top-level slot required for mapping.
-        src_region.unmap().and_then(|_| src_slot.delete())?;
-        dest_region.unmap().and_then(|_| dest_slot.delete())?;
+        offset += frame_bytes;
     }
-    Ok(dest)
+    Ok(())
 }
 
 impl SecurityCoordinatorInterface for FakeSecurityCoordinator {
     fn install(&mut self, pkg_contents: &ObjDescBundle) -> Result<String, SecurityRequestError> {
-        // TODO(sleffler): get bundle_id from the manifest; for now use the
-        //    cnode's CPtr since it is unique wrt all installed packages
-        let bundle_id = fmt::format(format_args!("fake.{}", pkg_contents.cnode));
+        let mut verifier = PackageVerifier::new();
+        let mut collector = ManifestCollector::new();
+        scan_package(pkg_contents, &mut verifier, &mut collector)?;
+        let signer = crate::trusted_keys::verify(&verifier).map_err(|err| {
+            warn!("Package signature check failed: {:?}", err);
+            err
+        })?;
+        let pkg_digest = verifier
+            .digest()
+            .map_err(|_| SecurityRequestError::SreInstallFailed)?;
+
+        let manifest = String::from(collector.manifest(&verifier)?);
+        let (bundle_id, version) = rollback::package_identity(&manifest)?;
         if self.bundles.contains_key(&bundle_id) {
             return Err(SecurityRequestError::SreDeleteFirst);
         }
+        rollback::check_version(
+            &bundle_id,
+            version,
+            rollback::read_version(self.keys.store(), &bundle_id),
+        )?;
+        rollback::write_version(self.keys.store_mut(), &bundle_id, version)
+            .map_err(|e| storage_error(e, SecurityRequestError::SreInstallFailed))?;
+        info!("Install {} version {} signed by {}", &bundle_id, version, signer);
         assert!(self
             .bundles
-            .insert(bundle_id.clone(), BundleData::new(pkg_contents))
+            .insert(
+                bundle_id.clone(),
+                BundleData::new(pkg_contents, manifest, signer, pkg_digest)
+            )
             .is_none());
         Ok(bundle_id)
     }
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
-        self.remove_bundle(bundle_id)
+        self.remove_bundle(bundle_id)?;
+        self.keys
+            .destroy_bundle_key(bundle_id)
+            .map_err(|e| storage_error(e, SecurityRequestError::SreUninstallFailed))
+    }
+    fn allow_rollback(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        rollback::write_version(self.keys.store_mut(), bundle_id, 0)
+            .map_err(|e| storage_error(e, SecurityRequestError::SreAllowRollbackFailed))
+    }
+    fn factory_reset(&mut self) -> Result<(), SecurityRequestError> {
+        info!("Factory reset: removing {} bundles", self.bundles.len());
+        // NB: dropping BundleData reclaims the package frames
+        self.bundles.clear();
+        // NB: version records survive so a reset cannot be used to
+        //     install an older (possibly vulnerable) package; everything
+        //     else is erased in place (the store is too large to hold
+        //     a second copy while resetting)
+        self.keys
+            .store_mut()
+            .retain(&|ns, _| ns == rollback::VERSION_NS)
+            .map_err(|_| SecurityRequestError::SreFactoryResetFailed)?;
+        // NB: the stored records went with the key store; the caller
+        //   records the reset as the first record that follows
+        self.audit.clear();
+        Ok(())
     }
     fn size_buffer(&self, bundle_id: &str) -> Result<usize, SecurityRequestError> {
         let bundle = self.get_bundle(bundle_id)?;
@@ -165,51 +415,193 @@ impl SecurityCoordinatorInterface for FakeSecurityCoordinator {
     }
     fn get_manifest(&self, bundle_id: &str) -> Result<String, SecurityRequestError> {
         let bundle = self.get_bundle(bundle_id)?;
-        // return &?
-        Ok(bundle.manifest.clone())
+        // NB: the signer is not part of the signed manifest, it is
+        //   recorded at install time
+        Ok(fmt::format(format_args!(
+            "{}\n[Signature]\nSigner={}\n",
+            bundle.manifest, bundle.signer
+        )))
     }
-    fn load_application(&self, bundle_id: &str) -> Result<ObjDescBundle, SecurityRequestError> {
+    fn load_application(&self, bundle_id: &str) -> Result<ImageFrames, SecurityRequestError> {
         let bundle_data = self.get_bundle(bundle_id)?;
-        // Clone everything (struct + associated seL4 objects) so the
-        // return is as though it was newly instantiated from flash.
-        // XXX just return the package for now
-        deep_copy(&bundle_data.pkg_contents)
+        // Return the package as though it was newly instantiated from
+        // flash; only writeable data are copied.
+        share_image(&bundle_data.pkg_contents)
             .map_err(|_| SecurityRequestError::SreLoadApplicationFailed)
     }
     fn load_model(
         &self,
         bundle_id: &str,
         _model_id: &str,
-    ) -> Result<ObjDescBundle, SecurityRequestError> {
+    ) -> Result<ImageFrames, SecurityRequestError> {
         let bundle_data = self.get_bundle(bundle_id)?;
         // TODO(sleffler): check model id
-        // Clone everything (struct + associated seL4 objects) so the
-        // return is as though it was newly instantiated from flash.
+        // Return the package as though it was newly instantiated from
+        // flash; only writeable data are copied.
         // XXX just return the package for now
-        deep_copy(&bundle_data.pkg_contents).map_err(|_| SecurityRequestError::SreLoadModelFailed)
+        share_image(&bundle_data.pkg_contents).map_err(|_| SecurityRequestError::SreLoadModelFailed)
     }
-    fn read_key(&self, bundle_id: &str, key: &str) -> Result<&KeyValueData, SecurityRequestError> {
-        let bundle = self.get_bundle(bundle_id)?;
-        bundle
-            .keys
-            .get(key)
-            .ok_or(SecurityRequestError::SreKeyNotFound)
+    fn read_key(&self, bundle_id: &str, key: &str) -> Result<Vec<u8>, SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        self.keys.read(bundle_id, key)
     }
     fn write_key(
         &mut self,
         bundle_id: &str,
         key: &str,
-        value: &KeyValueData,
+        value: &[u8],
     ) -> Result<(), SecurityRequestError> {
-        let bundle = self.get_bundle_mut(bundle_id)?;
-        let _ = bundle.keys.insert(key.to_string(), *value);
-        Ok(())
+        let bundle = self.get_bundle(bundle_id)?;
+        check_quota(
+            self.keys.store(),
+            bundle_id,
+            key,
+            sealed_len(value.len()),
+            &bundle.key_quota,
+        )?;
+        self.keys.write(bundle_id, key, value)
     }
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError> {
-        let bundle = self.get_bundle_mut(bundle_id)?;
+        self.get_bundle(bundle_id)?;
         // TODO(sleffler): error if no entry?
-        let _ = bundle.keys.remove(key);
-        Ok(())
+        match self.keys.delete(bundle_id, key) {
+            Err(kata_storage::StorageError::NotFound) => Ok(()),
+            r => r.map_err(|e| storage_error(e, SecurityRequestError::SreDeleteFailed)),
+        }
+    }
+    fn list_keys(
+        &self,
+        bundle_id: &str,
+        after: Option<&str>,
+    ) -> Result<(Vec<String>, bool), SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        Ok(list_keys(self.keys.store(), bundle_id, after))
+    }
+    fn get_key_usage(&self, bundle_id: &str) -> Result<KeyValueUsage, SecurityRequestError> {
+        let bundle = self.get_bundle(bundle_id)?;
+        Ok(key_usage(self.keys.store(), bundle_id, &bundle.key_quota))
+    }
+
+    fn audit(&mut self, record: AuditRecord) {
+        self.audit.append(record, Some(self.keys.store_mut()));
+    }
+    fn get_audit_log(&self, after: Option<u32>) -> Result<Vec<AuditRecord>, SecurityRequestError> {
+        Ok(self.audit.records_after(after))
+    }
+
+    fn get_random(&mut self, buf: &mut [u8]) -> Result<(), SecurityRequestError> {
+        self.drbg()?
+            .generate(buf, &[])
+            .map_err(|_| SecurityRequestError::SreGetRandomFailed)
+    }
+
+    fn crypto_generate(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        key_type: CryptoKeyType,
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        kata_crypto_keys::check_name(name).map_err(crypto_error)?;
+        // NB: the DRBG has no entropy source (see boot_input) so the key
+        //   would be predictable; refuse unless built for testing
+        if cfg!(not(feature = "test_insecure_keygen")) {
+            warn!("{}: no entropy source to generate key {}", bundle_id, name);
+            return Err(SecurityRequestError::SreCryptoFailed);
+        }
+        let mut secret = [0u8; kata_crypto_keys::SECRET_SIZE];
+        self.get_random(&mut secret)?;
+        let key = CryptoKey::new(crypto_keys::key_type(key_type), &secret);
+        secret.fill(0);
+        self.keys.write_crypto_key(bundle_id, name, &key)?;
+        Ok(key.public_key())
+    }
+    fn crypto_delete(&mut self, bundle_id: &str, name: &str) -> Result<(), SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        self.keys
+            .delete_crypto_key(bundle_id, name)
+            .map_err(|e| storage_error(e, SecurityRequestError::SreDeleteFailed))
+    }
+    fn crypto_sign(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        self.keys
+            .read_crypto_key(bundle_id, name)?
+            .sign(data)
+            .map_err(crypto_error)
+    }
+    fn crypto_verify(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        self.keys
+            .read_crypto_key(bundle_id, name)?
+            .verify(data, signature)
+            .map_err(crypto_error)
+    }
+    fn crypto_mac(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        self.keys
+            .read_crypto_key(bundle_id, name)?
+            .mac(data)
+            .map_err(crypto_error)
+    }
+    fn crypto_encrypt(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        let key = self.keys.read_crypto_key(bundle_id, name)?;
+        let mut nonce = [0u8; kata_crypto_keys::NONCE_SIZE];
+        self.get_random(&mut nonce)?;
+        key.encrypt(data, &nonce).map_err(crypto_error)
+    }
+    fn crypto_decrypt(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.get_bundle(bundle_id)?;
+        self.keys
+            .read_crypto_key(bundle_id, name)?
+            .decrypt(data)
+            .map_err(crypto_error)
+    }
+
+    fn get_attestation_report(
+        &self,
+        nonce: &[u8],
+    ) -> Result<AttestationReport, SecurityRequestError> {
+        let mut report = Report::new(SYSTEM_VERSION, nonce);
+        for (bundle_id, bundle) in self.bundles.iter() {
+            report.add_bundle(bundle_id, manifest_version(&bundle.manifest), &bundle.pkg_digest);
+        }
+        let report = report.encode();
+        let (public_key, signature) = kata_attestation::sign_digest(
+            &ATTESTATION_SEED,
+            &kata_attestation::report_digest(report.as_bytes()),
+        );
+        Ok(AttestationReport {
+            report,
+            public_key: public_key.to_vec(),
+            signature: signature.to_vec(),
+        })
     }
 
     fn test_mailbox(&mut self) -> Result<(), SecurityRequestError> {
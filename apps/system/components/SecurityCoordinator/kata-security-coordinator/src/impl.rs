@@ -13,139 +13,595 @@
 // limitations under the License.
 
 //! Kata OS security coordinator seL4 support
+//!
+//! Requests are delegated to the security core using the framed protocol
+//! in kata-security-mailbox; package signatures & versions are checked
+//! here as the package is streamed to the security core.
 
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ops::Range;
+use core::ptr;
+use kata_attestation::Report;
+use kata_drbg::HmacDrbg;
+use kata_drbg::MIN_ENTROPY_SIZE;
 use kata_memory_interface::kata_frame_alloc;
+use kata_memory_interface::kata_frame_alloc_in_cnode;
+use kata_memory_interface::kata_object_free_in_cnode;
 use kata_memory_interface::kata_object_free_toplevel;
+use kata_memory_interface::ObjDescBundle;
+use kata_os_common::copyregion::CopyRegion;
 use kata_os_common::sel4_sys;
+use kata_package_signature::PackageDigest;
+use kata_package_signature::PackageVerifier;
+use kata_security_interface::SecurityRequestError::*;
 use kata_security_interface::*;
-use log::trace;
+use kata_security_mailbox::*;
+use log::{trace, warn};
+use spin::Mutex;
+
+use crate::attestation::{manifest_version, BUNDLE_LIST_PAGE_BYTES, SYSTEM_VERSION};
+use crate::audit::AuditLog;
+use crate::crypto_keys;
+use crate::page_mapper::PageMapper;
+use crate::rollback;
+use crate::rollback::ManifestCollector;
+use crate::storage::KeyQuota;
 
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_Error;
+use sel4_sys::seL4_PageBits;
 use sel4_sys::seL4_Page_GetAddress;
+use sel4_sys::seL4_Word;
+
+const PAGE_SIZE: usize = 1 << seL4_PageBits;
+
+// Personalization string for the DRBG.
+const DRBG_PERSONALIZATION: &[u8] = b"KataOS SecurityCoordinator";
 
 extern "C" {
     static SECURITY_RECV_SLOT: seL4_CPtr;
+
+    fn mailbox_api_send(paddr: u32, size: u32);
+    fn mailbox_api_receive(paddr: *mut u32, size: *mut u32);
+
+    // Page shared with the security core.
+    static mut MAILBOX: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
+    // Regions for streaming packages & images.
+    static mut DEEP_COPY_SRC: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
+    static mut DEEP_COPY_DEST: [seL4_Word; PAGE_SIZE / size_of::<seL4_Word>()];
+}
+
+// MailboxTransport over the mailbox driver. Frames are passed in a page
+// shared with the security core; it is allocated on first use.
+struct Sel4Mailbox {
+    frame: Option<(ObjDescBundle, usize)>, // Shared page & its paddr
+    region: CopyRegion,
+}
+impl Sel4Mailbox {
+    fn new() -> Self {
+        Sel4Mailbox {
+            frame: None,
+            region: CopyRegion::new(unsafe { ptr::addr_of_mut!(MAILBOX[0]) }, PAGE_SIZE),
+        }
+    }
+
+    // Returns the paddr of the shared page, setting it up if needed.
+    fn setup(&mut self) -> Result<usize, MailboxError> {
+        if let Some((_, paddr)) = self.frame {
+            return Ok(paddr);
+        }
+        let frame = kata_frame_alloc(PAGE_SIZE).map_err(|_| MailboxError::TransportFailed)?;
+        let cptr = frame.objs[0].cptr;
+        if self.region.map(cptr).is_err() {
+            let _ = kata_object_free_toplevel(&frame);
+            return Err(MailboxError::TransportFailed);
+        }
+        let paddr = unsafe { seL4_Page_GetAddress(cptr) }.paddr;
+        trace!("mailbox: frame {:?} paddr 0x{:x}", frame, paddr);
+        self.frame = Some((frame, paddr));
+        Ok(paddr)
+    }
+}
+impl MailboxTransport for Sel4Mailbox {
+    fn transact(&mut self, buf: &mut [u8], len: usize) -> Result<usize, MailboxError> {
+        let paddr = self.setup()?;
+        self.region.as_mut()[..len].copy_from_slice(&buf[..len]);
+
+        let mut reply_paddr: u32 = 0;
+        let mut reply_len: u32 = 0;
+        unsafe {
+            mailbox_api_send(paddr as u32, len as u32);
+            mailbox_api_receive(&mut reply_paddr as *mut u32, &mut reply_len as *mut u32);
+        }
+        // NB: the security core replies in the request page.
+        let reply_len = reply_len as usize;
+        if reply_paddr as usize != paddr || reply_len > buf.len() {
+            return Err(MailboxError::TransportFailed);
+        }
+        buf[..reply_len].copy_from_slice(&self.region.as_ref()[..reply_len]);
+        Ok(reply_len)
+    }
+}
+
+// Maps a security core failure to a SecurityRequestError; |default| is
+// used for errors without a direct equivalent.
+fn mailbox_error(err: MailboxError, default: SecurityRequestError) -> SecurityRequestError {
+    trace!("security core request failed: {:?}", err);
+    match err {
+        MailboxError::Status(Status::BundleNotFound) => SreBundleNotFound,
+        MailboxError::Status(Status::DeleteFirst) => SreDeleteFirst,
+        MailboxError::Status(Status::KeyNotFound) => SreKeyNotFound,
+        MailboxError::Status(Status::KeyInvalid) => SreKeyInvalid,
+        MailboxError::Status(Status::ValueInvalid) => SreValueInvalid,
+        MailboxError::Status(Status::QuotaExceeded) => SreQuotaExceeded,
+        MailboxError::Status(Status::CryptoFailed) => SreCryptoFailed,
+        _ => default,
+    }
+}
+
+// Returns the manifest of the |size| byte package in |pages| and its
+// location in the package.
+fn package_manifest(
+    pages: &mut PageMapper,
+    size: usize,
+) -> Result<(String, Range<usize>), SecurityRequestError> {
+    let mut verifier = PackageVerifier::new();
+    let mut collector = ManifestCollector::new();
+    let mut offset = 0;
+    while offset < size {
+        let page = pages.at(offset).map_err(|_| SreInstallFailed)?;
+        let len = core::cmp::min(page.len(), size - offset);
+        if verifier.update(&page[..len]).is_err() {
+            break; // NB: reported below as a missing manifest
+        }
+        collector.update(&verifier, offset, &page[..len])?;
+        offset += len;
+        if matches!(verifier.manifest_range(), Some(range) if range.end <= offset) {
+            break;
+        }
+    }
+    let manifest = String::from(collector.manifest(&verifier)?);
+    Ok((manifest, verifier.manifest_range().unwrap()))
+}
+
+// Copies the |size| byte image of |bundle_id| into the frames of |image|.
+fn read_image(
+    client: &mut SecurityCoreClient<Sel4Mailbox>,
+    bundle_id: &str,
+    image: &ObjDescBundle,
+    size: usize,
+    default: SecurityRequestError,
+) -> Result<(), SecurityRequestError> {
+    let mut pages = PageMapper::new(
+        image,
+        CopyRegion::new(unsafe { ptr::addr_of_mut!(DEEP_COPY_DEST[0]) }, PAGE_SIZE),
+    );
+    let mut offset = 0;
+    while offset < size {
+        // NB: MAX_DATA_CHUNK divides PAGE_SIZE so chunks never span frames
+        let len = core::cmp::min(MAX_DATA_CHUNK, size - offset);
+        let page = pages.at(offset).map_err(|_| default)?;
+        let n = client
+            .read_image(bundle_id, offset, &mut page[..len])
+            .map_err(|e| mailbox_error(e, default))?;
+        if n != len {
+            warn!("{}: image short, {} of {} bytes", bundle_id, offset + n, size);
+            return Err(default);
+        }
+        offset += len;
+    }
+    Ok(())
+}
+
+// Returns the signed digest of |bundle_id|'s package, computed from the
+// image held by the security core.
+fn package_digest(
+    client: &mut SecurityCoreClient<Sel4Mailbox>,
+    bundle_id: &str,
+) -> Result<PackageDigest, SecurityRequestError> {
+    let size = client
+        .size_buffer(bundle_id)
+        .map_err(|e| mailbox_error(e, SreAttestationFailed))?;
+    let mut verifier = PackageVerifier::new();
+    let mut chunk = [0u8; MAX_DATA_CHUNK];
+    let mut offset = 0;
+    while offset < size {
+        let len = core::cmp::min(MAX_DATA_CHUNK, size - offset);
+        let n = client
+            .read_image(bundle_id, offset, &mut chunk[..len])
+            .map_err(|e| mailbox_error(e, SreAttestationFailed))?;
+        if n == 0 {
+            warn!("{}: image short, {} of {} bytes", bundle_id, offset, size);
+            return Err(SreAttestationFailed);
+        }
+        let _ = verifier.update(&chunk[..n]); // NB: digest reports the error
+        offset += n;
+    }
+    verifier.digest().map_err(|_| SreAttestationFailed)
 }
 
 pub struct SeL4SecurityCoordinator {
-    // TODO(sleffler): mailbox api state
+    // NB: most requests take &self but talking to the security core
+    //   needs mutable state
+    core: Mutex<SecurityCoreClient<Sel4Mailbox>>,
+    // NB: held only in memory so the audit log is lost on reboot; the
+    //   security core stores key-value data only for installed bundles
+    //   and the mailbox protocol has no request for system records
+    // TODO(sleffler): persist once the security core can hold the log
+    audit: AuditLog,
+    // Seeded from the security core on first use.
+    drbg: Option<HmacDrbg>,
 }
 impl SeL4SecurityCoordinator {
-    pub fn new() -> Self { SeL4SecurityCoordinator {} }
+    pub fn new() -> Self {
+        SeL4SecurityCoordinator {
+            core: Mutex::new(SecurityCoreClient::new(Sel4Mailbox::new())),
+            audit: AuditLog::new(),
+            drbg: None,
+        }
+    }
+
+    // Returns the DRBG, (re)seeding it from the security core as needed.
+    fn drbg(&mut self) -> Result<&mut HmacDrbg, SecurityRequestError> {
+        let mut entropy = [0u8; MIN_ENTROPY_SIZE + MIN_ENTROPY_SIZE / 2];
+        let core = self.core.get_mut();
+        match self.drbg.as_mut() {
+            Some(drbg) if !drbg.needs_reseed() => {}
+            Some(drbg) => {
+                core.get_entropy(&mut entropy[..MIN_ENTROPY_SIZE])
+                    .map_err(|e| mailbox_error(e, SreGetRandomFailed))?;
+                drbg.reseed(&entropy[..MIN_ENTROPY_SIZE], &[])
+                    .map_err(|_| SreGetRandomFailed)?;
+            }
+            None => {
+                // NB: the nonce is taken from the same source
+                core.get_entropy(&mut entropy)
+                    .map_err(|e| mailbox_error(e, SreGetRandomFailed))?;
+                let (seed, nonce) = entropy.split_at(MIN_ENTROPY_SIZE);
+                self.drbg = Some(
+                    HmacDrbg::new(seed, nonce, DRBG_PERSONALIZATION)
+                        .map_err(|_| SreGetRandomFailed)?,
+                );
+            }
+        }
+        entropy.fill(0);
+        Ok(self.drbg.as_mut().unwrap())
+    }
+
+    // Returns |bundle_id|'s key-value storage quota from its manifest.
+    fn key_quota(
+        &self,
+        bundle_id: &str,
+        default: SecurityRequestError,
+    ) -> Result<KeyQuota, SecurityRequestError> {
+        self.core
+            .lock()
+            .get_manifest(bundle_id)
+            .map(|manifest| KeyQuota::from_manifest(&manifest))
+            .map_err(|e| mailbox_error(e, default))
+    }
+
+    // Returns a copy of |bundle_id|'s image fetched from the security core.
+    // The frames are in a container CNode (allocated from the slot allocator).
+    fn load_image(
+        &self,
+        bundle_id: &str,
+        default: SecurityRequestError,
+    ) -> Result<ObjDescBundle, SecurityRequestError> {
+        let mut client = self.core.lock();
+        let size = client
+            .size_buffer(bundle_id)
+            .map_err(|e| mailbox_error(e, default))?;
+        let image = kata_frame_alloc_in_cnode(size).map_err(|_| default)?;
+        if let Err(e) = read_image(&mut client, bundle_id, &image, size, default) {
+            let _ = kata_object_free_in_cnode(&image);
+            return Err(e);
+        }
+        Ok(image)
+    }
 }
 pub type KataSecurityCoordinatorInterface = SeL4SecurityCoordinator;
 
 impl SecurityCoordinatorInterface for SeL4SecurityCoordinator {
-    fn install(&mut self, _pkg_contents: &ObjDescBundle) -> Result<String, SecurityRequestError> {
-        Err(SreInstallFailed)
+    fn install(&mut self, pkg_contents: &ObjDescBundle) -> Result<String, SecurityRequestError> {
+        let size = pkg_contents.size_bytes();
+        if size == 0 {
+            return Err(SreInstallFailed);
+        }
+        let mut pages = PageMapper::new(
+            pkg_contents,
+            CopyRegion::new(unsafe { ptr::addr_of_mut!(DEEP_COPY_SRC[0]) }, PAGE_SIZE),
+        );
+        // Check the bundle id & version from the manifest before anything
+        // is sent to the security core.
+        let (manifest, manifest_range) = package_manifest(&mut pages, size)?;
+        let (bundle_id, version) = rollback::package_identity(&manifest)?;
+        let core = self.core.get_mut();
+        let highest = core
+            .read_version(&bundle_id)
+            .map_err(|e| mailbox_error(e, SreInstallFailed))?;
+        rollback::check_version(&bundle_id, version, highest)?;
+
+        // Stream the package to the security core, checking the signature
+        // and that the manifest is the one checked above before the last
+        // chunk so a bad package is never committed.
+        let mut verifier = PackageVerifier::new();
+        let mut collector = ManifestCollector::new();
+        let mut signer = "";
+        let result = core.install(size, &bundle_id, manifest_range.clone(), |offset, buf| {
+            let page = pages.at(offset).map_err(|_| SreInstallFailed)?;
+            buf.copy_from_slice(&page[..buf.len()]);
+            let _ = verifier.update(buf); // NB: verify reports the error
+            collector.update(&verifier, offset, buf)?;
+            if offset + buf.len() == size {
+                signer = crate::trusted_keys::verify(&verifier).map_err(|err| {
+                    warn!("Package signature check failed: {:?}", err);
+                    err
+                })?;
+                if verifier.manifest_range() != Some(manifest_range.clone())
+                    || collector.manifest(&verifier)? != manifest
+                {
+                    warn!("{}: package changed during install", &bundle_id);
+                    return Err(SreBundleDataInvalid);
+                }
+            }
+            Ok(())
+        });
+        // NB: on error the caller frees |pkg_contents| (see install_request)
+        match result {
+            Ok(_) => {}
+            Err(InstallError::Source(err)) => return Err(err),
+            Err(InstallError::Mailbox(err)) => return Err(mailbox_error(err, SreInstallFailed)),
+        }
+        if let Err(e) = core.write_version(&bundle_id, version) {
+            // NB: InstallEnd does not replace an installed bundle so this
+            //   removes only the package committed above
+            warn!("{}: version not recorded: {:?}", &bundle_id, e);
+            if let Err(e) = core.uninstall(&bundle_id) {
+                warn!("{}: uninstall failed: {:?}", &bundle_id, e);
+            }
+            return Err(mailbox_error(e, SreInstallFailed));
+        }
+        // The package is held by the security core; release our copy.
+        drop(pages);
+        if let Err(e) = kata_object_free_in_cnode(pkg_contents) {
+            warn!("{}: free of package failed: {:?}", &bundle_id, e);
+        }
+        trace!("Install {} version {} signed by {}", &bundle_id, version, signer);
+        Ok(bundle_id)
     }
-    fn uninstall(&mut self, _bundle_id: &str) -> Result<(), SecurityRequestError> {
-        Err(SreUninstallFailed)
+    fn uninstall(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        self.core
+            .get_mut()
+            .uninstall(bundle_id)
+            .map_err(|e| mailbox_error(e, SreUninstallFailed))
     }
-    fn size_buffer(&self, _bundle_id: &str) -> Result<usize, SecurityRequestError> {
-        Err(SreSizeBufferFailed)
+    fn allow_rollback(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        self.core
+            .get_mut()
+            .write_version(bundle_id, 0)
+            .map_err(|e| mailbox_error(e, SreAllowRollbackFailed))
     }
-    fn get_manifest(&self, _bundle_id: &str) -> Result<String, SecurityRequestError> {
-        Err(SreGetManifestFailed)
+    fn factory_reset(&mut self) -> Result<(), SecurityRequestError> {
+        self.core
+            .get_mut()
+            .factory_reset()
+            .map_err(|e| mailbox_error(e, SreFactoryResetFailed))?;
+        // NB: the caller records the reset as the first record that follows
+        self.audit.clear();
+        Ok(())
     }
-    fn load_application(&self, _bundle_id: &str) -> Result<ObjDescBundle, SecurityRequestError> {
-        Err(SreLoadApplicationFailed)
+    fn size_buffer(&self, bundle_id: &str) -> Result<usize, SecurityRequestError> {
+        self.core
+            .lock()
+            .size_buffer(bundle_id)
+            .map_err(|e| mailbox_error(e, SreSizeBufferFailed))
+    }
+    fn get_manifest(&self, bundle_id: &str) -> Result<String, SecurityRequestError> {
+        self.core
+            .lock()
+            .get_manifest(bundle_id)
+            .map_err(|e| mailbox_error(e, SreGetManifestFailed))
+    }
+    // NB: images are read from the security core so every frame is a
+    //   private copy; there is nothing to share.
+    fn load_application(&self, bundle_id: &str) -> Result<ImageFrames, SecurityRequestError> {
+        self.load_image(bundle_id, SreLoadApplicationFailed)
+            .map(ImageFrames::new_private)
     }
     fn load_model(
         &self,
-        _bundle_id: &str,
+        bundle_id: &str,
         _model_id: &str,
-    ) -> Result<ObjDescBundle, SecurityRequestError> {
-        Err(SreLoadModelFailed)
+    ) -> Result<ImageFrames, SecurityRequestError> {
+        // TODO(sleffler): check model id; for now return the package
+        self.load_image(bundle_id, SreLoadModelFailed)
+            .map(ImageFrames::new_private)
     }
-    fn read_key(
-        &self,
-        _bundle_id: &str,
-        _key: &str,
-    ) -> Result<&KeyValueData, SecurityRequestError> {
-        Err(SreReadFailed)
+    fn read_key(&self, bundle_id: &str, key: &str) -> Result<Vec<u8>, SecurityRequestError> {
+        self.core
+            .lock()
+            .read_key(bundle_id, key)
+            .map_err(|e| mailbox_error(e, SreReadFailed))
     }
     fn write_key(
         &mut self,
-        _bundle_id: &str,
-        _key: &str,
-        _value: &KeyValueData,
+        bundle_id: &str,
+        key: &str,
+        value: &[u8],
     ) -> Result<(), SecurityRequestError> {
-        Err(SreWriteFailed)
+        let quota = self.key_quota(bundle_id, SreWriteFailed)?;
+        self.core
+            .get_mut()
+            .write_key(bundle_id, key, value, quota.max_keys, quota.max_bytes)
+            .map_err(|e| mailbox_error(e, SreWriteFailed))
     }
-    fn delete_key(&mut self, _bundle_id: &str, _key: &str) -> Result<(), SecurityRequestError> {
-        Err(SreDeleteFailed)
+    fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError> {
+        match self.core.get_mut().delete_key(bundle_id, key) {
+            Err(MailboxError::Status(Status::KeyNotFound)) => Ok(()),
+            r => r.map_err(|e| mailbox_error(e, SreDeleteFailed)),
+        }
+    }
+    fn list_keys(
+        &self,
+        bundle_id: &str,
+        after: Option<&str>,
+    ) -> Result<(Vec<String>, bool), SecurityRequestError> {
+        self.core
+            .lock()
+            .list_keys(bundle_id, after, KEY_LIST_PAGE_BYTES)
+            .map_err(|e| mailbox_error(e, SreListKeysFailed))
+    }
+    fn get_key_usage(&self, bundle_id: &str) -> Result<KeyValueUsage, SecurityRequestError> {
+        let quota = self.key_quota(bundle_id, SreReadFailed)?;
+        let usage = self
+            .core
+            .lock()
+            .key_usage(bundle_id)
+            .map_err(|e| mailbox_error(e, SreReadFailed))?;
+        Ok(KeyValueUsage {
+            keys: usage.keys,
+            bytes: usage.bytes,
+            max_keys: quota.max_keys,
+            max_bytes: quota.max_bytes,
+        })
     }
 
-    fn test_mailbox(&mut self) -> Result<(), SecurityRequestError> {
-        trace!("test_mailbox_command()");
+    fn audit(&mut self, record: AuditRecord) { self.audit.append(record, None); }
+    fn get_audit_log(&self, after: Option<u32>) -> Result<Vec<AuditRecord>, SecurityRequestError> {
+        Ok(self.audit.records_after(after))
+    }
 
-        const MESSAGE_SIZE_DWORDS: usize = 17; // Just a random message size for testing.
+    fn get_random(&mut self, buf: &mut [u8]) -> Result<(), SecurityRequestError> {
+        self.drbg()?
+            .generate(buf, &[])
+            .map_err(|_| SreGetRandomFailed)
+    }
 
-        extern "C" {
-            fn mailbox_api_send(paddr: u32, size: u32);
-            fn mailbox_api_receive(paddr: *mut u32, size: *mut u32);
+    // NB: crypto keys are held by the security core; only the results
+    //   of operations are returned
+    fn crypto_generate(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        key_type: CryptoKeyType,
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.core
+            .get_mut()
+            .crypto_generate(bundle_id, name, crypto_keys::key_type(key_type) as u32)
+            .map_err(|e| mailbox_error(e, SreCryptoFailed))
+    }
+    fn crypto_delete(&mut self, bundle_id: &str, name: &str) -> Result<(), SecurityRequestError> {
+        self.core
+            .get_mut()
+            .crypto_delete(bundle_id, name)
+            .map_err(|e| mailbox_error(e, SreDeleteFailed))
+    }
+    fn crypto_sign(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.core
+            .lock()
+            .crypto_sign(bundle_id, name, data)
+            .map_err(|e| mailbox_error(e, SreCryptoFailed))
+    }
+    fn crypto_verify(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SecurityRequestError> {
+        self.core
+            .lock()
+            .crypto_verify(bundle_id, name, data, signature)
+            .map_err(|e| mailbox_error(e, SreCryptoFailed))
+    }
+    fn crypto_mac(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.core
+            .lock()
+            .crypto_mac(bundle_id, name, data)
+            .map_err(|e| mailbox_error(e, SreCryptoFailed))
+    }
+    fn crypto_encrypt(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.core
+            .get_mut()
+            .crypto_encrypt(bundle_id, name, data)
+            .map_err(|e| mailbox_error(e, SreCryptoFailed))
+    }
+    fn crypto_decrypt(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.core
+            .lock()
+            .crypto_decrypt(bundle_id, name, data)
+            .map_err(|e| mailbox_error(e, SreCryptoFailed))
+    }
+
+    fn get_attestation_report(
+        &self,
+        nonce: &[u8],
+    ) -> Result<AttestationReport, SecurityRequestError> {
+        let mut client = self.core.lock();
+        let mut report = Report::new(SYSTEM_VERSION, nonce);
+        let mut after: Option<String> = None;
+        loop {
+            let (mut bundle_ids, more) = client
+                .list_bundles(after.as_deref(), BUNDLE_LIST_PAGE_BYTES)
+                .map_err(|e| mailbox_error(e, SreAttestationFailed))?;
+            for bundle_id in &bundle_ids {
+                let manifest = client
+                    .get_manifest(bundle_id)
+                    .map_err(|e| mailbox_error(e, SreAttestationFailed))?;
+                let pkg_digest = package_digest(&mut client, bundle_id)?;
+                report.add_bundle(bundle_id, manifest_version(&manifest), &pkg_digest);
+            }
+            if !more {
+                break;
+            }
+            // NB: an empty page with more set would never finish
+            after = Some(bundle_ids.pop().ok_or(SreAttestationFailed)?);
         }
+        let report = report.encode();
+        let (public_key, signature) = client
+            .attest_sign(&kata_attestation::report_digest(report.as_bytes()))
+            .map_err(|e| mailbox_error(e, SreAttestationFailed))?;
+        Ok(AttestationReport {
+            report,
+            public_key,
+            signature,
+        })
+    }
 
-        // Allocate a 4k page to serve as our message buffer.
-        let frame_bundle =
-            kata_frame_alloc(PAGE_SIZE).map_err(|_| SecurityRequestError::SreTestFailed)?;
-        trace!("test_mailbox: Frame {:?}", frame_bundle);
+    fn test_mailbox(&mut self) -> Result<(), SecurityRequestError> {
+        trace!("test_mailbox_command()");
 
-        unsafe {
-            // Map the message buffer into our copyregion so we can access it.
-            // NB: re-use one of the deep_copy copyregions.
-            let mut msg_region = CopyRegion::new(ptr::addr_of_mut!(DEEP_COPY_SRC[0]), PAGE_SIZE);
-            msg_region
-                .map(frame_bundle.objs[0].cptr)
-                .map_err(|_| SecurityRequestError::SreTestFailed)?;
-
-            let message_ptr = msg_region.as_word_mut();
-
-            // Write to the message buffer through the copyregion.
-            let offset_a = 0;
-            let offset_b = MESSAGE_SIZE_DWORDS - 1;
-            message_ptr[offset_a] = 0xDEADBEEF;
-            message_ptr[offset_b] = 0xF00DCAFE;
-            trace!(
-                "test_mailbox: old buf contents  0x{:X} 0x{:X}",
-                message_ptr[offset_a],
-                message_ptr[offset_b]
-            );
-
-            // Send the _physical_ address of the message buffer to the security
-            // core.
-            let paddr = seL4_Page_GetAddress(frame_bundle.objs[0].cptr);
-            mailbox_api_send(paddr.paddr as u32, (MESSAGE_SIZE_DWORDS * size_of::<u32>()) as u32);
-
-            // Wait for the response to arrive.
-            let mut response_paddr: u32 = 0;
-            let mut response_size: u32 = 0;
-            mailbox_api_receive(&mut response_paddr as *mut u32, &mut response_size as *mut u32);
-
-            // The security core should have replaced the first and last dwords
-            // with 0x12345678 and 0x87654321.
-            trace!("test_mailbox: expected contents 0x12345678 0x87654321");
-            trace!(
-                "test_mailbox: new buf contents  0x{:X} 0x{:X}",
-                message_ptr[offset_a],
-                message_ptr[offset_b]
-            );
-
-            let dword_a = message_ptr[offset_a];
-            let dword_b = message_ptr[offset_b];
-
-            msg_region
-                .unmap()
-                .map_err(|_| SecurityRequestError::SreTestFailed)?;
-
-            // Done, free the message buffer.
-            kata_object_free_toplevel(&frame_bundle)
-                .map_err(|_| SecurityRequestError::SreTestFailed)?;
-
-            if dword_a != 0x12345678 || dword_b != 0x87654321 {
-                return Err(SecurityRequestError::SreTestFailed);
-            }
+        const MESSAGE: &[u8] = b"KataOS security core mailbox test";
+        let reply = self.core.get_mut().echo(MESSAGE).map_err(|e| {
+            trace!("test_mailbox: echo failed: {:?}", e);
+            SreTestFailed
+        })?;
+        if reply != MESSAGE {
+            trace!("test_mailbox: echo mismatch: {:?}", reply);
+            return Err(SreTestFailed);
         }
 
         trace!("test_mailbox_command() done");
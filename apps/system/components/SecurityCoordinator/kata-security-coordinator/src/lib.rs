@@ -21,8 +21,13 @@
 extern crate alloc;
 use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::vec::Vec;
 use kata_memory_interface::ObjDescBundle;
-use kata_security_interface::KeyValueData;
+use kata_security_interface::AttestationReport;
+use kata_security_interface::AuditRecord;
+use kata_security_interface::CryptoKeyType;
+use kata_security_interface::ImageFrames;
+use kata_security_interface::KeyValueUsage;
 use kata_security_interface::SecurityCoordinatorInterface;
 use kata_security_interface::SecurityRequestError;
 
@@ -34,6 +39,24 @@ compile_error!("features \"fake\" and \"sel4\" are mutually exclusive");
 mod platform;
 pub use platform::KataSecurityCoordinatorInterface;
 
+pub mod access;
+mod attestation;
+#[cfg(feature = "fake")]
+mod attestation_key;
+// NB: the sel4 platform has no local storage to persist the log
+#[cfg_attr(feature = "sel4", allow(dead_code))]
+mod audit;
+mod crypto_keys;
+mod page_mapper;
+mod rollback;
+#[cfg(feature = "fake")]
+mod root_secret;
+// NB: the sel4 platform uses only the quota support, keys are held
+//   by the security core
+#[cfg_attr(feature = "sel4", allow(dead_code))]
+mod storage;
+mod trusted_keys;
+
 #[cfg(not(test))]
 pub static mut KATA_SECURITY: KataSecurityCoordinator = KataSecurityCoordinator::empty();
 
@@ -60,33 +83,39 @@ impl SecurityCoordinatorInterface for KataSecurityCoordinator {
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
         self.manager.as_mut().unwrap().uninstall(bundle_id)
     }
+    fn allow_rollback(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError> {
+        self.manager.as_mut().unwrap().allow_rollback(bundle_id)
+    }
+    fn factory_reset(&mut self) -> Result<(), SecurityRequestError> {
+        self.manager.as_mut().unwrap().factory_reset()
+    }
     fn size_buffer(&self, bundle_id: &str) -> Result<usize, SecurityRequestError> {
         self.manager.as_ref().unwrap().size_buffer(bundle_id)
     }
     fn get_manifest(&self, bundle_id: &str) -> Result<String, SecurityRequestError> {
         self.manager.as_ref().unwrap().get_manifest(bundle_id)
     }
-    fn load_application(&self, bundle_id: &str) -> Result<ObjDescBundle, SecurityRequestError> {
+    fn load_application(&self, bundle_id: &str) -> Result<ImageFrames, SecurityRequestError> {
         self.manager.as_ref().unwrap().load_application(bundle_id)
     }
     fn load_model(
         &self,
         bundle_id: &str,
         model_id: &str,
-    ) -> Result<ObjDescBundle, SecurityRequestError> {
+    ) -> Result<ImageFrames, SecurityRequestError> {
         self.manager
             .as_ref()
             .unwrap()
             .load_model(bundle_id, model_id)
     }
-    fn read_key(&self, bundle_id: &str, key: &str) -> Result<&KeyValueData, SecurityRequestError> {
+    fn read_key(&self, bundle_id: &str, key: &str) -> Result<Vec<u8>, SecurityRequestError> {
         self.manager.as_ref().unwrap().read_key(bundle_id, key)
     }
     fn write_key(
         &mut self,
         bundle_id: &str,
         key: &str,
-        value: &KeyValueData,
+        value: &[u8],
     ) -> Result<(), SecurityRequestError> {
         self.manager
             .as_mut()
@@ -96,6 +125,102 @@ impl SecurityCoordinatorInterface for KataSecurityCoordinator {
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError> {
         self.manager.as_mut().unwrap().delete_key(bundle_id, key)
     }
+    fn list_keys(
+        &self,
+        bundle_id: &str,
+        after: Option<&str>,
+    ) -> Result<(Vec<String>, bool), SecurityRequestError> {
+        self.manager.as_ref().unwrap().list_keys(bundle_id, after)
+    }
+    fn get_key_usage(&self, bundle_id: &str) -> Result<KeyValueUsage, SecurityRequestError> {
+        self.manager.as_ref().unwrap().get_key_usage(bundle_id)
+    }
+    fn audit(&mut self, record: AuditRecord) { self.manager.as_mut().unwrap().audit(record) }
+    fn get_audit_log(&self, after: Option<u32>) -> Result<Vec<AuditRecord>, SecurityRequestError> {
+        self.manager.as_ref().unwrap().get_audit_log(after)
+    }
+    fn get_random(&mut self, buf: &mut [u8]) -> Result<(), SecurityRequestError> {
+        self.manager.as_mut().unwrap().get_random(buf)
+    }
+    fn crypto_generate(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        key_type: CryptoKeyType,
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.manager
+            .as_mut()
+            .unwrap()
+            .crypto_generate(bundle_id, name, key_type)
+    }
+    fn crypto_delete(&mut self, bundle_id: &str, name: &str) -> Result<(), SecurityRequestError> {
+        self.manager
+            .as_mut()
+            .unwrap()
+            .crypto_delete(bundle_id, name)
+    }
+    fn crypto_sign(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.manager
+            .as_ref()
+            .unwrap()
+            .crypto_sign(bundle_id, name, data)
+    }
+    fn crypto_verify(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SecurityRequestError> {
+        self.manager
+            .as_ref()
+            .unwrap()
+            .crypto_verify(bundle_id, name, data, signature)
+    }
+    fn crypto_mac(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.manager
+            .as_ref()
+            .unwrap()
+            .crypto_mac(bundle_id, name, data)
+    }
+    fn crypto_encrypt(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.manager
+            .as_mut()
+            .unwrap()
+            .crypto_encrypt(bundle_id, name, data)
+    }
+    fn crypto_decrypt(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        self.manager
+            .as_ref()
+            .unwrap()
+            .crypto_decrypt(bundle_id, name, data)
+    }
+    fn get_attestation_report(
+        &self,
+        nonce: &[u8],
+    ) -> Result<AttestationReport, SecurityRequestError> {
+        self.manager.as_ref().unwrap().get_attestation_report(nonce)
+    }
     fn test_mailbox(&mut self) -> Result<(), SecurityRequestError> {
         self.manager.as_mut().unwrap().test_mailbox()
     }
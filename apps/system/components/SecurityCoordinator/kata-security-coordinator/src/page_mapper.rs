@@ -0,0 +1,83 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Frame-at-a-time access to the frames of an ObjDescBundle.
+
+use kata_memory_interface::ObjDescBundle;
+use kata_os_common::copyregion::CopyRegion;
+use kata_os_common::cspace_slot::CSpaceSlot;
+use kata_os_common::sel4_sys;
+
+use sel4_sys::seL4_Error;
+use sel4_sys::seL4_Result;
+
+// Maps the frames of |bundle| one at a time through |region|. Frames
+// may be of mixed sizes but each must fit in |region|.
+pub struct PageMapper<'a> {
+    bundle: &'a ObjDescBundle,
+    slot: CSpaceSlot,
+    region: CopyRegion,
+    mapped: Option<usize>, // Index of the mapped frame
+}
+impl<'a> PageMapper<'a> {
+    pub fn new(bundle: &'a ObjDescBundle, region: CopyRegion) -> Self {
+        PageMapper {
+            bundle,
+            slot: CSpaceSlot::new(),
+            region,
+            mapped: None,
+        }
+    }
+
+    // Returns frame |index| of the bundle, mapping it if needed.
+    pub fn page(&mut self, index: usize) -> Result<&mut [u8], seL4_Error> {
+        if self.mapped != Some(index) {
+            self.unmap()?;
+            let (cptr, frame_bytes) = self
+                .bundle
+                .frame_iter()
+                .nth(index)
+                .ok_or(seL4_Error::seL4_RangeError)?;
+            self.slot
+                .dup_to(self.bundle.cnode, cptr, self.bundle.depth)
+                .and_then(|_| self.region.map_sized(self.slot.slot, frame_bytes))?;
+            self.mapped = Some(index);
+        }
+        Ok(self.region.as_mut())
+    }
+
+    // Returns the bytes of the bundle from |offset| to the end of the
+    // frame holding it, mapping the frame if needed.
+    pub fn at(&mut self, offset: usize) -> Result<&mut [u8], seL4_Error> {
+        let bundle = self.bundle;
+        let mut frame_off = 0;
+        for (index, (_, frame_bytes)) in bundle.frame_iter().enumerate() {
+            if offset < frame_off + frame_bytes {
+                return Ok(&mut self.page(index)?[offset - frame_off..]);
+            }
+            frame_off += frame_bytes;
+        }
+        Err(seL4_Error::seL4_RangeError)
+    }
+
+    pub fn unmap(&mut self) -> seL4_Result {
+        if self.mapped.take().is_some() {
+            self.region.unmap().and_then(|_| self.slot.delete())?;
+        }
+        Ok(())
+    }
+}
+impl<'a> Drop for PageMapper<'a> {
+    fn drop(&mut self) { let _ = self.unmap(); }
+}
@@ -0,0 +1,267 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Anti-rollback support shared by the platform implementations.
+//!
+//! A package's manifest (a signed section of the package) names the
+//! bundle ([Manifest] BundleId=) and carries a version number ([Manifest]
+//! Version=, 0 if absent) that must increase with each release. The
+//! highest version installed for each bundle id is recorded and kept when
+//! the bundle is uninstalled or the device is factory reset; installing an
+//! older version is rejected (before anything is committed) until the
+//! record is cleared with allow_rollback.
+
+use crate::attestation::{manifest_field, manifest_version};
+use crate::storage::KeyStore;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp;
+use kata_package_signature::PackageVerifier;
+use kata_security_interface::SecurityRequestError;
+use kata_storage::StorageError;
+use log::warn;
+
+// Max bytes of a package manifest.
+pub const MAX_MANIFEST_SIZE: usize = 4096;
+
+// Max bytes of a bundle id; the key store is sized for ids this long.
+pub const MAX_BUNDLE_ID_LEN: usize = 64;
+
+// Namespace holding the highest version installed, keyed by bundle id.
+// NB: bundle ids never start with '.'.
+#[cfg_attr(feature = "sel4", allow(dead_code))]
+pub const VERSION_NS: &str = ".version";
+
+// Returns the version of the package with |manifest|.
+pub fn package_version(manifest: &str) -> Result<u32, SecurityRequestError> {
+    match manifest_version(manifest) {
+        "" => Ok(0),
+        version => version.parse::<u32>().map_err(|_| {
+            warn!("Manifest version {} invalid", version);
+            SecurityRequestError::SreBundleDataInvalid
+        }),
+    }
+}
+
+// Returns the bundle id & version of the package with |manifest|.
+// NB: bundle ids starting with '.' are reserved for internal use.
+pub fn package_identity(manifest: &str) -> Result<(String, u32), SecurityRequestError> {
+    let bundle_id = manifest_field(manifest, "BundleId");
+    if bundle_id.is_empty() || bundle_id.len() > MAX_BUNDLE_ID_LEN || bundle_id.starts_with('.') {
+        warn!("Manifest bundle id {:?} invalid", bundle_id);
+        return Err(SecurityRequestError::SreBundleIdInvalid);
+    }
+    Ok((String::from(bundle_id), package_version(manifest)?))
+}
+
+// Collects a package's manifest as the package is fed, in order, through
+// a PackageVerifier.
+#[derive(Default)]
+pub struct ManifestCollector {
+    bytes: Vec<u8>,
+}
+impl ManifestCollector {
+    pub fn new() -> Self { Self::default() }
+
+    // Adds any manifest data in |chunk|, the package bytes at |offset|.
+    // Call after |chunk| is fed to |verifier|.
+    pub fn update(
+        &mut self,
+        verifier: &PackageVerifier,
+        offset: usize,
+        chunk: &[u8],
+    ) -> Result<(), SecurityRequestError> {
+        if let Some(range) = verifier.manifest_range() {
+            if range.len() > MAX_MANIFEST_SIZE {
+                warn!("Manifest too large ({} bytes)", range.len());
+                return Err(SecurityRequestError::SreBundleDataInvalid);
+            }
+            let start = cmp::max(range.start, offset);
+            let end = cmp::min(range.end, offset + chunk.len());
+            if start < end {
+                self.bytes
+                    .extend_from_slice(&chunk[start - offset..end - offset]);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the manifest; fails if the package has no manifest or not
+    // all of it has been seen.
+    pub fn manifest(&self, verifier: &PackageVerifier) -> Result<&str, SecurityRequestError> {
+        match verifier.manifest_range() {
+            Some(range) if range.len() == self.bytes.len() => core::str::from_utf8(&self.bytes)
+                .map_err(|_| SecurityRequestError::SreBundleDataInvalid),
+            Some(_) => Err(SecurityRequestError::SreBundleDataInvalid),
+            None => {
+                warn!("Package has no manifest");
+                Err(SecurityRequestError::SreBundleDataInvalid)
+            }
+        }
+    }
+}
+
+// Checks installing |version| of |bundle_id| is not a rollback from the
+// |highest| version installed.
+pub fn check_version(
+    bundle_id: &str,
+    version: u32,
+    highest: u32,
+) -> Result<(), SecurityRequestError> {
+    if version < highest {
+        warn!(
+            "{}: version {} is older than installed version {}",
+            bundle_id, version, highest
+        );
+        return Err(SecurityRequestError::SreVersionRollback);
+    }
+    Ok(())
+}
+
+// Returns the highest version recorded in |store| for |bundle_id| (0 if none).
+#[cfg_attr(feature = "sel4", allow(dead_code))]
+pub fn read_version(store: &KeyStore, bundle_id: &str) -> u32 {
+    store
+        .read(VERSION_NS, bundle_id)
+        .ok()
+        .and_then(|value| value.try_into().ok())
+        .map_or(0, u32::from_le_bytes)
+}
+
+// Records |version| as the highest installed for |bundle_id|; 0 forgets
+// any recorded version.
+#[cfg_attr(feature = "sel4", allow(dead_code))]
+pub fn write_version(
+    store: &mut KeyStore,
+    bundle_id: &str,
+    version: u32,
+) -> Result<(), StorageError> {
+    if version != 0 {
+        return store.write(VERSION_NS, bundle_id, &version.to_le_bytes());
+    }
+    match store.delete(VERSION_NS, bundle_id) {
+        Err(StorageError::NotFound) => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kata_package_signature::{manifest_header, SECTION_HEADER_SIZE, SECTION_MAGIC};
+    use kata_security_interface::SecurityRequestError::*;
+
+    const MANIFEST: &str = "[Manifest]\nBundleId=com.example.app\nVersion=7\n";
+
+    // Returns a package holding a code section & |manifest|.
+    fn package(manifest: &str) -> Vec<u8> {
+        let mut pkg = Vec::new();
+        pkg.extend_from_slice(&SECTION_MAGIC.to_be_bytes());
+        pkg.resize(SECTION_HEADER_SIZE, 0); // NB: fsize 0
+        pkg.extend_from_slice(&manifest_header(manifest.len()));
+        pkg.extend_from_slice(manifest.as_bytes());
+        pkg
+    }
+
+    #[test]
+    fn test_package_version() {
+        assert_eq!(package_version(MANIFEST), Ok(7));
+        assert_eq!(package_version("[Manifest]\nBundleId=x\n"), Ok(0));
+        assert_eq!(package_version("[Other]\nVersion=3\n"), Ok(0));
+        assert_eq!(package_version("[Manifest]\nVersion=-1\n"), Err(SreBundleDataInvalid));
+        assert_eq!(
+            package_version("[Manifest]\nVersion=1.2\n"),
+            Err(SreBundleDataInvalid)
+        );
+    }
+
+    #[test]
+    fn test_check_version() {
+        assert_eq!(check_version("a", 0, 0), Ok(()));
+        assert_eq!(check_version("a", 3, 3), Ok(()));
+        assert_eq!(check_version("a", 4, 3), Ok(()));
+        assert_eq!(check_version("a", 2, 3), Err(SreVersionRollback));
+    }
+
+    #[test]
+    fn test_package_identity() {
+        assert_eq!(package_identity(MANIFEST), Ok((String::from("com.example.app"), 7)));
+        assert_eq!(package_identity("[Manifest]\nVersion=1\n"), Err(SreBundleIdInvalid));
+        assert_eq!(
+            package_identity("[Manifest]\nBundleId=.version\n"),
+            Err(SreBundleIdInvalid)
+        );
+        let long_id = "a".repeat(MAX_BUNDLE_ID_LEN + 1);
+        assert_eq!(
+            package_identity(&format!("[Manifest]\nBundleId={}\n", &long_id[1..])),
+            Ok((String::from(&long_id[1..]), 0))
+        );
+        assert_eq!(
+            package_identity(&format!("[Manifest]\nBundleId={}\n", long_id)),
+            Err(SreBundleIdInvalid)
+        );
+    }
+
+    #[test]
+    fn test_manifest_collector() {
+        let pkg = package(MANIFEST);
+        for chunk in [1, 7, 48, 4096] {
+            let mut verifier = PackageVerifier::new();
+            let mut collector = ManifestCollector::new();
+            for (index, bytes) in pkg.chunks(chunk).enumerate() {
+                let _ = verifier.update(bytes);
+                collector.update(&verifier, index * chunk, bytes).unwrap();
+            }
+            assert_eq!(collector.manifest(&verifier), Ok(MANIFEST));
+        }
+
+        // Truncated.
+        let mut verifier = PackageVerifier::new();
+        let mut collector = ManifestCollector::new();
+        let bytes = &pkg[..pkg.len() - 1];
+        let _ = verifier.update(bytes);
+        collector.update(&verifier, 0, bytes).unwrap();
+        assert_eq!(collector.manifest(&verifier), Err(SreBundleDataInvalid));
+
+        // No manifest.
+        let mut verifier = PackageVerifier::new();
+        let _ = verifier.update(&pkg[..SECTION_HEADER_SIZE]);
+        assert_eq!(
+            ManifestCollector::new().manifest(&verifier),
+            Err(SreBundleDataInvalid)
+        );
+
+        // Too large.
+        let mut verifier = PackageVerifier::new();
+        let header = manifest_header(MAX_MANIFEST_SIZE + 1);
+        let _ = verifier.update(&header);
+        assert_eq!(
+            ManifestCollector::new().update(&verifier, 0, &header),
+            Err(SreBundleDataInvalid)
+        );
+    }
+
+    #[test]
+    fn test_version_records() {
+        let mut store = crate::storage::new_key_store();
+        assert_eq!(read_version(&store, "a"), 0);
+        write_version(&mut store, "a", 3).unwrap();
+        assert_eq!(read_version(&store, "a"), 3);
+        assert_eq!(read_version(&store, "b"), 0);
+        write_version(&mut store, "a", 0).unwrap();
+        assert_eq!(read_version(&store, "a"), 0);
+        // Forgetting a version that is not recorded is ok.
+        write_version(&mut store, "a", 0).unwrap();
+    }
+}
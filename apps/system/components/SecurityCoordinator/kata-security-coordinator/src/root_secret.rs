@@ -0,0 +1,25 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Device root secret; per-bundle storage keys are derived from it.
+
+use kata_sealed_storage::RootSecret;
+
+#[cfg(not(feature = "test_root_secret"))]
+compile_error!("no device root secret available; enable \"test_root_secret\"");
+
+// TODO(sleffler): have the security core derive bundle keys so the root
+//   secret never leaves it
+#[cfg(feature = "test_root_secret")]
+pub static ROOT_SECRET: RootSecret = kata_sealed_storage::TEST_ROOT_SECRET;
@@ -0,0 +1,435 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Key-value storage shared by the platform implementations.
+
+use crate::audit::AUDIT_STORE_BYTES;
+use crate::rollback::{MAX_BUNDLE_ID_LEN, VERSION_NS};
+use alloc::fmt;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use kata_crypto_keys::{CryptoKey, KEY_BYTES_SIZE, MAX_BUNDLE_KEYS, MAX_NAME_LEN};
+use kata_sealed_storage::{sealed_len, NonceSequence, RootSecret, SealingKey};
+use kata_security_interface::KeyValueUsage;
+use kata_security_interface::SecurityRequestError;
+use kata_security_interface::KEY_LIST_PAGE_BYTES;
+use kata_storage::KeyValueStore;
+use kata_storage::RamBackend;
+use kata_storage::StorageError;
+use kata_storage::{RECORD_HEADER_SIZE, REGION_HEADER_SIZE};
+use log::warn;
+
+// NB: the store is RAM-only; nothing on this path reaches flash so
+//   keys, the key epoch, version records (rollback.rs) and the audit
+//   log (audit.rs) are lost on reboot. kata-storage provides only the
+//   RAM and (host-only) file backends; a flash-backed StorageBackend
+//   needs flash access that neither platform exposes to this component.
+// TODO(sleffler): back with flash (via the security core) once available
+const KEY_STORE_BLOCK_SIZE: usize = 4096;
+
+// Bundles the store holds with each filled to DEFAULT_KEY_QUOTA and
+// MAX_BUNDLE_KEYS crypto keys; the space is shared so a bundle with a
+// larger quota leaves room for fewer.
+pub const KEY_STORE_BUNDLES: usize = 4;
+
+// Space one bundle at |quota| takes in the store. The quota counts key
+// names & sealed values; each record also has a header & the namespace.
+const fn bundle_store_bytes(quota: &KeyQuota) -> usize {
+    let data = quota.max_bytes + quota.max_keys * (RECORD_HEADER_SIZE + MAX_BUNDLE_ID_LEN);
+    let crypto = MAX_BUNDLE_KEYS
+        * (RECORD_HEADER_SIZE
+            + (CRYPTO_PREFIX.len() + MAX_BUNDLE_ID_LEN)
+            + (CRYPTO_PREFIX.len() + MAX_NAME_LEN)
+            + sealed_len(KEY_BYTES_SIZE));
+    let epoch = RECORD_HEADER_SIZE + EPOCH_NS.len() + MAX_BUNDLE_ID_LEN + EPOCH_SIZE;
+    let version = RECORD_HEADER_SIZE + VERSION_NS.len() + MAX_BUNDLE_ID_LEN + VERSION_SIZE;
+    data + crypto + epoch + version
+}
+
+// Bytes of records the store must hold: the bundles, the audit log and
+// the next epoch counter.
+const KEY_STORE_BYTES: usize = KEY_STORE_BUNDLES * bundle_store_bytes(&DEFAULT_KEY_QUOTA)
+    + AUDIT_STORE_BYTES
+    + (RECORD_HEADER_SIZE + EPOCH_NS.len() + NEXT_EPOCH_KEY.len() + EPOCH_SIZE);
+const KEY_STORE_REGION_BLOCKS: usize =
+    (REGION_HEADER_SIZE + KEY_STORE_BYTES + KEY_STORE_BLOCK_SIZE - 1) / KEY_STORE_BLOCK_SIZE;
+const KEY_STORE_BLOCKS: usize = 2 * KEY_STORE_REGION_BLOCKS; // NB: 2 regions
+
+// Keys are stored with the bundle id as the namespace.
+pub type KeyStore = KeyValueStore<RamBackend>;
+
+pub fn new_key_store() -> KeyStore {
+    KeyValueStore::new(RamBackend::new(KEY_STORE_BLOCK_SIZE, KEY_STORE_BLOCKS)).expect("key store")
+}
+
+// Namespace holding each bundle's key epoch (keyed by bundle id) and the
+// next epoch to hand out. NB: bundle ids never start with '.'.
+const EPOCH_NS: &str = ".epoch";
+const NEXT_EPOCH_KEY: &str = ".next";
+const EPOCH_SIZE: usize = 8; // NB: u64
+const VERSION_SIZE: usize = 4; // NB: u32, see rollback::write_version
+
+// Namespace holding |bundle_id|'s crypto keys; they are kept apart from
+// its key-value data so the key material is never returned to the bundle.
+const CRYPTO_PREFIX: &str = ".crypto:";
+fn crypto_ns(bundle_id: &str) -> String {
+    fmt::format(format_args!("{}{}", CRYPTO_PREFIX, bundle_id))
+}
+// Stored name of crypto key |name|; see read_crypto_key.
+fn crypto_name(name: &str) -> String { fmt::format(format_args!("{}{}", CRYPTO_PREFIX, name)) }
+
+// KeyStore where each bundle's values are sealed with a key derived from
+// the device root secret, the bundle id, and the bundle's epoch (see
+// kata-sealed-storage). Quotas & usage count sealed (stored) bytes.
+pub struct SealedKeyStore {
+    store: KeyStore,
+    root_secret: &'static RootSecret,
+    nonces: NonceSequence,
+}
+impl SealedKeyStore {
+    pub fn new(root_secret: &'static RootSecret) -> Self {
+        SealedKeyStore {
+            store: new_key_store(),
+            root_secret,
+            nonces: NonceSequence::new(),
+        }
+    }
+
+    // Underlying store; only key names & sizes are meaningful.
+    pub fn store(&self) -> &KeyStore { &self.store }
+
+    // Underlying store, for unsealed system records (e.g. the audit log)
+    // held in reserved namespaces.
+    pub fn store_mut(&mut self) -> &mut KeyStore { &mut self.store }
+
+    fn read_epoch(&self, key: &str) -> Result<u64, StorageError> {
+        let value = self.store.read(EPOCH_NS, key)?;
+        let bytes: [u8; 8] = value
+            .as_slice()
+            .try_into()
+            .map_err(|_| StorageError::Corrupt)?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    // Returns |bundle_id|'s sealing key, creating it on first use.
+    fn create_bundle_key(&mut self, bundle_id: &str) -> Result<SealingKey, StorageError> {
+        let epoch = match self.read_epoch(bundle_id) {
+            Ok(epoch) => epoch,
+            Err(StorageError::NotFound) => {
+                let epoch = match self.read_epoch(NEXT_EPOCH_KEY) {
+                    Ok(epoch) => epoch,
+                    Err(StorageError::NotFound) => 0,
+                    Err(e) => return Err(e),
+                };
+                // NB: advance the counter first so a crash never re-issues
+                //   an epoch that may have been used
+                self.store
+                    .write(EPOCH_NS, NEXT_EPOCH_KEY, &(epoch + 1).to_be_bytes())?;
+                self.store
+                    .write(EPOCH_NS, bundle_id, &epoch.to_be_bytes())?;
+                epoch
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(SealingKey::derive(self.root_secret, bundle_id, epoch))
+    }
+
+    // Destroys |bundle_id|'s sealing key along with any data sealed with
+    // it. A later install of the same bundle gets a new key.
+    pub fn destroy_bundle_key(&mut self, bundle_id: &str) -> Result<(), StorageError> {
+        match self.store.delete(EPOCH_NS, bundle_id) {
+            Ok(_) | Err(StorageError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        self.store.delete_namespace(&crypto_ns(bundle_id))?;
+        self.store.delete_namespace(bundle_id)
+    }
+
+    // Opens the value sealed for |bundle_id| at |ns|:|key|.
+    fn open(
+        &self,
+        ns: &str,
+        bundle_id: &str,
+        key: &str,
+        default: SecurityRequestError,
+    ) -> Result<Vec<u8>, SecurityRequestError> {
+        let sealed = self
+            .store
+            .read(ns, key)
+            .map_err(|e| storage_error(e, default))?;
+        let epoch = self.read_epoch(bundle_id).map_err(|_| default)?;
+        SealingKey::derive(self.root_secret, bundle_id, epoch)
+            .open(key, &sealed)
+            .map_err(|e| {
+                warn!("Cannot open {}:{}: {:?}", ns, key, e);
+                default
+            })
+    }
+
+    // Seals |value| for |bundle_id| and writes it to |ns|:|key|.
+    fn seal(
+        &mut self,
+        ns: &str,
+        bundle_id: &str,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), SecurityRequestError> {
+        let bundle_key = self
+            .create_bundle_key(bundle_id)
+            .map_err(|e| storage_error(e, SecurityRequestError::SreWriteFailed))?;
+        let sealed = bundle_key.seal(key, value, &self.nonces.next_nonce());
+        self.store
+            .write(ns, key, &sealed)
+            .map_err(|e| storage_error(e, SecurityRequestError::SreWriteFailed))
+    }
+
+    pub fn read(&self, bundle_id: &str, key: &str) -> Result<Vec<u8>, SecurityRequestError> {
+        self.open(bundle_id, bundle_id, key, SecurityRequestError::SreReadFailed)
+    }
+
+    pub fn write(
+        &mut self,
+        bundle_id: &str,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), SecurityRequestError> {
+        self.seal(bundle_id, bundle_id, key, value)
+    }
+
+    // NB: crypto keys are sealed with the bundle's key but the names are
+    //   prefixed so a key cannot be opened as key-value data (or v.v.)
+    pub fn read_crypto_key(
+        &self,
+        bundle_id: &str,
+        name: &str,
+    ) -> Result<CryptoKey, SecurityRequestError> {
+        let bytes = self.open(
+            &crypto_ns(bundle_id),
+            bundle_id,
+            &crypto_name(name),
+            SecurityRequestError::SreCryptoFailed,
+        )?;
+        CryptoKey::from_bytes(&bytes).map_err(|_| SecurityRequestError::SreCryptoFailed)
+    }
+
+    // Adds |key| as |name| for |bundle_id|. Existing keys are never
+    // replaced; they must be deleted first.
+    pub fn write_crypto_key(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        key: &CryptoKey,
+    ) -> Result<(), SecurityRequestError> {
+        let ns = crypto_ns(bundle_id);
+        let name = crypto_name(name);
+        if self.store.contains_key(&ns, &name) {
+            return Err(SecurityRequestError::SreDeleteFirst);
+        }
+        if self.store.namespace_usage(&ns).keys >= MAX_BUNDLE_KEYS {
+            return Err(SecurityRequestError::SreQuotaExceeded);
+        }
+        let mut bytes = key.to_bytes();
+        let result = self.seal(&ns, bundle_id, &name, &bytes);
+        bytes.fill(0);
+        result
+    }
+
+    pub fn delete_crypto_key(&mut self, bundle_id: &str, name: &str) -> Result<(), StorageError> {
+        self.store.delete(&crypto_ns(bundle_id), &crypto_name(name))
+    }
+
+    pub fn delete(&mut self, bundle_id: &str, key: &str) -> Result<(), StorageError> {
+        self.store.delete(bundle_id, key)
+    }
+}
+
+// Per-bundle limits on key-value storage. Bytes count key names + values.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KeyQuota {
+    pub max_keys: usize,
+    pub max_bytes: usize,
+}
+
+// System default used when a bundle's manifest does not say otherwise.
+pub const DEFAULT_KEY_QUOTA: KeyQuota = KeyQuota {
+    max_keys: 32,
+    max_bytes: 4 * 1024,
+};
+
+impl KeyQuota {
+    // Returns the quota specified in the [Storage] section of |manifest|
+    // (MaxKeys & MaxBytes); anything missing or malformed gets the default.
+    pub fn from_manifest(manifest: &str) -> Self {
+        let mut quota = DEFAULT_KEY_QUOTA;
+        let mut in_storage = false;
+        for line in manifest.lines().map(|l| l.trim()) {
+            if line.starts_with('[') {
+                in_storage = line == "[Storage]";
+                continue;
+            }
+            if !in_storage {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.trim(), value.trim()),
+                _ => continue,
+            };
+            match (name, value.parse::<usize>()) {
+                ("MaxKeys", Ok(max_keys)) => quota.max_keys = max_keys,
+                ("MaxBytes", Ok(max_bytes)) => quota.max_bytes = max_bytes,
+                _ => {}
+            }
+        }
+        quota
+    }
+}
+
+// Checks writing |value_len| bytes to |ns|:|key| stays within |quota|.
+// NB: |value_len| is the stored size (i.e. after sealing).
+pub fn check_quota(
+    keys: &KeyStore,
+    ns: &str,
+    key: &str,
+    value_len: usize,
+    quota: &KeyQuota,
+) -> Result<(), SecurityRequestError> {
+    let usage = keys.namespace_usage(ns);
+    let (new_keys, new_bytes) = match keys.value_len(ns, key) {
+        Ok(old_len) => (usage.keys, usage.bytes - old_len + value_len),
+        Err(_) => (usage.keys + 1, usage.bytes + key.len() + value_len),
+    };
+    if new_keys > quota.max_keys || new_bytes > quota.max_bytes {
+        return Err(SecurityRequestError::SreQuotaExceeded);
+    }
+    Ok(())
+}
+
+pub fn key_usage(keys: &KeyStore, ns: &str, quota: &KeyQuota) -> KeyValueUsage {
+    let usage = keys.namespace_usage(ns);
+    KeyValueUsage {
+        keys: usage.keys,
+        bytes: usage.bytes,
+        max_keys: quota.max_keys,
+        max_bytes: quota.max_bytes,
+    }
+}
+
+// Returns the keys in |ns| that follow |after|, up to KEY_LIST_PAGE_BYTES
+// worth, and whether more remain.
+pub fn list_keys(keys: &KeyStore, ns: &str, after: Option<&str>) -> (Vec<String>, bool) {
+    let mut page = Vec::new();
+    let mut page_bytes = 0;
+    for key in keys.keys_after(ns, after) {
+        // NB: +2 for the serialized length of a key up to MAX_KEY_LEN
+        page_bytes += key.len() + 2;
+        if page_bytes > KEY_LIST_PAGE_BYTES {
+            return (page, true);
+        }
+        page.push(key.to_string());
+    }
+    (page, false)
+}
+
+// Maps a StorageError to a SecurityRequestError; |default| is used for
+// errors without a direct equivalent.
+pub fn storage_error(err: StorageError, default: SecurityRequestError) -> SecurityRequestError {
+    match err {
+        StorageError::NotFound => SecurityRequestError::SreKeyNotFound,
+        StorageError::KeyInvalid => SecurityRequestError::SreKeyInvalid,
+        StorageError::ValueTooLarge => SecurityRequestError::SreValueInvalid,
+        _ => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditLog, AUDIT_LOG_CAPACITY, MAX_AUDIT_DETAIL_LEN};
+    use crate::rollback;
+    use kata_crypto_keys::{KeyType, SECRET_SIZE};
+    use kata_sealed_storage::TEST_ROOT_SECRET;
+    use kata_security_interface::{AuditEvent, AuditRecord};
+
+    static ROOT_SECRET: RootSecret = TEST_ROOT_SECRET;
+
+    // Returns the longest bundle id allowed, distinguished by |index|.
+    fn bundle_id(index: usize) -> String {
+        format!("{:0>width$}", index, width = MAX_BUNDLE_ID_LEN)
+    }
+    // Returns the longest crypto key name allowed, distinguished by |index|.
+    fn crypto_key_name(index: usize) -> String {
+        format!("{:0>width$}", index, width = MAX_NAME_LEN)
+    }
+
+    #[test]
+    fn test_bundles_at_quota() {
+        let quota = DEFAULT_KEY_QUOTA;
+        let mut keys = SealedKeyStore::new(&ROOT_SECRET);
+
+        // Fill each bundle's quota exactly with 3-byte keys and values
+        // that seal to make up the remainder.
+        let value = vec![0x5a; quota.max_bytes / quota.max_keys - 3 - sealed_len(0)];
+        for index in 0..KEY_STORE_BUNDLES {
+            let id = bundle_id(index);
+            rollback::write_version(keys.store_mut(), &id, u32::MAX).unwrap();
+            for k in 0..quota.max_keys {
+                let key = format!("{:03}", k);
+                check_quota(keys.store(), &id, &key, sealed_len(value.len()), &quota).unwrap();
+                keys.write(&id, &key, &value).unwrap();
+            }
+            assert_eq!(key_usage(keys.store(), &id, &quota).bytes, quota.max_bytes);
+            assert_eq!(
+                check_quota(keys.store(), &id, "new", sealed_len(0), &quota),
+                Err(SecurityRequestError::SreQuotaExceeded)
+            );
+            for k in 0..MAX_BUNDLE_KEYS {
+                let key = CryptoKey::new(KeyType::Ed25519, &[k as u8; SECRET_SIZE]);
+                keys.write_crypto_key(&id, &crypto_key_name(k), &key)
+                    .unwrap();
+            }
+        }
+
+        // Fill the audit log with the largest records.
+        let mut audit = AuditLog::new();
+        for index in 0..AUDIT_LOG_CAPACITY {
+            let record = AuditRecord {
+                seq: 0,
+                timestamp_ms: u64::MAX,
+                badge: !0,
+                event: AuditEvent::WriteKey,
+                bundle_id: bundle_id(index),
+                detail: "k".repeat(MAX_AUDIT_DETAIL_LEN),
+            };
+            audit.append(record, Some(keys.store_mut()));
+        }
+
+        // Everything written is held.
+        for index in 0..KEY_STORE_BUNDLES {
+            let id = bundle_id(index);
+            assert_eq!(rollback::read_version(keys.store(), &id), u32::MAX);
+            assert_eq!(list_keys(keys.store(), &id, None).0.len(), quota.max_keys);
+            for k in 0..quota.max_keys {
+                assert_eq!(keys.read(&id, &format!("{:03}", k)).unwrap(), value);
+            }
+            for k in 0..MAX_BUNDLE_KEYS {
+                assert!(keys.read_crypto_key(&id, &crypto_key_name(k)).is_ok());
+            }
+        }
+        let records = AuditLog::load(keys.store()).records_after(None);
+        assert_eq!(records.len(), AUDIT_LOG_CAPACITY);
+        let stats = keys.store().stats();
+        assert!(stats.live <= KEY_STORE_BYTES);
+    }
+}
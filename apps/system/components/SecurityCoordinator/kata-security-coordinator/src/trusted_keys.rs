@@ -0,0 +1,45 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Public keys trusted to sign packages.
+
+use kata_package_signature::PackageVerifier;
+use kata_package_signature::SignatureError;
+use kata_package_signature::TrustedKey;
+use kata_security_interface::SecurityRequestError;
+
+// TRUSTED_KEYS is generated by build.rs from KATA_PACKAGE_SIGNING_PUBKEY
+// and the "test_signing_key" feature.
+// NB: the signer is identified by public key; the name is recorded with
+//   each installed bundle.
+include!(concat!(env!("OUT_DIR"), "/trusted_keys.rs"));
+
+#[cfg(all(no_trusted_keys, not(test)))]
+compile_error!(
+    "no package signing key trusted; set KATA_PACKAGE_SIGNING_PUBKEY or enable \"test_signing_key\""
+);
+
+// Checks the package fed to |verifier| was signed by a trusted key and
+// returns the signer's name.
+pub fn verify(verifier: &PackageVerifier) -> Result<&'static str, SecurityRequestError> {
+    verifier
+        .verify(TRUSTED_KEYS)
+        .map(|key| key.name)
+        .map_err(|err| match err {
+            SignatureError::Unsigned => SecurityRequestError::SrePackageUnsigned,
+            SignatureError::Malformed
+            | SignatureError::UnknownSigner
+            | SignatureError::BadSignature => SecurityRequestError::SrePackageSignatureInvalid,
+        })
+}
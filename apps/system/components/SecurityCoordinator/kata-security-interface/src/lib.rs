@@ -18,15 +18,25 @@
 
 extern crate alloc;
 use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use core::str;
+use kata_memory_interface::kata_frame_alloc_in_cnode;
+use kata_memory_interface::kata_object_free_in_cnode;
+use kata_memory_interface::MemoryManagerError;
+use kata_memory_interface::ObjDesc;
 use kata_memory_interface::ObjDescBundle;
 use kata_os_common::camkes::Camkes;
+use kata_os_common::copyregion::CopyRegion;
 use kata_os_common::cspace_slot::CSpaceSlot;
 use kata_os_common::sel4_sys;
 use log::trace;
 use serde::{Deserialize, Serialize};
 
 use sel4_sys::seL4_CPtr;
+use sel4_sys::seL4_Error;
+use sel4_sys::seL4_Result;
+use sel4_sys::seL4_Word;
 
 // NB: serde helper for arrays w/ >32 elements
 //   c.f. https://github.com/serde-rs/serde/pull/1860
@@ -43,10 +53,25 @@ const SECURITY_REQUEST_DATA_SIZE: usize = 2048;
 pub const SECURITY_REPLY_DATA_SIZE: usize = 2048;
 pub type SecurityReplyData = [u8; SECURITY_REPLY_DATA_SIZE];
 
-// TODO(sleffler): temp constraint on value part of key-value pairs
-pub const KEY_VALUE_DATA_SIZE: usize = 100;
+// Max size of the value part of key-value pairs. Values are stored with
+// their actual length; this is just the upper bound (tune as needed but
+// keep in sync with sdk-interface).
+pub const KEY_VALUE_DATA_SIZE: usize = 3 * 1024;
 pub type KeyValueData = [u8; KEY_VALUE_DATA_SIZE];
 
+// Values larger than this do not fit in the camkes ipc buffer (along with
+// the bundle id & key) and are instead passed in page frames.
+pub const KEY_VALUE_INLINE_SIZE: usize = 1024;
+
+// Serialized requests/replies larger than this are passed in page frames
+// by kata_security_request_large. The slack covers the SecurityPayload
+// envelope (variant tag + length).
+pub const SECURITY_PAYLOAD_INLINE_SIZE: usize = SECURITY_REPLY_DATA_SIZE - 8;
+
+// Max bytes of key names returned by one SrListKeys request; a bundle
+// with more keys is listed in pages.
+pub const KEY_LIST_PAGE_BYTES: usize = 1024;
+
 // NB: struct's marked repr(C) are processed by cbindgen to get a .h file
 //   used in camkes C interfaces.
 
@@ -88,6 +113,18 @@ pub struct UninstallRequest<'a> {
 }
 impl<'a> SecurityCapability for UninstallRequest<'a> {}
 
+// SecurityRequestAllowRollback
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AllowRollbackRequest<'a> {
+    pub bundle_id: &'a str,
+}
+impl<'a> SecurityCapability for AllowRollbackRequest<'a> {}
+
+// SecurityRequestFactoryReset
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FactoryResetRequest {}
+impl SecurityCapability for FactoryResetRequest {}
+
 // SecurityRequestSizeBuffer
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SizeBufferRequest<'a> {
@@ -121,15 +158,44 @@ pub struct LoadApplicationRequest<'a> {
 }
 impl<'a> SecurityCapability for LoadApplicationRequest<'a> {}
 
+// Page frames holding a loaded (verified) image. The frames are in image
+// order. Frames listed in |private| are copies owned by the recipient;
+// the rest are read-only capabilities derived from the installed package
+// that are revoked when the bundle is uninstalled. Use
+// kata_security_release_image to reclaim everything.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageFrames {
+    pub frames: ObjDescBundle,
+    pub private: Vec<ObjDesc>,
+}
+impl ImageFrames {
+    // Returns an image where every frame in |frames| is a private copy.
+    pub fn new_private(frames: ObjDescBundle) -> Self {
+        let private = frames.objs.clone();
+        ImageFrames { frames, private }
+    }
+
+    // Returns the private frames (in the image's container).
+    pub fn private_frames(&self) -> ObjDescBundle {
+        ObjDescBundle::new(self.frames.cnode, self.frames.depth, self.private.clone())
+    }
+
+    // Returns the number of frames shared with the installed package.
+    pub fn shared_count(&self) -> usize {
+        let private: usize = self.private.iter().map(|od| od.retype_count()).sum();
+        self.frames.count() - private
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoadApplicationResponse {
     // Memory pages with verfied application contents.
     // TODO(sleffler) verify these are all Frames
-    pub bundle_frames: ObjDescBundle,
+    pub bundle_frames: ImageFrames,
 }
 impl SecurityCapability for LoadApplicationResponse {
-    fn get_container_cap(&self) -> Option<seL4_CPtr> { Some(self.bundle_frames.cnode) }
-    fn set_container_cap(&mut self, cap: seL4_CPtr) { self.bundle_frames.cnode = cap; }
+    fn get_container_cap(&self) -> Option<seL4_CPtr> { Some(self.bundle_frames.frames.cnode) }
+    fn set_container_cap(&mut self, cap: seL4_CPtr) { self.bundle_frames.frames.cnode = cap; }
 }
 
 // SecurityRequestLoadModel
@@ -144,11 +210,11 @@ impl<'a> SecurityCapability for LoadModelRequest<'a> {}
 pub struct LoadModelResponse {
     // Memory pages with verified model contents.
     // TODO(sleffler) verify these are all Frames
-    pub model_frames: ObjDescBundle,
+    pub model_frames: ImageFrames,
 }
 impl SecurityCapability for LoadModelResponse {
-    fn get_container_cap(&self) -> Option<seL4_CPtr> { Some(self.model_frames.cnode) }
-    fn set_container_cap(&mut self, cap: seL4_CPtr) { self.model_frames.cnode = cap; }
+    fn get_container_cap(&self) -> Option<seL4_CPtr> { Some(self.model_frames.frames.cnode) }
+    fn set_container_cap(&mut self, cap: seL4_CPtr) { self.model_frames.frames.cnode = cap; }
 }
 
 // SecurityRequestReadKey
@@ -161,18 +227,50 @@ impl<'a> SecurityCapability for ReadKeyRequest<'a> {}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReadKeyResponse<'a> {
-    pub value: &'a [u8],
+    #[serde(borrow)]
+    pub value: SecurityPayload<'a>,
+}
+impl<'a> SecurityCapability for ReadKeyResponse<'a> {
+    fn get_container_cap(&self) -> Option<seL4_CPtr> { self.value.get_container_cap() }
+    fn set_container_cap(&mut self, cap: seL4_CPtr) { self.value.set_container_cap(cap); }
 }
-impl<'a> SecurityCapability for ReadKeyResponse<'a> {}
 
 // SecurityRequestWriteKey
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WriteKeyRequest<'a> {
     pub bundle_id: &'a str,
     pub key: &'a str,
-    pub value: &'a [u8],
+    #[serde(borrow)]
+    pub value: SecurityPayload<'a>,
+}
+impl<'a> SecurityCapability for WriteKeyRequest<'a> {
+    fn get_container_cap(&self) -> Option<seL4_CPtr> { self.value.get_container_cap() }
+    fn set_container_cap(&mut self, cap: seL4_CPtr) { self.value.set_container_cap(cap); }
+}
+
+// Data that may be too large for the ipc buffer as passed in a request
+// or reply (e.g. the value part of a key-value pair).
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SecurityPayload<'a> {
+    // Data is serialized with the message.
+    Inline(&'a [u8]),
+    // Data (of the specified length) is held in page frames. The CNode
+    // container is attached to the message.
+    Frames(ObjDescBundle, usize),
+}
+impl<'a> SecurityCapability for SecurityPayload<'a> {
+    fn get_container_cap(&self) -> Option<seL4_CPtr> {
+        match self {
+            SecurityPayload::Inline(_) => None,
+            SecurityPayload::Frames(frames, _) => Some(frames.cnode),
+        }
+    }
+    fn set_container_cap(&mut self, cap: seL4_CPtr) {
+        if let SecurityPayload::Frames(frames, _) = self {
+            frames.cnode = cap;
+        }
+    }
 }
-impl<'a> SecurityCapability for WriteKeyRequest<'a> {}
 
 // SecurityRequestDeleteKey
 #[derive(Debug, Serialize, Deserialize)]
@@ -182,6 +280,181 @@ pub struct DeleteKeyRequest<'a> {
 }
 impl<'a> SecurityCapability for DeleteKeyRequest<'a> {}
 
+// SecurityRequestListKeys
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListKeysRequest<'a> {
+    pub bundle_id: &'a str,
+    pub after: Option<&'a str>, // Resume after this key (None to start)
+}
+impl<'a> SecurityCapability for ListKeysRequest<'a> {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListKeysResponse<'a> {
+    #[serde(borrow)]
+    pub keys: Vec<&'a str>,
+    pub more: bool, // More keys follow the last one returned
+}
+impl<'a> SecurityCapability for ListKeysResponse<'a> {}
+
+// SecurityRequestGetKeyUsage
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetKeyUsageRequest<'a> {
+    pub bundle_id: &'a str,
+}
+impl<'a> SecurityCapability for GetKeyUsageRequest<'a> {}
+
+// Key-value storage used by a bundle and its quota.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KeyValueUsage {
+    pub keys: usize,      // Number of keys
+    pub bytes: usize,     // Bytes of key names + values
+    pub max_keys: usize,  // Quota on keys
+    pub max_bytes: usize, // Quota on bytes
+}
+impl SecurityCapability for KeyValueUsage {}
+
+// SecurityRequestGetAuditLog
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetAuditLogRequest {
+    pub after: Option<u32>, // Return records after this sequence # (None for all)
+}
+impl SecurityCapability for GetAuditLogRequest {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetAuditLogResponse {
+    pub records: Vec<AuditRecord>,
+}
+impl SecurityCapability for GetAuditLogResponse {}
+
+// Security-relevant operations recorded in the audit log.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AuditEvent {
+    Install,         // Package installed
+    InstallRejected, // Package unsigned or signature invalid (detail is the error)
+    Uninstall,       // Bundle uninstalled
+    LoadModel,       // Model loaded (detail is the model id)
+    WriteKey,        // Key-value written (detail is the key)
+    DeleteKey,       // Key-value deleted (detail is the key)
+    AccessDenied,    // Request not permitted for caller (detail is the request)
+    CryptoGenerate,  // Crypto key generated (detail is the key name)
+    CryptoDelete,    // Crypto key deleted (detail is the key name)
+    AllowRollback,   // Recorded version forgotten; next install may be older
+    FactoryReset,    // All packages & stored data erased (detail is the error if it failed)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u32,          // Assigned by the log, increases monotonically
+    pub timestamp_ms: u64, // TimerService time of the request
+    pub badge: seL4_Word,  // Badge of the requesting component
+    pub event: AuditEvent,
+    pub bundle_id: String, // Empty if not known (e.g. AccessDenied)
+    pub detail: String,    // Event-specific (see AuditEvent)
+}
+
+// SecurityRequestGetRandom
+// Max bytes returned by one request; kata_security_get_random issues
+// multiple requests for larger buffers.
+pub const SECURITY_RANDOM_MAX: usize = 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetRandomRequest {
+    pub len: usize, // NB: at most SECURITY_RANDOM_MAX
+}
+impl SecurityCapability for GetRandomRequest {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetRandomResponse<'a> {
+    pub data: &'a [u8],
+}
+impl<'a> SecurityCapability for GetRandomResponse<'a> {}
+
+// SecurityRequestCrypto*
+// Keys held by the SecurityCoordinator for a bundle (see kata-crypto-keys).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CryptoKeyType {
+    Ed25519,      // Sign & verify
+    HmacSha256,   // MAC & verify
+    Aes256GcmSiv, // Encrypt & decrypt
+}
+
+// Max bytes of data passed to a crypto operation; leaves room in the
+// request & reply buffers for the other arguments and for ciphertext
+// overhead.
+pub const CRYPTO_DATA_MAX: usize = 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoGenerateRequest<'a> {
+    pub bundle_id: &'a str,
+    pub name: &'a str,
+    pub key_type: CryptoKeyType,
+}
+impl<'a> SecurityCapability for CryptoGenerateRequest<'a> {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoGenerateResponse<'a> {
+    pub public_key: &'a [u8], // Empty for symmetric keys
+}
+impl<'a> SecurityCapability for CryptoGenerateResponse<'a> {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoDeleteRequest<'a> {
+    pub bundle_id: &'a str,
+    pub name: &'a str,
+}
+impl<'a> SecurityCapability for CryptoDeleteRequest<'a> {}
+
+// SrCryptoSign, SrCryptoMac, SrCryptoEncrypt & SrCryptoDecrypt.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoOpRequest<'a> {
+    pub bundle_id: &'a str,
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+impl<'a> SecurityCapability for CryptoOpRequest<'a> {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoOpResponse<'a> {
+    pub result: &'a [u8],
+}
+impl<'a> SecurityCapability for CryptoOpResponse<'a> {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoVerifyRequest<'a> {
+    pub bundle_id: &'a str,
+    pub name: &'a str,
+    pub data: &'a [u8],
+    pub signature: &'a [u8], // Signature or MAC
+}
+impl<'a> SecurityCapability for CryptoVerifyRequest<'a> {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoVerifyResponse {
+    pub valid: bool,
+}
+impl SecurityCapability for CryptoVerifyResponse {}
+
+// SecurityRequestGetAttestationReport
+// Max bytes of the verifier-chosen nonce included in a report.
+pub const ATTESTATION_NONCE_MAX: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetAttestationReportRequest<'a> {
+    pub nonce: &'a [u8], // NB: at most ATTESTATION_NONCE_MAX
+}
+impl<'a> SecurityCapability for GetAttestationReportRequest<'a> {}
+
+// Report text (see kata-attestation) and its signature by the device
+// attestation key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationReport {
+    pub report: String,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+impl SecurityCapability for AttestationReport {}
+
 // SecurityRequestTestMailbox
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TestMailboxRequest {}
@@ -196,7 +469,7 @@ impl SecurityCapability for CapScanRequest {}
 //   the camkes-generated interface code uses basic C which does not
 //   tolerate overlapping member names.
 #[repr(C)]
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SecurityRequestError {
     SreSuccess = 0,
     SreBundleIdInvalid,
@@ -212,6 +485,11 @@ pub enum SecurityRequestError {
     SreCapAllocFailed,
     SreCapMoveFailed,
     SreObjCapInvalid,
+    SrePackageUnsigned,
+    SrePackageSignatureInvalid,
+    SreQuotaExceeded,
+    SrePermissionDenied,
+    SreVersionRollback,
     // Generic errors, mostly used in unit tests
     SreEchoFailed,
     SreInstallFailed,
@@ -223,26 +501,51 @@ pub enum SecurityRequestError {
     SreReadFailed,
     SreWriteFailed,
     SreDeleteFailed,
+    SreListKeysFailed,
+    SreGetRandomFailed,
+    SreCryptoFailed,
+    SreAttestationFailed,
+    SreFactoryResetFailed,
+    SreAllowRollbackFailed,
     SreTestFailed,
 }
 
+// NB: requests marked (large) are sent with kata_security_request_large.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SecurityRequest {
     SrEcho = 0, // Security core replies with request payload
 
-    SrInstall,   // Install package [pkg_buffer] -> bundle_id
-    SrUninstall, // Uninstall package [bundle_id]
+    SrInstall,       // Install package [pkg_buffer] -> bundle_id
+    SrUninstall,     // Uninstall package [bundle_id]
+    SrAllowRollback, // Permit next install to be an older version [bundle_id]
+    SrFactoryReset,  // Remove all packages & erase all stored data
 
     SrSizeBuffer,      // Size application image [bundle_id] -> u32
-    SrGetManifest,     // Return application manifest [bundle_id] -> String
+    SrGetManifest,     // Return application manifest [bundle_id] -> String (large)
     SrLoadApplication, // Load application [bundle_id]
     // TODO(sleffler): define <tag>?
     SrLoadModel, // Load ML model [bundle_id, <tag>]
 
-    SrReadKey,   // Read key value [bundle_id, key] -> value
-    SrWriteKey,  // Write key value [bundle_id, key, value]
-    SrDeleteKey, // Delete key [bundle_id, key]
+    SrReadKey,     // Read key value [bundle_id, key] -> value
+    SrWriteKey,    // Write key value [bundle_id, key, value]
+    SrDeleteKey,   // Delete key [bundle_id, key]
+    SrListKeys,    // List keys [bundle_id, after] -> keys, more
+    SrGetKeyUsage, // Key-value storage usage [bundle_id] -> KeyValueUsage
+
+    SrGetAuditLog, // Audit log records [after] -> Vec<AuditRecord> (large)
+
+    SrGetRandom, // Random bytes [len] -> data
+
+    SrCryptoGenerate, // Generate key [bundle_id, name, key_type] -> public_key
+    SrCryptoDelete,   // Delete key [bundle_id, name]
+    SrCryptoSign,     // Sign [bundle_id, name, data] -> signature
+    SrCryptoVerify,   // Check signature/MAC [bundle_id, name, data, signature] -> valid
+    SrCryptoMac,      // MAC [bundle_id, name, data] -> mac
+    SrCryptoEncrypt,  // Encrypt [bundle_id, name, data] -> ciphertext
+    SrCryptoDecrypt,  // Decrypt [bundle_id, name, data] -> plaintext
+
+    SrGetAttestationReport, // Signed report [nonce] -> AttestationReport (large)
 
     SrTestMailbox, // Run mailbox tests
     SrCapScan,     // Dump contents CNode to console
@@ -250,24 +553,93 @@ pub enum SecurityRequest {
 
 // Interface to underlying facilities; also used to inject fakes for unit tests.
 pub trait SecurityCoordinatorInterface {
+    // Installs the package in |pkg_contents|. On success the frames & their
+    // container belong to the implementation; on error the caller frees them.
     fn install(&mut self, pkg_contents: &ObjDescBundle) -> Result<String, SecurityRequestError>;
     fn uninstall(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError>;
+    // Forgets the highest version installed for |bundle_id| so the next
+    // install is not rejected as a rollback.
+    fn allow_rollback(&mut self, bundle_id: &str) -> Result<(), SecurityRequestError>;
+    // Removes every installed package and erases all stored data (key-value
+    // stores, crypto keys & the audit log). Version records are kept so a
+    // reset cannot be used to roll back a bundle (see allow_rollback).
+    fn factory_reset(&mut self) -> Result<(), SecurityRequestError>;
     fn size_buffer(&self, bundle_id: &str) -> Result<usize, SecurityRequestError>;
     fn get_manifest(&self, bundle_id: &str) -> Result<String, SecurityRequestError>;
-    fn load_application(&self, bundle_id: &str) -> Result<ObjDescBundle, SecurityRequestError>;
+    fn load_application(&self, bundle_id: &str) -> Result<ImageFrames, SecurityRequestError>;
     fn load_model(
         &self,
         bundle_id: &str,
         model_id: &str,
-    ) -> Result<ObjDescBundle, SecurityRequestError>;
-    fn read_key(&self, bundle_id: &str, key: &str) -> Result<&KeyValueData, SecurityRequestError>;
+    ) -> Result<ImageFrames, SecurityRequestError>;
+    fn read_key(&self, bundle_id: &str, key: &str) -> Result<Vec<u8>, SecurityRequestError>;
     fn write_key(
         &mut self,
         bundle_id: &str,
         key: &str,
-        value: &KeyValueData,
+        value: &[u8],
     ) -> Result<(), SecurityRequestError>;
     fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), SecurityRequestError>;
+    // Returns up to KEY_LIST_PAGE_BYTES of the keys after |after| (in
+    // sorted order) and whether there are more.
+    fn list_keys(
+        &self,
+        bundle_id: &str,
+        after: Option<&str>,
+    ) -> Result<(Vec<String>, bool), SecurityRequestError>;
+    fn get_key_usage(&self, bundle_id: &str) -> Result<KeyValueUsage, SecurityRequestError>;
+    // Appends |record| to the audit log; the sequence # is assigned here.
+    fn audit(&mut self, record: AuditRecord);
+    // Returns the audit log records after sequence # |after| (all if None).
+    // NB: the log is volatile; records do not survive a reboot.
+    fn get_audit_log(&self, after: Option<u32>) -> Result<Vec<AuditRecord>, SecurityRequestError>;
+    // Fills |buf| (at most SECURITY_RANDOM_MAX bytes) from the DRBG.
+    fn get_random(&mut self, buf: &mut [u8]) -> Result<(), SecurityRequestError>;
+    // Keys held for a bundle; the key material is never returned.
+    fn crypto_generate(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        key_type: CryptoKeyType,
+    ) -> Result<Vec<u8>, SecurityRequestError>;
+    fn crypto_delete(&mut self, bundle_id: &str, name: &str) -> Result<(), SecurityRequestError>;
+    fn crypto_sign(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError>;
+    fn crypto_verify(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SecurityRequestError>;
+    fn crypto_mac(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError>;
+    fn crypto_encrypt(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError>;
+    fn crypto_decrypt(
+        &self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityRequestError>;
+    // Returns a report of the installed software that includes |nonce|,
+    // signed by the device attestation key.
+    fn get_attestation_report(
+        &self,
+        nonce: &[u8],
+    ) -> Result<AttestationReport, SecurityRequestError>;
     fn test_mailbox(&mut self) -> Result<(), SecurityRequestError>;
 }
 
@@ -323,6 +695,70 @@ pub fn kata_security_request<T: Serialize + SecurityCapability>(
     }
 }
 
+// Like kata_security_request but for operations whose request or reply
+// may not fit in the ipc buffer. Both are sent wrapped in a
+// SecurityPayload: data up to SECURITY_PAYLOAD_INLINE_SIZE is passed
+// inline, anything larger in page frames. Frames for the request are
+// allocated here; frames for the reply are allocated by the
+// SecurityCoordinator, sized to the reply, and freed here once copied.
+// Returns the serialized reply. |copy_region| is used to access frames.
+// NB: |request_args| must not carry a capability; the attached frames
+//   (if any) occupy the single capability passed with a request.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_request_large<T: Serialize + SecurityCapability>(
+    request: SecurityRequest,
+    request_args: &T,
+    copy_region: &mut CopyRegion,
+) -> Result<Vec<u8>, SecurityRequestError> {
+    assert!(request_args.get_container_cap().is_none());
+    let request_data = postcard::to_allocvec(request_args)
+        .map_err(|_| SecurityRequestError::SreSerializeFailed)?;
+
+    // NB: a large reply is returned in a CNode of page frames, make
+    //   sure the receive slot is empty or it can silently fail.
+    let mut container_slot = CSpaceSlot::new();
+    container_slot.set_recv_path();
+
+    let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
+    if request_data.len() <= SECURITY_PAYLOAD_INLINE_SIZE {
+        kata_security_request(request, &SecurityPayload::Inline(&request_data), reply)?;
+    } else {
+        let frames = kata_frame_alloc_in_cnode(request_data.len())
+            .map_err(|_| SecurityRequestError::SreCapAllocFailed)?;
+        let result = kata_copy_to_frames(&frames, &request_data, copy_region)
+            .map_err(|_| SecurityRequestError::SreWriteFailed)
+            .and_then(|_| {
+                kata_security_request(
+                    request,
+                    &SecurityPayload::Frames(frames.clone(), request_data.len()),
+                    reply,
+                )
+            });
+        let _ = kata_object_free_in_cnode(&frames);
+        result?;
+    }
+    match postcard::from_bytes::<SecurityPayload>(reply)
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)?
+    {
+        SecurityPayload::Inline(data) => Ok(data.to_vec()),
+        SecurityPayload::Frames(mut frames, len) => {
+            sel4_sys::debug_assert_slot_cnode!(container_slot.slot);
+            frames.cnode = container_slot.release(); // NB: take ownership
+            let result = if len <= frames.size_bytes() {
+                let mut data = vec![0u8; len];
+                kata_copy_from_frames(&frames, &mut data, copy_region)
+                    .map(|_| data)
+                    .map_err(|_| SecurityRequestError::SreReadFailed)
+            } else {
+                Err(SecurityRequestError::SreDeserializeFailed)
+            };
+            let _ = kata_object_free_in_cnode(&frames);
+            result
+        }
+    }
+}
+
 #[inline]
 #[allow(dead_code)]
 pub fn kata_security_echo(request: &str) -> Result<String, SecurityRequestError> {
@@ -365,6 +801,30 @@ pub fn kata_security_uninstall(bundle_id: &str) -> Result<(), SecurityRequestErr
     )
 }
 
+// Permits the next install of |bundle_id| to be an older version than
+// the highest installed so far.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_allow_rollback(bundle_id: &str) -> Result<(), SecurityRequestError> {
+    kata_security_request(
+        SecurityRequest::SrAllowRollback,
+        &AllowRollbackRequest { bundle_id },
+        &mut [0u8; SECURITY_REPLY_DATA_SIZE],
+    )
+}
+
+// Removes every installed package and erases all stored data. Running
+// applications must be stopped first (see kata_pkg_mgmt_factory_reset).
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_factory_reset() -> Result<(), SecurityRequestError> {
+    kata_security_request(
+        SecurityRequest::SrFactoryReset,
+        &FactoryResetRequest {},
+        &mut [0u8; SECURITY_REPLY_DATA_SIZE],
+    )
+}
+
 #[inline]
 #[allow(dead_code)]
 pub fn kata_security_size_buffer(bundle_id: &str) -> Result<usize, SecurityRequestError> {
@@ -375,16 +835,21 @@ pub fn kata_security_size_buffer(bundle_id: &str) -> Result<usize, SecurityReque
     Ok(response.buffer_size)
 }
 
+// Returns the manifest for |bundle_id|. Manifests may be arbitrarily
+// large; |copy_region| is used to access those that do not fit in the
+// ipc buffer.
 #[inline]
 #[allow(dead_code)]
-pub fn kata_security_get_manifest(bundle_id: &str) -> Result<String, SecurityRequestError> {
-    let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
-    kata_security_request(
+pub fn kata_security_get_manifest(
+    bundle_id: &str,
+    copy_region: &mut CopyRegion,
+) -> Result<String, SecurityRequestError> {
+    let reply = kata_security_request_large(
         SecurityRequest::SrGetManifest,
         &GetManifestRequest { bundle_id },
-        reply,
+        copy_region,
     )?;
-    let response = postcard::from_bytes::<GetManifestResponse>(reply)
+    let response = postcard::from_bytes::<GetManifestResponse>(&reply)
         .map_err(|_| SecurityRequestError::SreDeserializeFailed)?;
     Ok(response.manifest.to_string())
 }
@@ -394,7 +859,7 @@ pub fn kata_security_get_manifest(bundle_id: &str) -> Result<String, SecurityReq
 pub fn kata_security_load_application(
     bundle_id: &str,
     container_slot: &CSpaceSlot,
-) -> Result<ObjDescBundle, SecurityRequestError> {
+) -> Result<ImageFrames, SecurityRequestError> {
     container_slot.set_recv_path();
     // NB: SrLoadApplication returns a CNode with the application
     //   contents, make sure the receive slot is empty or it can
@@ -414,7 +879,7 @@ pub fn kata_security_load_application(
     )?;
     if let Ok(mut response) = postcard::from_bytes::<LoadApplicationResponse>(reply) {
         sel4_sys::debug_assert_slot_cnode!(container_slot.slot);
-        response.bundle_frames.cnode = container_slot.slot;
+        response.bundle_frames.frames.cnode = container_slot.slot;
         Ok(response.bundle_frames)
     } else {
         Err(SecurityRequestError::SreDeserializeFailed)
@@ -427,7 +892,7 @@ pub fn kata_security_load_model(
     bundle_id: &str,
     model_id: &str,
     container_slot: &CSpaceSlot,
-) -> Result<ObjDescBundle, SecurityRequestError> {
+) -> Result<ImageFrames, SecurityRequestError> {
     container_slot.set_recv_path();
     // NB: SrLoadApplication returns a CNode with the application
     //   contents, make sure the receive slot is empty or it can
@@ -450,44 +915,148 @@ pub fn kata_security_load_model(
     )?;
     if let Ok(mut response) = postcard::from_bytes::<LoadModelResponse>(reply) {
         sel4_sys::debug_assert_slot_cnode!(container_slot.slot);
-        response.model_frames.cnode = container_slot.slot;
+        response.model_frames.frames.cnode = container_slot.slot;
         Ok(response.model_frames)
     } else {
         Err(SecurityRequestError::SreDeserializeFailed)
     }
 }
 
+// Reclaims an image returned by kata_security_load_application or
+// kata_security_load_model. Only the private frames are returned to the
+// MemoryManager; shared frames are derived capabilities that are deleted
+// along with the container CNode.
 #[inline]
 #[allow(dead_code)]
-pub fn kata_security_read_key(
+pub fn kata_security_release_image(image: &ImageFrames) -> Result<(), MemoryManagerError> {
+    kata_object_free_in_cnode(&image.private_frames())
+}
+
+// Copies |data| to the page frames in |frames|, mapping each frame in
+// turn through |copy_region|.
+pub fn kata_copy_to_frames(
+    frames: &ObjDescBundle,
+    data: &[u8],
+    copy_region: &mut CopyRegion,
+) -> seL4_Result {
+    if data.len() > frames.size_bytes() {
+        return Err(seL4_Error::seL4_RangeError);
+    }
+    let slot = CSpaceSlot::new();
+    for (cptr, chunk) in frames.cptr_iter().zip(data.chunks(copy_region.size())) {
+        slot.dup_to(frames.cnode, cptr, frames.depth)
+            .and_then(|_| copy_region.map(slot.slot))?;
+        copy_region.as_mut()[..chunk.len()].copy_from_slice(chunk);
+        copy_region.unmap().and_then(|_| slot.delete())?;
+    }
+    Ok(())
+}
+
+// Fills |data| from the page frames in |frames|, mapping each frame in
+// turn through |copy_region|.
+pub fn kata_copy_from_frames(
+    frames: &ObjDescBundle,
+    data: &mut [u8],
+    copy_region: &mut CopyRegion,
+) -> seL4_Result {
+    if data.len() > frames.size_bytes() {
+        return Err(seL4_Error::seL4_RangeError);
+    }
+    let slot = CSpaceSlot::new();
+    for (cptr, chunk) in frames.cptr_iter().zip(data.chunks_mut(copy_region.size())) {
+        slot.dup_to(frames.cnode, cptr, frames.depth)
+            .and_then(|_| copy_region.map(slot.slot))?;
+        chunk.copy_from_slice(&copy_region.as_ref()[..chunk.len()]);
+        copy_region.unmap().and_then(|_| slot.delete())?;
+    }
+    Ok(())
+}
+
+// Reads the value of |key| into |keyval| and returns the part of
+// |keyval| holding the data. |copy_region| is used to access the value
+// when it is too large to be returned in the ipc buffer.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_read_key<'a>(
     bundle_id: &str,
     key: &str,
-    keyval: &mut [u8],
-) -> Result<(), SecurityRequestError> {
+    keyval: &'a mut [u8],
+    copy_region: &mut CopyRegion,
+) -> Result<&'a [u8], SecurityRequestError> {
+    // NB: a large value is returned in a CNode of page frames, make
+    //   sure the receive slot is empty or it can silently fail.
+    let mut container_slot = CSpaceSlot::new();
+    container_slot.set_recv_path();
+
     let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
     kata_security_request(SecurityRequest::SrReadKey, &ReadKeyRequest { bundle_id, key }, reply)?;
     let response = postcard::from_bytes::<ReadKeyResponse>(reply)
         .map_err(|_| SecurityRequestError::SreDeserializeFailed)?;
-    keyval.copy_from_slice(response.value);
-    Ok(())
+    match response.value {
+        SecurityPayload::Inline(value) => {
+            let keyval = keyval
+                .get_mut(..value.len())
+                .ok_or(SecurityRequestError::SreValueInvalid)?;
+            keyval.copy_from_slice(value);
+            Ok(keyval)
+        }
+        SecurityPayload::Frames(mut frames, len) => {
+            sel4_sys::debug_assert_slot_cnode!(container_slot.slot);
+            frames.cnode = container_slot.release(); // NB: take ownership
+            let result = match keyval.get_mut(..len) {
+                Some(keyval) => match kata_copy_from_frames(&frames, keyval, copy_region) {
+                    Ok(_) => Ok(&*keyval),
+                    Err(_) => Err(SecurityRequestError::SreReadFailed),
+                },
+                None => Err(SecurityRequestError::SreValueInvalid),
+            };
+            let _ = kata_object_free_in_cnode(&frames);
+            result
+        }
+    }
 }
 
+// Writes |value| for |key|. |copy_region| is used to pass the value
+// when it is too large to be sent in the ipc buffer.
 #[inline]
 #[allow(dead_code)]
 pub fn kata_security_write_key(
     bundle_id: &str,
     key: &str,
     value: &[u8],
+    copy_region: &mut CopyRegion,
 ) -> Result<(), SecurityRequestError> {
-    kata_security_request(
-        SecurityRequest::SrWriteKey,
-        &WriteKeyRequest {
-            bundle_id,
-            key,
-            value,
-        },
-        &mut [0u8; SECURITY_REPLY_DATA_SIZE],
-    )
+    if value.len() > KEY_VALUE_DATA_SIZE {
+        return Err(SecurityRequestError::SreValueInvalid);
+    }
+    if value.len() <= KEY_VALUE_INLINE_SIZE {
+        return kata_security_request(
+            SecurityRequest::SrWriteKey,
+            &WriteKeyRequest {
+                bundle_id,
+                key,
+                value: SecurityPayload::Inline(value),
+            },
+            &mut [0u8; SECURITY_REPLY_DATA_SIZE],
+        );
+    }
+    let frames = kata_frame_alloc_in_cnode(value.len())
+        .map_err(|_| SecurityRequestError::SreCapAllocFailed)?;
+    let result = kata_copy_to_frames(&frames, value, copy_region)
+        .map_err(|_| SecurityRequestError::SreWriteFailed)
+        .and_then(|_| {
+            kata_security_request(
+                SecurityRequest::SrWriteKey,
+                &WriteKeyRequest {
+                    bundle_id,
+                    key,
+                    value: SecurityPayload::Frames(frames.clone(), value.len()),
+                },
+                &mut [0u8; SECURITY_REPLY_DATA_SIZE],
+            )
+        });
+    let _ = kata_object_free_in_cnode(&frames);
+    result
 }
 
 #[inline]
@@ -500,6 +1069,221 @@ pub fn kata_security_delete_key(bundle_id: &str, key: &str) -> Result<(), Securi
     )
 }
 
+// Returns one page of the keys for |bundle_id| that follow |after| (all
+// keys if None) and whether more remain; pass the last key returned to
+// get the next page.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_list_keys(
+    bundle_id: &str,
+    after: Option<&str>,
+) -> Result<(Vec<String>, bool), SecurityRequestError> {
+    let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
+    kata_security_request(
+        SecurityRequest::SrListKeys,
+        &ListKeysRequest { bundle_id, after },
+        reply,
+    )?;
+    let response = postcard::from_bytes::<ListKeysResponse>(reply)
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)?;
+    Ok((response.keys.iter().map(|k| k.to_string()).collect(), response.more))
+}
+
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_get_key_usage(bundle_id: &str) -> Result<KeyValueUsage, SecurityRequestError> {
+    let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
+    kata_security_request(
+        SecurityRequest::SrGetKeyUsage,
+        &GetKeyUsageRequest { bundle_id },
+        reply,
+    )?;
+    postcard::from_bytes::<KeyValueUsage>(reply)
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)
+}
+
+// Returns the audit log records after sequence # |after| (all records if
+// None). |copy_region| is used to access a log too large for the ipc buffer.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_get_audit_log(
+    after: Option<u32>,
+    copy_region: &mut CopyRegion,
+) -> Result<Vec<AuditRecord>, SecurityRequestError> {
+    let reply = kata_security_request_large(
+        SecurityRequest::SrGetAuditLog,
+        &GetAuditLogRequest { after },
+        copy_region,
+    )?;
+    let response = postcard::from_bytes::<GetAuditLogResponse>(&reply)
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)?;
+    Ok(response.records)
+}
+
+// Fills |buf| with random bytes.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_get_random(buf: &mut [u8]) -> Result<(), SecurityRequestError> {
+    for chunk in buf.chunks_mut(SECURITY_RANDOM_MAX) {
+        let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
+        kata_security_request(
+            SecurityRequest::SrGetRandom,
+            &GetRandomRequest { len: chunk.len() },
+            reply,
+        )?;
+        let response = postcard::from_bytes::<GetRandomResponse>(reply)
+            .map_err(|_| SecurityRequestError::SreDeserializeFailed)?;
+        if response.data.len() != chunk.len() {
+            return Err(SecurityRequestError::SreGetRandomFailed);
+        }
+        chunk.copy_from_slice(response.data);
+    }
+    Ok(())
+}
+
+// Generates a |key_type| key |name| for |bundle_id|; returns the public
+// key (empty for symmetric keys).
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_crypto_generate(
+    bundle_id: &str,
+    name: &str,
+    key_type: CryptoKeyType,
+) -> Result<Vec<u8>, SecurityRequestError> {
+    let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
+    kata_security_request(
+        SecurityRequest::SrCryptoGenerate,
+        &CryptoGenerateRequest {
+            bundle_id,
+            name,
+            key_type,
+        },
+        reply,
+    )?;
+    postcard::from_bytes::<CryptoGenerateResponse>(reply)
+        .map(|response| response.public_key.to_vec())
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)
+}
+
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_crypto_delete(
+    bundle_id: &str,
+    name: &str,
+) -> Result<(), SecurityRequestError> {
+    kata_security_request(
+        SecurityRequest::SrCryptoDelete,
+        &CryptoDeleteRequest { bundle_id, name },
+        &mut [0u8; SECURITY_REPLY_DATA_SIZE],
+    )
+}
+
+// Applies key |name| of |bundle_id| to |data| (at most CRYPTO_DATA_MAX
+// bytes) for |request| and returns the result.
+fn kata_security_crypto_op(
+    request: SecurityRequest,
+    bundle_id: &str,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityRequestError> {
+    let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
+    kata_security_request(
+        request,
+        &CryptoOpRequest {
+            bundle_id,
+            name,
+            data,
+        },
+        reply,
+    )?;
+    postcard::from_bytes::<CryptoOpResponse>(reply)
+        .map(|response| response.result.to_vec())
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)
+}
+
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_crypto_sign(
+    bundle_id: &str,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityRequestError> {
+    kata_security_crypto_op(SecurityRequest::SrCryptoSign, bundle_id, name, data)
+}
+
+// Returns whether |signature| is a signature (or MAC) of |data| made
+// with key |name| of |bundle_id|.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_crypto_verify(
+    bundle_id: &str,
+    name: &str,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, SecurityRequestError> {
+    let reply = &mut [0u8; SECURITY_REPLY_DATA_SIZE];
+    kata_security_request(
+        SecurityRequest::SrCryptoVerify,
+        &CryptoVerifyRequest {
+            bundle_id,
+            name,
+            data,
+            signature,
+        },
+        reply,
+    )?;
+    postcard::from_bytes::<CryptoVerifyResponse>(reply)
+        .map(|response| response.valid)
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)
+}
+
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_crypto_mac(
+    bundle_id: &str,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityRequestError> {
+    kata_security_crypto_op(SecurityRequest::SrCryptoMac, bundle_id, name, data)
+}
+
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_crypto_encrypt(
+    bundle_id: &str,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityRequestError> {
+    kata_security_crypto_op(SecurityRequest::SrCryptoEncrypt, bundle_id, name, data)
+}
+
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_crypto_decrypt(
+    bundle_id: &str,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityRequestError> {
+    kata_security_crypto_op(SecurityRequest::SrCryptoDecrypt, bundle_id, name, data)
+}
+
+// Returns a signed attestation report that includes |nonce|. |copy_region|
+// is used to access a report too large for the ipc buffer.
+#[inline]
+#[allow(dead_code)]
+pub fn kata_security_get_attestation_report(
+    nonce: &[u8],
+    copy_region: &mut CopyRegion,
+) -> Result<AttestationReport, SecurityRequestError> {
+    let reply = kata_security_request_large(
+        SecurityRequest::SrGetAttestationReport,
+        &GetAttestationReportRequest { nonce },
+        copy_region,
+    )?;
+    postcard::from_bytes::<AttestationReport>(&reply)
+        .map_err(|_| SecurityRequestError::SreDeserializeFailed)
+}
+
 #[inline]
 #[allow(dead_code)]
 pub fn kata_security_test_mailbox() -> Result<(), SecurityRequestError> {
@@ -0,0 +1,1318 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kata OS security core mailbox protocol.
+//!
+//! Requests and replies are exchanged in a page shared with the security
+//! core; the mailbox carries only the page's physical address and the
+//! frame length, and the reply overwrites the request. A frame is a
+//! fixed-size header followed by the payload:
+//!
+//!   magic: u16       FRAME_MAGIC
+//!   version: u8      FRAME_VERSION
+//!   flags: u8        FLAG_REPLY is set in replies
+//!   request_id: u32  Chosen by the client, echoed in the reply
+//!   opcode: u16      Opcode, echoed in the reply
+//!   status: u16      Status of the request (0 in requests)
+//!   length: u32      Payload bytes following the header
+//!
+//! Integers are little-endian. Payload fields are written in the order
+//! listed with each Opcode: integers are fixed-size, strings & byte
+//! strings are a u32 length followed by the bytes.
+//!
+//! The Crypto* requests operate on a bundle's named keys (see
+//! kata-crypto-keys); the key material never leaves the security core.
+//! The key_type of CryptoGenerate is a kata_crypto_keys::KeyType value.
+//!
+//! Packages are checked by the SecurityCoordinator before they are sent;
+//! InstallBegin names the bundle (the id comes from the package manifest)
+//! and where the manifest lies in the package, which GetManifest returns.
+//! InstallEnd fails with DeleteFirst if the bundle is already installed.
+//!
+//! ReadVersion & WriteVersion access the highest version installed for a
+//! bundle id (0 if none is recorded); it is kept when the bundle is
+//! uninstalled so an older version cannot be installed in its place.
+//!
+//! FactoryReset removes every bundle and erases all data held by the
+//! security core except version records, which are kept so a reset cannot
+//! be used to roll a bundle back.
+//!
+//! AttestSign signs an attestation report digest (see kata-attestation)
+//! with the device attestation key held by the security core.
+//!
+//! SecurityCoreClient implements the client side over a MailboxTransport.
+//! With the "simulator" feature SimulatedSecurityCore provides a host-side
+//! security core that speaks the same protocol.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::ops::Range;
+use num_enum::TryFromPrimitive;
+
+#[cfg(any(test, feature = "simulator"))]
+mod simulator;
+#[cfg(any(test, feature = "simulator"))]
+pub use simulator::SimulatedSecurityCore;
+
+pub const FRAME_MAGIC: u16 = 0x534b; // "KS"
+pub const FRAME_VERSION: u8 = 1;
+pub const FLAG_REPLY: u8 = 0x01;
+
+pub const HEADER_SIZE: usize = 16;
+pub const MAX_FRAME_SIZE: usize = 4096; // NB: one page
+pub const MAX_PAYLOAD_SIZE: usize = MAX_FRAME_SIZE - HEADER_SIZE;
+
+// Max bytes moved by one InstallData, ReadImage or GetEntropy request;
+// leaves room in a frame for the other arguments.
+pub const MAX_DATA_CHUNK: usize = 2048;
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+pub enum Opcode {
+    Echo = 0, // [data: bytes] -> [data: bytes]
+
+    InstallBegin, // [size: u32, bundle_id: str, manifest_offset: u32, manifest_len: u32] -> []
+    InstallData,  // [offset: u32, data: bytes] -> []
+    InstallEnd,   // [] -> [bundle_id: str]
+    InstallAbort, // [] -> []
+    Uninstall,    // [bundle_id: str] -> []
+
+    SizeBuffer,  // [bundle_id: str] -> [size: u32]
+    GetManifest, // [bundle_id: str, offset: u32] -> [size: u32, data: bytes]
+    ReadImage,   // [bundle_id: str, offset: u32, len: u32] -> [data: bytes]
+
+    ReadKey,   // [bundle_id: str, key: str] -> [value: bytes]
+    WriteKey,  // [bundle_id: str, key: str, value: bytes, max_keys: u32, max_bytes: u32] -> []
+    DeleteKey, // [bundle_id: str, key: str] -> []
+    ListKeys, // [bundle_id: str, after: str, max_bytes: u32] -> [more: u32, count: u32, key: str..]
+    KeyUsage, // [bundle_id: str] -> [keys: u32, bytes: u32]
+
+    GetEntropy, // [len: u32] -> [data: bytes]
+
+    CryptoGenerate, // [bundle_id: str, name: str, key_type: u32] -> [public_key: bytes]
+    CryptoDelete,   // [bundle_id: str, name: str] -> []
+    CryptoSign,     // [bundle_id: str, name: str, data: bytes] -> [signature: bytes]
+    CryptoVerify,   // [bundle_id: str, name: str, data: bytes, signature: bytes] -> [valid: u32]
+    CryptoMac,      // [bundle_id: str, name: str, data: bytes] -> [mac: bytes]
+    CryptoEncrypt,  // [bundle_id: str, name: str, data: bytes] -> [ciphertext: bytes]
+    CryptoDecrypt,  // [bundle_id: str, name: str, data: bytes] -> [plaintext: bytes]
+
+    ListBundles, // [after: str, max_bytes: u32] -> [more: u32, count: u32, bundle_id: str..]
+    AttestSign,  // [digest: bytes] -> [public_key: bytes, signature: bytes]
+
+    ReadVersion,  // [bundle_id: str] -> [version: u32]
+    WriteVersion, // [bundle_id: str, version: u32] -> []
+
+    FactoryReset, // [] -> []
+}
+
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+pub enum Status {
+    Ok = 0,
+    BadFrame,       // Malformed header or payload
+    BadOpcode,      // Unknown opcode
+    BundleNotFound, // No such bundle
+    DeleteFirst,    // Bundle already installed or key already exists
+    InstallFailed,  // Install out of sequence or package rejected
+    KeyNotFound,    // No such key
+    KeyInvalid,     // Key empty or too long
+    ValueInvalid,   // Value too large
+    QuotaExceeded,  // Write would exceed the bundle's quota
+    NoSpace,        // Security core storage is full
+    CryptoFailed,   // Key type does not support the operation or decrypt failed
+    Failed,         // Anything else
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MailboxError {
+    TransportFailed, // Mailbox send/receive failed
+    TooLarge,        // Request does not fit in a frame
+    BadReply,        // Reply frame malformed
+    Mismatch,        // Reply is not for the request sent
+    Status(Status),  // Security core rejected the request
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FrameHeader {
+    pub flags: u8,
+    pub request_id: u32,
+    pub opcode: u16,
+    pub status: u16,
+    pub length: u32,
+}
+impl FrameHeader {
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..2].copy_from_slice(&FRAME_MAGIC.to_le_bytes());
+        buf[2] = FRAME_VERSION;
+        buf[3] = self.flags;
+        buf[4..8].copy_from_slice(&self.request_id.to_le_bytes());
+        buf[8..10].copy_from_slice(&self.opcode.to_le_bytes());
+        buf[10..12].copy_from_slice(&self.status.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.length.to_le_bytes());
+    }
+
+    // Decodes the header of the |len| byte frame in |buf| and checks the
+    // payload is present.
+    pub fn decode(buf: &[u8], len: usize) -> Option<Self> {
+        if len < HEADER_SIZE || len > buf.len() || len > MAX_FRAME_SIZE {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        if u16_at(0) != FRAME_MAGIC || buf[2] != FRAME_VERSION {
+            return None;
+        }
+        let header = FrameHeader {
+            flags: buf[3],
+            request_id: u32_at(4),
+            opcode: u16_at(8),
+            status: u16_at(10),
+            length: u32_at(12),
+        };
+        if HEADER_SIZE + header.length as usize != len {
+            return None;
+        }
+        Some(header)
+    }
+}
+
+// Builds a payload.
+#[derive(Default)]
+pub struct PayloadWriter {
+    buf: Vec<u8>,
+}
+impl PayloadWriter {
+    pub fn new() -> Self { Self::default() }
+    pub fn as_bytes(&self) -> &[u8] { &self.buf }
+
+    pub fn u32(&mut self, val: u32) -> &mut Self {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+        self
+    }
+    pub fn bytes(&mut self, val: &[u8]) -> &mut Self {
+        self.u32(val.len() as u32);
+        self.buf.extend_from_slice(val);
+        self
+    }
+    pub fn str(&mut self, val: &str) -> &mut Self { self.bytes(val.as_bytes()) }
+}
+
+// Parses a payload; each accessor returns None if the payload is short.
+pub struct PayloadReader<'a> {
+    buf: &'a [u8],
+}
+impl<'a> PayloadReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self { PayloadReader { buf } }
+    pub fn is_empty(&self) -> bool { self.buf.is_empty() }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.buf.len() {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Some(head)
+    }
+    pub fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+    pub fn str(&mut self) -> Option<&'a str> { core::str::from_utf8(self.bytes()?).ok() }
+}
+
+pub trait MailboxTransport {
+    // Sends the |len| byte request frame at the front of |buf| and waits
+    // for the reply, which overwrites |buf|. Returns the reply length.
+    fn transact(&mut self, buf: &mut [u8], len: usize) -> Result<usize, MailboxError>;
+}
+
+// Usage of a bundle's key-value storage as reported by the security core.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct KeyUsage {
+    pub keys: usize,
+    pub bytes: usize,
+}
+
+// Client side of the protocol; one request is outstanding at a time.
+pub struct SecurityCoreClient<T: MailboxTransport> {
+    transport: T,
+    next_request_id: u32,
+    buf: Vec<u8>,
+}
+impl<T: MailboxTransport> SecurityCoreClient<T> {
+    pub fn new(transport: T) -> Self {
+        SecurityCoreClient {
+            transport,
+            next_request_id: 1,
+            buf: alloc::vec![0u8; MAX_FRAME_SIZE],
+        }
+    }
+
+    pub fn transport(&mut self) -> &mut T { &mut self.transport }
+
+    // Sends |opcode| with |payload| and returns the reply payload.
+    pub fn call(&mut self, opcode: Opcode, payload: &[u8]) -> Result<&[u8], MailboxError> {
+        if payload.len() > MAX_PAYLOAD_SIZE {
+            return Err(MailboxError::TooLarge);
+        }
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        FrameHeader {
+            flags: 0,
+            request_id,
+            opcode: opcode as u16,
+            status: Status::Ok as u16,
+            length: payload.len() as u32,
+        }
+        .encode(&mut self.buf);
+        self.buf[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(payload);
+
+        let reply_len = self
+            .transport
+            .transact(&mut self.buf, HEADER_SIZE + payload.len())?;
+        let reply = FrameHeader::decode(&self.buf, reply_len).ok_or(MailboxError::BadReply)?;
+        if (reply.flags & FLAG_REPLY) == 0
+            || reply.request_id != request_id
+            || reply.opcode != opcode as u16
+        {
+            return Err(MailboxError::Mismatch);
+        }
+        match Status::try_from(reply.status) {
+            Ok(Status::Ok) => Ok(&self.buf[HEADER_SIZE..reply_len]),
+            Ok(status) => Err(MailboxError::Status(status)),
+            Err(_) => Err(MailboxError::BadReply),
+        }
+    }
+
+    // Like call but for replies with no payload.
+    fn call_empty(&mut self, opcode: Opcode, payload: &[u8]) -> Result<(), MailboxError> {
+        self.call(opcode, payload).map(|_| ())
+    }
+
+    pub fn echo(&mut self, data: &[u8]) -> Result<Vec<u8>, MailboxError> {
+        let reply = self.call(Opcode::Echo, PayloadWriter::new().bytes(data).as_bytes())?;
+        let mut reader = PayloadReader::new(reply);
+        reader
+            .bytes()
+            .map(|data| data.to_vec())
+            .ok_or(MailboxError::BadReply)
+    }
+
+    // Installs the |size| byte package returned by |next_chunk| as
+    // |bundle_id|; |manifest| locates the package manifest. |next_chunk|
+    // is called with the offset of each chunk wanted (up to MAX_DATA_CHUNK
+    // bytes) and fills the buffer passed. If |next_chunk| fails the
+    // install is aborted (nothing is committed) and the error returned.
+    pub fn install<E>(
+        &mut self,
+        size: usize,
+        bundle_id: &str,
+        manifest: Range<usize>,
+        mut next_chunk: impl FnMut(usize, &mut [u8]) -> Result<(), E>,
+    ) -> Result<String, InstallError<E>> {
+        self.call_empty(
+            Opcode::InstallBegin,
+            PayloadWriter::new()
+                .u32(size as u32)
+                .str(bundle_id)
+                .u32(manifest.start as u32)
+                .u32(manifest.len() as u32)
+                .as_bytes(),
+        )
+        .map_err(InstallError::Mailbox)?;
+        let mut chunk = alloc::vec![0u8; MAX_DATA_CHUNK];
+        let mut offset = 0;
+        while offset < size {
+            let len = core::cmp::min(MAX_DATA_CHUNK, size - offset);
+            let result = next_chunk(offset, &mut chunk[..len])
+                .map_err(InstallError::Source)
+                .and_then(|_| {
+                    self.call_empty(
+                        Opcode::InstallData,
+                        PayloadWriter::new()
+                            .u32(offset as u32)
+                            .bytes(&chunk[..len])
+                            .as_bytes(),
+                    )
+                    .map_err(InstallError::Mailbox)
+                });
+            if let Err(err) = result {
+                let _ = self.call_empty(Opcode::InstallAbort, &[]);
+                return Err(err);
+            }
+            offset += len;
+        }
+        self.finish_install().map_err(InstallError::Mailbox)
+    }
+
+    fn finish_install(&mut self) -> Result<String, MailboxError> {
+        let reply = self.call(Opcode::InstallEnd, &[])?;
+        PayloadReader::new(reply)
+            .str()
+            .map(String::from)
+            .ok_or(MailboxError::BadReply)
+    }
+
+    pub fn install_abort(&mut self) -> Result<(), MailboxError> {
+        self.call_empty(Opcode::InstallAbort, &[])
+    }
+
+    pub fn uninstall(&mut self, bundle_id: &str) -> Result<(), MailboxError> {
+        self.call_empty(Opcode::Uninstall, PayloadWriter::new().str(bundle_id).as_bytes())
+    }
+
+    pub fn size_buffer(&mut self, bundle_id: &str) -> Result<usize, MailboxError> {
+        let reply =
+            self.call(Opcode::SizeBuffer, PayloadWriter::new().str(bundle_id).as_bytes())?;
+        PayloadReader::new(reply)
+            .u32()
+            .map(|size| size as usize)
+            .ok_or(MailboxError::BadReply)
+    }
+
+    // Returns the bundle's manifest. Manifests may be larger than a
+    // frame so they are read in chunks of up to MAX_DATA_CHUNK bytes.
+    pub fn get_manifest(&mut self, bundle_id: &str) -> Result<String, MailboxError> {
+        let mut manifest = Vec::new();
+        loop {
+            let reply = self.call(
+                Opcode::GetManifest,
+                PayloadWriter::new()
+                    .str(bundle_id)
+                    .u32(manifest.len() as u32)
+                    .as_bytes(),
+            )?;
+            let mut reader = PayloadReader::new(reply);
+            let size = reader.u32().ok_or(MailboxError::BadReply)? as usize;
+            let data = reader.bytes().ok_or(MailboxError::BadReply)?;
+            if data.len() > MAX_DATA_CHUNK || manifest.len() + data.len() > size {
+                return Err(MailboxError::BadReply);
+            }
+            manifest.extend_from_slice(data);
+            if manifest.len() == size {
+                break;
+            }
+            if data.is_empty() {
+                return Err(MailboxError::BadReply);
+            }
+        }
+        String::from_utf8(manifest).map_err(|_| MailboxError::BadReply)
+    }
+
+    // Reads up to |buf.len()| (at most MAX_DATA_CHUNK) bytes of the
+    // bundle's image starting at |offset|. Returns the number of bytes
+    // read; this is short at the end of the image.
+    pub fn read_image(
+        &mut self,
+        bundle_id: &str,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, MailboxError> {
+        let len = core::cmp::min(buf.len(), MAX_DATA_CHUNK);
+        let reply = self.call(
+            Opcode::ReadImage,
+            PayloadWriter::new()
+                .str(bundle_id)
+                .u32(offset as u32)
+                .u32(len as u32)
+                .as_bytes(),
+        )?;
+        let data = PayloadReader::new(reply)
+            .bytes()
+            .ok_or(MailboxError::BadReply)?;
+        if data.len() > len {
+            return Err(MailboxError::BadReply);
+        }
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    pub fn read_key(&mut self, bundle_id: &str, key: &str) -> Result<Vec<u8>, MailboxError> {
+        let reply = self.call(
+            Opcode::ReadKey,
+            PayloadWriter::new().str(bundle_id).str(key).as_bytes(),
+        )?;
+        PayloadReader::new(reply)
+            .bytes()
+            .map(|value| value.to_vec())
+            .ok_or(MailboxError::BadReply)
+    }
+
+    // Writes |value| for |key|; the security core rejects the write if
+    // the bundle would then exceed |max_keys| keys or |max_bytes| bytes.
+    pub fn write_key(
+        &mut self,
+        bundle_id: &str,
+        key: &str,
+        value: &[u8],
+        max_keys: usize,
+        max_bytes: usize,
+    ) -> Result<(), MailboxError> {
+        self.call_empty(
+            Opcode::WriteKey,
+            PayloadWriter::new()
+                .str(bundle_id)
+                .str(key)
+                .bytes(value)
+                .u32(max_keys as u32)
+                .u32(max_bytes as u32)
+                .as_bytes(),
+        )
+    }
+
+    pub fn delete_key(&mut self, bundle_id: &str, key: &str) -> Result<(), MailboxError> {
+        self.call_empty(
+            Opcode::DeleteKey,
+            PayloadWriter::new().str(bundle_id).str(key).as_bytes(),
+        )
+    }
+
+    // Returns the bundle's keys following |after| and whether more remain.
+    // Each key costs its length + 2 against |max_bytes|.
+    pub fn list_keys(
+        &mut self,
+        bundle_id: &str,
+        after: Option<&str>,
+        max_bytes: usize,
+    ) -> Result<(Vec<String>, bool), MailboxError> {
+        let reply = self.call(
+            Opcode::ListKeys,
+            PayloadWriter::new()
+                .str(bundle_id)
+                .str(after.unwrap_or(""))
+                .u32(max_bytes as u32)
+                .as_bytes(),
+        )?;
+        read_names(reply)
+    }
+
+    // Returns the ids of installed bundles that sort after |after| (all
+    // if None). Paged like list_keys.
+    pub fn list_bundles(
+        &mut self,
+        after: Option<&str>,
+        max_bytes: usize,
+    ) -> Result<(Vec<String>, bool), MailboxError> {
+        let reply = self.call(
+            Opcode::ListBundles,
+            PayloadWriter::new()
+                .str(after.unwrap_or(""))
+                .u32(max_bytes as u32)
+                .as_bytes(),
+        )?;
+        read_names(reply)
+    }
+
+    // Returns the highest version recorded for |bundle_id| (0 if none).
+    pub fn read_version(&mut self, bundle_id: &str) -> Result<u32, MailboxError> {
+        let reply =
+            self.call(Opcode::ReadVersion, PayloadWriter::new().str(bundle_id).as_bytes())?;
+        PayloadReader::new(reply)
+            .u32()
+            .ok_or(MailboxError::BadReply)
+    }
+
+    // Records |version| as the highest installed for |bundle_id|; 0
+    // forgets any recorded version.
+    pub fn write_version(&mut self, bundle_id: &str, version: u32) -> Result<(), MailboxError> {
+        self.call_empty(
+            Opcode::WriteVersion,
+            PayloadWriter::new().str(bundle_id).u32(version).as_bytes(),
+        )
+    }
+
+    // Removes all bundles and erases all data held by the security core;
+    // version records are kept.
+    pub fn factory_reset(&mut self) -> Result<(), MailboxError> {
+        self.call_empty(Opcode::FactoryReset, &[])
+    }
+
+    // Signs an attestation report |digest| with the device attestation
+    // key. Returns the public key and signature.
+    pub fn attest_sign(&mut self, digest: &[u8]) -> Result<(Vec<u8>, Vec<u8>), MailboxError> {
+        let reply = self.call(Opcode::AttestSign, PayloadWriter::new().bytes(digest).as_bytes())?;
+        let mut reader = PayloadReader::new(reply);
+        match (reader.bytes(), reader.bytes()) {
+            (Some(public_key), Some(signature)) => Ok((public_key.to_vec(), signature.to_vec())),
+            _ => Err(MailboxError::BadReply),
+        }
+    }
+
+    // Fills |buf| with bytes from the security core's entropy source.
+    pub fn get_entropy(&mut self, buf: &mut [u8]) -> Result<(), MailboxError> {
+        for chunk in buf.chunks_mut(MAX_DATA_CHUNK) {
+            let reply = self.call(
+                Opcode::GetEntropy,
+                PayloadWriter::new().u32(chunk.len() as u32).as_bytes(),
+            )?;
+            let data = PayloadReader::new(reply)
+                .bytes()
+                .ok_or(MailboxError::BadReply)?;
+            if data.len() != chunk.len() {
+                return Err(MailboxError::BadReply);
+            }
+            chunk.copy_from_slice(data);
+        }
+        Ok(())
+    }
+
+    // Generates a |key_type| key |name| for the bundle and returns its
+    // public key (empty for symmetric keys).
+    pub fn crypto_generate(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        key_type: u32,
+    ) -> Result<Vec<u8>, MailboxError> {
+        let reply = self.call(
+            Opcode::CryptoGenerate,
+            PayloadWriter::new()
+                .str(bundle_id)
+                .str(name)
+                .u32(key_type)
+                .as_bytes(),
+        )?;
+        PayloadReader::new(reply)
+            .bytes()
+            .map(|public_key| public_key.to_vec())
+            .ok_or(MailboxError::BadReply)
+    }
+
+    pub fn crypto_delete(&mut self, bundle_id: &str, name: &str) -> Result<(), MailboxError> {
+        self.call_empty(
+            Opcode::CryptoDelete,
+            PayloadWriter::new().str(bundle_id).str(name).as_bytes(),
+        )
+    }
+
+    // Applies key |name| to |data| for |opcode|, returning the result.
+    fn crypto_op(
+        &mut self,
+        opcode: Opcode,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, MailboxError> {
+        let reply = self.call(
+            opcode,
+            PayloadWriter::new()
+                .str(bundle_id)
+                .str(name)
+                .bytes(data)
+                .as_bytes(),
+        )?;
+        PayloadReader::new(reply)
+            .bytes()
+            .map(|result| result.to_vec())
+            .ok_or(MailboxError::BadReply)
+    }
+
+    pub fn crypto_sign(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, MailboxError> {
+        self.crypto_op(Opcode::CryptoSign, bundle_id, name, data)
+    }
+
+    // Returns whether |signature| is a signature or MAC of |data| made
+    // with key |name|.
+    pub fn crypto_verify(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, MailboxError> {
+        let reply = self.call(
+            Opcode::CryptoVerify,
+            PayloadWriter::new()
+                .str(bundle_id)
+                .str(name)
+                .bytes(data)
+                .bytes(signature)
+                .as_bytes(),
+        )?;
+        PayloadReader::new(reply)
+            .u32()
+            .map(|valid| valid != 0)
+            .ok_or(MailboxError::BadReply)
+    }
+
+    pub fn crypto_mac(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, MailboxError> {
+        self.crypto_op(Opcode::CryptoMac, bundle_id, name, data)
+    }
+
+    pub fn crypto_encrypt(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, MailboxError> {
+        self.crypto_op(Opcode::CryptoEncrypt, bundle_id, name, data)
+    }
+
+    pub fn crypto_decrypt(
+        &mut self,
+        bundle_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, MailboxError> {
+        self.crypto_op(Opcode::CryptoDecrypt, bundle_id, name, data)
+    }
+
+    pub fn key_usage(&mut self, bundle_id: &str) -> Result<KeyUsage, MailboxError> {
+        let reply = self.call(Opcode::KeyUsage, PayloadWriter::new().str(bundle_id).as_bytes())?;
+        let mut reader = PayloadReader::new(reply);
+        match (reader.u32(), reader.u32()) {
+            (Some(keys), Some(bytes)) => Ok(KeyUsage {
+                keys: keys as usize,
+                bytes: bytes as usize,
+            }),
+            _ => Err(MailboxError::BadReply),
+        }
+    }
+}
+
+// Decodes a [more: u32, count: u32, name: str..] reply.
+fn read_names(reply: &[u8]) -> Result<(Vec<String>, bool), MailboxError> {
+    let mut reader = PayloadReader::new(reply);
+    let more = reader.u32().ok_or(MailboxError::BadReply)? != 0;
+    let count = reader.u32().ok_or(MailboxError::BadReply)?;
+    let mut names = Vec::new();
+    for _ in 0..count {
+        names.push(String::from(reader.str().ok_or(MailboxError::BadReply)?));
+    }
+    Ok((names, more))
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum InstallError<E> {
+    Mailbox(MailboxError), // Security core request failed
+    Source(E),             // Fetching package contents failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_MAX_KEYS: usize = 4;
+    const TEST_MAX_BYTES: usize = 1024;
+
+    fn client() -> SecurityCoreClient<SimulatedSecurityCore> {
+        SecurityCoreClient::new(SimulatedSecurityCore::new())
+    }
+
+    fn package(size: usize) -> Vec<u8> { (0..size).map(|i| (i % 251) as u8).collect() }
+
+    fn install_with_manifest(
+        client: &mut SecurityCoreClient<SimulatedSecurityCore>,
+        bundle_id: &str,
+        pkg: &[u8],
+        manifest: Range<usize>,
+    ) -> Result<String, InstallError<()>> {
+        client.install(pkg.len(), bundle_id, manifest, |offset, buf| {
+            buf.copy_from_slice(&pkg[offset..offset + buf.len()]);
+            Ok(())
+        })
+    }
+
+    fn install(
+        client: &mut SecurityCoreClient<SimulatedSecurityCore>,
+        bundle_id: &str,
+        pkg: &[u8],
+    ) -> String {
+        install_with_manifest(client, bundle_id, pkg, 0..0).unwrap()
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = FrameHeader {
+            flags: FLAG_REPLY,
+            request_id: 0x12345678,
+            opcode: Opcode::ReadKey as u16,
+            status: Status::KeyNotFound as u16,
+            length: 3,
+        };
+        let mut buf = [0u8; HEADER_SIZE + 3];
+        header.encode(&mut buf);
+        assert_eq!(FrameHeader::decode(&buf, buf.len()), Some(header));
+        // Length must match the payload.
+        assert_eq!(FrameHeader::decode(&buf, buf.len() - 1), None);
+        assert_eq!(FrameHeader::decode(&buf, HEADER_SIZE - 1), None);
+        buf[0] ^= 1;
+        assert_eq!(FrameHeader::decode(&buf, buf.len()), None);
+    }
+
+    #[test]
+    fn test_payload() {
+        let mut writer = PayloadWriter::new();
+        writer.u32(7).str("key").bytes(&[1, 2, 3]);
+        let mut reader = PayloadReader::new(writer.as_bytes());
+        assert_eq!(reader.u32(), Some(7));
+        assert_eq!(reader.str(), Some("key"));
+        assert_eq!(reader.bytes(), Some(&[1u8, 2, 3][..]));
+        assert!(reader.is_empty());
+        assert_eq!(reader.u32(), None);
+
+        // Length prefix beyond the end of the payload.
+        let mut reader = PayloadReader::new(&[9, 0, 0, 0, 1]);
+        assert_eq!(reader.bytes(), None);
+    }
+
+    #[test]
+    fn test_echo() {
+        let mut client = client();
+        assert_eq!(client.echo(b"hello").unwrap(), b"hello");
+        assert_eq!(client.echo(&[]).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut client = client();
+        assert_eq!(
+            client.echo(&[0u8; MAX_PAYLOAD_SIZE]).unwrap_err(),
+            MailboxError::TooLarge
+        );
+    }
+
+    #[test]
+    fn test_install_load_uninstall() {
+        let mut client = client();
+        let manifest = "[Manifest]\nBundleId=com.example.app\n";
+        let mut pkg = package(3 * MAX_DATA_CHUNK + 100);
+        pkg[10..10 + manifest.len()].copy_from_slice(manifest.as_bytes());
+        let bundle_id =
+            install_with_manifest(&mut client, "com.example.app", &pkg, 10..10 + manifest.len())
+                .unwrap();
+        assert_eq!(bundle_id, "com.example.app");
+        assert_eq!(client.size_buffer(&bundle_id).unwrap(), pkg.len());
+        assert_eq!(client.get_manifest(&bundle_id).unwrap(), manifest);
+
+        // Read back the image in chunks; the final chunk is short.
+        let mut image = Vec::new();
+        let mut chunk = [0u8; MAX_DATA_CHUNK];
+        loop {
+            let n = client
+                .read_image(&bundle_id, image.len(), &mut chunk)
+                .unwrap();
+            image.extend_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                break;
+            }
+        }
+        assert_eq!(image, pkg);
+
+        client.uninstall(&bundle_id).unwrap();
+        assert_eq!(
+            client.size_buffer(&bundle_id).unwrap_err(),
+            MailboxError::Status(Status::BundleNotFound)
+        );
+        assert_eq!(
+            client.uninstall(&bundle_id).unwrap_err(),
+            MailboxError::Status(Status::BundleNotFound)
+        );
+    }
+
+    #[test]
+    fn test_install_rejected() {
+        let mut client = client();
+        let pkg = package(100);
+        install(&mut client, "a", &pkg);
+        // Already installed.
+        assert_eq!(
+            install_with_manifest(&mut client, "a", &pkg, 0..0).unwrap_err(),
+            InstallError::Mailbox(MailboxError::Status(Status::DeleteFirst))
+        );
+        // Manifest outside the package or not text.
+        assert_eq!(
+            install_with_manifest(&mut client, "b", &pkg, 90..110).unwrap_err(),
+            InstallError::Mailbox(MailboxError::Status(Status::InstallFailed))
+        );
+        assert_eq!(
+            install_with_manifest(&mut client, "b", &pkg, 200..210).unwrap_err(),
+            InstallError::Mailbox(MailboxError::Status(Status::InstallFailed))
+        );
+        assert_eq!(
+            client.list_bundles(None, 1024).unwrap(),
+            (vec!["a".to_string()], false)
+        );
+    }
+
+    #[test]
+    fn test_entropy() {
+        // Requests larger than a chunk are split.
+        let mut a = [0u8; MAX_DATA_CHUNK + 10];
+        client().get_entropy(&mut a).unwrap();
+        assert!(a.iter().any(|&b| b != 0));
+
+        // The simulator's sequence is reproducible.
+        let mut b = [0u8; MAX_DATA_CHUNK + 10];
+        client().get_entropy(&mut b).unwrap();
+        assert_eq!(a, b);
+
+        // But successive requests differ.
+        let mut client = client();
+        let (mut c, mut d) = ([0u8; 32], [0u8; 32]);
+        client.get_entropy(&mut c).unwrap();
+        client.get_entropy(&mut d).unwrap();
+        assert_ne!(c, d);
+    }
+
+    #[test]
+    fn test_large_manifest() {
+        let mut client = client();
+        let bundle_id = install(&mut client, "a", &package(100));
+        for size in [0, MAX_DATA_CHUNK, 3 * MAX_DATA_CHUNK + 1] {
+            let manifest: String = (0..size).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+            client
+                .transport()
+                .set_manifest(&bundle_id, &manifest)
+                .unwrap();
+            assert_eq!(client.get_manifest(&bundle_id).unwrap(), manifest);
+        }
+    }
+
+    #[test]
+    fn test_install_aborted() {
+        let mut client = client();
+        let pkg = package(2 * MAX_DATA_CHUNK);
+        let result = client.install(pkg.len(), "a", 0..0, |offset, buf| {
+            if offset > 0 {
+                return Err("read failed");
+            }
+            buf.copy_from_slice(&pkg[..buf.len()]);
+            Ok(())
+        });
+        assert_eq!(result.unwrap_err(), InstallError::Source("read failed"));
+        // Nothing is pending so a new install starts cleanly.
+        assert_eq!(
+            client.call(Opcode::InstallEnd, &[]).unwrap_err(),
+            MailboxError::Status(Status::InstallFailed)
+        );
+        install(&mut client, "a", &pkg);
+    }
+
+    #[test]
+    fn test_install_out_of_order() {
+        let mut client = client();
+        assert_eq!(
+            client
+                .call(
+                    Opcode::InstallData,
+                    PayloadWriter::new().u32(0).bytes(b"x").as_bytes()
+                )
+                .unwrap_err(),
+            MailboxError::Status(Status::InstallFailed)
+        );
+        client
+            .call(
+                Opcode::InstallBegin,
+                PayloadWriter::new()
+                    .u32(4)
+                    .str("a")
+                    .u32(0)
+                    .u32(0)
+                    .as_bytes(),
+            )
+            .unwrap();
+        // Data must be contiguous and within the declared size.
+        assert_eq!(
+            client
+                .call(
+                    Opcode::InstallData,
+                    PayloadWriter::new().u32(1).bytes(b"x").as_bytes()
+                )
+                .unwrap_err(),
+            MailboxError::Status(Status::InstallFailed)
+        );
+        assert_eq!(
+            client
+                .call(
+                    Opcode::InstallData,
+                    PayloadWriter::new().u32(0).bytes(b"xxxxx").as_bytes()
+                )
+                .unwrap_err(),
+            MailboxError::Status(Status::InstallFailed)
+        );
+        // Short package.
+        assert_eq!(
+            client.call(Opcode::InstallEnd, &[]).unwrap_err(),
+            MailboxError::Status(Status::InstallFailed)
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut client = client();
+        let bundle_id = install(&mut client, "a", &package(100));
+        assert_eq!(
+            client.read_key(&bundle_id, "foo").unwrap_err(),
+            MailboxError::Status(Status::KeyNotFound)
+        );
+        client
+            .write_key(&bundle_id, "foo", b"123", TEST_MAX_KEYS, TEST_MAX_BYTES)
+            .unwrap();
+        assert_eq!(client.read_key(&bundle_id, "foo").unwrap(), b"123");
+        let value = [0x5a; 3 * 1024];
+        client
+            .write_key(&bundle_id, "foo", &value, TEST_MAX_KEYS, 4096)
+            .unwrap();
+        assert_eq!(client.read_key(&bundle_id, "foo").unwrap(), value);
+        client.delete_key(&bundle_id, "foo").unwrap();
+        assert_eq!(
+            client.read_key(&bundle_id, "foo").unwrap_err(),
+            MailboxError::Status(Status::KeyNotFound)
+        );
+        assert_eq!(
+            client
+                .write_key("no.such.bundle", "foo", b"1", TEST_MAX_KEYS, TEST_MAX_BYTES)
+                .unwrap_err(),
+            MailboxError::Status(Status::BundleNotFound)
+        );
+        assert_eq!(
+            client
+                .write_key(&bundle_id, "", b"1", TEST_MAX_KEYS, TEST_MAX_BYTES)
+                .unwrap_err(),
+            MailboxError::Status(Status::KeyInvalid)
+        );
+    }
+
+    #[test]
+    fn test_keys_per_bundle() {
+        let mut client = client();
+        let a = install(&mut client, "a", &package(100));
+        let b = install(&mut client, "b", &package(200));
+        assert_ne!(a, b);
+        client
+            .write_key(&a, "foo", b"a", TEST_MAX_KEYS, TEST_MAX_BYTES)
+            .unwrap();
+        assert_eq!(
+            client.read_key(&b, "foo").unwrap_err(),
+            MailboxError::Status(Status::KeyNotFound)
+        );
+        // Uninstall drops the bundle's keys.
+        client.uninstall(&a).unwrap();
+        let a = install(&mut client, "a", &package(100));
+        assert_eq!(
+            client.read_key(&a, "foo").unwrap_err(),
+            MailboxError::Status(Status::KeyNotFound)
+        );
+    }
+
+    #[test]
+    fn test_crypto_keys() {
+        use kata_crypto_keys::{KeyType, MAX_BUNDLE_KEYS};
+        const ED25519: u32 = KeyType::Ed25519 as u32;
+        const HMAC: u32 = KeyType::HmacSha256 as u32;
+        const AES: u32 = KeyType::Aes256GcmSiv as u32;
+
+        let mut client = client();
+        let a = install(&mut client, "a", &package(100));
+        let b = install(&mut client, "b", &package(200));
+
+        let public_key = client.crypto_generate(&a, "sign", ED25519).unwrap();
+        assert_eq!(public_key.len(), kata_crypto_keys::PUBLIC_KEY_SIZE);
+        let signature = client.crypto_sign(&a, "sign", b"data").unwrap();
+        assert!(client
+            .crypto_verify(&a, "sign", b"data", &signature)
+            .unwrap());
+        assert!(!client
+            .crypto_verify(&a, "sign", b"other", &signature)
+            .unwrap());
+
+        assert!(client.crypto_generate(&a, "mac", HMAC).unwrap().is_empty());
+        let mac = client.crypto_mac(&a, "mac", b"data").unwrap();
+        assert!(client.crypto_verify(&a, "mac", b"data", &mac).unwrap());
+
+        client.crypto_generate(&a, "enc", AES).unwrap();
+        let ciphertext = client.crypto_encrypt(&a, "enc", b"data").unwrap();
+        assert_eq!(client.crypto_decrypt(&a, "enc", &ciphertext).unwrap(), b"data");
+        // Nonces differ so the same plaintext encrypts differently.
+        assert_ne!(client.crypto_encrypt(&a, "enc", b"data").unwrap(), ciphertext);
+
+        // Operations must match the key type.
+        assert_eq!(
+            client.crypto_sign(&a, "mac", b"data").unwrap_err(),
+            MailboxError::Status(Status::CryptoFailed)
+        );
+        assert_eq!(
+            client
+                .crypto_decrypt(&a, "enc", &ciphertext[1..])
+                .unwrap_err(),
+            MailboxError::Status(Status::CryptoFailed)
+        );
+
+        // Keys are per-bundle.
+        assert_eq!(
+            client.crypto_sign(&b, "sign", b"data").unwrap_err(),
+            MailboxError::Status(Status::KeyNotFound)
+        );
+
+        // Names are not reused without a delete & there is a limit per bundle.
+        assert_eq!(
+            client.crypto_generate(&a, "sign", ED25519).unwrap_err(),
+            MailboxError::Status(Status::DeleteFirst)
+        );
+        assert_eq!(
+            client.crypto_generate(&a, "", ED25519).unwrap_err(),
+            MailboxError::Status(Status::KeyInvalid)
+        );
+        assert_eq!(
+            client.crypto_generate(&a, "bad", 99).unwrap_err(),
+            MailboxError::Status(Status::BadFrame)
+        );
+        for i in 3..MAX_BUNDLE_KEYS {
+            client.crypto_generate(&a, &i.to_string(), HMAC).unwrap();
+        }
+        assert_eq!(
+            client.crypto_generate(&a, "full", HMAC).unwrap_err(),
+            MailboxError::Status(Status::QuotaExceeded)
+        );
+        client.crypto_delete(&a, "sign").unwrap();
+        assert_eq!(
+            client.crypto_delete(&a, "sign").unwrap_err(),
+            MailboxError::Status(Status::KeyNotFound)
+        );
+        let new_public_key = client.crypto_generate(&a, "sign", ED25519).unwrap();
+        assert_ne!(new_public_key, public_key);
+
+        // Uninstall drops the bundle's keys.
+        client.uninstall(&a).unwrap();
+        let a = install(&mut client, "a", &package(100));
+        assert_eq!(
+            client.crypto_mac(&a, "mac", b"data").unwrap_err(),
+            MailboxError::Status(Status::KeyNotFound)
+        );
+    }
+
+    #[test]
+    fn test_list_keys_and_usage() {
+        let mut client = client();
+        let bundle_id = install(&mut client, "a", &package(100));
+        for key in ["c", "a", "b"] {
+            client
+                .write_key(&bundle_id, key, b"12", TEST_MAX_KEYS, TEST_MAX_BYTES)
+                .unwrap();
+        }
+        assert_eq!(
+            client.list_keys(&bundle_id, None, 1024).unwrap(),
+            (vec!["a".to_string(), "b".to_string(), "c".to_string()], false)
+        );
+        // Each key costs 3 bytes so only 2 fit.
+        assert_eq!(
+            client.list_keys(&bundle_id, None, 7).unwrap(),
+            (vec!["a".to_string(), "b".to_string()], true)
+        );
+        assert_eq!(
+            client.list_keys(&bundle_id, Some("b"), 7).unwrap(),
+            (vec!["c".to_string()], false)
+        );
+        assert_eq!(client.key_usage(&bundle_id).unwrap(), KeyUsage { keys: 3, bytes: 9 });
+    }
+
+    #[test]
+    fn test_list_bundles() {
+        let mut client = client();
+        assert_eq!(client.list_bundles(None, 1024).unwrap(), (vec![], false));
+        let ids: Vec<String> = (0..3)
+            .map(|i| install(&mut client, &format!("b{}", i), &package(10)))
+            .collect();
+        assert_eq!(client.list_bundles(None, 1024).unwrap(), (ids.clone(), false));
+        // Paged: each id costs its length + 2.
+        let (page, more) = client.list_bundles(None, ids[0].len() + 2).unwrap();
+        assert_eq!((page, more), (vec![ids[0].clone()], true));
+        let (page, more) = client.list_bundles(Some(&ids[0]), 1024).unwrap();
+        assert_eq!((page, more), (ids[1..].to_vec(), false));
+    }
+
+    #[test]
+    fn test_attest_sign() {
+        let mut client = client();
+        let report = kata_attestation::Report::new("1.0", b"nonce").encode();
+        let digest = kata_attestation::report_digest(report.as_bytes());
+        let (public_key, signature) = client.attest_sign(&digest).unwrap();
+        assert!(kata_attestation::verify(
+            &report,
+            &public_key,
+            &signature,
+            &[kata_attestation::TEST_ATTESTATION_PUBLIC_KEY]
+        )
+        .is_ok());
+        assert_eq!(
+            client.attest_sign(&digest[1..]).unwrap_err(),
+            MailboxError::Status(Status::BadFrame)
+        );
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut client = client();
+        assert_eq!(client.read_version("com.example.app").unwrap(), 0);
+        client.write_version("com.example.app", 3).unwrap();
+        assert_eq!(client.read_version("com.example.app").unwrap(), 3);
+        assert_eq!(client.read_version("com.example.other").unwrap(), 0);
+
+        // Versions are kept when the bundle is uninstalled.
+        let bundle_id = install(&mut client, "com.example.installed", &package(10));
+        client.write_version(&bundle_id, 2).unwrap();
+        client.uninstall(&bundle_id).unwrap();
+        assert_eq!(client.read_version(&bundle_id).unwrap(), 2);
+
+        client.write_version("com.example.app", 0).unwrap();
+        assert_eq!(client.read_version("com.example.app").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_factory_reset() {
+        let mut client = client();
+        let bundle_id = install(&mut client, "com.example.installed", &package(10));
+        client
+            .write_key(&bundle_id, "key", b"value", TEST_MAX_KEYS, TEST_MAX_BYTES)
+            .unwrap();
+        client.write_version(&bundle_id, 2).unwrap();
+
+        client.factory_reset().unwrap();
+        assert_eq!(client.list_bundles(None, 1024).unwrap(), (vec![], false));
+        assert_eq!(
+            client.read_key(&bundle_id, "key").unwrap_err(),
+            MailboxError::Status(Status::BundleNotFound)
+        );
+        // Version records survive so the bundle cannot be rolled back.
+        assert_eq!(client.read_version(&bundle_id).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_quota() {
+        let mut client = client();
+        let bundle_id = install(&mut client, "a", &package(100));
+        client.write_key(&bundle_id, "a", b"1234", 2, 10).unwrap();
+        // Too many keys.
+        client.write_key(&bundle_id, "b", b"1", 2, 10).unwrap();
+        assert_eq!(
+            client.write_key(&bundle_id, "c", b"1", 2, 10).unwrap_err(),
+            MailboxError::Status(Status::QuotaExceeded)
+        );
+        // Too many bytes; an overwrite counts only the change in size.
+        client.write_key(&bundle_id, "a", b"123456", 2, 10).unwrap();
+        assert_eq!(
+            client
+                .write_key(&bundle_id, "a", b"12345678", 2, 10)
+                .unwrap_err(),
+            MailboxError::Status(Status::QuotaExceeded)
+        );
+        assert_eq!(client.read_key(&bundle_id, "a").unwrap(), b"123456");
+    }
+
+    // Transport that mangles replies from the simulator.
+    struct BadTransport {
+        core: SimulatedSecurityCore,
+        mangle: fn(&mut [u8], usize) -> usize,
+    }
+    impl MailboxTransport for BadTransport {
+        fn transact(&mut self, buf: &mut [u8], len: usize) -> Result<usize, MailboxError> {
+            let len = self.core.transact(buf, len)?;
+            Ok((self.mangle)(buf, len))
+        }
+    }
+
+    fn bad_client(mangle: fn(&mut [u8], usize) -> usize) -> SecurityCoreClient<BadTransport> {
+        SecurityCoreClient::new(BadTransport {
+            core: SimulatedSecurityCore::new(),
+            mangle,
+        })
+    }
+
+    #[test]
+    fn test_bad_replies() {
+        // Wrong request id.
+        let mut client = bad_client(|buf, len| {
+            buf[4] ^= 1;
+            len
+        });
+        assert_eq!(client.echo(b"x").unwrap_err(), MailboxError::Mismatch);
+
+        // Wrong opcode.
+        let mut client = bad_client(|buf, len| {
+            buf[8] ^= 1;
+            len
+        });
+        assert_eq!(client.echo(b"x").unwrap_err(), MailboxError::Mismatch);
+
+        // Request echoed back without the reply flag.
+        let mut client = bad_client(|buf, len| {
+            buf[3] = 0;
+            len
+        });
+        assert_eq!(client.echo(b"x").unwrap_err(), MailboxError::Mismatch);
+
+        // Truncated.
+        let mut client = bad_client(|_, len| len - 1);
+        assert_eq!(client.echo(b"x").unwrap_err(), MailboxError::BadReply);
+
+        // Unknown status.
+        let mut client = bad_client(|buf, len| {
+            buf[10] = 0xff;
+            len
+        });
+        assert_eq!(client.echo(b"x").unwrap_err(), MailboxError::BadReply);
+    }
+
+    #[test]
+    fn test_bad_requests() {
+        let mut core = SimulatedSecurityCore::new();
+        let mut buf = [0u8; MAX_FRAME_SIZE];
+
+        // Garbage gets a BadFrame reply.
+        let len = core.transact(&mut buf, HEADER_SIZE).unwrap();
+        let reply = FrameHeader::decode(&buf, len).unwrap();
+        assert_eq!(reply.status, Status::BadFrame as u16);
+
+        // Unknown opcode.
+        FrameHeader {
+            flags: 0,
+            request_id: 9,
+            opcode: 0xffff,
+            status: 0,
+            length: 0,
+        }
+        .encode(&mut buf);
+        let len = core.transact(&mut buf, HEADER_SIZE).unwrap();
+        let reply = FrameHeader::decode(&buf, len).unwrap();
+        assert_eq!(reply.request_id, 9);
+        assert_eq!(reply.status, Status::BadOpcode as u16);
+
+        // Short payload.
+        let mut client = client();
+        assert_eq!(
+            client.call(Opcode::ReadKey, &[1, 0, 0]).unwrap_err(),
+            MailboxError::Status(Status::BadFrame)
+        );
+    }
+}
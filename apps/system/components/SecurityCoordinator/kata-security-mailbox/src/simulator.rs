@@ -0,0 +1,422 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Host-side security core simulator.
+//!
+//! Models the security core's side of the mailbox protocol with all
+//! state held in memory. Packages are accepted as-is (signature checks
+//! are done by the SecurityCoordinator) and key-value data is stored in
+//! the clear. The "entropy" source is a fixed pseudo-random sequence so
+//! host tests are reproducible; it is also used to generate crypto keys
+//! and encryption nonces. Attestation reports are signed with the
+//! well-known test attestation key.
+
+use crate::*;
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use core::ops::Bound;
+use core::ops::Range;
+use kata_crypto_keys::{CryptoKey, KeyType};
+
+const MAX_KEY_LEN: usize = 255;
+const MAX_VALUE_LEN: usize = 3 * 1024;
+
+struct Bundle {
+    image: Vec<u8>,
+    manifest: String,
+    keys: BTreeMap<String, Vec<u8>>,
+    crypto_keys: BTreeMap<String, CryptoKey>,
+}
+impl Bundle {
+    fn usage(&self) -> KeyUsage {
+        KeyUsage {
+            keys: self.keys.len(),
+            bytes: self.keys.iter().map(|(k, v)| k.len() + v.len()).sum(),
+        }
+    }
+}
+
+struct PendingInstall {
+    size: usize,
+    image: Vec<u8>,
+    bundle_id: String,
+    manifest: Range<usize>, // Location of the manifest in |image|
+}
+
+pub struct SimulatedSecurityCore {
+    bundles: BTreeMap<String, Bundle>,
+    pending: Option<PendingInstall>,
+    versions: BTreeMap<String, u32>, // Highest installed, kept on uninstall & reset
+    entropy: u64,                    // xorshift64 state for GetEntropy
+}
+impl Default for SimulatedSecurityCore {
+    fn default() -> Self { Self::new() }
+}
+impl SimulatedSecurityCore {
+    pub fn new() -> Self {
+        SimulatedSecurityCore {
+            bundles: BTreeMap::new(),
+            pending: None,
+            versions: BTreeMap::new(),
+            entropy: 0x4b41_5441_534b_0001,
+        }
+    }
+
+    // Returns the next byte of the (non-random) entropy sequence.
+    fn next_entropy(&mut self) -> u8 {
+        self.entropy ^= self.entropy << 13;
+        self.entropy ^= self.entropy >> 7;
+        self.entropy ^= self.entropy << 17;
+        (self.entropy >> 32) as u8
+    }
+
+    // Replaces the manifest of an installed bundle (for testing).
+    pub fn set_manifest(&mut self, bundle_id: &str, manifest: &str) -> Result<(), Status> {
+        self.bundle_mut(bundle_id)?.manifest = manifest.to_string();
+        Ok(())
+    }
+
+    fn bundle(&self, bundle_id: &str) -> Result<&Bundle, Status> {
+        self.bundles.get(bundle_id).ok_or(Status::BundleNotFound)
+    }
+    fn bundle_mut(&mut self, bundle_id: &str) -> Result<&mut Bundle, Status> {
+        self.bundles
+            .get_mut(bundle_id)
+            .ok_or(Status::BundleNotFound)
+    }
+
+    // Returns the crypto key named by the [bundle_id: str, name: str]
+    // arguments at the front of |args|.
+    fn crypto_key(&self, args: &mut PayloadReader) -> Result<&CryptoKey, Status> {
+        let bundle = self.bundle(args.str().ok_or(Status::BadFrame)?)?;
+        bundle
+            .crypto_keys
+            .get(args.str().ok_or(Status::BadFrame)?)
+            .ok_or(Status::KeyNotFound)
+    }
+
+    // Processes the request in |payload|, returning the reply payload.
+    fn dispatch(&mut self, opcode: Opcode, payload: &[u8]) -> Result<PayloadWriter, Status> {
+        let mut args = PayloadReader::new(payload);
+        let mut reply = PayloadWriter::new();
+        match opcode {
+            Opcode::Echo => {
+                reply.bytes(args.bytes().ok_or(Status::BadFrame)?);
+            }
+            Opcode::InstallBegin => {
+                let size = args.u32().ok_or(Status::BadFrame)? as usize;
+                let bundle_id = args.str().ok_or(Status::BadFrame)?.to_string();
+                let offset = args.u32().ok_or(Status::BadFrame)? as usize;
+                let len = args.u32().ok_or(Status::BadFrame)? as usize;
+                if offset + len > size {
+                    return Err(Status::InstallFailed);
+                }
+                self.pending = Some(PendingInstall {
+                    size,
+                    image: Vec::with_capacity(size),
+                    bundle_id,
+                    manifest: offset..offset + len,
+                });
+            }
+            Opcode::InstallData => {
+                let offset = args.u32().ok_or(Status::BadFrame)? as usize;
+                let data = args.bytes().ok_or(Status::BadFrame)?;
+                let pending = self.pending.as_mut().ok_or(Status::InstallFailed)?;
+                if offset != pending.image.len() || offset + data.len() > pending.size {
+                    return Err(Status::InstallFailed);
+                }
+                pending.image.extend_from_slice(data);
+            }
+            Opcode::InstallEnd => {
+                let pending = self.pending.take().ok_or(Status::InstallFailed)?;
+                if pending.image.len() != pending.size {
+                    return Err(Status::InstallFailed);
+                }
+                if self.bundles.contains_key(&pending.bundle_id) {
+                    return Err(Status::DeleteFirst);
+                }
+                let manifest = core::str::from_utf8(&pending.image[pending.manifest])
+                    .map_err(|_| Status::InstallFailed)?
+                    .to_string();
+                let bundle_id = pending.bundle_id;
+                self.bundles.insert(
+                    bundle_id.clone(),
+                    Bundle {
+                        image: pending.image,
+                        manifest,
+                        keys: BTreeMap::new(),
+                        crypto_keys: BTreeMap::new(),
+                    },
+                );
+                reply.str(&bundle_id);
+            }
+            Opcode::InstallAbort => {
+                self.pending = None;
+            }
+            Opcode::Uninstall => {
+                let bundle_id = args.str().ok_or(Status::BadFrame)?;
+                self.bundles
+                    .remove(bundle_id)
+                    .ok_or(Status::BundleNotFound)?;
+            }
+            Opcode::SizeBuffer => {
+                let bundle = self.bundle(args.str().ok_or(Status::BadFrame)?)?;
+                reply.u32(bundle.image.len() as u32);
+            }
+            Opcode::GetManifest => {
+                let manifest = self
+                    .bundle(args.str().ok_or(Status::BadFrame)?)?
+                    .manifest
+                    .as_bytes();
+                let offset = args.u32().ok_or(Status::BadFrame)? as usize;
+                let start = core::cmp::min(offset, manifest.len());
+                let end = core::cmp::min(start + MAX_DATA_CHUNK, manifest.len());
+                reply
+                    .u32(manifest.len() as u32)
+                    .bytes(&manifest[start..end]);
+            }
+            Opcode::ReadImage => {
+                let bundle = self.bundle(args.str().ok_or(Status::BadFrame)?)?;
+                let offset = args.u32().ok_or(Status::BadFrame)? as usize;
+                let len = args.u32().ok_or(Status::BadFrame)? as usize;
+                let start = core::cmp::min(offset, bundle.image.len());
+                let end =
+                    core::cmp::min(start + core::cmp::min(len, MAX_DATA_CHUNK), bundle.image.len());
+                reply.bytes(&bundle.image[start..end]);
+            }
+            Opcode::ReadKey => {
+                let bundle = self.bundle(args.str().ok_or(Status::BadFrame)?)?;
+                let key = args.str().ok_or(Status::BadFrame)?;
+                reply.bytes(bundle.keys.get(key).ok_or(Status::KeyNotFound)?);
+            }
+            Opcode::WriteKey => {
+                let bundle = self.bundle_mut(args.str().ok_or(Status::BadFrame)?)?;
+                let key = args.str().ok_or(Status::BadFrame)?;
+                let value = args.bytes().ok_or(Status::BadFrame)?;
+                let max_keys = args.u32().ok_or(Status::BadFrame)? as usize;
+                let max_bytes = args.u32().ok_or(Status::BadFrame)? as usize;
+                if key.is_empty() || key.len() > MAX_KEY_LEN {
+                    return Err(Status::KeyInvalid);
+                }
+                if value.len() > MAX_VALUE_LEN {
+                    return Err(Status::ValueInvalid);
+                }
+                let usage = bundle.usage();
+                let (keys, bytes) = match bundle.keys.get(key) {
+                    Some(old) => (usage.keys, usage.bytes - old.len() + value.len()),
+                    None => (usage.keys + 1, usage.bytes + key.len() + value.len()),
+                };
+                if keys > max_keys || bytes > max_bytes {
+                    return Err(Status::QuotaExceeded);
+                }
+                bundle.keys.insert(key.to_string(), value.to_vec());
+            }
+            Opcode::DeleteKey => {
+                let bundle = self.bundle_mut(args.str().ok_or(Status::BadFrame)?)?;
+                let key = args.str().ok_or(Status::BadFrame)?;
+                bundle.keys.remove(key).ok_or(Status::KeyNotFound)?;
+            }
+            Opcode::ListKeys => {
+                let bundle = self.bundle(args.str().ok_or(Status::BadFrame)?)?;
+                let after = args.str().ok_or(Status::BadFrame)?;
+                let max_bytes = args.u32().ok_or(Status::BadFrame)? as usize;
+                let start = after_bound(after);
+                write_names(
+                    bundle
+                        .keys
+                        .range::<str, _>((start, Bound::Unbounded))
+                        .map(|(k, _)| k),
+                    max_bytes,
+                    &mut reply,
+                );
+            }
+            Opcode::KeyUsage => {
+                let usage = self.bundle(args.str().ok_or(Status::BadFrame)?)?.usage();
+                reply.u32(usage.keys as u32).u32(usage.bytes as u32);
+            }
+            Opcode::GetEntropy => {
+                let len = args.u32().ok_or(Status::BadFrame)? as usize;
+                if len > MAX_DATA_CHUNK {
+                    return Err(Status::BadFrame);
+                }
+                let data: Vec<u8> = (0..len).map(|_| self.next_entropy()).collect();
+                reply.bytes(&data);
+            }
+            Opcode::CryptoGenerate => {
+                let bundle_id = args.str().ok_or(Status::BadFrame)?;
+                let name = args.str().ok_or(Status::BadFrame)?;
+                let key_type = u8::try_from(args.u32().ok_or(Status::BadFrame)?)
+                    .ok()
+                    .and_then(|key_type| KeyType::try_from(key_type).ok())
+                    .ok_or(Status::BadFrame)?;
+                kata_crypto_keys::check_name(name).map_err(|_| Status::KeyInvalid)?;
+                let mut secret = [0u8; kata_crypto_keys::SECRET_SIZE];
+                secret.fill_with(|| self.next_entropy());
+                let bundle = self.bundle_mut(bundle_id)?;
+                if bundle.crypto_keys.contains_key(name) {
+                    return Err(Status::DeleteFirst);
+                }
+                if bundle.crypto_keys.len() >= kata_crypto_keys::MAX_BUNDLE_KEYS {
+                    return Err(Status::QuotaExceeded);
+                }
+                let key = CryptoKey::new(key_type, &secret);
+                reply.bytes(&key.public_key());
+                bundle.crypto_keys.insert(name.to_string(), key);
+            }
+            Opcode::CryptoDelete => {
+                let bundle = self.bundle_mut(args.str().ok_or(Status::BadFrame)?)?;
+                let name = args.str().ok_or(Status::BadFrame)?;
+                bundle.crypto_keys.remove(name).ok_or(Status::KeyNotFound)?;
+            }
+            Opcode::CryptoSign => {
+                let key = self.crypto_key(&mut args)?;
+                let data = args.bytes().ok_or(Status::BadFrame)?;
+                reply.bytes(&key.sign(data).map_err(|_| Status::CryptoFailed)?);
+            }
+            Opcode::CryptoVerify => {
+                let key = self.crypto_key(&mut args)?;
+                let data = args.bytes().ok_or(Status::BadFrame)?;
+                let signature = args.bytes().ok_or(Status::BadFrame)?;
+                let valid = key
+                    .verify(data, signature)
+                    .map_err(|_| Status::CryptoFailed)?;
+                reply.u32(valid as u32);
+            }
+            Opcode::CryptoMac => {
+                let key = self.crypto_key(&mut args)?;
+                let data = args.bytes().ok_or(Status::BadFrame)?;
+                reply.bytes(&key.mac(data).map_err(|_| Status::CryptoFailed)?);
+            }
+            Opcode::CryptoEncrypt => {
+                let mut nonce = [0u8; kata_crypto_keys::NONCE_SIZE];
+                nonce.fill_with(|| self.next_entropy());
+                let key = self.crypto_key(&mut args)?;
+                let data = args.bytes().ok_or(Status::BadFrame)?;
+                reply.bytes(
+                    &key.encrypt(data, &nonce)
+                        .map_err(|_| Status::CryptoFailed)?,
+                );
+            }
+            Opcode::CryptoDecrypt => {
+                let key = self.crypto_key(&mut args)?;
+                let data = args.bytes().ok_or(Status::BadFrame)?;
+                reply.bytes(&key.decrypt(data).map_err(|_| Status::CryptoFailed)?);
+            }
+            Opcode::ListBundles => {
+                let after = args.str().ok_or(Status::BadFrame)?;
+                let max_bytes = args.u32().ok_or(Status::BadFrame)? as usize;
+                let start = after_bound(after);
+                write_names(
+                    self.bundles
+                        .range::<str, _>((start, Bound::Unbounded))
+                        .map(|(k, _)| k),
+                    max_bytes,
+                    &mut reply,
+                );
+            }
+            Opcode::AttestSign => {
+                let digest: &[u8; kata_attestation::DIGEST_SIZE] = args
+                    .bytes()
+                    .and_then(|digest| digest.try_into().ok())
+                    .ok_or(Status::BadFrame)?;
+                let (public_key, signature) =
+                    kata_attestation::sign_digest(&kata_attestation::TEST_ATTESTATION_SEED, digest);
+                reply.bytes(&public_key).bytes(&signature);
+            }
+            Opcode::FactoryReset => {
+                self.bundles.clear();
+                self.pending = None;
+                // NB: versions are kept to block rollback after a reset
+            }
+            Opcode::ReadVersion => {
+                let bundle_id = args.str().ok_or(Status::BadFrame)?;
+                reply.u32(self.versions.get(bundle_id).copied().unwrap_or(0));
+            }
+            Opcode::WriteVersion => {
+                let bundle_id = args.str().ok_or(Status::BadFrame)?;
+                match args.u32().ok_or(Status::BadFrame)? {
+                    0 => self.versions.remove(bundle_id),
+                    version => self.versions.insert(bundle_id.to_string(), version),
+                };
+            }
+        }
+        Ok(reply)
+    }
+}
+// Returns the range start for a listing that resumes after |after|.
+fn after_bound(after: &str) -> Bound<&str> {
+    if after.is_empty() {
+        Bound::Unbounded
+    } else {
+        Bound::Excluded(after)
+    }
+}
+
+// Writes a [more: u32, count: u32, name: str..] reply holding as many of
+// |names| as fit in |max_bytes|; each name costs its length + 2.
+fn write_names<'a>(
+    names: impl Iterator<Item = &'a String>,
+    max_bytes: usize,
+    reply: &mut PayloadWriter,
+) {
+    let mut page = Vec::new();
+    let mut page_bytes = 0;
+    let mut more = false;
+    for name in names {
+        page_bytes += name.len() + 2;
+        if page_bytes > max_bytes {
+            more = true;
+            break;
+        }
+        page.push(name);
+    }
+    reply.u32(more as u32).u32(page.len() as u32);
+    for name in page {
+        reply.str(name);
+    }
+}
+
+impl MailboxTransport for SimulatedSecurityCore {
+    fn transact(&mut self, buf: &mut [u8], len: usize) -> Result<usize, MailboxError> {
+        let (request_id, opcode, result) = match FrameHeader::decode(buf, len) {
+            Some(request) if (request.flags & FLAG_REPLY) == 0 => {
+                let result = match Opcode::try_from(request.opcode) {
+                    Ok(opcode) => {
+                        let payload = buf[HEADER_SIZE..len].to_vec();
+                        self.dispatch(opcode, &payload)
+                    }
+                    Err(_) => Err(Status::BadOpcode),
+                };
+                (request.request_id, request.opcode, result)
+            }
+            _ => (0, 0, Err(Status::BadFrame)),
+        };
+        let (status, payload) = match result {
+            Ok(reply) if reply.as_bytes().len() <= MAX_PAYLOAD_SIZE => (Status::Ok, reply),
+            Ok(_) => (Status::Failed, PayloadWriter::new()),
+            Err(status) => (status, PayloadWriter::new()),
+        };
+        let payload = payload.as_bytes();
+        FrameHeader {
+            flags: FLAG_REPLY,
+            request_id,
+            opcode,
+            status: status as u16,
+            length: payload.len() as u32,
+        }
+        .encode(buf);
+        buf[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(payload);
+        Ok(HEADER_SIZE + payload.len())
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! File-backed storage for host tools & tests.
+
+use crate::check_range;
+use crate::StorageBackend;
+use crate::StorageError;
+use crate::ERASED_BYTE;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+pub struct FileBackend {
+    file: File,
+    block_size: usize,
+    block_count: usize,
+}
+impl FileBackend {
+    // Opens (creating if needed) the device image at |path|. A new or
+    // short image is extended with erased blocks.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        block_size: usize,
+        block_count: usize,
+    ) -> Result<Self, StorageError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|_| StorageError::IoError)?;
+        let len = file.metadata().map_err(|_| StorageError::IoError)?.len() as usize;
+        let capacity = block_size * block_count;
+        if len < capacity {
+            let fill = vec![ERASED_BYTE; capacity - len];
+            file.write_all_at(&fill, len as u64)
+                .map_err(|_| StorageError::IoError)?;
+        }
+        Ok(FileBackend {
+            file,
+            block_size,
+            block_count,
+        })
+    }
+}
+impl StorageBackend for FileBackend {
+    fn block_size(&self) -> usize { self.block_size }
+    fn block_count(&self) -> usize { self.block_count }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+        check_range(self, offset, buf.len())?;
+        self.file
+            .read_exact_at(buf, offset as u64)
+            .map_err(|_| StorageError::IoError)
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        check_range(self, offset, data.len())?;
+        let mut current = vec![0u8; data.len()];
+        self.read(offset, &mut current)?;
+        if current.iter().any(|b| *b != ERASED_BYTE) {
+            return Err(StorageError::NotErased);
+        }
+        self.file
+            .write_all_at(data, offset as u64)
+            .map_err(|_| StorageError::IoError)
+    }
+    fn erase(&mut self, block: usize) -> Result<(), StorageError> {
+        let offset = block * self.block_size;
+        check_range(self, offset, self.block_size)?;
+        self.file
+            .write_all_at(&vec![ERASED_BYTE; self.block_size], offset as u64)
+            .map_err(|_| StorageError::IoError)
+    }
+    fn sync(&mut self) -> Result<(), StorageError> {
+        self.file.sync_data().map_err(|_| StorageError::IoError)
+    }
+}
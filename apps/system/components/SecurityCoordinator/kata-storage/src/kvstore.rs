@@ -0,0 +1,877 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Log-structured key-value store.
+//!
+//! The backend is split into two equal regions. One region is active
+//! and holds a header followed by a log of records that is only ever
+//! appended to. Each record carries a CRC so a write torn by power loss
+//! is detected (and discarded) when the store is next opened. When the
+//! active region fills, the live records are copied to the other region
+//! (compaction) and the copy is committed by writing its header, with a
+//! larger generation number, last. On open the valid region with the
+//! largest generation wins, so at every point during compaction either
+//! the old or the new region is a complete copy of the store.
+//!
+//! Keys are grouped by namespace (e.g. bundle id). An in-memory index
+//! maps each (namespace, key) to the location of its value on the
+//! backend; values are read from the backend on demand.
+
+use crate::StorageBackend;
+use crate::StorageError;
+use crate::ERASED_BYTE;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Bound;
+use crc::crc32;
+use crc::Hasher32;
+use log::{info, warn};
+
+const REGION_MAGIC: u32 = 0x4b56_5354; // "KVST"
+pub const REGION_HEADER_SIZE: usize = 16; // magic, generation, reserved, crc32
+
+const RECORD_MAGIC: u32 = 0x4b56_5245; // "KVRE"
+pub const RECORD_HEADER_SIZE: usize = 16; // magic, kind, ns_len, key_len, value_len, crc32
+
+pub const MAX_NAMESPACE_LEN: usize = 255;
+pub const MAX_KEY_LEN: usize = 255;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum RecordKind {
+    Put = 1,
+    Delete = 2,
+    DeleteNamespace = 3,
+}
+impl RecordKind {
+    fn from_u8(kind: u8) -> Option<RecordKind> {
+        match kind {
+            1 => Some(RecordKind::Put),
+            2 => Some(RecordKind::Delete),
+            3 => Some(RecordKind::DeleteNamespace),
+            _ => None,
+        }
+    }
+}
+
+// Location of a live value.
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    offset: usize,     // Backend offset of the value
+    len: usize,        // Value length (bytes)
+    record_len: usize, // Space used by the record holding the value
+}
+
+#[derive(Debug, Default)]
+pub struct KeyValueStoreStats {
+    pub capacity: usize,    // Max bytes of records (one region)
+    pub used: usize,        // Bytes of records in the active region
+    pub live: usize,        // Bytes of records holding live values
+    pub keys: usize,        // Number of live keys
+    pub compactions: usize, // Compactions since open
+}
+
+// Space used by one namespace.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NamespaceUsage {
+    pub keys: usize,  // Number of keys
+    pub bytes: usize, // Bytes of key names + values
+}
+
+pub struct KeyValueStore<B: StorageBackend> {
+    backend: B,
+    region_blocks: usize, // Blocks per region
+    region_size: usize,   // Bytes per region
+    active: usize,        // Active region (0 or 1)
+    generation: u32,      // Generation of the active region
+    tail: usize,          // Region offset where the next record goes
+    index: BTreeMap<String, BTreeMap<String, Entry>>,
+    live_bytes: usize,
+    compactions: usize,
+}
+
+fn record_len(ns: &str, key: &str, value_len: usize) -> usize {
+    RECORD_HEADER_SIZE + ns.len() + key.len() + value_len
+}
+
+fn check_name(name: &str, max_len: usize) -> Result<(), StorageError> {
+    if name.is_empty() || name.len() > max_len {
+        return Err(StorageError::KeyInvalid);
+    }
+    Ok(())
+}
+
+fn encode_region_header(generation: u32) -> [u8; REGION_HEADER_SIZE] {
+    let mut header = [0u8; REGION_HEADER_SIZE];
+    header[0..4].copy_from_slice(&REGION_MAGIC.to_le_bytes());
+    header[4..8].copy_from_slice(&generation.to_le_bytes());
+    let crc = crc32::checksum_ieee(&header[0..12]);
+    header[12..16].copy_from_slice(&crc.to_le_bytes());
+    header
+}
+
+// Returns the generation of a valid region header.
+fn decode_region_header(header: &[u8; REGION_HEADER_SIZE]) -> Option<u32> {
+    let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+    let crc = u32::from_le_bytes(header[12..16].try_into().unwrap());
+    if magic != REGION_MAGIC || crc != crc32::checksum_ieee(&header[0..12]) {
+        return None;
+    }
+    Some(u32::from_le_bytes(header[4..8].try_into().unwrap()))
+}
+
+fn encode_record(kind: RecordKind, ns: &str, key: &str, value: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(record_len(ns, key, value.len()));
+    record.extend_from_slice(&RECORD_MAGIC.to_le_bytes());
+    record.push(kind as u8);
+    record.push(ns.len() as u8);
+    record.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    let mut digest = crc32::Digest::new(crc32::IEEE);
+    digest.write(&record);
+    digest.write(ns.as_bytes());
+    digest.write(key.as_bytes());
+    digest.write(value);
+    record.extend_from_slice(&digest.sum32().to_le_bytes());
+    record.extend_from_slice(ns.as_bytes());
+    record.extend_from_slice(key.as_bytes());
+    record.extend_from_slice(value);
+    record
+}
+
+// A record read back during recovery.
+struct Record {
+    kind: RecordKind,
+    ns: String,
+    key: String,
+    value_offset: usize, // Region offset of the value
+    value_len: usize,
+}
+
+impl<B: StorageBackend> KeyValueStore<B> {
+    // Opens the store on |backend|, recovering any existing contents.
+    // A backend without a valid store is formatted.
+    pub fn new(backend: B) -> Result<Self, StorageError> {
+        let region_blocks = backend.block_count() / 2;
+        if region_blocks == 0 {
+            return Err(StorageError::NoSpace);
+        }
+        let region_size = region_blocks * backend.block_size();
+        let mut store = KeyValueStore {
+            backend,
+            region_blocks,
+            region_size,
+            active: 0,
+            generation: 0,
+            tail: REGION_HEADER_SIZE,
+            index: BTreeMap::new(),
+            live_bytes: 0,
+            compactions: 0,
+        };
+        let generations = [store.read_region_header(0)?, store.read_region_header(1)?];
+        match generations {
+            [None, None] => store.format()?,
+            [Some(g0), Some(g1)] if g1 > g0 => store.mount(1, g1)?,
+            [Some(g0), _] => store.mount(0, g0)?,
+            [None, Some(g1)] => store.mount(1, g1)?,
+        }
+        Ok(store)
+    }
+
+    // Returns the backend; mostly for tests.
+    pub fn backend(&self) -> &B { &self.backend }
+    pub fn into_backend(self) -> B { self.backend }
+
+    // Largest value that can be stored under |ns|:|key|.
+    pub fn max_value_len(&self, ns: &str, key: &str) -> usize {
+        (self.region_size - REGION_HEADER_SIZE).saturating_sub(record_len(ns, key, 0))
+    }
+
+    pub fn stats(&self) -> KeyValueStoreStats {
+        KeyValueStoreStats {
+            capacity: self.region_size - REGION_HEADER_SIZE,
+            used: self.tail - REGION_HEADER_SIZE,
+            live: self.live_bytes,
+            keys: self.index.values().map(|keys| keys.len()).sum(),
+            compactions: self.compactions,
+        }
+    }
+
+    // Returns the keys in |ns| in sorted order.
+    pub fn keys<'a>(&'a self, ns: &str) -> impl Iterator<Item = &'a str> {
+        self.keys_after(ns, None)
+    }
+
+    // Returns the keys in |ns| that sort after |after| (all keys if None)
+    // in sorted order; used to page through a large namespace.
+    pub fn keys_after<'a>(
+        &'a self,
+        ns: &str,
+        after: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a str> {
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        self.index.get(ns).into_iter().flat_map(move |keys| {
+            keys.range::<str, _>((start, Bound::Unbounded))
+                .map(|(k, _)| k.as_str())
+        })
+    }
+
+    pub fn contains_key(&self, ns: &str, key: &str) -> bool { self.entry(ns, key).is_ok() }
+
+    // Returns the length of the value stored under |ns|:|key|.
+    pub fn value_len(&self, ns: &str, key: &str) -> Result<usize, StorageError> {
+        self.entry(ns, key).map(|entry| entry.len)
+    }
+
+    pub fn namespace_usage(&self, ns: &str) -> NamespaceUsage {
+        self.index
+            .get(ns)
+            .map_or_else(NamespaceUsage::default, |keys| NamespaceUsage {
+                keys: keys.len(),
+                bytes: keys.iter().map(|(k, entry)| k.len() + entry.len).sum(),
+            })
+    }
+
+    fn entry(&self, ns: &str, key: &str) -> Result<&Entry, StorageError> {
+        self.index
+            .get(ns)
+            .and_then(|keys| keys.get(key))
+            .ok_or(StorageError::NotFound)
+    }
+
+    pub fn read(&self, ns: &str, key: &str) -> Result<Vec<u8>, StorageError> {
+        let entry = self.entry(ns, key)?;
+        let mut value = vec![0u8; entry.len];
+        self.backend.read(entry.offset, &mut value)?;
+        Ok(value)
+    }
+
+    pub fn write(&mut self, ns: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        check_name(ns, MAX_NAMESPACE_LEN)?;
+        check_name(key, MAX_KEY_LEN)?;
+        if value.len() > self.max_value_len(ns, key) {
+            return Err(StorageError::ValueTooLarge);
+        }
+        let record = encode_record(RecordKind::Put, ns, key, value);
+        if !self.fits(record.len()) {
+            let old_len = self.entry(ns, key).map_or(0, |e| e.record_len);
+            if self.live_bytes - old_len + record.len() > self.region_size - REGION_HEADER_SIZE {
+                return Err(StorageError::NoSpace);
+            }
+            // NB: the new value replaces the old one as part of the
+            //   compaction so the overwrite is atomic
+            return self.compact_filtered(&|n, k| n != ns || k != key, Some((ns, key, value)));
+        }
+        let offset = self.append(&record)?;
+        let value_offset = offset + record_len(ns, key, 0);
+        self.apply(RecordKind::Put, ns, key, value_offset, value.len());
+        Ok(())
+    }
+
+    pub fn delete(&mut self, ns: &str, key: &str) -> Result<(), StorageError> {
+        self.entry(ns, key)?;
+        let record = encode_record(RecordKind::Delete, ns, key, &[]);
+        if !self.fits(record.len()) {
+            // NB: compaction without the key is the delete
+            return self.compact_filtered(&|n, k| n != ns || k != key, None);
+        }
+        self.append(&record)?;
+        self.apply(RecordKind::Delete, ns, key, 0, 0);
+        Ok(())
+    }
+
+    // Deletes all keys in |ns|.
+    pub fn delete_namespace(&mut self, ns: &str) -> Result<(), StorageError> {
+        if !self.index.contains_key(ns) {
+            return Ok(());
+        }
+        let record = encode_record(RecordKind::DeleteNamespace, ns, "", &[]);
+        if !self.fits(record.len()) {
+            return self.compact_filtered(&|n, _| n != ns, None);
+        }
+        self.append(&record)?;
+        self.apply(RecordKind::DeleteNamespace, ns, "", 0, 0);
+        Ok(())
+    }
+
+    // Reclaims space held by overwritten & deleted values.
+    pub fn compact(&mut self) -> Result<(), StorageError> {
+        self.compact_filtered(&|_, _| true, None)
+    }
+
+    // Deletes every (namespace, key) for which |keep| returns false. The
+    // region holding the old contents is erased so nothing deleted
+    // remains on the backend.
+    pub fn retain(&mut self, keep: &dyn Fn(&str, &str) -> bool) -> Result<(), StorageError> {
+        self.compact_filtered(keep, None)?;
+        self.erase_region(1 - self.active)?;
+        self.backend.sync()
+    }
+
+    fn region_base(&self, region: usize) -> usize { region * self.region_size }
+
+    fn fits(&self, len: usize) -> bool { self.tail + len <= self.region_size }
+
+    fn read_region_header(&self, region: usize) -> Result<Option<u32>, StorageError> {
+        let mut header = [0u8; REGION_HEADER_SIZE];
+        self.backend.read(self.region_base(region), &mut header)?;
+        Ok(decode_region_header(&header))
+    }
+
+    fn erase_region(&mut self, region: usize) -> Result<(), StorageError> {
+        for block in 0..self.region_blocks {
+            self.backend.erase(region * self.region_blocks + block)?;
+        }
+        Ok(())
+    }
+
+    fn format(&mut self) -> Result<(), StorageError> {
+        info!("Formatting key-value store ({} bytes)", self.region_size);
+        self.erase_region(0)?;
+        self.backend.write(0, &encode_region_header(1))?;
+        self.backend.sync()?;
+        self.active = 0;
+        self.generation = 1;
+        self.tail = REGION_HEADER_SIZE;
+        Ok(())
+    }
+
+    // Rebuilds the index from the log in |region|.
+    fn mount(&mut self, region: usize, generation: u32) -> Result<(), StorageError> {
+        self.active = region;
+        self.generation = generation;
+        self.tail = REGION_HEADER_SIZE;
+        while let Some(record) = self.read_record(self.tail)? {
+            let base = self.region_base(self.active);
+            self.apply(
+                record.kind,
+                &record.ns,
+                &record.key,
+                base + record.value_offset,
+                record.value_len,
+            );
+            self.tail = record.value_offset + record.value_len;
+        }
+        if !self.is_erased(self.tail)? {
+            // Flash cannot be re-written in place so move the live data
+            // past the damage.
+            warn!("Key-value store has a torn record at offset {}", self.tail);
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    // Returns the valid record at region offset |offset|, if any.
+    fn read_record(&self, offset: usize) -> Result<Option<Record>, StorageError> {
+        if offset + RECORD_HEADER_SIZE > self.region_size {
+            return Ok(None);
+        }
+        let base = self.region_base(self.active);
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        self.backend.read(base + offset, &mut header)?;
+        let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let kind = RecordKind::from_u8(header[4]);
+        let ns_len = header[5] as usize;
+        let key_len = u16::from_le_bytes(header[6..8].try_into().unwrap()) as usize;
+        let value_len = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        if magic != RECORD_MAGIC || kind.is_none() || key_len > MAX_KEY_LEN {
+            return Ok(None);
+        }
+        let payload_len = ns_len + key_len + value_len;
+        if offset + RECORD_HEADER_SIZE + payload_len > self.region_size {
+            return Ok(None);
+        }
+        let mut payload = vec![0u8; payload_len];
+        self.backend
+            .read(base + offset + RECORD_HEADER_SIZE, &mut payload)?;
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&header[0..12]);
+        digest.write(&payload);
+        if digest.sum32() != crc {
+            return Ok(None);
+        }
+        let ns = core::str::from_utf8(&payload[..ns_len]).ok();
+        let key = core::str::from_utf8(&payload[ns_len..ns_len + key_len]).ok();
+        match (ns, key) {
+            (Some(ns), Some(key)) => Ok(Some(Record {
+                kind: kind.unwrap(),
+                ns: ns.to_string(),
+                key: key.to_string(),
+                value_offset: offset + RECORD_HEADER_SIZE + ns_len + key_len,
+                value_len,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    // Returns true if the active region is erased from |offset| to the end.
+    fn is_erased(&self, offset: usize) -> Result<bool, StorageError> {
+        let base = self.region_base(self.active);
+        let mut buf = [0u8; 64];
+        let mut pos = offset;
+        while pos < self.region_size {
+            let n = core::cmp::min(buf.len(), self.region_size - pos);
+            self.backend.read(base + pos, &mut buf[..n])?;
+            if buf[..n].iter().any(|b| *b != ERASED_BYTE) {
+                return Ok(false);
+            }
+            pos += n;
+        }
+        Ok(true)
+    }
+
+    // Appends |record| to the log & returns its backend offset.
+    fn append(&mut self, record: &[u8]) -> Result<usize, StorageError> {
+        let offset = self.region_base(self.active) + self.tail;
+        if let Err(e) = self
+            .backend
+            .write(offset, record)
+            .and_then(|_| self.backend.sync())
+        {
+            // The record may be partially written; force compaction
+            // before the next append.
+            self.tail = self.region_size;
+            return Err(e);
+        }
+        self.tail += record.len();
+        Ok(offset)
+    }
+
+    // Updates the index for a record.
+    fn apply(&mut self, kind: RecordKind, ns: &str, key: &str, offset: usize, len: usize) {
+        match kind {
+            RecordKind::Put => {
+                let entry = Entry {
+                    offset,
+                    len,
+                    record_len: record_len(ns, key, len),
+                };
+                self.live_bytes += entry.record_len;
+                if let Some(old) = self
+                    .index
+                    .entry(ns.to_string())
+                    .or_default()
+                    .insert(key.to_string(), entry)
+                {
+                    self.live_bytes -= old.record_len;
+                }
+            }
+            RecordKind::Delete => {
+                if let Some(keys) = self.index.get_mut(ns) {
+                    if let Some(old) = keys.remove(key) {
+                        self.live_bytes -= old.record_len;
+                    }
+                    if keys.is_empty() {
+                        self.index.remove(ns);
+                    }
+                }
+            }
+            RecordKind::DeleteNamespace => {
+                if let Some(keys) = self.index.remove(ns) {
+                    self.live_bytes -= keys.values().map(|e| e.record_len).sum::<usize>();
+                }
+            }
+        }
+    }
+
+    // Copies the live values selected by |keep|, plus |put| if present,
+    // to the inactive region and makes it the active region.
+    fn compact_filtered(
+        &mut self,
+        keep: &dyn Fn(&str, &str) -> bool,
+        put: Option<(&str, &str, &[u8])>,
+    ) -> Result<(), StorageError> {
+        let target = 1 - self.active;
+        let base = self.region_base(target);
+        self.erase_region(target)?;
+
+        let mut index: BTreeMap<String, BTreeMap<String, Entry>> = BTreeMap::new();
+        let mut live_bytes = 0;
+        let mut tail = REGION_HEADER_SIZE;
+        let mut copy = |backend: &mut B, ns: &str, key: &str, value: &[u8]| {
+            let record = encode_record(RecordKind::Put, ns, key, value);
+            if tail + record.len() > self.region_size {
+                // NB: cannot happen, callers check the live data fit
+                return Err(StorageError::Corrupt);
+            }
+            backend.write(base + tail, &record)?;
+            let entry = Entry {
+                offset: base + tail + record_len(ns, key, 0),
+                len: value.len(),
+                record_len: record.len(),
+            };
+            index
+                .entry(ns.to_string())
+                .or_default()
+                .insert(key.to_string(), entry);
+            live_bytes += record.len();
+            tail += record.len();
+            Ok(())
+        };
+        for (ns, keys) in &self.index {
+            for (key, entry) in keys {
+                if !keep(ns, key) {
+                    continue;
+                }
+                let mut value = vec![0u8; entry.len];
+                self.backend.read(entry.offset, &mut value)?;
+                copy(&mut self.backend, ns, key, &value)?;
+            }
+        }
+        if let Some((ns, key, value)) = put {
+            copy(&mut self.backend, ns, key, value)?;
+        }
+        self.backend.sync()?;
+
+        // Commit: the header makes the new region valid and, with the
+        // larger generation, supersedes the old region.
+        let generation = self.generation.wrapping_add(1);
+        self.backend
+            .write(base, &encode_region_header(generation))?;
+        self.backend.sync()?;
+
+        self.active = target;
+        self.generation = generation;
+        self.tail = tail;
+        self.index = index;
+        self.live_bytes = live_bytes;
+        self.compactions += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileBackend;
+    use crate::RamBackend;
+
+    const BLOCK_SIZE: usize = 256;
+    const BLOCK_COUNT: usize = 8; // 1KiB regions
+
+    fn new_store() -> KeyValueStore<RamBackend> {
+        KeyValueStore::new(RamBackend::new(BLOCK_SIZE, BLOCK_COUNT)).unwrap()
+    }
+
+    // Simulates a reboot.
+    fn reopen(store: KeyValueStore<RamBackend>) -> KeyValueStore<RamBackend> {
+        let bytes = store.into_backend().as_bytes().to_vec();
+        KeyValueStore::new(RamBackend::from_bytes(BLOCK_SIZE, &bytes)).unwrap()
+    }
+
+    #[test]
+    fn test_read_write_delete() {
+        let mut store = new_store();
+        assert_eq!(store.read("app", "foo"), Err(StorageError::NotFound));
+        store.write("app", "foo", b"bar").unwrap();
+        assert_eq!(store.read("app", "foo").unwrap(), b"bar");
+        store.write("app", "foo", b"a longer value").unwrap();
+        assert_eq!(store.read("app", "foo").unwrap(), b"a longer value");
+        store.write("app", "empty", b"").unwrap();
+        assert_eq!(store.read("app", "empty").unwrap(), b"");
+        store.delete("app", "foo").unwrap();
+        assert_eq!(store.read("app", "foo"), Err(StorageError::NotFound));
+        assert_eq!(store.delete("app", "foo"), Err(StorageError::NotFound));
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let mut store = new_store();
+        store.write("app1", "key", b"one").unwrap();
+        store.write("app2", "key", b"two").unwrap();
+        store.write("app2", "other", b"2").unwrap();
+        assert_eq!(store.read("app1", "key").unwrap(), b"one");
+        assert_eq!(store.read("app2", "key").unwrap(), b"two");
+        assert_eq!(store.keys("app2").collect::<Vec<_>>(), ["key", "other"]);
+        assert_eq!(store.keys_after("app2", Some("key")).collect::<Vec<_>>(), ["other"]);
+        assert_eq!(
+            store.keys_after("app2", Some("ka")).collect::<Vec<_>>(),
+            ["key", "other"]
+        );
+        assert_eq!(store.keys_after("app2", Some("other")).count(), 0);
+        assert_eq!(store.value_len("app2", "other"), Ok(1));
+        assert_eq!(
+            store.namespace_usage("app2"),
+            NamespaceUsage {
+                keys: 2,
+                bytes: "key".len() + 3 + "other".len() + 1
+            }
+        );
+        assert_eq!(store.namespace_usage("none"), NamespaceUsage::default());
+        store.delete_namespace("app2").unwrap();
+        assert_eq!(store.keys("app2").count(), 0);
+        assert_eq!(store.read("app1", "key").unwrap(), b"one");
+        assert_eq!(store.stats().keys, 1);
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let mut store = new_store();
+        assert_eq!(store.write("", "key", b"v"), Err(StorageError::KeyInvalid));
+        assert_eq!(store.write("app", "", b"v"), Err(StorageError::KeyInvalid));
+        let long = "k".repeat(MAX_KEY_LEN + 1);
+        assert_eq!(store.write("app", &long, b"v"), Err(StorageError::KeyInvalid));
+        let big = vec![0u8; store.max_value_len("app", "key") + 1];
+        assert_eq!(store.write("app", "key", &big), Err(StorageError::ValueTooLarge));
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut store = new_store();
+        store.write("app", "a", b"1").unwrap();
+        store.write("app", "b", b"2").unwrap();
+        store.write("app", "a", b"3").unwrap();
+        store.delete("app", "b").unwrap();
+        store.write("other", "c", b"4").unwrap();
+        store.delete_namespace("other").unwrap();
+        let store = reopen(store);
+        assert_eq!(store.read("app", "a").unwrap(), b"3");
+        assert_eq!(store.read("app", "b"), Err(StorageError::NotFound));
+        assert_eq!(store.keys("other").count(), 0);
+    }
+
+    #[test]
+    fn test_compaction() {
+        let mut store = new_store();
+        store.write("app", "fixed", b"unchanged").unwrap();
+        for i in 0..200 {
+            store
+                .write("app", "counter", format!("{}", i).as_bytes())
+                .unwrap();
+        }
+        let stats = store.stats();
+        assert!(stats.compactions > 0);
+        assert!(stats.used <= stats.capacity);
+        assert_eq!(stats.keys, 2);
+        let store = reopen(store);
+        assert_eq!(store.read("app", "counter").unwrap(), b"199");
+        assert_eq!(store.read("app", "fixed").unwrap(), b"unchanged");
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut store = new_store();
+        store.write("app", "a", b"secret").unwrap();
+        store.write("app", "b", b"2").unwrap();
+        store.write("sys", "c", b"3").unwrap();
+        store.retain(&|ns, _| ns == "sys").unwrap();
+        assert_eq!(store.keys("app").count(), 0);
+        assert_eq!(store.read("sys", "c").unwrap(), b"3");
+        assert_eq!(store.stats().keys, 1);
+        // Nothing deleted remains on the backend.
+        let bytes = store.backend().as_bytes();
+        assert!(!bytes.windows(6).any(|w| w == b"secret"));
+        let store = reopen(store);
+        assert_eq!(store.keys("app").count(), 0);
+        assert_eq!(store.read("sys", "c").unwrap(), b"3");
+    }
+
+    #[test]
+    fn test_full() {
+        let mut store = new_store();
+        let value = [0x5a; 100];
+        let mut n = 0;
+        loop {
+            match store.write("app", &format!("key{}", n), &value) {
+                Ok(_) => n += 1,
+                Err(e) => {
+                    assert_eq!(e, StorageError::NoSpace);
+                    break;
+                }
+            }
+        }
+        assert!(n > 0);
+        // Overwriting in place and deleting still work when full.
+        store.write("app", "key0", &[0xa5; 100]).unwrap();
+        for i in 0..n {
+            store.delete("app", &format!("key{}", i)).unwrap();
+        }
+        assert_eq!(store.stats().live, 0);
+        store.write("app", "again", &value).unwrap();
+        let store = reopen(store);
+        assert_eq!(store.keys("app").collect::<Vec<_>>(), ["again"]);
+    }
+
+    // Backend that loses power after |budget| bytes have been written.
+    // The write in progress is torn: only the bytes within budget land.
+    struct PowerFailBackend {
+        ram: RamBackend,
+        budget: usize,
+    }
+    impl StorageBackend for PowerFailBackend {
+        fn block_size(&self) -> usize { self.ram.block_size() }
+        fn block_count(&self) -> usize { self.ram.block_count() }
+        fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+            self.ram.read(offset, buf)
+        }
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+            let n = core::cmp::min(self.budget, data.len());
+            self.budget -= n;
+            self.ram.write(offset, &data[..n])?;
+            if n < data.len() {
+                return Err(StorageError::IoError);
+            }
+            Ok(())
+        }
+        fn erase(&mut self, block: usize) -> Result<(), StorageError> {
+            // NB: count an erase as a one-byte write; a torn erase leaves
+            //   the block untouched
+            if self.budget == 0 {
+                return Err(StorageError::IoError);
+            }
+            self.budget -= 1;
+            self.ram.erase(block)
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Write(&'static str, String, Vec<u8>),
+        Delete(&'static str, String),
+        DeleteNamespace(&'static str),
+    }
+
+    fn workload() -> Vec<Op> {
+        let mut ops = Vec::new();
+        for i in 0..40 {
+            let ns = if i % 3 == 0 { "app1" } else { "app2" };
+            ops.push(Op::Write(ns, format!("k{}", i % 5), vec![i as u8; 8 + (i % 9) * 5]));
+            if i % 5 == 4 {
+                ops.push(Op::Delete(ns, format!("k{}", (i + 1) % 5)));
+            }
+            if i == 30 {
+                ops.push(Op::DeleteNamespace("app1"));
+            }
+        }
+        ops
+    }
+
+    type Model = BTreeMap<(String, String), Vec<u8>>;
+
+    fn apply_op(model: &mut Model, op: &Op) {
+        match op {
+            Op::Write(ns, key, value) => {
+                model.insert((ns.to_string(), key.clone()), value.clone());
+            }
+            Op::Delete(ns, key) => {
+                model.remove(&(ns.to_string(), key.clone()));
+            }
+            Op::DeleteNamespace(ns) => model.retain(|(n, _), _| n != ns),
+        }
+    }
+
+    fn run_op<B: StorageBackend>(
+        store: &mut KeyValueStore<B>,
+        op: &Op,
+    ) -> Result<(), StorageError> {
+        match op {
+            Op::Write(ns, key, value) => store.write(ns, key, value),
+            // NB: NotFound is fine, the model tracks existence
+            Op::Delete(ns, key) => match store.delete(ns, key) {
+                Err(StorageError::NotFound) => Ok(()),
+                r => r,
+            },
+            Op::DeleteNamespace(ns) => store.delete_namespace(ns),
+        }
+    }
+
+    fn contents<B: StorageBackend>(store: &KeyValueStore<B>) -> Model {
+        let mut model = Model::new();
+        for ns in ["app1", "app2"] {
+            for key in store.keys(ns) {
+                model.insert((ns.to_string(), key.to_string()), store.read(ns, key).unwrap());
+            }
+        }
+        model
+    }
+
+    #[test]
+    fn test_power_fail() {
+        // Measure the bytes written by the complete workload.
+        let mut total = 0;
+        {
+            let backend = PowerFailBackend {
+                ram: RamBackend::new(BLOCK_SIZE, BLOCK_COUNT),
+                budget: usize::MAX,
+            };
+            let mut store = KeyValueStore::new(backend).unwrap();
+            for op in workload() {
+                run_op(&mut store, &op).unwrap();
+            }
+            assert!(store.stats().compactions > 0);
+            total += usize::MAX - store.backend().budget;
+        }
+
+        // Cut power at every point during the workload; after reboot the
+        // store must hold every acknowledged operation and either all or
+        // none of the operation in progress.
+        for budget in (0..total).step_by(3) {
+            let backend = PowerFailBackend {
+                ram: RamBackend::new(BLOCK_SIZE, BLOCK_COUNT),
+                budget,
+            };
+            let mut store = match KeyValueStore::new(backend) {
+                Ok(store) => store,
+                Err(_) => continue, // Died while formatting
+            };
+            let mut model = Model::new();
+            let mut in_progress = None;
+            for op in workload() {
+                if run_op(&mut store, &op).is_err() {
+                    in_progress = Some(op);
+                    break;
+                }
+                apply_op(&mut model, &op);
+            }
+
+            let bytes = store.into_backend().ram.as_bytes().to_vec();
+            let mut store = KeyValueStore::new(RamBackend::from_bytes(BLOCK_SIZE, &bytes))
+                .unwrap_or_else(|e| panic!("budget {}: reopen failed: {:?}", budget, e));
+            let recovered = contents(&store);
+            if recovered != model {
+                let op = in_progress.expect("no op in progress");
+                apply_op(&mut model, &op);
+                assert_eq!(recovered, model, "budget {}: op {:?}", budget, op);
+            }
+
+            // The recovered store remains usable.
+            store.write("app2", "after", b"reboot").unwrap();
+            let store = reopen(store);
+            assert_eq!(store.read("app2", "after").unwrap(), b"reboot");
+        }
+    }
+
+    #[test]
+    fn test_file_backend() {
+        let path = std::env::temp_dir().join(format!("kata-storage-{}.img", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let backend = FileBackend::open(&path, BLOCK_SIZE, BLOCK_COUNT).unwrap();
+            let mut store = KeyValueStore::new(backend).unwrap();
+            for i in 0..100 {
+                store.write("app", "counter", &[i as u8; 30]).unwrap();
+            }
+            store.write("app", "name", b"kata").unwrap();
+        }
+        {
+            let backend = FileBackend::open(&path, BLOCK_SIZE, BLOCK_COUNT).unwrap();
+            let store = KeyValueStore::new(backend).unwrap();
+            assert_eq!(store.read("app", "counter").unwrap(), [99u8; 30]);
+            assert_eq!(store.read("app", "name").unwrap(), b"kata");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kata OS persistent storage support.
+//!
+//! StorageBackend abstracts a flash-like block device: bytes are read
+//! and written at arbitrary offsets but may only be written once after
+//! the containing block is erased (erased bytes read as ERASED_BYTE).
+//! KeyValueStore implements a log-structured key-value store on top of
+//! a backend.
+//!
+//! Only RamBackend (and, for host tests, FileBackend) are provided;
+//! there is no flash-backed StorageBackend so data held by a
+//! KeyValueStore on target does not survive a reboot.
+
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
+
+mod kvstore;
+pub use kvstore::*;
+
+mod ram;
+pub use ram::RamBackend;
+
+#[cfg(any(test, feature = "std"))]
+mod file;
+#[cfg(any(test, feature = "std"))]
+pub use file::FileBackend;
+
+// Value of a byte after erase.
+pub const ERASED_BYTE: u8 = 0xff;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StorageError {
+    NotFound,      // No such key
+    NoSpace,       // Store is full (even after compaction)
+    KeyInvalid,    // Namespace or key empty or too long
+    ValueTooLarge, // Value exceeds the max record size
+    OutOfRange,    // Access beyond the end of the device
+    NotErased,     // Write to a location not erased
+    Corrupt,       // Unrecoverable on-device state
+    IoError,       // Backend i/o failure
+}
+
+pub trait StorageBackend {
+    // Erase granularity (bytes).
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> usize;
+    fn capacity(&self) -> usize { self.block_size() * self.block_count() }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError>;
+    // Writes |data| at |offset|; the target bytes must be erased.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError>;
+    // Erases |block| (sets all bytes to ERASED_BYTE).
+    fn erase(&mut self, block: usize) -> Result<(), StorageError>;
+    // Returns once all previous writes & erases are durable.
+    fn sync(&mut self) -> Result<(), StorageError> { Ok(()) }
+}
+
+// Range check helper for backends.
+pub(crate) fn check_range(
+    backend: &dyn StorageBackend,
+    offset: usize,
+    len: usize,
+) -> Result<(), StorageError> {
+    match offset.checked_add(len) {
+        Some(end) if end <= backend.capacity() => Ok(()),
+        _ => Err(StorageError::OutOfRange),
+    }
+}
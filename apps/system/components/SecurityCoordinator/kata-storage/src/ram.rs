@@ -0,0 +1,72 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! RAM-backed storage.
+
+use crate::check_range;
+use crate::StorageBackend;
+use crate::StorageError;
+use crate::ERASED_BYTE;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// StorageBackend held in memory. Flash semantics are enforced (writes
+// must be to erased bytes) so this also serves to check clients.
+pub struct RamBackend {
+    block_size: usize,
+    data: Vec<u8>,
+}
+impl RamBackend {
+    pub fn new(block_size: usize, block_count: usize) -> Self {
+        RamBackend {
+            block_size,
+            data: vec![ERASED_BYTE; block_size * block_count],
+        }
+    }
+
+    // Raw device contents; used to simulate power loss in tests.
+    pub fn as_bytes(&self) -> &[u8] { &self.data }
+    pub fn from_bytes(block_size: usize, data: &[u8]) -> Self {
+        assert_eq!(data.len() % block_size, 0);
+        RamBackend {
+            block_size,
+            data: data.to_vec(),
+        }
+    }
+}
+impl StorageBackend for RamBackend {
+    fn block_size(&self) -> usize { self.block_size }
+    fn block_count(&self) -> usize { self.data.len() / self.block_size }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+        check_range(self, offset, buf.len())?;
+        buf.copy_from_slice(&self.data[offset..offset + buf.len()]);
+        Ok(())
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        check_range(self, offset, data.len())?;
+        let target = &mut self.data[offset..offset + data.len()];
+        if target.iter().any(|b| *b != ERASED_BYTE) {
+            return Err(StorageError::NotErased);
+        }
+        target.copy_from_slice(data);
+        Ok(())
+    }
+    fn erase(&mut self, block: usize) -> Result<(), StorageError> {
+        let offset = block * self.block_size;
+        check_range(self, offset, self.block_size)?;
+        self.data[offset..offset + self.block_size].fill(ERASED_BYTE);
+        Ok(())
+    }
+}
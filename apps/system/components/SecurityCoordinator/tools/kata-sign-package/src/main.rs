@@ -0,0 +1,161 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Host tool to sign Kata OS packages for testing.
+//!
+//!   kata-sign-package [--seed <file>] [--bundle-id <id> [--version <n>]]
+//!           <input> <output>
+//!       Append a signature trailer to the package in <input> and write
+//!       the result to <output>. Any existing signature is replaced.
+//!       With --bundle-id a manifest section naming the bundle id and
+//!       version (0 if not given) is put first, replacing any manifest
+//!       already present.
+//!   kata-sign-package [--seed <file>] --public-key
+//!       Print the public key as 64 hex digits; this is the form read
+//!       from KATA_PACKAGE_SIGNING_PUBKEY when building the
+//!       SecurityCoordinator (see kata-security-coordinator/build.rs).
+//!
+//! The signing key is an Ed25519 seed given as 64 hex digits in <file>;
+//! without --seed the well-known development key is used (trusted only
+//! by SecurityCoordinator builds with the "test_signing_key" feature).
+
+use kata_package_signature::*;
+use std::env;
+use std::fs;
+use std::process;
+
+// Well-known development signing key; its public key is
+// kata_package_signature::TEST_PUBLIC_KEY.
+const TEST_SIGNING_SEED: [u8; 32] = *b"kata-os test package signing key";
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: kata-sign-package [--seed <file>] [--bundle-id <id> [--version <n>]] <input> <output>"
+    );
+    eprintln!("       kata-sign-package [--seed <file>] --public-key");
+    process::exit(2);
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("kata-sign-package: {}", msg);
+    process::exit(1);
+}
+
+fn read_seed(path: &str) -> [u8; 32] {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+    let text = text.trim();
+    if text.len() != 64 {
+        fail(format!("{}: expected 64 hex digits", path));
+    }
+    let mut seed = [0u8; 32];
+    for (i, b) in seed.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&text[2 * i..2 * i + 2], 16)
+            .unwrap_or_else(|_| fail(format!("{}: invalid hex", path)));
+    }
+    seed
+}
+
+fn main() {
+    let mut seed = TEST_SIGNING_SEED;
+    let mut print_public_key = false;
+    let mut bundle_id = None;
+    let mut version = 0u32;
+    let mut files = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => seed = read_seed(&args.next().unwrap_or_else(|| usage())),
+            "--public-key" => print_public_key = true,
+            "--bundle-id" => bundle_id = Some(args.next().unwrap_or_else(|| usage())),
+            "--version" => {
+                version = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "-h" | "--help" => usage(),
+            _ => files.push(arg),
+        }
+    }
+
+    if print_public_key {
+        if !files.is_empty() {
+            usage();
+        }
+        let hex: Vec<String> = public_key(&seed)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        println!("{}", hex.concat());
+        return;
+    }
+    if files.len() != 2 {
+        usage();
+    }
+
+    // Split the package into sections, dropping any existing trailer &
+    // padding.
+    let pkg = fs::read(&files[0]).unwrap_or_else(|e| fail(format!("{}: {}", files[0], e)));
+    PackageVerifier::new()
+        .update(&pkg)
+        .unwrap_or_else(|e| fail(format!("{}: {:?}", files[0], e)));
+    let mut sections = Vec::new();
+    let mut offset = 0;
+    while offset + SECTION_HEADER_SIZE <= pkg.len() {
+        let header = pkg[offset..offset + SECTION_HEADER_SIZE]
+            .try_into()
+            .unwrap();
+        let section = match SectionInfo::parse(header) {
+            Some(section) => section,
+            None => break,
+        };
+        let end = offset + SECTION_HEADER_SIZE + section.fsize;
+        if end > pkg.len() {
+            fail(format!("{}: truncated section at offset {}", files[0], offset));
+        }
+        sections.push((section, &pkg[offset..end]));
+        offset = end;
+    }
+    if sections.is_empty() {
+        fail(format!("{}: no sections found", files[0]));
+    }
+
+    let mut body = Vec::new();
+    if let Some(bundle_id) = bundle_id {
+        let manifest = format!("[Manifest]\nBundleId={}\nVersion={}\n", bundle_id, version);
+        body.extend_from_slice(&manifest_header(manifest.len()));
+        body.extend_from_slice(manifest.as_bytes());
+        sections.retain(|(section, _)| !section.is_manifest());
+    }
+    for (_, bytes) in &sections {
+        body.extend_from_slice(bytes);
+    }
+    let mut verifier = PackageVerifier::new();
+    verifier
+        .update(&body)
+        .unwrap_or_else(|e| fail(format!("{}: {:?}", files[0], e)));
+    let digest = verifier
+        .digest()
+        .unwrap_or_else(|e| fail(format!("{}: {:?}", files[0], e)));
+
+    let mut signed = body;
+    signed.extend_from_slice(&signature_trailer(&seed, &digest));
+    fs::write(&files[1], &signed).unwrap_or_else(|e| fail(format!("{}: {}", files[1], e)));
+    eprintln!(
+        "{}: signed {} sections, {} bytes",
+        files[1],
+        verifier.sections(),
+        signed.len()
+    );
+}
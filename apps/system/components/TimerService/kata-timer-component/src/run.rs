@@ -80,6 +80,9 @@ pub unsafe extern "C" fn timer_cancel(timer_id: TimerId) -> TimerServiceError {
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn timer_now_ms() -> u64 { TIMER_SRV.lock().now_ms() }
+
 #[no_mangle]
 pub unsafe extern "C" fn timer_interrupt_handle() {
     extern "C" {
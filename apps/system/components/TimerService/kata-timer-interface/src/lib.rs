@@ -26,6 +26,8 @@ pub trait HardwareTimer {
     fn ack_interrupt(&self);
     // The current value of the timer.
     fn now(&self) -> Ticks;
+    // The current value of the timer in milliseconds.
+    fn now_ms(&self) -> u64;
     // Return the deadline `duration` in the future, in Ticks.
     fn deadline(&self, duration: Duration) -> Ticks;
     fn set_alarm(&self, deadline: Ticks);
@@ -75,6 +77,15 @@ pub fn timer_service_cancel(timer_id: u32) -> TimerServiceError {
     unsafe { timer_cancel(timer_id) }
 }
 
+#[inline]
+#[allow(dead_code)]
+pub fn timer_service_now_ms() -> u64 {
+    extern "C" {
+        fn timer_now_ms() -> u64;
+    }
+    unsafe { timer_now_ms() }
+}
+
 #[inline]
 #[allow(dead_code)]
 pub fn timer_service_notification() -> seL4_CPtr {
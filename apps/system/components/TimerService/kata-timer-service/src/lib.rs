@@ -28,7 +28,7 @@ use spin::Mutex;
 // TODO(jesionowski): NUM_CLIENTS should be derived through the static
 // camkes configuration. This may take some template hacking as the number
 // of clients is generated as a C #define.
-const NUM_CLIENTS: usize = 2;
+const NUM_CLIENTS: usize = 3;
 
 // We use a TimerId as a bit vector denoting completed timers.
 const TIMERS_PER_CLIENT: usize = 32;
@@ -60,6 +60,8 @@ pub static mut TIMER_SRV: Mutex<KataTimerService> = Mutex::new(KataTimerService
 impl KataTimerService {
     pub fn init(&mut self) { self.timer.setup(); }
 
+    pub fn now_ms(&self) -> u64 { self.timer.now_ms() }
+
     pub fn completed_timers(&mut self, client_id: seL4_Word) -> u32 {
         assert!(0 < client_id && client_id <= NUM_CLIENTS);
 
@@ -51,6 +51,8 @@ impl HardwareTimer for OtTimer {
         Ticks::from(((high as u64) << 32) | low as u64)
     }
 
+    fn now_ms(&self) -> u64 { (self.now() * 1000) / TIMER_FREQ as u64 }
+
     fn deadline(&self, duration: Duration) -> Ticks {
         let tick_duration = (TIMER_FREQ as u64 * duration.as_millis() as u64) / 1000;
         self.now() + tick_duration
@@ -22,6 +22,7 @@ use core::mem::size_of;
 use sel4_sys::seL4_CPtr;
 use sel4_sys::seL4_CapRights;
 use sel4_sys::seL4_Default_VMAttributes;
+use sel4_sys::seL4_Error;
 use sel4_sys::seL4_Page_Map;
 use sel4_sys::seL4_Page_Unmap;
 use sel4_sys::seL4_Result;
@@ -58,6 +59,7 @@ pub struct CopyRegion {
     region: *mut seL4_Word,
     size: usize,
     cur_frame: Option<seL4_CPtr>,
+    frame_bytes: usize, // Size of the mapped frame, 0 when no frame mapped
 }
 impl CopyRegion {
     pub fn new(region: *mut seL4_Word, size: usize) -> Self {
@@ -65,48 +67,57 @@ impl CopyRegion {
             region,
             size,
             cur_frame: None,
+            frame_bytes: 0,
         }
     }
 
     // Returns the region size in bytes.
     pub fn size(&self) -> usize { self.size }
 
-    // Returns the region size if mapped, otherwise 0.
-    pub fn mapped_bytes(&self) -> usize {
-        if self.cur_frame.is_some() {
-            self.size
-        } else {
-            0
-        }
-    }
+    // Returns the size of the mapped frame, otherwise 0. This is less
+    // than the region size when a frame smaller than the region is mapped.
+    pub fn mapped_bytes(&self) -> usize { self.frame_bytes }
 
     // Returns an immutable [u8] ref to the mapped region.
     pub fn as_ref(&mut self) -> &[u8] {
         assert!(self.cur_frame.is_some());
-        unsafe { core::slice::from_raw_parts(self.region as _, self.size) }
+        unsafe { core::slice::from_raw_parts(self.region as _, self.frame_bytes) }
     }
 
     // Returns a mutable [u8] ref to the mapped region.
     pub fn as_mut(&mut self) -> &mut [u8] {
         assert!(self.cur_frame.is_some());
-        unsafe { core::slice::from_raw_parts_mut(self.region as _, self.size) }
+        unsafe { core::slice::from_raw_parts_mut(self.region as _, self.frame_bytes) }
     }
 
     // Returns an immutable [seL4_Word] ref to the mapped region.
     pub fn as_word_ref(&mut self) -> &[seL4_Word] {
         assert!(self.cur_frame.is_some());
-        unsafe { core::slice::from_raw_parts(self.region, self.size / size_of::<seL4_Word>()) }
+        unsafe {
+            core::slice::from_raw_parts(self.region, self.frame_bytes / size_of::<seL4_Word>())
+        }
     }
 
     // Returns a mutable [seL4_Word] ref to the mapped region.
     pub fn as_word_mut(&mut self) -> &mut [seL4_Word] {
         assert!(self.cur_frame.is_some());
-        unsafe { core::slice::from_raw_parts_mut(self.region, self.size / size_of::<seL4_Word>()) }
+        unsafe {
+            core::slice::from_raw_parts_mut(self.region, self.frame_bytes / size_of::<seL4_Word>())
+        }
     }
 
-    // Maps the |frame| in the SELF_VSPACE_ROOT for r/w.
+    // Maps the |frame| in the SELF_VSPACE_ROOT for r/w. The frame is
+    // assumed to fill the region; use map_sized otherwise.
+    pub fn map(&mut self, frame: seL4_CPtr) -> seL4_Result { self.map_sized(frame, self.size) }
+
+    // Maps the |frame| of |frame_bytes| in the SELF_VSPACE_ROOT for r/w.
+    // The frame must fit in the region (and for large pages the region
+    // must be suitably aligned).
     // XXX need rights + attribs?
-    pub fn map(&mut self, frame: seL4_CPtr) -> seL4_Result {
+    pub fn map_sized(&mut self, frame: seL4_CPtr, frame_bytes: usize) -> seL4_Result {
+        if frame_bytes > self.size {
+            return Err(seL4_Error::seL4_RangeError);
+        }
         black_box(frame); // NB: compiler WAR for frame clobber
         unsafe {
             seL4_Page_Map(
@@ -121,6 +132,7 @@ impl CopyRegion {
             )
         }?;
         self.cur_frame = Some(frame);
+        self.frame_bytes = frame_bytes;
         Ok(())
     }
 
@@ -128,10 +140,11 @@ impl CopyRegion {
     pub fn unmap(&mut self) -> seL4_Result {
         if let Some(cptr) = self.cur_frame {
             #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-            unsafe { sel4_sys::seL4_ARM_Page_Unify_Instruction(cptr, 0, self.size()) }?;
+            unsafe { sel4_sys::seL4_ARM_Page_Unify_Instruction(cptr, 0, self.frame_bytes) }?;
 
             unsafe { seL4_Page_Unmap(cptr) }?;
             self.cur_frame = None;
+            self.frame_bytes = 0;
         }
         Ok(())
     }
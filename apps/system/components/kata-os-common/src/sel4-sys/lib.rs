@@ -316,6 +316,15 @@ pub struct seL4_UntypedDesc {
     align: [seL4_Word; 0],
 }
 impl seL4_UntypedDesc {
+    pub fn new(paddr: seL4_Word, size_bits: usize, is_device: bool, is_tainted: bool) -> Self {
+        seL4_UntypedDesc {
+            paddr,
+            sizeBits: size_bits as u8,
+            isDevice: is_device as u8,
+            isTainted: is_tainted as u8,
+            align: [],
+        }
+    }
     pub fn is_device(&self) -> bool { self.isDevice != 0 }
     pub fn is_tainted(&self) -> bool { self.isTainted != 0 }
     pub fn size_bits(&self) -> usize { self.sizeBits as usize }
@@ -24,8 +24,9 @@ set(CARGO_OPTIONS
 # BUILD_DIR: directory for cargo build output
 # LIB_FILENAME: filename of library created by cargo
 # DEPENDS: And target or file dependencies that need to be run before cargo
+# FEATURES: cargo features to enable (e.g. crate/feature in a workspace)
 function(RustAddLibrary lib_name)
-    cmake_parse_arguments(PARSE_ARGV 1 RUST "" "SOURCE_DIR;BUILD_DIR;LIB_FILENAME" "DEPENDS")
+    cmake_parse_arguments(PARSE_ARGV 1 RUST "" "SOURCE_DIR;BUILD_DIR;LIB_FILENAME" "DEPENDS;FEATURES")
     if(NOT "${RUST_UNPARSED_ARGUMENTS}" STREQUAL "")
         message(FATAL_ERROR "Unknown arguments to RustAddLibrary ${RUST_UNPARSED_ARGUMENTS}")
     endif()
@@ -43,6 +44,11 @@ function(RustAddLibrary lib_name)
         set(CARGO_RELEASE "--release")
     endif()
 
+    if(NOT "${RUST_FEATURES}" STREQUAL "")
+        string(REPLACE ";" "," RUST_FEATURES "${RUST_FEATURES}")
+        set(CARGO_FEATURES "--features" "${RUST_FEATURES}")
+    endif()
+
     add_custom_target(
         ${lib_name}_custom
         BYPRODUCTS
@@ -54,7 +60,7 @@ function(RustAddLibrary lib_name)
             ${CMAKE_COMMAND} -E env RUSTFLAGS=${RUSTFLAGS}
             cargo "+$ENV{KATA_RUST_VERSION}" build
             --target ${RUST_TARGET}
-            ${CARGO_OPTIONS} ${CARGO_RELEASE}
+            ${CARGO_OPTIONS} ${CARGO_RELEASE} ${CARGO_FEATURES}
             --target-dir ${RUST_BUILD_DIR}
             --out-dir ${RUST_BUILD_DIR}
     )